@@ -0,0 +1,127 @@
+// Times every benchmark in this directory on the tree walking interpreter and
+// on the bytecode VM, checking that both print the same output. Run them with
+// `cargo bench`, or only some of them with
+// `cargo bench --bench vm -- fib sieve --runs 10`. Naming the bench keeps the
+// arguments from the test harnesses of the other targets, which reject `--runs`.
+
+use rattlescript::Engine;
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const STACK_SIZE: usize = 1 << 30;
+
+// Collects what a benchmark prints, so the two runs can be compared
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Runs a script in a fresh engine each time, returning the median time and
+// what the last run printed. Only running the file is timed, not starting
+// the process or the engine.
+fn time_script(script: &Path, use_vm: bool, runs: usize) -> (Duration, Vec<u8>) {
+    let mut timings = vec![];
+    let mut printed = vec![];
+    for _ in 0..runs {
+        let output = Output::default();
+        let mut engine = Engine::new();
+        if use_vm {
+            engine.enable_vm();
+        }
+        engine.set_output(output.clone());
+        let start = Instant::now();
+        if let Err(err) = engine.eval_file(script) {
+            eprintln!("[-] {} failed:\n{}", script.display(), err);
+            std::process::exit(1);
+        }
+        timings.push(start.elapsed());
+        printed = output.0.take();
+    }
+    timings.sort();
+    (timings[timings.len() / 2], printed)
+}
+
+fn bench(names: Vec<String>, runs: usize) {
+    let bench_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("benches");
+    let mut scripts: Vec<PathBuf> = match std::fs::read_dir(&bench_dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rat"))
+            .filter(|path| {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                names.is_empty() || names.iter().any(|name| *name == stem)
+            })
+            .collect(),
+        Err(err) => {
+            eprintln!("[-] Couldn't read {}: {}", bench_dir.display(), err);
+            std::process::exit(1);
+        }
+    };
+    scripts.sort();
+
+    println!(
+        "{:<16}{:>14}{:>12}{:>10}",
+        "benchmark", "tree walker", "vm", "speedup"
+    );
+    for script in scripts {
+        let (tree, tree_output) = time_script(&script, false, runs);
+        let (vm, vm_output) = time_script(&script, true, runs);
+        let name = script.file_stem().unwrap_or_default().to_string_lossy();
+        if tree_output != vm_output {
+            eprintln!("[-] {}: the VM printed something different", name);
+            std::process::exit(1);
+        }
+        println!(
+            "{:<16}{:>13.3}s{:>11.3}s{:>9.2}x",
+            name,
+            tree.as_secs_f64(),
+            vm.as_secs_f64(),
+            tree.as_secs_f64() / vm.as_secs_f64()
+        );
+    }
+}
+
+fn main() {
+    let mut names = vec![];
+    let mut runs = 5;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // Passed by `cargo bench`
+            "--bench" => {}
+            "-r" | "--runs" => match args.next().and_then(|runs| runs.parse().ok()) {
+                Some(count) if count > 0 => runs = count,
+                _ => {
+                    eprintln!("--runs needs a positive number");
+                    std::process::exit(1);
+                }
+            },
+            _ => names.push(arg),
+        }
+    }
+
+    // Deep recursion needs more than the main thread's stack, as in the CLI
+    let benchmarks = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || bench(names, runs));
+    match benchmarks.map(|thread| thread.join()) {
+        Ok(Ok(())) => {}
+        Ok(Err(_)) => std::process::exit(101),
+        Err(err) => {
+            eprintln!("Couldn't start the benchmarks: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
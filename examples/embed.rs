@@ -0,0 +1,72 @@
+// Runs RattleScript from a Rust program: `cargo run --example embed`
+
+use rattlescript::{Engine, Limits, Sandbox, Type, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// A host type that scripts can only use through its registered methods
+struct Counter {
+    count: i64,
+}
+
+fn main() -> rattlescript::Result<()> {
+    let mut engine = Engine::new();
+
+    // Globals set from Rust are visible to scripts
+    engine.set("greeting", Value::String(Rc::new("Hello".to_string())));
+    engine.eval("def greet(name) => `{greeting}, {name}!`")?;
+
+    // And script functions can be called from Rust
+    let name = Value::String(Rc::new("Rust".to_string()));
+    let message = engine.call("greet", vec![name])?;
+    println!("{}", engine.format(&message)?);
+
+    let total = engine.eval("let numbers = [1, 2, 3]\nnumbers.len() * 10")?;
+    if let Value::Integer(total) = total {
+        println!("total: {}", total);
+    }
+
+    // Rust functions can be registered as globals or as methods of built-in
+    // types, and their arguments and results are converted automatically
+    engine.register_fn("sum", |numbers: Vec<f64>| numbers.iter().sum::<f64>());
+    engine.register_fn("lookup", |table: HashMap<String, i64>, key: String| {
+        table.get(&key).copied()
+    });
+    engine.register_method(Type::String, "shout", |text: String| {
+        format!("{}!", text.to_uppercase())
+    });
+    let result = engine.eval("(sum([1, 2.5]), lookup({\"a\": 1}, \"b\"), \"hi\".shout())")?;
+    println!("{}", engine.format(&result)?);
+
+    // Host objects carry Rust values through scripts
+    engine.register_fn("counter", || Value::object(Counter { count: 0 }));
+    engine.register_object_method::<Counter, _>("add", |counter: Rc<RefCell<Counter>>, by: i64| {
+        counter.borrow_mut().count += by;
+        counter.borrow().count
+    });
+    let count = engine.eval("let c = counter()\nc.add(2)\nc.add(3)")?;
+    println!("count: {}", engine.format(&count)?);
+
+    // Errors come back as values, with the kind and location of the problem
+    if let Err(error) = engine.eval("greet()") {
+        println!("{:?} at {}: {}", error.kind, error.span.0, error.message);
+    }
+
+    // A sandbox restricts what scripts can do outside the interpreter, and
+    // what it denies fails with an error scripts can catch
+    engine.set_sandbox(Sandbox::deny_all());
+    let kind = engine.eval("try { open(\"Cargo.toml\") } catch error { error.kind }")?;
+    println!("open() in the sandbox: {}", engine.format(&kind)?);
+
+    // Limits stop runaway scripts with an error instead of hanging or
+    // crashing the host
+    engine.set_limits(Limits {
+        max_steps: Some(10_000),
+        ..Limits::default()
+    });
+    if let Err(error) = engine.eval("while true {}") {
+        println!("{}", error);
+    }
+    Ok(())
+}
@@ -5,6 +5,7 @@
  */
 
 use crate::common::Span;
+use std::cell::{Cell, OnceCell};
 use std::collections::HashMap;
 use std::rc::Rc;
 
@@ -30,22 +31,193 @@ impl std::fmt::Display for ArgumentType {
 pub type FunctionArgs = Vec<(String, Option<Rc<AST>>, ArgumentType)>;
 pub type CallArgs = Vec<(Option<String>, Rc<AST>)>;
 pub type ImportObject = (Vec<(String, Option<String>)>, Span);
+pub type MatchArm = (Rc<Pattern>, Option<Rc<AST>>, Rc<AST>, Layout);
+
+// Where the resolver found a variable: `depth` scopes up from the one it is
+// used in, at index `index` of that scope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
+
+// The names of the variables declared in a scope, in slot order, filled in
+// by the resolver. On a block this is the scope its statements run in, and
+// blocks that don't declare anything are left without one so they run in the
+// enclosing scope.
+#[derive(Debug, Default)]
+pub struct Layout(OnceCell<Rc<Vec<String>>>);
+
+impl Layout {
+    pub fn get(&self) -> Option<&Rc<Vec<String>>> {
+        self.0.get()
+    }
+
+    pub fn set(&self, names: Vec<String>) {
+        // Each tree is only resolved once
+        let _ = self.0.set(Rc::new(names));
+    }
+}
+
+// The name `import path as alias` binds
+pub fn import_name(path: &str, alias: &Option<String>) -> String {
+    match alias {
+        Some(name) => name.clone(),
+        None => std::path::Path::new(path)
+            .with_extension("")
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string(),
+    }
+}
+
+#[derive(Debug)]
+pub enum Pattern {
+    Wildcard(Span),
+    Binding(Span, String),
+    Value(Span, Rc<AST>),
+    Or(Span, Vec<Rc<Pattern>>),
+    Rest(Span, Option<String>),
+    Array(Span, Vec<Rc<Pattern>>),
+    Tuple(Span, Vec<Rc<Pattern>>),
+    Dict(Span, Vec<(Rc<AST>, Rc<Pattern>)>, Option<String>),
+    Class(Span, Rc<AST>, Vec<(Option<String>, Rc<Pattern>)>),
+}
+
+impl Pattern {
+    pub fn span(&self) -> &Span {
+        match self {
+            Pattern::Wildcard(span) => span,
+            Pattern::Binding(span, ..) => span,
+            Pattern::Value(span, ..) => span,
+            Pattern::Or(span, ..) => span,
+            Pattern::Rest(span, ..) => span,
+            Pattern::Array(span, ..) => span,
+            Pattern::Tuple(span, ..) => span,
+            Pattern::Dict(span, ..) => span,
+            Pattern::Class(span, ..) => span,
+        }
+    }
+
+    // Calls `f` on the expressions inside the pattern, e.g. the value in a
+    // value pattern or the class in a class pattern
+    pub fn for_each_expression(&self, f: &mut dyn FnMut(&Rc<AST>)) {
+        match self {
+            Pattern::Wildcard(_) | Pattern::Binding(..) | Pattern::Rest(..) => {}
+            Pattern::Value(_, expr) => f(expr),
+            Pattern::Or(_, items) | Pattern::Array(_, items) | Pattern::Tuple(_, items) => {
+                items.iter().for_each(|item| item.for_each_expression(f))
+            }
+            Pattern::Dict(_, items, _) => items.iter().for_each(|(key, item)| {
+                f(key);
+                item.for_each_expression(f);
+            }),
+            Pattern::Class(_, class, args) => {
+                f(class);
+                args.iter()
+                    .for_each(|(_, item)| item.for_each_expression(f));
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fn write_list<T: std::fmt::Display>(
+            f: &mut std::fmt::Formatter,
+            items: impl Iterator<Item = T>,
+        ) -> std::fmt::Result {
+            for (i, item) in items.enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", item)?;
+            }
+            Ok(())
+        }
+        match self {
+            Pattern::Wildcard(_) => write!(f, "_"),
+            Pattern::Binding(_, name) => write!(f, "{}", name),
+            Pattern::Value(_, expr) => write!(f, "{}", expr),
+            Pattern::Or(_, alternatives) => {
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{}", alternative)?;
+                }
+                Ok(())
+            }
+            Pattern::Rest(_, name) => write!(f, "*{}", name.as_deref().unwrap_or("_")),
+            Pattern::Array(_, items) => {
+                write!(f, "[")?;
+                write_list(f, items.iter())?;
+                write!(f, "]")
+            }
+            Pattern::Tuple(_, items) => {
+                write!(f, "(")?;
+                write_list(f, items.iter())?;
+                if items.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Pattern::Dict(_, items, rest) => {
+                write!(f, "{{")?;
+                write_list(
+                    f,
+                    items
+                        .iter()
+                        .map(|(key, value)| format!("{}: {}", key, value)),
+                )?;
+                if let Some(rest) = rest {
+                    if !items.is_empty() {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "**{}", rest)?;
+                }
+                write!(f, "}}")
+            }
+            Pattern::Class(_, class, args) => {
+                write!(f, "{}(", class)?;
+                write_list(
+                    f,
+                    args.iter().map(|(name, pattern)| match name {
+                        Some(name) => format!("{}: {}", name, pattern),
+                        None => pattern.to_string(),
+                    }),
+                )?;
+                write!(f, ")")
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum AST {
     And(Span, Rc<AST>, Rc<AST>),
     Assert(Span, Rc<AST>, Option<String>),
     Assignment(Span, Rc<AST>, Rc<AST>),
-    Block(Span, Vec<Rc<AST>>),
+    Block(Span, Vec<Rc<AST>>, Layout),
     Class {
         span: Span,
         name: String,
         parents: Option<Vec<String>>,
         fields: HashMap<String, (Rc<AST>, bool)>,
+        layout: Layout,
     },
     BooleanLiteral(Span, bool),
     Call(Span, Rc<AST>, CallArgs),
     Divide(Span, Rc<AST>, Rc<AST>),
+    Enum {
+        span: Span,
+        name: String,
+        variants: Vec<(String, Vec<String>)>,
+        fields: HashMap<String, (Rc<AST>, bool)>,
+        layout: Layout,
+    },
     Modulo(Span, Rc<AST>, Rc<AST>),
     FloatLiteral(Span, f64),
     Function {
@@ -55,7 +227,9 @@ pub enum AST {
         required: usize,
         is_static: bool,
         in_class: bool,
+        is_generator: bool,
         body: Rc<AST>,
+        layout: Layout,
     },
     Namespace {
         span: Span,
@@ -66,6 +240,7 @@ pub enum AST {
     In(Span, Rc<AST>, Rc<AST>),
     Index(Span, Rc<AST>, Rc<AST>),
     IntegerLiteral(Span, i64),
+    Match(Span, Rc<AST>, Vec<MatchArm>),
     Minus(Span, Rc<AST>, Rc<AST>),
     Multiply(Span, Rc<AST>, Rc<AST>),
     Power(Span, Rc<AST>, Rc<AST>),
@@ -83,8 +258,17 @@ pub enum AST {
         step: Option<Rc<AST>>,
     },
     StringLiteral(Span, String),
+    Throw(Span, Rc<AST>),
+    Try {
+        span: Span,
+        body: Rc<AST>,
+        catch: Option<(Option<String>, Rc<AST>)>,
+        finally: Option<Rc<AST>>,
+    },
     VarDeclaration(Span, String, Rc<AST>),
-    Variable(Span, String),
+    // A `let` that destructures its value, e.g. `let (a, b) = pair`
+    Destructure(Span, Rc<Pattern>, Rc<AST>),
+    Variable(Span, String, Cell<Option<Slot>>),
     Equals(Span, Rc<AST>, Rc<AST>),
     NotEquals(Span, Rc<AST>, Rc<AST>),
     LessThan(Span, Rc<AST>, Rc<AST>),
@@ -92,15 +276,19 @@ pub enum AST {
     LessEquals(Span, Rc<AST>, Rc<AST>),
     GreaterEquals(Span, Rc<AST>, Rc<AST>),
     While(Span, Rc<AST>, Rc<AST>),
+    Yield(Span, Rc<AST>),
+    // `super` or `super(Parent)`, only valid before a field access
+    Super(Span, Option<Rc<AST>>),
     Continue(Span),
     Break(Span),
-    ForEach(Span, String, Rc<AST>, Rc<AST>),
+    ForEach(Span, Rc<Pattern>, Rc<AST>, Rc<AST>, Layout),
     For {
         span: Span,
         init: Option<Rc<AST>>,
         cond: Option<Rc<AST>>,
         step: Option<Rc<AST>>,
         body: Rc<AST>,
+        layout: Layout,
     },
     Import {
         span: Span,
@@ -113,7 +301,8 @@ pub enum AST {
         names: Vec<(String, Option<String>)>,
     },
     FieldAccess(Span, Rc<AST>, String),
-    Comprehension(Span, String, Rc<AST>, Rc<AST>, Option<Rc<AST>>),
+    Comprehension(Span, Rc<Pattern>, Rc<AST>, Rc<AST>, Option<Rc<AST>>, Layout),
+    SetComprehension(Span, Rc<Pattern>, Rc<AST>, Rc<AST>, Option<Rc<AST>>, Layout),
     FormatStringLiteral(Span, Vec<String>, Vec<Rc<AST>>),
     Range(Span, Rc<AST>, Rc<AST>),
     StarExpression(Span, Rc<AST>),
@@ -124,6 +313,7 @@ pub enum AST {
     ArrayLiteral(Span, Vec<Rc<AST>>),
     TupleLiteral(Span, Vec<Rc<AST>>),
     DictionaryLiteral(Span, Vec<(Rc<AST>, Rc<AST>)>),
+    SetLiteral(Span, Vec<Rc<AST>>),
 }
 
 impl AST {
@@ -137,11 +327,13 @@ impl AST {
             AST::Call(span, ..) => span,
             AST::Class { span, .. } => span,
             AST::Divide(span, ..) => span,
+            AST::Enum { span, .. } => span,
             AST::FloatLiteral(span, ..) => span,
             AST::Function { span, .. } => span,
             AST::If(span, ..) => span,
             AST::Index(span, ..) => span,
             AST::IntegerLiteral(span, ..) => span,
+            AST::Match(span, ..) => span,
             AST::Minus(span, ..) => span,
             AST::In(span, ..) => span,
             AST::Multiply(span, ..) => span,
@@ -155,7 +347,12 @@ impl AST {
             AST::Return(span, ..) => span,
             AST::Slice { span, .. } => span,
             AST::StringLiteral(span, ..) => span,
+            AST::Throw(span, ..) => span,
+            AST::Yield(span, ..) => span,
+            AST::Super(span, ..) => span,
+            AST::Try { span, .. } => span,
             AST::VarDeclaration(span, ..) => span,
+            AST::Destructure(span, ..) => span,
             AST::Variable(span, ..) => span,
             AST::Equals(span, ..) => span,
             AST::NotEquals(span, ..) => span,
@@ -170,6 +367,7 @@ impl AST {
             AST::For { span, .. } => span,
             AST::FieldAccess(span, ..) => span,
             AST::Comprehension(span, ..) => span,
+            AST::SetComprehension(span, ..) => span,
             AST::FormatStringLiteral(span, ..) => span,
             AST::Range(span, ..) => span,
             AST::PostIncrement(span, ..) => span,
@@ -177,6 +375,7 @@ impl AST {
             AST::ArrayLiteral(span, ..) => span,
             AST::TupleLiteral(span, ..) => span,
             AST::DictionaryLiteral(span, ..) => span,
+            AST::SetLiteral(span, ..) => span,
             AST::Import { span, .. } => span,
             AST::FromImport { span, .. } => span,
             AST::Namespace { span, .. } => span,
@@ -184,6 +383,179 @@ impl AST {
             AST::StarStarExpression(span, ..) => span,
         }
     }
+
+    // Whether a `yield` appears in this statement, not counting nested functions
+    pub fn contains_yield(&self) -> bool {
+        match self {
+            AST::Yield(..) => true,
+            AST::Block(_, stmts, _) => stmts.iter().any(|stmt| stmt.contains_yield()),
+            AST::If(_, _, body, else_body) => {
+                body.contains_yield()
+                    || else_body.as_ref().is_some_and(|body| body.contains_yield())
+            }
+            AST::While(_, _, body) | AST::ForEach(_, _, _, body, _) | AST::For { body, .. } => {
+                body.contains_yield()
+            }
+            AST::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                body.contains_yield()
+                    || catch
+                        .as_ref()
+                        .is_some_and(|(_, body)| body.contains_yield())
+                    || finally.as_ref().is_some_and(|body| body.contains_yield())
+            }
+            AST::Match(_, _, arms) => arms.iter().any(|(_, _, body, _)| body.contains_yield()),
+            _ => false,
+        }
+    }
+
+    // Calls `f` on every direct child node, including the expressions inside
+    // patterns and function bodies
+    pub fn for_each_child(&self, f: &mut dyn FnMut(&Rc<AST>)) {
+        match self {
+            AST::BooleanLiteral(..)
+            | AST::IntegerLiteral(..)
+            | AST::FloatLiteral(..)
+            | AST::StringLiteral(..)
+            | AST::Nothing(..)
+            | AST::Variable(..)
+            | AST::Continue(..)
+            | AST::Break(..)
+            | AST::Import { .. }
+            | AST::FromImport { .. } => {}
+            AST::And(_, lhs, rhs)
+            | AST::Or(_, lhs, rhs)
+            | AST::Assignment(_, lhs, rhs)
+            | AST::Divide(_, lhs, rhs)
+            | AST::Modulo(_, lhs, rhs)
+            | AST::In(_, lhs, rhs)
+            | AST::Index(_, lhs, rhs)
+            | AST::Minus(_, lhs, rhs)
+            | AST::Multiply(_, lhs, rhs)
+            | AST::Power(_, lhs, rhs)
+            | AST::Plus(_, lhs, rhs)
+            | AST::Equals(_, lhs, rhs)
+            | AST::NotEquals(_, lhs, rhs)
+            | AST::LessThan(_, lhs, rhs)
+            | AST::GreaterThan(_, lhs, rhs)
+            | AST::LessEquals(_, lhs, rhs)
+            | AST::GreaterEquals(_, lhs, rhs)
+            | AST::While(_, lhs, rhs)
+            | AST::Range(_, lhs, rhs) => {
+                f(lhs);
+                f(rhs);
+            }
+            AST::Assert(_, expr, _)
+            | AST::Not(_, expr)
+            | AST::Negate(_, expr)
+            | AST::Return(_, expr)
+            | AST::Throw(_, expr)
+            | AST::VarDeclaration(_, _, expr)
+            | AST::Yield(_, expr)
+            | AST::FieldAccess(_, expr, _)
+            | AST::StarExpression(_, expr)
+            | AST::StarStarExpression(_, expr)
+            | AST::PostIncrement(_, expr, _)
+            | AST::PreIncrement(_, expr, _)
+            | AST::Namespace { body: expr, .. } => f(expr),
+            AST::Block(_, exprs, _)
+            | AST::FormatStringLiteral(_, _, exprs)
+            | AST::ArrayLiteral(_, exprs)
+            | AST::TupleLiteral(_, exprs)
+            | AST::SetLiteral(_, exprs) => exprs.iter().for_each(f),
+            AST::Super(_, class) => class.iter().for_each(f),
+            AST::Call(_, func, args) => {
+                f(func);
+                args.iter().for_each(|(_, arg)| f(arg));
+            }
+            AST::Class { fields, .. } | AST::Enum { fields, .. } => {
+                fields.values().for_each(|(value, _)| f(value))
+            }
+            AST::Function { args, body, .. } => {
+                args.iter()
+                    .filter_map(|(_, default, _)| default.as_ref())
+                    .for_each(&mut *f);
+                f(body);
+            }
+            AST::If(_, cond, body, else_body) => {
+                f(cond);
+                f(body);
+                else_body.iter().for_each(f);
+            }
+            AST::Match(_, subject, arms) => {
+                f(subject);
+                for (pattern, guard, body, _) in arms {
+                    pattern.for_each_expression(f);
+                    guard.iter().for_each(&mut *f);
+                    f(body);
+                }
+            }
+            AST::Slice {
+                lhs,
+                start,
+                end,
+                step,
+                ..
+            } => {
+                f(lhs);
+                [start, end, step].into_iter().flatten().for_each(f);
+            }
+            AST::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                f(body);
+                catch.iter().for_each(|(_, body)| f(body));
+                finally.iter().for_each(f);
+            }
+            AST::Destructure(_, pattern, value) => {
+                pattern.for_each_expression(f);
+                f(value);
+            }
+            AST::ForEach(_, pattern, iter, body, _) => {
+                pattern.for_each_expression(f);
+                f(iter);
+                f(body);
+            }
+            AST::For {
+                init,
+                cond,
+                step,
+                body,
+                ..
+            } => {
+                [init, cond, step].into_iter().flatten().for_each(&mut *f);
+                f(body);
+            }
+            AST::Comprehension(_, pattern, iter, expr, cond, _)
+            | AST::SetComprehension(_, pattern, iter, expr, cond, _) => {
+                pattern.for_each_expression(f);
+                f(iter);
+                f(expr);
+                cond.iter().for_each(f);
+            }
+            AST::DictionaryLiteral(_, items) => items.iter().for_each(|(key, value)| {
+                f(key);
+                f(value);
+            }),
+        }
+    }
+
+    // Whether this node or anything inside it matches `predicate`
+    pub fn any(&self, predicate: &dyn Fn(&AST) -> bool) -> bool {
+        if predicate(self) {
+            return true;
+        }
+        let mut found = false;
+        self.for_each_child(&mut |child| found = found || child.any(predicate));
+        found
+    }
 }
 
 impl std::fmt::Display for AST {
@@ -192,7 +564,7 @@ impl std::fmt::Display for AST {
             AST::And(_, lhs, rhs) => write!(f, "({} and {})", lhs, rhs),
             AST::Assert(_, expr, _) => write!(f, "assert {}", expr),
             AST::Assignment(_, lhs, rhs) => write!(f, "{} = {}", lhs, rhs),
-            AST::Block(_, exprs) => write!(f, "<block with {} exprs>", exprs.len()),
+            AST::Block(_, exprs, _) => write!(f, "<block with {} exprs>", exprs.len()),
             AST::BooleanLiteral(_, val) => write!(f, "{}", val),
             AST::Call(_, func, args) => {
                 write!(f, "{}(", func)?;
@@ -206,6 +578,7 @@ impl std::fmt::Display for AST {
             }
             AST::Class { name, .. } => write!(f, "<cls {}>", name,),
             AST::Divide(_, lhs, rhs) => write!(f, "({} / {})", lhs, rhs),
+            AST::Enum { name, .. } => write!(f, "<enum {}>", name),
             AST::Modulo(_, lhs, rhs) => write!(f, "({} % {})", lhs, rhs),
             AST::FloatLiteral(_, val) => write!(f, "{}", val),
             AST::Function { name, .. } => write!(
@@ -216,6 +589,20 @@ impl std::fmt::Display for AST {
             AST::If(_, cond, ..) => write!(f, "if {}", cond),
             AST::Index(_, lhs, rhs) => write!(f, "{}[{}]", lhs, rhs),
             AST::IntegerLiteral(_, val) => write!(f, "{}", val),
+            AST::Match(_, subject, arms) => {
+                write!(f, "match {} {{ ", subject)?;
+                for (i, (pattern, guard, ..)) in arms.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", pattern)?;
+                    if let Some(guard) = guard {
+                        write!(f, " if {}", guard)?;
+                    }
+                    write!(f, " => ...")?;
+                }
+                write!(f, " }}")
+            }
             AST::Minus(_, lhs, rhs) => write!(f, "({} - {})", lhs, rhs),
             AST::Multiply(_, lhs, rhs) => write!(f, "({} * {})", lhs, rhs),
             AST::Power(_, lhs, rhs) => write!(f, "({} ** {})", lhs, rhs),
@@ -247,8 +634,26 @@ impl std::fmt::Display for AST {
                 write!(f, "]")
             }
             AST::StringLiteral(_, val) => write!(f, "\"{}\"", val),
+            AST::Throw(_, expr) => write!(f, "throw {}", expr),
+            AST::Yield(_, expr) => write!(f, "yield {}", expr),
+            AST::Super(_, None) => write!(f, "super"),
+            AST::Super(_, Some(class)) => write!(f, "super({})", class),
+            AST::Try { catch, finally, .. } => {
+                write!(f, "try {{ ... }}")?;
+                if let Some((name, _)) = catch {
+                    match name {
+                        Some(name) => write!(f, " catch {} {{ ... }}", name)?,
+                        None => write!(f, " catch {{ ... }}")?,
+                    }
+                }
+                if finally.is_some() {
+                    write!(f, " finally {{ ... }}")?;
+                }
+                Ok(())
+            }
             AST::VarDeclaration(_, name, expr) => write!(f, "let {} = {}", name, expr),
-            AST::Variable(_, name) => write!(f, "{}", name),
+            AST::Destructure(_, pattern, expr) => write!(f, "let {} = {}", pattern, expr),
+            AST::Variable(_, name, _) => write!(f, "{}", name),
             AST::Equals(_, lhs, rhs) => write!(f, "({} == {})", lhs, rhs),
             AST::NotEquals(_, lhs, rhs) => write!(f, "({} != {})", lhs, rhs),
             AST::LessThan(_, lhs, rhs) => write!(f, "({} < {})", lhs, rhs),
@@ -277,10 +682,14 @@ impl std::fmt::Display for AST {
                 write!(f, ")")
             }
             AST::FieldAccess(_, lhs, rhs) => write!(f, "{}.{}", lhs, rhs),
-            AST::Comprehension(_, var, iter, expr, cond) => match cond {
+            AST::Comprehension(_, var, iter, expr, cond, _) => match cond {
                 Some(cond) => write!(f, "[{} for {} in {} if {}]", expr, var, iter, cond),
                 None => write!(f, "[{} for {} in {}]", expr, var, iter),
             },
+            AST::SetComprehension(_, var, iter, expr, cond, _) => match cond {
+                Some(cond) => write!(f, "{{{} for {} in {} if {}}}", expr, var, iter, cond),
+                None => write!(f, "{{{} for {} in {}}}", expr, var, iter),
+            },
             AST::FormatStringLiteral(_, strings, exprs) => {
                 write!(f, "\"")?;
                 for (i, string) in strings.iter().enumerate() {
@@ -318,6 +727,16 @@ impl std::fmt::Display for AST {
                 }
                 write!(f, "}}")
             }
+            AST::SetLiteral(_, exprs) => {
+                write!(f, "{{")?;
+                for (i, expr) in exprs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", expr)?;
+                }
+                write!(f, "}}")
+            }
             AST::Namespace {
                 span: _,
                 name,
@@ -23,7 +23,7 @@ macro_rules! make {
 }
 pub(crate) use make;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct Location {
     pub line: usize,
     pub column: usize,
@@ -42,7 +42,7 @@ impl std::fmt::Debug for Location {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct Span(pub Location, pub Location);
 
 impl std::fmt::Display for Span {
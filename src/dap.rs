@@ -0,0 +1,389 @@
+/*
+   Copyright (C) 2023  Haven Selph
+   Copyright (C) 2023  Mustafa Quraish
+   Check the LICENSE file for more information.
+*/
+
+use crate::engine::Engine;
+use crate::interpreter::debugger::{DebugClient, Paused, Resume, StopReason};
+use crate::json::{read_message, write_message, Json};
+use std::cell::RefCell;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// Scripts run on one thread, which is the only one the editor is told about
+const THREAD_ID: usize = 1;
+
+/// A debug adapter for RattleScript files, speaking the Debug Adapter
+/// Protocol. Editors launch a file with it, and can pause the file at
+/// breakpoints, step through it, look at the call stack and variables and
+/// evaluate expressions while it's paused.
+///
+/// The file runs on the adapter's thread, so the adapter only answers while
+/// the file is paused, and the process exits when the editor disconnects
+/// before the file is done.
+pub struct DebugAdapter {
+    connection: Rc<RefCell<Connection>>,
+}
+
+// The streams messages go over, shared with the running file, whose output
+// is sent to the editor as events
+struct Connection {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    seq: usize,
+}
+
+// What the editor asked to run
+struct Launch {
+    program: PathBuf,
+    stop_on_entry: bool,
+}
+
+impl DebugAdapter {
+    pub fn new(input: impl BufRead + 'static, output: impl Write + 'static) -> DebugAdapter {
+        let connection = Connection {
+            input: Box::new(input),
+            output: Box::new(output),
+            seq: 0,
+        };
+        DebugAdapter {
+            connection: Rc::new(RefCell::new(connection)),
+        }
+    }
+
+    /// Answers requests until the editor has launched a file and set its
+    /// breakpoints, runs the file, and then answers requests until the editor
+    /// disconnects or the input ends.
+    pub fn serve(self) -> std::io::Result<()> {
+        let mut launch = None;
+        let mut breakpoints = vec![];
+        loop {
+            let Some(request) = self.connection.borrow_mut().read()? else {
+                return Ok(());
+            };
+            let command = request.get("command").and_then(Json::as_str);
+            let args = request.get("arguments").unwrap_or(&Json::Null);
+            let mut connection = self.connection.borrow_mut();
+            match command.unwrap_or_default() {
+                "initialize" => {
+                    let capabilities = Json::object(vec![
+                        ("supportsConfigurationDoneRequest", true.into()),
+                        ("supportsEvaluateForHovers", true.into()),
+                    ]);
+                    connection.respond(&request, capabilities)?;
+                    connection.event("initialized", Json::Null)?;
+                }
+                "launch" => {
+                    let Some(program) = args.get("program").and_then(Json::as_str) else {
+                        connection.fail(&request, "Expected a program to launch")?;
+                        continue;
+                    };
+                    if let Some(cwd) = args.get("cwd").and_then(Json::as_str) {
+                        if let Err(err) = std::env::set_current_dir(cwd) {
+                            let message = format!("Couldn't change directory to {}: {}", cwd, err);
+                            connection.fail(&request, &message)?;
+                            continue;
+                        }
+                    }
+                    launch = Some(Launch {
+                        program: PathBuf::from(program),
+                        stop_on_entry: args.get("stopOnEntry") == Some(&Json::Bool(true)),
+                    });
+                    connection.respond(&request, Json::Null)?;
+                }
+                "setBreakpoints" => {
+                    let (path, lines) = breakpoint_lines(args);
+                    connection.respond(&request, verified(&lines))?;
+                    breakpoints.push((path, lines));
+                }
+                "configurationDone" => {
+                    connection.respond(&request, Json::Null)?;
+                    match launch.take() {
+                        Some(launch) => {
+                            drop(connection);
+                            self.launch(launch, &breakpoints)?;
+                        }
+                        None => connection.output("stderr", "Nothing was launched\n")?,
+                    }
+                }
+                "disconnect" | "terminate" => {
+                    connection.respond(&request, Json::Null)?;
+                    return Ok(());
+                }
+                _ => connection.answer(&request)?,
+            }
+        }
+    }
+
+    // Runs a file until it's done, telling the editor how it ended
+    fn launch(&self, launch: Launch, breakpoints: &[(PathBuf, Vec<usize>)]) -> std::io::Result<()> {
+        let mut engine = Engine::new();
+        engine.set_output(ProgramOutput(self.connection.clone()));
+        let client = Client(self.connection.clone());
+        engine.set_debugger(client, launch.stop_on_entry);
+        for (path, lines) in breakpoints {
+            engine.set_breakpoints(path, lines);
+        }
+
+        let result = engine.eval_file(&launch.program);
+        let mut connection = self.connection.borrow_mut();
+        if let Err(err) = &result {
+            let mut message = vec![];
+            err.write_with_source(&mut message, false)?;
+            connection.output("stderr", &String::from_utf8_lossy(&message))?;
+        }
+        let exit_code: usize = if result.is_ok() { 0 } else { 1 };
+        connection.event("exited", Json::object(vec![("exitCode", exit_code.into())]))?;
+        connection.event("terminated", Json::Null)
+    }
+}
+
+impl Connection {
+    fn read(&mut self) -> std::io::Result<Option<Json>> {
+        read_message(&mut self.input)
+    }
+
+    fn send(&mut self, kind: &str, mut fields: Vec<(&str, Json)>) -> std::io::Result<()> {
+        self.seq += 1;
+        fields.splice(0..0, [("seq", self.seq.into()), ("type", kind.into())]);
+        write_message(&mut self.output, &Json::object(fields))
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) -> std::io::Result<()> {
+        self.reply(request, true, ("body", body))
+    }
+
+    fn fail(&mut self, request: &Json, message: &str) -> std::io::Result<()> {
+        self.reply(request, false, ("message", message.into()))
+    }
+
+    fn reply(&mut self, request: &Json, success: bool, field: (&str, Json)) -> std::io::Result<()> {
+        let request_seq = request.get("seq").cloned().unwrap_or(Json::Null);
+        let command = request.get("command").cloned().unwrap_or(Json::Null);
+        let fields = vec![
+            ("request_seq", request_seq),
+            ("success", success.into()),
+            ("command", command),
+            field,
+        ];
+        self.send("response", fields)
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> std::io::Result<()> {
+        self.send("event", vec![("event", event.into()), ("body", body)])
+    }
+
+    fn output(&mut self, category: &str, output: &str) -> std::io::Result<()> {
+        let body = Json::object(vec![
+            ("category", category.into()),
+            ("output", output.into()),
+        ]);
+        self.event("output", body)
+    }
+
+    // Answers the requests that don't depend on whether a file is running
+    fn answer(&mut self, request: &Json) -> std::io::Result<()> {
+        match request.get("command").and_then(Json::as_str) {
+            Some("threads") => {
+                let thread = Json::object(vec![("id", THREAD_ID.into()), ("name", "main".into())]);
+                let threads = Json::object(vec![("threads", vec![thread].into())]);
+                self.respond(request, threads)
+            }
+            Some("setExceptionBreakpoints") => self.respond(request, Json::Null),
+            Some(command) => self.fail(request, &format!("Unsupported request: {}", command)),
+            None => Ok(()),
+        }
+    }
+}
+
+// The file of a `setBreakpoints` request and its lines
+fn breakpoint_lines(args: &Json) -> (PathBuf, Vec<usize>) {
+    let source = args.get("source").unwrap_or(&Json::Null);
+    let path = source
+        .get("path")
+        .and_then(Json::as_str)
+        .unwrap_or_default();
+    let breakpoints = args.get("breakpoints").and_then(Json::as_array);
+    let lines = breakpoints
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|breakpoint| breakpoint.get("line")?.as_usize())
+        .collect();
+    (PathBuf::from(path), lines)
+}
+
+// Every breakpoint is accepted where it was put, since any line can have one
+fn verified(lines: &[usize]) -> Json {
+    let breakpoints = lines
+        .iter()
+        .map(|line| Json::object(vec![("verified", true.into()), ("line", (*line).into())]))
+        .collect::<Vec<_>>();
+    Json::object(vec![("breakpoints", breakpoints.into())])
+}
+
+// Sends what the file prints to the editor
+struct ProgramOutput(Rc<RefCell<Connection>>);
+
+impl Write for ProgramOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let output = String::from_utf8_lossy(buf);
+        self.0.borrow_mut().output("stdout", &output)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Answers the editor while the file is paused
+struct Client(Rc<RefCell<Connection>>);
+
+impl Client {
+    // Answers a request, returning how the file carries on if it does
+    fn request(&mut self, paused: &mut Paused, request: &Json) -> std::io::Result<Option<Resume>> {
+        let command = request.get("command").and_then(Json::as_str);
+        let args = request.get("arguments").unwrap_or(&Json::Null);
+        // Frames and variables are numbered from 0 and 1, variables of each
+        // frame having two numbers, one for its locals and one for its globals
+        let frame = args.get("frameId").and_then(Json::as_usize).unwrap_or(0);
+        let resume = match command.unwrap_or_default() {
+            "continue" => Some(Resume::Continue),
+            "next" => Some(Resume::StepOver),
+            "stepIn" => Some(Resume::StepIn),
+            "stepOut" => Some(Resume::StepOut),
+            _ => None,
+        };
+        let body = match command.unwrap_or_default() {
+            _ if resume.is_some() => Json::Null,
+            // It already is
+            "pause" => Json::Null,
+            "stackTrace" => {
+                let frames = paused.frames();
+                let frames = frames
+                    .iter()
+                    .enumerate()
+                    .map(|(id, frame)| {
+                        let location = frame.span.0;
+                        let path = Path::new(location.filename);
+                        let path =
+                            std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+                        let name = path.file_name().unwrap_or_default().to_string_lossy();
+                        let source = Json::object(vec![
+                            ("name", name.into_owned().into()),
+                            ("path", path.to_string_lossy().into_owned().into()),
+                        ]);
+                        Json::object(vec![
+                            ("id", id.into()),
+                            ("name", frame.name.clone().into()),
+                            ("source", source),
+                            ("line", location.line.into()),
+                            ("column", location.column.into()),
+                        ])
+                    })
+                    .collect::<Vec<_>>();
+                Json::object(vec![
+                    ("totalFrames", frames.len().into()),
+                    ("stackFrames", frames.into()),
+                ])
+            }
+            "scopes" => {
+                let scope = |name: &str, reference: usize| {
+                    Json::object(vec![
+                        ("name", name.into()),
+                        ("variablesReference", reference.into()),
+                        ("expensive", false.into()),
+                    ])
+                };
+                let scopes = vec![
+                    scope("Locals", frame * 2 + 1),
+                    scope("Globals", frame * 2 + 2),
+                ];
+                Json::object(vec![("scopes", scopes.into())])
+            }
+            "variables" => {
+                let reference = args.get("variablesReference").and_then(Json::as_usize);
+                let variables = match reference.unwrap_or_default() {
+                    0 => vec![],
+                    reference if reference % 2 == 1 => paused.locals(reference / 2),
+                    reference => paused.globals(reference / 2 - 1),
+                };
+                let variables = variables
+                    .into_iter()
+                    .map(|(name, value)| {
+                        Json::object(vec![
+                            ("name", name.into()),
+                            ("value", value.into()),
+                            ("variablesReference", 0usize.into()),
+                        ])
+                    })
+                    .collect::<Vec<_>>();
+                Json::object(vec![("variables", variables.into())])
+            }
+            "evaluate" => {
+                let expression = args.get("expression").and_then(Json::as_str);
+                match paused.evaluate(frame, expression.unwrap_or_default()) {
+                    Ok(result) => Json::object(vec![
+                        ("result", result.into()),
+                        ("variablesReference", 0usize.into()),
+                    ]),
+                    Err(err) => {
+                        self.0.borrow_mut().fail(request, &err.to_string())?;
+                        return Ok(None);
+                    }
+                }
+            }
+            "setBreakpoints" => {
+                let (path, lines) = breakpoint_lines(args);
+                paused.set_breakpoints(&path, &lines);
+                verified(&lines)
+            }
+            "configurationDone" => Json::Null,
+            "disconnect" | "terminate" => {
+                self.0.borrow_mut().respond(request, Json::Null)?;
+                std::process::exit(0);
+            }
+            _ => {
+                self.0.borrow_mut().answer(request)?;
+                return Ok(None);
+            }
+        };
+        self.0.borrow_mut().respond(request, body)?;
+        Ok(resume)
+    }
+}
+
+impl DebugClient for Client {
+    fn paused(&mut self, paused: &mut Paused) -> Resume {
+        let reason = match paused.reason() {
+            StopReason::Entry => "entry",
+            StopReason::Breakpoint => "breakpoint",
+            StopReason::Step => "step",
+        };
+        let stopped = Json::object(vec![
+            ("reason", reason.into()),
+            ("threadId", THREAD_ID.into()),
+            ("allThreadsStopped", true.into()),
+        ]);
+        let result = self.0.borrow_mut().event("stopped", stopped);
+        let result = result.and_then(|_| loop {
+            let Some(request) = self.0.borrow_mut().read()? else {
+                break Ok(None);
+            };
+            if let Some(resume) = self.request(paused, &request)? {
+                break Ok(Some(resume));
+            }
+        });
+        match result {
+            Ok(Some(resume)) => resume,
+            // Without an editor to answer to, there's no one to debug for
+            Ok(None) => std::process::exit(0),
+            Err(err) => {
+                eprintln!("Debug adapter error: {}", err);
+                std::process::exit(1)
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+/*
+   Copyright (C) 2023  Haven Selph
+   Copyright (C) 2023  Mustafa Quraish
+   Check the LICENSE file for more information.
+*/
+
+use crate::ast::AST;
+use crate::common::{Location, Ref, Span};
+use crate::error::{runtime_error as error, Error, Result};
+use crate::interpreter::debugger::DebugClient;
+use crate::interpreter::limits::Limits;
+use crate::interpreter::native::NativeFunction;
+use crate::interpreter::profiler::Profile;
+use crate::interpreter::sandbox::Sandbox;
+use crate::interpreter::value::{Type, Value};
+use crate::interpreter::{Interpreter, Scope};
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Runs RattleScript code from a host program.
+///
+/// Everything an engine runs shares one global scope, so a function defined
+/// by one call to [`Engine::eval`] can be called by the next one, or from
+/// Rust with [`Engine::call`].
+pub struct Engine {
+    interpreter: Interpreter,
+    globals: Ref<Scope>,
+    verbose: bool,
+    // Spans need names that live forever, so each file's is leaked only once
+    filenames: RefCell<HashMap<PathBuf, &'static str>>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine {
+            interpreter: Interpreter::new(),
+            globals: Scope::new(None, false),
+            verbose: false,
+            filenames: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Runs code on the bytecode VM instead of the tree walking interpreter.
+    pub fn enable_vm(&mut self) {
+        self.interpreter.enable_vm();
+    }
+
+    /// Restricts what scripts can do outside the interpreter, like opening
+    /// files or importing modules. Imported modules get the same sandbox.
+    pub fn set_sandbox(&mut self, sandbox: Sandbox) {
+        self.interpreter.set_sandbox(sandbox);
+    }
+
+    /// Limits how much work each run can do, like how many steps it may take
+    /// or how deep its calls may go. Every call to [`Engine::eval`],
+    /// [`Engine::call`] and the like starts with a fresh budget.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.interpreter.set_limits(limits);
+    }
+
+    /// Sends what scripts print, with `print()`, `dump()`, `.dbg()` and the
+    /// prompt of `input()`, to `output` instead of stdout.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.interpreter.set_output(Rc::new(RefCell::new(output)));
+    }
+
+    /// Attaches a debugger, which `client` takes over for whenever a script
+    /// pauses: at a breakpoint, a call to `breakpoint()`, after a step or,
+    /// with `stop_on_entry`, before the first statement. Code runs on the
+    /// tree walking interpreter while a debugger is attached, even with the
+    /// VM enabled.
+    pub fn set_debugger(&mut self, client: impl DebugClient + 'static, stop_on_entry: bool) {
+        let client = Box::new(client);
+        self.interpreter.set_debugger(client, stop_on_entry);
+    }
+
+    /// Replaces the lines of a file that scripts pause at when a debugger is
+    /// attached. An empty list removes them all.
+    pub fn set_breakpoints(&mut self, path: impl AsRef<Path>, lines: &[usize]) {
+        self.interpreter.set_breakpoints(path.as_ref(), lines);
+    }
+
+    /// Starts timing the calls scripts make and counting the lines they run,
+    /// which [`Engine::profile`] reports. Enabling it again starts over.
+    pub fn enable_profiler(&mut self) {
+        self.interpreter.enable_profiler();
+    }
+
+    /// What scripts have spent their time on since the profiler was
+    /// enabled, or `None` if it wasn't.
+    pub fn profile(&self) -> Option<Profile> {
+        self.interpreter.profile()
+    }
+
+    /// Prints the tokens and syntax tree of everything the engine runs.
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+    }
+
+    /// Runs source code in the global scope, returning the value of its last
+    /// statement.
+    pub fn eval(&mut self, source: &str) -> Result<Value> {
+        let ast = self
+            .parse(source.to_string(), "<eval>")
+            .map_err(first_error)?;
+        self.run(&ast)
+    }
+
+    /// Runs a file in the global scope, returning the value of its last
+    /// statement. Of several syntax errors, only the first is returned, and
+    /// [`Engine::check_file`] finds all of them.
+    pub fn eval_file(&mut self, path: impl AsRef<Path>) -> Result<Value> {
+        let (source, filename) = self.read_file(path.as_ref())?;
+        let ast = self.parse(source, filename).map_err(first_error)?;
+        self.run(&ast)
+    }
+
+    /// Finds every syntax error in a file without running it.
+    pub fn check_file(&self, path: impl AsRef<Path>) -> Vec<Error> {
+        let (source, filename) = match self.read_file(path.as_ref()) {
+            Ok(file) => file,
+            Err(err) => return vec![err],
+        };
+        match self.parse(source, filename) {
+            Ok(_) => vec![],
+            Err(errors) => errors,
+        }
+    }
+
+    /// The value of a global variable.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().get(name)
+    }
+
+    /// Declares a global variable, replacing its value if it already exists.
+    pub fn set(&mut self, name: &str, value: Value) {
+        self.globals.borrow_mut().declare(name, value);
+    }
+
+    /// Registers a Rust function as a global function. Its arguments are
+    /// converted from script values with [`FromValue`] and its return value
+    /// with [`IntoValue`], and a variable of the same name shadows it.
+    ///
+    /// [`FromValue`]: crate::FromValue
+    /// [`IntoValue`]: crate::IntoValue
+    pub fn register_fn<Args>(&mut self, name: &str, function: impl NativeFunction<Args>) {
+        let function = function.into_builtin(name);
+        self.interpreter.registry_mut().add_global(name, function);
+    }
+
+    /// Registers a method on a built-in type. The function gets the value the
+    /// method is called on as its first argument.
+    pub fn register_method<Args>(
+        &mut self,
+        type_: Type,
+        name: &str,
+        function: impl NativeFunction<Args>,
+    ) {
+        let function = function.into_builtin(name);
+        self.interpreter
+            .registry_mut()
+            .add_method(type_, name, function);
+    }
+
+    /// Registers a method on host objects wrapping a `T`, which
+    /// [`Value::object`] creates. The function gets the object as its first
+    /// argument, as an `Rc<RefCell<T>>`.
+    pub fn register_object_method<T: Any, Args>(
+        &mut self,
+        name: &str,
+        function: impl NativeFunction<Args>,
+    ) {
+        let function = function.into_builtin(name);
+        self.interpreter
+            .registry_mut()
+            .add_object_method::<T>(name, function);
+    }
+
+    /// Calls a global function, or anything else that can be called, with
+    /// positional arguments.
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value> {
+        let Some(callee) = self.get(name) else {
+            error!(host_span("<call>"), "Variable '{}' not found", name)
+        };
+        self.call_value(&callee, args)
+    }
+
+    /// Calls a value, like a function returned by a script, with positional
+    /// arguments.
+    pub fn call_value(&mut self, callee: &Value, args: Vec<Value>) -> Result<Value> {
+        let args = args.into_iter().map(|arg| (None, arg)).collect();
+        let (span, globals) = (host_span("<call>"), self.globals.clone());
+        self.interpreter.start_run();
+        let result = self
+            .interpreter
+            .do_call(&span, globals, None, callee.clone(), &args);
+        self.finish(result)
+    }
+
+    /// Formats a value the way `print` shows it.
+    pub fn format(&mut self, value: &Value) -> Result<String> {
+        let result = self.interpreter.format_value(&host_span("<format>"), value);
+        self.finish(result)
+    }
+
+    /// Formats a value the way `repr` shows it.
+    pub fn repr(&mut self, value: &Value) -> Result<String> {
+        let result = self.interpreter.repr_value(&host_span("<repr>"), value);
+        self.finish(result)
+    }
+
+    // Lexes and parses source code, returning all the syntax errors the
+    // parser finds. Incomplete code fails with `ErrorKind::UnexpectedEOF`,
+    // which the REPL uses to ask for more input.
+    pub(crate) fn parse(
+        &self,
+        source: String,
+        filename: &'static str,
+    ) -> std::result::Result<Rc<AST>, Vec<Error>> {
+        let mut lexer = crate::lexer::Lexer::new(source, filename);
+        let tokens = lexer.lex().map_err(|err| vec![err])?;
+
+        if self.verbose {
+            for token in &tokens {
+                println!("{:?}", token);
+            }
+        }
+
+        let mut parser = crate::parser::Parser::new(tokens);
+        parser.parse()
+    }
+
+    // Resolves and runs a parsed program in the global scope
+    pub(crate) fn run(&mut self, ast: &Rc<AST>) -> Result<Value> {
+        let registry = self.interpreter.registry();
+        crate::resolver::resolve(ast, self.globals.borrow().names(), registry)?;
+        if let AST::Block(_, _, layout) = ast.as_ref() {
+            self.globals.borrow_mut().extend(layout.get());
+        }
+
+        if self.verbose {
+            println!("{:#?}", ast);
+        }
+
+        self.interpreter.start_run();
+        let result = self.interpreter.run_body(ast, self.globals.clone());
+        self.finish(result)
+    }
+
+    // An error leaves whatever was being thrown behind, which would otherwise
+    // leak into the next thing the engine runs
+    fn finish<T>(&mut self, result: Result<T>) -> Result<T> {
+        if result.is_err() {
+            self.interpreter.clear_control_flow();
+        }
+        result
+    }
+
+    fn read_file(&self, path: &Path) -> Result<(String, &'static str)> {
+        let filename = *self
+            .filenames
+            .borrow_mut()
+            .entry(path.to_path_buf())
+            .or_insert_with(|| Box::leak(path.to_string_lossy().into_owned().into_boxed_str()));
+        match std::fs::read_to_string(path) {
+            Ok(source) => Ok((source, filename)),
+            Err(err) => error!(host_span(filename), "Couldn't read {}: {}", filename, err),
+        }
+    }
+}
+
+// The parser always has at least one error when it fails
+fn first_error(errors: Vec<Error>) -> Error {
+    errors
+        .into_iter()
+        .next()
+        .expect("Parsing failed without an error")
+}
+
+// Errors that come from the host rather than from a script point at the
+// start of a pseudo file named after what failed
+fn host_span(filename: &'static str) -> Span {
+    let location = Location {
+        line: 1,
+        column: 1,
+        filename,
+    };
+    Span(location, location)
+}
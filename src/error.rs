@@ -5,20 +5,67 @@
  */
 
 use crate::common::Span;
+use crate::json::Json;
+use std::collections::HashMap;
+use std::io::Write;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum ErrorKind {
     Lexer,
     Parser,
     UnexpectedEOF,
+    Resolver,
     Runtime,
+    Thrown,
+    // Something the sandbox of the interpreter doesn't allow
+    Sandbox,
+    // Going over one of the limits of the interpreter
+    Limit,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Error {
     pub kind: ErrorKind,
     pub span: Span,
     pub message: String,
+    // Boxed, since most errors have none and errors are returned everywhere
+    details: Option<Box<Details>>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Details {
+    traceback: Vec<Frame>,
+    hints: Vec<String>,
+    labels: Vec<Label>,
+}
+
+// A place in the code an error points at besides where it happened, like the
+// definition of a function that was called wrong
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+// A function call or import that was in progress when an error happened
+#[derive(Debug, Clone)]
+pub struct Frame {
+    // The name of the function, or the path of the imported file
+    pub name: String,
+    // Where it was called or imported
+    pub span: Span,
+    pub import: bool,
+}
+
+impl std::fmt::Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.import {
+            write!(f, "{}, importing {}", self.span.0, self.name)
+        } else {
+            write!(f, "{}, calling {}()", self.span.0, self.name)
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -27,7 +74,11 @@ impl std::fmt::Display for Error {
             ErrorKind::Lexer | ErrorKind::Parser | ErrorKind::UnexpectedEOF => {
                 write!(f, "SyntaxError: {}", self.message)
             }
+            ErrorKind::Resolver => write!(f, "NameError: {}", self.message),
             ErrorKind::Runtime => write!(f, "RuntimeError: {}", self.message),
+            ErrorKind::Thrown => write!(f, "Exception: {}", self.message),
+            ErrorKind::Sandbox => write!(f, "SandboxError: {}", self.message),
+            ErrorKind::Limit => write!(f, "LimitError: {}", self.message),
         }
     }
 }
@@ -36,126 +87,452 @@ pub type Result<T> = std::result::Result<T, Error>;
 
 macro_rules! lexer_error {
     ($span:expr, $($arg:tt)*) => {
-        return Err(crate::error::Error{
-            kind: crate::error::ErrorKind::Lexer,
-            span: $span.clone(),
-            message: format!($($arg)*),
-        })
+        return Err(crate::error::Error::new(
+            crate::error::ErrorKind::Lexer,
+            $span.clone(),
+            format!($($arg)*),
+        ))
     }
 }
 pub(crate) use lexer_error;
 
 macro_rules! parser_error {
     ($span:expr, $($arg:tt)*) => {
-        return Err(crate::error::Error{
-            kind: crate::error::ErrorKind::Parser,
-            span: $span.clone(),
-            message: format!($($arg)*),
-        })
+        return Err(crate::error::Error::new(
+            crate::error::ErrorKind::Parser,
+            $span.clone(),
+            format!($($arg)*),
+        ))
     }
 }
 pub(crate) use parser_error;
 
 macro_rules! eof_error {
     ($span:expr, $($arg:tt)*) => {
-        return Err(crate::error::Error{
-            kind: crate::error::ErrorKind::UnexpectedEOF,
-            span: $span.clone(),
-            message: format!("Unexpected EOF: {}", format!($($arg)*)),
-        })
+        return Err(crate::error::Error::new(
+            crate::error::ErrorKind::UnexpectedEOF,
+            $span.clone(),
+            format!("Unexpected EOF: {}", format!($($arg)*)),
+        ))
     }
 }
 pub(crate) use eof_error;
 
+macro_rules! resolver_error {
+    ($span:expr, $($arg:tt)*) => {
+        return Err(crate::error::Error::new(
+            crate::error::ErrorKind::Resolver,
+            $span.clone(),
+            format!($($arg)*),
+        ))
+    }
+}
+pub(crate) use resolver_error;
+
 macro_rules! runtime_error {
     ($span:expr, $($arg:tt)*) => {
-        return Err(crate::error::Error{
-            kind: crate::error::ErrorKind::Runtime,
-            span: $span.clone(),
-            message: format!($($arg)*),
-        })
+        return Err(crate::error::Error::new(
+            crate::error::ErrorKind::Runtime,
+            $span.clone(),
+            format!($($arg)*),
+        ))
     }
 }
 pub(crate) use runtime_error;
 
+macro_rules! thrown_error {
+    ($span:expr, $($arg:tt)*) => {
+        return Err(crate::error::Error::new(
+            crate::error::ErrorKind::Thrown,
+            $span.clone(),
+            format!($($arg)*),
+        ))
+    }
+}
+pub(crate) use thrown_error;
+
+macro_rules! sandbox_error {
+    ($span:expr, $($arg:tt)*) => {
+        return Err(crate::error::Error::new(
+            crate::error::ErrorKind::Sandbox,
+            $span.clone(),
+            format!($($arg)*),
+        ))
+    }
+}
+pub(crate) use sandbox_error;
+
+macro_rules! limit_error {
+    ($span:expr, $($arg:tt)*) => {
+        return Err(crate::error::Error::new(
+            crate::error::ErrorKind::Limit,
+            $span.clone(),
+            format!($($arg)*),
+        ))
+    }
+}
+pub(crate) use limit_error;
+
 impl Error {
+    pub(crate) fn new(kind: ErrorKind, span: Span, message: String) -> Error {
+        Error {
+            kind,
+            span,
+            message,
+            details: None,
+        }
+    }
+
+    // The calls and imports the error passed through on its way out, the
+    // innermost first
+    pub fn traceback(&self) -> &[Frame] {
+        self.details
+            .as_ref()
+            .map_or(&[], |details| &details.traceback)
+    }
+
+    // Suggestions for fixing the error
+    pub fn hints(&self) -> &[String] {
+        self.details.as_ref().map_or(&[], |details| &details.hints)
+    }
+
+    // Other places in the code the error relates to
+    pub fn labels(&self) -> &[Label] {
+        self.details.as_ref().map_or(&[], |details| &details.labels)
+    }
+
+    fn details_mut(&mut self) -> &mut Details {
+        self.details.get_or_insert_with(Box::default)
+    }
+
+    pub(crate) fn push_frame(&mut self, frame: Frame) {
+        self.details_mut().traceback.push(frame);
+    }
+
+    pub(crate) fn with_hint(mut self, hint: String) -> Error {
+        self.details_mut().hints.push(hint);
+        self
+    }
+
+    pub(crate) fn with_label(mut self, span: Span, message: &str) -> Error {
+        let message = message.to_string();
+        self.details_mut().labels.push(Label { span, message });
+        self
+    }
+
+    // Suggests the closest of the names that could have been meant instead of
+    // a misspelt one, if any is close enough
+    pub(crate) fn suggest<'a>(
+        self,
+        name: &str,
+        candidates: impl IntoIterator<Item = &'a str>,
+    ) -> Error {
+        match closest_name(name, candidates) {
+            Some(closest) => self.with_hint(format!("Did you mean '{}'?", closest)),
+            None => self,
+        }
+    }
+
+    // The frames of the traceback, the outermost first. A run of the same
+    // call, like a function recursing, is one frame with how often it repeats.
+    pub fn frames(&self) -> Vec<(&Frame, usize)> {
+        let mut frames: Vec<(&Frame, usize)> = Vec::new();
+        for frame in self.traceback().iter().rev() {
+            match frames.last_mut() {
+                Some((last, count)) if last.span == frame.span && last.name == frame.name => {
+                    *count += 1
+                }
+                _ => frames.push((frame, 1)),
+            }
+        }
+        frames
+    }
+
+    // The error as one line of JSON, for tools that read errors. The
+    // traceback is the outermost call first, like when it's printed.
+    pub fn to_json(&self) -> String {
+        let hints = self.hints().iter().map(|hint| hint.as_str().into());
+        let labels = self.labels().iter().map(|label| {
+            let mut fields = vec![("message", label.message.as_str().into())];
+            fields.extend(json_span(&label.span));
+            Json::object(fields)
+        });
+        let traceback = self.traceback().iter().rev().map(|frame| {
+            let mut fields = vec![
+                ("name", frame.name.as_str().into()),
+                ("import", frame.import.into()),
+            ];
+            fields.extend(json_span(&frame.span));
+            Json::object(fields)
+        });
+        let mut fields = vec![
+            ("kind", format!("{:?}", self.kind).into()),
+            ("message", self.message.as_str().into()),
+        ];
+        fields.extend(json_span(&self.span));
+        fields.push(("hints", Json::Array(hints.collect())));
+        fields.push(("labels", Json::Array(labels.collect())));
+        fields.push(("traceback", Json::Array(traceback.collect())));
+        Json::object(fields).to_string()
+    }
+
+    pub fn print_traceback(&self) {
+        let _ = self.write_traceback(&mut std::io::stdout());
+    }
+
     pub fn print_with_source(&self) {
+        let _ = self.write_with_source(&mut std::io::stdout(), true);
+    }
+
+    pub fn print_notes(&self) {
+        let _ = self.write_notes(&mut std::io::stdout());
+    }
+
+    // Writes the traceback with the line of each call, if there is one
+    pub fn write_traceback(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        if self.traceback().is_empty() {
+            return Ok(());
+        }
+        let mut sources: HashMap<&str, Option<String>> = HashMap::new();
+        writeln!(out, "Traceback (most recent call last):")?;
+        for (frame, count) in self.frames() {
+            writeln!(out, "  {}", frame)?;
+            let location = frame.span.0;
+            let source = sources
+                .entry(location.filename)
+                .or_insert_with(|| std::fs::read_to_string(location.filename).ok());
+            let line = source
+                .as_ref()
+                .and_then(|source| source.lines().nth(location.line - 1));
+            if let Some(line) = line {
+                writeln!(out, "    {}", line.trim())?;
+            }
+            if count > 1 {
+                writeln!(out, "  [Repeated {} more times]", count - 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Writes the traceback, then the error in a box with the code around it,
+    // highlighted in red if `colors` is set
+    pub fn write_with_source(&self, out: &mut dyn Write, colors: bool) -> std::io::Result<()> {
+        self.write_traceback(out)?;
         let msg = &self.message;
         let filename = &self.span.0.filename;
         let file_content = match std::fs::read_to_string(filename) {
             Ok(content) => content,
             Err(_) => {
-                println!("{}: Error: {}", self.span.0, msg);
-                return;
+                writeln!(out, "{}: Error: {}", self.span.0, msg)?;
+                return self.write_notes(out);
             }
         };
         let lines = file_content.lines().collect::<Vec<&str>>();
-        let context = 3;
 
-        let start = self.span.0;
-        let end = self.span.1;
+        writeln!(
+            out,
+            "╭────────────────────────────────────────────────────────────────────────────────"
+        )?;
+        writeln!(out, "│ {}: Error: {}", self.span.0, msg)?;
+        writeln!(
+            out,
+            "├─────┬──────────────────────────────────────────────────────────────────────────"
+        )?;
+        write_lines(out, colors, &lines, &self.span, 3)?;
+
+        // Whether the line number column is still open below the last lines
+        let mut column = true;
+        for label in self.labels() {
+            let note = format!("{}: Note: {}", label.span.0, label.message);
+            write_row(out, &note, &mut column)?;
+            if let Ok(content) = std::fs::read_to_string(label.span.0.filename) {
+                writeln!(
+                    out,
+                    "├─────┬──────────────────────────────────────────────────────────────────────────"
+                )?;
+                let lines = content.lines().collect::<Vec<&str>>();
+                write_lines(out, colors, &lines, &label.span, 0)?;
+                column = true;
+            }
+        }
+        for hint in self.hints() {
+            write_row(out, &format!("Hint: {}", hint), &mut column)?;
+        }
 
-        let min_line = if start.line <= context {
-            0
+        if column {
+            writeln!(
+                out,
+                "╰─────┴──────────────────────────────────────────────────────────────────────────"
+            )
         } else {
-            start.line - context - 1
-        };
-        let max_line = lines.len().min(end.line + context);
+            writeln!(
+                out,
+                "╰────────────────────────────────────────────────────────────────────────────────"
+            )
+        }
+    }
 
-        println!(
-            "╭────────────────────────────────────────────────────────────────────────────────"
-        );
-        println!("│ {}: Error: {}", start, msg);
-        println!(
-            "├─────┬──────────────────────────────────────────────────────────────────────────"
-        );
-        #[allow(clippy::needless_range_loop)]
-        for line_no in min_line..max_line {
-            let line = lines[line_no];
-            if start.line - 1 <= line_no && line_no < end.line {
-                let highlight_start = if line_no == start.line - 1 {
-                    start.column - 1
-                } else {
-                    0
-                };
-                let highlight_end = if line_no == end.line - 1 {
-                    end.column - 1
+    // Writes the labels and hints, for when the source can't be shown
+    pub fn write_notes(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        for label in self.labels() {
+            writeln!(out, "{}: Note: {}", label.span.0, label.message)?;
+        }
+        for hint in self.hints() {
+            writeln!(out, "Hint: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+// Writes a row of text across the box, closing the line number column first
+fn write_row(out: &mut dyn Write, row: &str, column: &mut bool) -> std::io::Result<()> {
+    if *column {
+        writeln!(
+            out,
+            "├─────┴──────────────────────────────────────────────────────────────────────────"
+        )?;
+        *column = false;
+    }
+    writeln!(out, "│ {}", row)
+}
+
+// Writes the lines of a span with a few lines of context around them,
+// highlighting the span
+fn write_lines(
+    out: &mut dyn Write,
+    colors: bool,
+    lines: &[&str],
+    span: &Span,
+    context: usize,
+) -> std::io::Result<()> {
+    let (red, reset) = if colors {
+        ("\x1b[0;31m", "\x1b[0m")
+    } else {
+        ("", "")
+    };
+    let start = span.0;
+    let end = span.1;
+
+    let min_line = if start.line <= context {
+        0
+    } else {
+        start.line - context - 1
+    };
+    let max_line = lines.len().min(end.line + context);
+
+    #[allow(clippy::needless_range_loop)]
+    for line_no in min_line..max_line {
+        let line = lines[line_no];
+        if start.line - 1 <= line_no && line_no < end.line {
+            let highlight_start = if line_no == start.line - 1 {
+                start.column - 1
+            } else {
+                0
+            };
+            let highlight_end = if line_no == end.line - 1 {
+                end.column - 1
+            } else {
+                line.len()
+            };
+
+            let text_before = &line[..highlight_start];
+            let text_highlight = &line[highlight_start..highlight_end];
+            let text_after = &line[highlight_end..];
+            writeln!(
+                out,
+                "│ {:>3} │ {}{}{}{}{}",
+                line_no + 1,
+                text_before,
+                red,
+                text_highlight,
+                reset,
+                text_after
+            )?;
+
+            if start.line == end.line {
+                if text_highlight.len() <= 1 {
+                    writeln!(
+                        out,
+                        "│     │ {}{}▲{}",
+                        " ".repeat(text_before.len()),
+                        red,
+                        reset
+                    )?;
                 } else {
-                    line.len()
-                };
-
-                let text_before = &line[..highlight_start];
-                let text_highlight = &line[highlight_start..highlight_end];
-                let text_after = &line[highlight_end..];
-                println!(
-                    "│ {:>3} │ {}\x1b[0;31m{}\x1b[0m{}",
-                    line_no + 1,
-                    text_before,
-                    text_highlight,
-                    text_after
-                );
-
-                if start.line == end.line {
-                    if text_highlight.len() <= 1 {
-                        println!(
-                            "│     │ {}\x1b[0;31m▲\x1b[0m",
-                            " ".repeat(text_before.len())
-                        );
-                    } else {
-                        println!(
-                            "│     │ {}\x1b[0;31m└{}┘\x1b[0m",
-                            " ".repeat(text_before.len()),
-                            "─".repeat(text_highlight.len() - 2)
-                        );
-                    }
+                    writeln!(
+                        out,
+                        "│     │ {}{}└{}┘{}",
+                        " ".repeat(text_before.len()),
+                        red,
+                        "─".repeat(text_highlight.len() - 2),
+                        reset
+                    )?;
                 }
-            } else {
-                println!("│ {:>3} │ {}", line_no + 1, line);
             }
+        } else {
+            writeln!(out, "│ {:>3} │ {}", line_no + 1, line)?;
         }
+    }
+    Ok(())
+}
 
-        println!(
-            "╰─────┴──────────────────────────────────────────────────────────────────────────"
-        );
+// The fields of a span in JSON, with the same names as on error values
+fn json_span(span: &Span) -> [(&'static str, Json); 5] {
+    [
+        ("file", span.0.filename.into()),
+        ("line", span.0.line.into()),
+        ("column", span.0.column.into()),
+        ("end_line", span.1.line.into()),
+        ("end_column", span.1.column.into()),
+    ]
+}
+
+// The candidate closest to a name, if it takes at most one edit for every
+// three characters of the name, so names shorter than that get nothing.
+// Names starting with `__` are only suggested for names that do too.
+fn closest_name<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max = name.chars().count() / 3;
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .filter(|candidate| !candidate.starts_with("__") || name.starts_with("__"))
+        .map(|candidate| (name_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+// The edits between two names, where each character one has more than the
+// other only counts half, as long as the shorter one is at least half as long.
+// That keeps `lenght` close to `len`, but not `i` to `index`.
+fn name_distance(a: &str, b: &str) -> usize {
+    let (a_len, b_len) = (a.chars().count(), b.chars().count());
+    let distance = edit_distance(a, b);
+    let length_difference = a_len.abs_diff(b_len);
+    if a_len > b_len * 2 || b_len > a_len * 2 {
+        return distance;
+    }
+    distance - length_difference + length_difference.div_ceil(2)
+}
+
+// The number of characters to insert, delete, replace or swap with their
+// neighbour to turn one string into the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![(0..=b.len()).collect::<Vec<usize>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
     }
+    rows[a.len()][b.len()]
 }
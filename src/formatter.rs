@@ -0,0 +1,1108 @@
+/*
+   Copyright (C) 2023  Haven Selph
+   Copyright (C) 2023  Mustafa Quraish
+   Check the LICENSE file for more information.
+*/
+
+use crate::ast::{ArgumentType, FunctionArgs, MatchArm, Pattern, AST};
+use crate::common::{Location, Span};
+use crate::error::Error;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::token::{Comment, Token, TokenKind};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+const INDENT: &str = "    ";
+// Bracketed lists that would go past this column are split over several lines
+const MAX_WIDTH: usize = 100;
+
+// How tightly each kind of expression binds, so that parentheses are only
+// written where the parser needs them
+const ASSIGNMENT: u8 = 1;
+const OR: u8 = 2;
+const AND: u8 = 3;
+const IN: u8 = 4;
+const COMPARISON: u8 = 5;
+const ADDITIVE: u8 = 6;
+const MULTIPLICATIVE: u8 = 7;
+const POWER: u8 = 8;
+const PREFIX: u8 = 9;
+const RANGE: u8 = 10;
+const POSTFIX: u8 = 11;
+const ATOM: u8 = 12;
+
+/// Formats RattleScript code in the standard layout, keeping its comments.
+/// Fails with the syntax errors in the code if it doesn't parse.
+pub fn format_source(source: String, filename: &'static str) -> Result<String, Vec<Error>> {
+    let mut lexer = Lexer::new(source.clone(), filename);
+    let tokens = lexer.lex().map_err(|err| vec![err])?;
+    let comments = lexer.take_comments();
+    let ast = Parser::new(tokens.clone())
+        .without_resolving_imports()
+        .parse()?;
+    let mut formatter = Formatter {
+        lines: source.lines().map(|line| line.chars().collect()).collect(),
+        tokens,
+        comments,
+        next_comment: 0,
+    };
+    let AST::Block(_, stmts, _) = ast.as_ref() else {
+        unreachable!("The parser returns a block")
+    };
+    Ok(formatter.statements(stmts, 0, None))
+}
+
+fn before(a: &Location, b: &Location) -> bool {
+    (a.line, a.column) < (b.line, b.column)
+}
+
+// The width of the first line of some text
+fn width(text: &str) -> usize {
+    text.lines().next().map_or(0, |line| line.chars().count())
+}
+
+// The column after some text that starts at `column`
+fn end_column(column: usize, text: &str) -> usize {
+    match text.rfind('\n') {
+        Some(index) => text[index + 1..].chars().count(),
+        None => column + text.chars().count(),
+    }
+}
+
+fn precedence(ast: &AST) -> u8 {
+    match ast {
+        AST::Assignment(..) | AST::Function { .. } => ASSIGNMENT,
+        AST::Or(..) => OR,
+        AST::And(..) => AND,
+        AST::In(..) => IN,
+        AST::Equals(..)
+        | AST::NotEquals(..)
+        | AST::LessThan(..)
+        | AST::GreaterThan(..)
+        | AST::LessEquals(..)
+        | AST::GreaterEquals(..) => COMPARISON,
+        AST::Plus(..) | AST::Minus(..) => ADDITIVE,
+        AST::Multiply(..) | AST::Divide(..) | AST::Modulo(..) => MULTIPLICATIVE,
+        AST::Power(..) => POWER,
+        AST::Negate(..)
+        | AST::Not(..)
+        | AST::PreIncrement(..)
+        | AST::StarExpression(..)
+        | AST::StarStarExpression(..) => PREFIX,
+        AST::Range(..) => RANGE,
+        AST::Call(..)
+        | AST::Index(..)
+        | AST::Slice { .. }
+        | AST::FieldAccess(..)
+        | AST::PostIncrement(..) => POSTFIX,
+        _ => ATOM,
+    }
+}
+
+// The parser turns `x += y` into `x = x + y` with the same `x` on both sides
+fn compound<'a>(target: &Rc<AST>, value: &'a AST) -> Option<(&'static str, &'a Rc<AST>)> {
+    let (op, left, right) = match value {
+        AST::Plus(_, left, right) => ("+=", left, right),
+        AST::Minus(_, left, right) => ("-=", left, right),
+        AST::Multiply(_, left, right) => ("*=", left, right),
+        AST::Divide(_, left, right) => ("/=", left, right),
+        _ => return None,
+    };
+    Rc::ptr_eq(left, target).then_some((op, right))
+}
+
+// The parser turns `@deco def f() {}` into `f = deco(def f() {})`, and
+// returns the decorator and the function
+fn decorated(ast: &AST) -> Option<(&Rc<AST>, &Rc<AST>)> {
+    let AST::Assignment(_, target, value) = ast else {
+        return None;
+    };
+    let (AST::Variable(_, name, _), AST::Call(_, deco, args)) = (target.as_ref(), value.as_ref())
+    else {
+        return None;
+    };
+    match args.as_slice() {
+        [(None, function)] => match function.as_ref() {
+            AST::Function {
+                name: Some(function_name),
+                ..
+            } if function_name == name => Some((deco, function)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn statement_span(ast: &AST) -> Span {
+    match decorated(ast) {
+        Some((_, function)) => Span(ast.span().0, function.span().1),
+        None => *ast.span(),
+    }
+}
+
+// Lines of output at one depth, keeping a blank line wherever the source
+// had at least one
+struct Lines {
+    out: String,
+    indent: usize,
+    last_line: Option<usize>,
+}
+
+impl Lines {
+    fn new(indent: usize) -> Lines {
+        Lines {
+            out: String::new(),
+            indent,
+            last_line: None,
+        }
+    }
+
+    // Adds text that was on lines `start` to `end` of the source
+    fn push(&mut self, text: &str, start: usize, end: usize) {
+        if self.last_line.is_some_and(|last| start > last + 1) {
+            self.out.push('\n');
+        }
+        self.out += &INDENT.repeat(self.indent);
+        self.out += text;
+        self.out.push('\n');
+        self.last_line = Some(end);
+    }
+
+    // Adds a comment to the end of the last line
+    fn trail(&mut self, text: &str) {
+        self.out.pop();
+        self.out.push(' ');
+        self.out += text;
+        self.out.push('\n');
+    }
+}
+
+// An item of a bracketed list
+enum Item<'a> {
+    Expr(&'a Rc<AST>),
+    Keyword(&'a str, &'a Rc<AST>),
+    Pair(&'a Rc<AST>, &'a Rc<AST>),
+    Arm(&'a MatchArm),
+}
+
+impl Item<'_> {
+    fn span(&self) -> Span {
+        match self {
+            Item::Expr(expr) | Item::Keyword(_, expr) => *expr.span(),
+            Item::Pair(key, value) => key.span().extend(value.span()),
+            Item::Arm((pattern, _, body, _)) => pattern.span().extend(body.span()),
+        }
+    }
+}
+
+// A member of a class or enum
+enum Member<'a> {
+    Field(&'a str, &'a Rc<AST>, bool),
+    Variant(&'a str, &'a [String]),
+}
+
+struct Formatter {
+    lines: Vec<Vec<char>>,
+    tokens: Vec<Token>,
+    comments: Vec<Comment>,
+    // Comments before this one have been written
+    next_comment: usize,
+}
+
+impl Formatter {
+    // The source text of a span, for literals that should keep the way they
+    // were written
+    fn source(&self, span: &Span) -> Option<String> {
+        let mut text = String::new();
+        for line in span.0.line..=span.1.line {
+            let chars = self.lines.get(line - 1)?;
+            let start = if line == span.0.line {
+                span.0.column - 1
+            } else {
+                0
+            };
+            let end = if line == span.1.line {
+                span.1.column - 1
+            } else {
+                chars.len()
+            };
+            if line > span.0.line {
+                text.push('\n');
+            }
+            text.extend(chars.get(start..end)?);
+        }
+        Some(text)
+    }
+
+    // The first token at or after a location
+    fn token_index(&self, location: &Location) -> Option<usize> {
+        let index = self
+            .tokens
+            .partition_point(|token| before(&token.span.0, location));
+        (index < self.tokens.len()).then_some(index)
+    }
+
+    // Writes the comments that come before `location`, on their own line or
+    // after the code on their line
+    fn comments_before(&mut self, location: &Location, lines: &mut Lines) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if !before(&comment.span.0, location) {
+                break;
+            }
+            self.next_comment += 1;
+            if !comment.own_line && lines.last_line == Some(comment.span.0.line) {
+                lines.trail(&comment.text);
+            } else {
+                lines.push(&comment.text, comment.span.0.line, comment.span.1.line);
+            }
+        }
+    }
+
+    fn has_comments_before(&self, location: &Location) -> bool {
+        self.comments
+            .get(self.next_comment)
+            .is_some_and(|comment| before(&comment.span.0, location))
+    }
+
+    // Statements one per line, with the comments between them and those before
+    // `end`, where the block closes, or the rest of them at the end of a file
+    fn statements(&mut self, stmts: &[Rc<AST>], indent: usize, end: Option<&Location>) -> String {
+        let mut lines = Lines::new(indent);
+        for stmt in stmts {
+            let span = statement_span(stmt);
+            self.comments_before(&span.0, &mut lines);
+            let text = self.statement(stmt, indent);
+            // Comments inside the statement that had nowhere to go
+            self.comments_before(&span.1, &mut lines);
+            lines.push(&text, span.0.line, span.1.line);
+        }
+        match end {
+            Some(end) => self.comments_before(end, &mut lines),
+            None => {
+                let end = Location {
+                    line: usize::MAX,
+                    column: 0,
+                    filename: "",
+                };
+                self.comments_before(&end, &mut lines)
+            }
+        }
+        lines.out
+    }
+
+    fn block(&mut self, block: &Rc<AST>, indent: usize) -> String {
+        let AST::Block(span, stmts, _) = block.as_ref() else {
+            return self.statement(block, indent);
+        };
+        if let [stmt] = &stmts[..] {
+            // A short block written on one line stays that way
+            if span.0.line == span.1.line && !self.has_comments_before(&span.1) {
+                let text = self.statement(stmt, indent);
+                if !text.contains('\n') {
+                    return format!("{{ {} }}", text);
+                }
+            }
+        }
+        let first = stmts.first().map_or(span.1, |stmt| statement_span(stmt).0);
+        let open = self.open_comment(&span.0, &first);
+        let body = self.statements(stmts, indent + 1, Some(&span.1));
+        match body.is_empty() && open.is_empty() {
+            true => "{}".to_string(),
+            false => format!("{{{}\n{}{}}}", open, body, INDENT.repeat(indent)),
+        }
+    }
+
+    // A comment after the brace that opens a block at `open` and before the
+    // first thing in it at `first`, which stays after the brace
+    fn open_comment(&mut self, open: &Location, first: &Location) -> String {
+        match self.comments.get(self.next_comment) {
+            Some(comment)
+                if !comment.own_line
+                    && comment.span.0.line == open.line
+                    && before(&comment.span.0, first) =>
+            {
+                self.next_comment += 1;
+                format!(" {}", comment.text)
+            }
+            _ => String::new(),
+        }
+    }
+
+    fn statement(&mut self, ast: &Rc<AST>, indent: usize) -> String {
+        let column = indent * INDENT.len();
+        if let Some((deco, function)) = decorated(ast) {
+            let deco = self.expr(deco, POSTFIX, indent, column + 1);
+            let function = self.function(function, indent, "");
+            return format!("@{}\n{}{}", deco, INDENT.repeat(indent), function);
+        }
+        match ast.as_ref() {
+            AST::VarDeclaration(_, name, value) => {
+                let prefix = format!("let {} = ", name);
+                let value = self.expr(value, 0, indent, column + prefix.len());
+                prefix + &value
+            }
+            AST::Destructure(_, pattern, value) => {
+                let prefix = format!("let {} = ", self.binding_target(pattern, indent));
+                let value = self.tuple_expression(value, indent, column + prefix.len());
+                prefix + &value
+            }
+            AST::Assignment(_, target, value) => match target.as_ref() {
+                AST::TupleLiteral(span, targets)
+                    if targets
+                        .first()
+                        .is_some_and(|target| target.span().0 == span.0) =>
+                {
+                    let targets: Vec<_> = targets
+                        .iter()
+                        .map(|target| self.expr(target, OR, indent, column))
+                        .collect();
+                    let prefix = format!("{} = ", targets.join(", "));
+                    let value = self.tuple_expression(value, indent, column + prefix.len());
+                    prefix + &value
+                }
+                _ => self.expr(ast, 0, indent, column),
+            },
+            AST::If(_, cond, body, else_body) => self.if_statement(cond, body, else_body, indent),
+            AST::While(_, cond, body) => {
+                let cond = self.expr(cond, 0, indent, column + 6);
+                format!("while {} {}", cond, self.block(body, indent))
+            }
+            AST::For {
+                init,
+                cond,
+                step,
+                body,
+                ..
+            } => {
+                let mut head = "for (".to_string();
+                if let Some(init) = init {
+                    head += &self.statement(init, indent);
+                }
+                head.push(';');
+                if let Some(cond) = cond {
+                    head += &format!(" {}", self.expr(cond, 0, indent, column));
+                }
+                head.push(';');
+                if let Some(step) = step {
+                    head += &format!(" {}", self.expr(step, 0, indent, column));
+                }
+                format!("{}) {}", head, self.block(body, indent))
+            }
+            AST::ForEach(_, pattern, iter, body, _) => {
+                let prefix = format!("for {} in ", self.binding_target(pattern, indent));
+                let iter = self.expr(iter, 0, indent, column + prefix.len());
+                format!("{}{} {}", prefix, iter, self.block(body, indent))
+            }
+            AST::Return(_, expr) => format!("return {}", self.expr(expr, 0, indent, column + 7)),
+            AST::Yield(_, expr) => format!("yield {}", self.expr(expr, 0, indent, column + 6)),
+            AST::Throw(_, expr) => format!("throw {}", self.expr(expr, 0, indent, column + 6)),
+            AST::Break(_) => "break".to_string(),
+            AST::Continue(_) => "continue".to_string(),
+            AST::Assert(_, cond, message) => {
+                let mut text = format!("assert {}", self.expr(cond, 0, indent, column + 7));
+                if message.is_some() {
+                    // The message is kept as written, with its escapes
+                    let index = self.token_index(&cond.span().1);
+                    let token = index.and_then(|index| {
+                        let tokens = self.tokens[index..].iter();
+                        tokens
+                            .take_while(|token| token.kind != TokenKind::EOF)
+                            .find(|token| token.kind == TokenKind::StringLiteral)
+                    });
+                    if let Some(message) = token.and_then(|token| self.source(&token.span)) {
+                        text += &format!(", {}", message);
+                    }
+                }
+                text
+            }
+            AST::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                let mut text = format!("try {}", self.block(body, indent));
+                if let Some((name, body)) = catch {
+                    text += " catch ";
+                    if let Some(name) = name {
+                        text += &format!("{} ", name);
+                    }
+                    text += &self.block(body, indent);
+                }
+                if let Some(body) = finally {
+                    text += &format!(" finally {}", self.block(body, indent));
+                }
+                text
+            }
+            AST::Import { span, alias, .. } => {
+                let mut text = format!("import {}", self.module_path(span));
+                if let Some(alias) = alias {
+                    text += &format!(" as {}", alias);
+                }
+                text
+            }
+            AST::FromImport { span, names, .. } => {
+                let names: Vec<_> = names
+                    .iter()
+                    .map(|(name, alias)| match alias {
+                        Some(alias) => format!("{} as {}", name, alias),
+                        None => name.clone(),
+                    })
+                    .collect();
+                let names = match names.len() {
+                    1 => names[0].clone(),
+                    _ => format!("({})", names.join(", ")),
+                };
+                format!("from {} import {}", self.module_path(span), names)
+            }
+            AST::Function { name: Some(_), .. } => self.function(ast, indent, ""),
+            AST::Class { .. } => self.class(ast, indent, ""),
+            AST::Enum { .. } => self.enum_(ast, indent, ""),
+            AST::Namespace { name, body, .. } => {
+                format!("namespace {} {}", name, self.block(body, indent))
+            }
+            AST::Block(..) => self.block(ast, indent),
+            _ => self.expr(ast, 0, indent, column),
+        }
+    }
+
+    // The dotted module name of an import, which the parser has turned into
+    // a path
+    fn module_path(&self, span: &Span) -> String {
+        let Some(index) = self.token_index(&span.0) else {
+            return String::new();
+        };
+        self.tokens[index + 1..]
+            .iter()
+            .take_while(|token| matches!(token.kind, TokenKind::Identifier | TokenKind::Dot))
+            .map(|token| token.text.as_str())
+            .collect()
+    }
+
+    fn if_statement(
+        &mut self,
+        cond: &Rc<AST>,
+        body: &Rc<AST>,
+        else_body: &Option<Rc<AST>>,
+        indent: usize,
+    ) -> String {
+        let cond = self.expr(cond, 0, indent, indent * INDENT.len() + 3);
+        let mut text = format!("if {} {}", cond, self.if_body(body, indent));
+        if let Some(else_body) = else_body {
+            match body.as_ref() {
+                AST::Block(..) => text += " else ",
+                _ => text += &format!("\n{}else ", INDENT.repeat(indent)),
+            }
+            match else_body.as_ref() {
+                AST::If(_, cond, body, else_body) => {
+                    text += &self.if_statement(cond, body, else_body, indent)
+                }
+                _ => text += &self.if_body(else_body, indent),
+            }
+        }
+        text
+    }
+
+    // The body of an `if` stays on the same line unless it's a block, or would
+    // read as part of the condition
+    fn if_body(&mut self, body: &Rc<AST>, indent: usize) -> String {
+        if let AST::Block(..) = body.as_ref() {
+            return self.block(body, indent);
+        }
+        let snapshot = self.next_comment;
+        let text = self.statement(body, indent);
+        if text.starts_with(|c: char| c.is_alphanumeric() || "_\"`".contains(c)) {
+            return text;
+        }
+        self.next_comment = snapshot;
+        let text = self.statement(body, indent + 1);
+        let indent = INDENT.repeat(indent);
+        format!("{{\n{}{}{}\n{}}}", indent, INDENT, text, indent)
+    }
+
+    fn function(&mut self, ast: &Rc<AST>, indent: usize, prefix: &str) -> String {
+        let AST::Function {
+            name,
+            args,
+            in_class,
+            body,
+            ..
+        } = ast.as_ref()
+        else {
+            return self.expr(ast, 0, indent, indent * INDENT.len());
+        };
+        let params = self.params(args, *in_class, indent);
+        let head = match name {
+            Some(name) => format!("{}def {}({})", prefix, name, params),
+            None => format!("|{}|", params),
+        };
+        let column = indent * INDENT.len() + head.len() + 1;
+        format!("{} {}", head, self.function_body(body, indent, column))
+    }
+
+    fn params(&mut self, args: &FunctionArgs, method: bool, indent: usize) -> String {
+        let mut params = vec![];
+        if method {
+            params.push("self".to_string());
+        }
+        for (name, default, type_) in args {
+            params.push(match (type_, default) {
+                (ArgumentType::Variadic, _) => format!("*{}", name),
+                (ArgumentType::VariadicKeyword, _) => format!("**{}", name),
+                (_, Some(default)) => {
+                    let default = self.expr(default, ASSIGNMENT, indent, 0);
+                    format!("{} = {}", name, default)
+                }
+                (_, None) => name.clone(),
+            });
+        }
+        params.join(", ")
+    }
+
+    fn function_body(&mut self, body: &Rc<AST>, indent: usize, column: usize) -> String {
+        match body.as_ref() {
+            // The parser turns `=> expr` into a return with the span of `expr`
+            AST::Return(span, expr) if span == expr.span() => {
+                format!("=> {}", self.expr(expr, 0, indent, column + 3))
+            }
+            _ => self.block(body, indent),
+        }
+    }
+
+    fn class(&mut self, ast: &Rc<AST>, indent: usize, prefix: &str) -> String {
+        let AST::Class {
+            span,
+            name,
+            parents,
+            fields,
+            ..
+        } = ast.as_ref()
+        else {
+            return self.statement(ast, indent);
+        };
+        let mut head = format!("{}class {}", prefix, name);
+        if let Some(parents) = parents {
+            head += &format!("({})", parents.join(", "));
+        }
+        let members = self.members(fields, vec![], span, indent);
+        format!("{} {}", head, members)
+    }
+
+    fn enum_(&mut self, ast: &Rc<AST>, indent: usize, prefix: &str) -> String {
+        let AST::Enum {
+            span,
+            name,
+            variants,
+            fields,
+            ..
+        } = ast.as_ref()
+        else {
+            return self.statement(ast, indent);
+        };
+        // Variants aren't in the tree with their locations, so they are found
+        // in the tokens of the enum
+        let mut found = vec![];
+        let mut depth = 0;
+        let start = self.token_index(&span.0).unwrap_or(0);
+        for (i, token) in self.tokens.iter().enumerate().skip(start) {
+            match token.kind {
+                TokenKind::LeftBrace | TokenKind::LeftParen | TokenKind::LeftBracket => depth += 1,
+                TokenKind::RightBrace | TokenKind::RightParen | TokenKind::RightBracket => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                TokenKind::Identifier if depth == 1 => {
+                    let Some((variant, names)) = variants.get(found.len()) else {
+                        break;
+                    };
+                    let follows = &self.tokens[i - 1].kind;
+                    let is_name = !matches!(
+                        follows,
+                        TokenKind::Dot | TokenKind::Def | TokenKind::Let | TokenKind::FatArrow
+                    );
+                    if is_name && token.text == *variant {
+                        found.push((token.span.0, Member::Variant(variant, names)));
+                    }
+                }
+                _ => {}
+            }
+        }
+        let members = self.members(fields, found, span, indent);
+        format!("{}enum {} {}", prefix, name, members)
+    }
+
+    // The braces and members of a class or enum, in the order they were written
+    fn members<'a>(
+        &mut self,
+        fields: &'a HashMap<String, (Rc<AST>, bool)>,
+        mut members: Vec<(Location, Member<'a>)>,
+        span: &Span,
+        indent: usize,
+    ) -> String {
+        for (name, (value, is_static)) in fields {
+            members.push((value.span().0, Member::Field(name, value, *is_static)));
+        }
+        members.sort_by_key(|(location, _)| (location.line, location.column));
+        // A class with one member or an enum with only variants stays on one
+        // line if it was written that way
+        let variants = members
+            .iter()
+            .all(|(_, member)| matches!(member, Member::Variant(..)));
+        let one_line = span.0.line == span.1.line && (members.len() == 1 || variants);
+        if one_line && !members.is_empty() && !self.has_comments_before(&span.1) {
+            let members: Vec<_> = members
+                .iter()
+                .map(|(location, member)| self.member(member, *location, indent).0)
+                .collect();
+            if members.iter().all(|member| !member.contains('\n')) {
+                return format!("{{ {} }}", members.join(", "));
+            }
+        }
+        let first = members.first().map_or(span.1, |(location, _)| *location);
+        let open = self.open_comment(&span.0, &first);
+        let mut lines = Lines::new(indent + 1);
+        for (location, member) in members {
+            self.comments_before(&location, &mut lines);
+            let (text, end) = self.member(&member, location, indent + 1);
+            self.comments_before(&end, &mut lines);
+            lines.push(&text, location.line, end.line);
+        }
+        self.comments_before(&span.1, &mut lines);
+        match lines.out.is_empty() && open.is_empty() {
+            true => "{}".to_string(),
+            false => format!("{{{}\n{}{}}}", open, lines.out, INDENT.repeat(indent)),
+        }
+    }
+
+    // A member and where it ends
+    fn member(&mut self, member: &Member, location: Location, indent: usize) -> (String, Location) {
+        match *member {
+            Member::Variant(name, names) => match names.is_empty() {
+                true => (name.to_string(), location),
+                false => (format!("{}({})", name, names.join(", ")), location),
+            },
+            Member::Field(name, value, is_static) => {
+                let prefix = if is_static { "static " } else { "" };
+                let text = match value.as_ref() {
+                    AST::Function { .. } => self.function(value, indent, prefix),
+                    AST::Class { .. } => self.class(value, indent, prefix),
+                    AST::Enum { .. } => self.enum_(value, indent, prefix),
+                    _ => {
+                        let prefix = format!("{}let {} = ", prefix, name);
+                        let column = indent * INDENT.len() + prefix.len();
+                        prefix + &self.expr(value, 0, indent, column)
+                    }
+                };
+                (text, value.span().1)
+            }
+        }
+    }
+
+    // The value of a destructuring `let` or assignment, where a tuple doesn't
+    // need parentheses and only gets them if it had them
+    fn tuple_expression(&mut self, value: &Rc<AST>, indent: usize, column: usize) -> String {
+        match value.as_ref() {
+            AST::TupleLiteral(span, items) if items.len() > 1 && items[0].span().0 == span.0 => {
+                let items: Vec<_> = items
+                    .iter()
+                    .map(|item| self.expr(item, 0, indent, column))
+                    .collect();
+                items.join(", ")
+            }
+            _ => self.expr(value, 0, indent, column),
+        }
+    }
+
+    // The target of a `let` or `for`, where a tuple pattern doesn't need
+    // parentheses and only gets them if it had them
+    fn binding_target(&mut self, pattern: &Pattern, indent: usize) -> String {
+        match pattern {
+            Pattern::Tuple(span, items)
+                if items.first().is_some_and(|item| item.span().0 == span.0) =>
+            {
+                let items: Vec<_> = items
+                    .iter()
+                    .map(|item| self.pattern(item, indent))
+                    .collect();
+                items.join(", ")
+            }
+            _ => self.pattern(pattern, indent),
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern, indent: usize) -> String {
+        let list = |formatter: &mut Formatter, items: &[Rc<Pattern>]| {
+            let items: Vec<_> = items
+                .iter()
+                .map(|item| formatter.pattern(item, indent))
+                .collect();
+            items.join(", ")
+        };
+        match pattern {
+            Pattern::Wildcard(_) => "_".to_string(),
+            Pattern::Binding(_, name) => name.clone(),
+            Pattern::Value(_, expr) => self.expr(expr, 0, indent, 0),
+            Pattern::Or(_, alternatives) => {
+                let alternatives: Vec<_> = alternatives
+                    .iter()
+                    .map(|alternative| self.pattern(alternative, indent))
+                    .collect();
+                alternatives.join(" | ")
+            }
+            Pattern::Rest(_, name) => format!("*{}", name.as_deref().unwrap_or("_")),
+            Pattern::Array(_, items) => format!("[{}]", list(self, items)),
+            Pattern::Tuple(_, items) if items.len() == 1 => format!("({},)", list(self, items)),
+            Pattern::Tuple(_, items) => format!("({})", list(self, items)),
+            Pattern::Dict(_, items, rest) => {
+                let mut items: Vec<_> = items
+                    .iter()
+                    .map(|(key, value)| {
+                        let key = self.expr(key, 0, indent, 0);
+                        format!("{}: {}", key, self.pattern(value, indent))
+                    })
+                    .collect();
+                if let Some(rest) = rest {
+                    items.push(format!("**{}", rest));
+                }
+                format!("{{{}}}", items.join(", "))
+            }
+            Pattern::Class(_, class, args) => {
+                let class = self.expr(class, POSTFIX, indent, 0);
+                let args: Vec<_> = args
+                    .iter()
+                    .map(|(name, pattern)| match name {
+                        Some(name) => format!("{}: {}", name, self.pattern(pattern, indent)),
+                        None => self.pattern(pattern, indent),
+                    })
+                    .collect();
+                format!("{}({})", class, args.join(", "))
+            }
+        }
+    }
+
+    // An expression, in parentheses if it binds less tightly than `min`
+    fn expr(&mut self, ast: &Rc<AST>, min: u8, indent: usize, column: usize) -> String {
+        match precedence(ast) < min {
+            true => format!("({})", self.expr_inner(ast, indent, column + 1)),
+            false => self.expr_inner(ast, indent, column),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn binary(
+        &mut self,
+        left: &Rc<AST>,
+        op: &str,
+        right: &Rc<AST>,
+        left_min: u8,
+        right_min: u8,
+        indent: usize,
+        column: usize,
+    ) -> String {
+        let left = self.expr(left, left_min, indent, column);
+        let column = end_column(column, &left) + op.len() + 2;
+        let right = self.expr(right, right_min, indent, column);
+        format!("{} {} {}", left, op, right)
+    }
+
+    fn expr_inner(&mut self, ast: &Rc<AST>, indent: usize, column: usize) -> String {
+        match ast.as_ref() {
+            AST::IntegerLiteral(span, value) => {
+                self.source(span).unwrap_or_else(|| value.to_string())
+            }
+            AST::FloatLiteral(span, value) => {
+                self.source(span).unwrap_or_else(|| value.to_string())
+            }
+            AST::StringLiteral(span, _) | AST::FormatStringLiteral(span, ..) => {
+                self.source(span).unwrap_or_else(|| ast.to_string())
+            }
+            AST::BooleanLiteral(_, value) => value.to_string(),
+            AST::Nothing(_) => "nothing".to_string(),
+            AST::Variable(_, name, _) => name.clone(),
+            AST::Assignment(_, target, value) => match compound(target, value) {
+                Some((op, right)) => self.binary(target, op, right, OR, OR, indent, column),
+                None => self.binary(target, "=", value, OR, ASSIGNMENT, indent, column),
+            },
+            AST::Or(_, left, right) => self.binary(left, "or", right, OR, AND, indent, column),
+            AST::And(_, left, right) => self.binary(left, "and", right, AND, IN, indent, column),
+            AST::In(_, left, right) => {
+                self.binary(left, "in", right, COMPARISON, COMPARISON, indent, column)
+            }
+            AST::Equals(_, left, right)
+            | AST::NotEquals(_, left, right)
+            | AST::LessThan(_, left, right)
+            | AST::GreaterThan(_, left, right)
+            | AST::LessEquals(_, left, right)
+            | AST::GreaterEquals(_, left, right) => {
+                let op = match ast.as_ref() {
+                    AST::Equals(..) => "==",
+                    AST::NotEquals(..) => "!=",
+                    AST::LessThan(..) => "<",
+                    AST::GreaterThan(..) => ">",
+                    AST::LessEquals(..) => "<=",
+                    _ => ">=",
+                };
+                self.binary(left, op, right, COMPARISON, ADDITIVE, indent, column)
+            }
+            AST::Plus(_, left, right) => {
+                self.binary(left, "+", right, ADDITIVE, MULTIPLICATIVE, indent, column)
+            }
+            AST::Minus(_, left, right) => {
+                self.binary(left, "-", right, ADDITIVE, MULTIPLICATIVE, indent, column)
+            }
+            AST::Multiply(_, left, right) => {
+                self.binary(left, "*", right, MULTIPLICATIVE, POWER, indent, column)
+            }
+            AST::Divide(_, left, right) => {
+                self.binary(left, "/", right, MULTIPLICATIVE, POWER, indent, column)
+            }
+            AST::Modulo(_, left, right) => {
+                self.binary(left, "%", right, MULTIPLICATIVE, POWER, indent, column)
+            }
+            // `-1 ** 2` is `(-1) ** 2`, which is clearer with the parentheses
+            AST::Power(_, left, right) => {
+                self.binary(left, "**", right, POSTFIX, POWER, indent, column)
+            }
+            AST::Negate(_, expr) => {
+                // `--x` would be a decrement
+                let min = match expr.as_ref() {
+                    AST::Negate(..) | AST::PreIncrement(..) => ATOM,
+                    _ => PREFIX,
+                };
+                format!("-{}", self.expr(expr, min, indent, column + 1))
+            }
+            AST::Not(_, expr) => format!("not {}", self.expr(expr, PREFIX, indent, column + 4)),
+            AST::PreIncrement(_, expr, offset) => {
+                let op = if *offset > 0 { "++" } else { "--" };
+                format!("{}{}", op, self.expr(expr, PREFIX, indent, column + 2))
+            }
+            AST::PostIncrement(_, expr, offset) => {
+                let op = if *offset > 0 { "++" } else { "--" };
+                format!("{}{}", self.expr(expr, POSTFIX, indent, column), op)
+            }
+            AST::StarExpression(_, expr) => {
+                format!("*{}", self.expr(expr, RANGE, indent, column + 1))
+            }
+            AST::StarStarExpression(_, expr) => {
+                format!("**{}", self.expr(expr, RANGE, indent, column + 2))
+            }
+            AST::Range(_, start, end) => {
+                let start = self.expr(start, POSTFIX, indent, column);
+                let column = end_column(column, &start) + 2;
+                format!("{}..{}", start, self.expr(end, PREFIX, indent, column))
+            }
+            AST::Call(span, callee, args) => {
+                let callee = self.expr(callee, POSTFIX, indent, column);
+                let items: Vec<_> = args
+                    .iter()
+                    .map(|(name, value)| match name {
+                        Some(name) => Item::Keyword(name, value),
+                        None => Item::Expr(value),
+                    })
+                    .collect();
+                let open = self.open_bracket(span, &items);
+                let column = end_column(column, &callee);
+                callee + &self.list(("(", ")"), &items, open, &span.1, indent, column)
+            }
+            AST::Index(_, expr, index) => {
+                let expr = self.expr(expr, POSTFIX, indent, column);
+                let column = end_column(column, &expr) + 1;
+                format!("{}[{}]", expr, self.expr(index, 0, indent, column))
+            }
+            AST::Slice {
+                lhs,
+                start,
+                end,
+                step,
+                ..
+            } => {
+                let mut text = self.expr(lhs, POSTFIX, indent, column);
+                text.push('[');
+                if let Some(start) = start {
+                    text += &self.expr(start, 0, indent, column);
+                }
+                text.push(':');
+                if let Some(end) = end {
+                    text += &self.expr(end, 0, indent, column);
+                }
+                if let Some(step) = step {
+                    text += &format!(":{}", self.expr(step, 0, indent, column));
+                }
+                text + "]"
+            }
+            AST::FieldAccess(_, expr, name) => {
+                // `1.x` would lex as a number
+                let min = match expr.as_ref() {
+                    AST::IntegerLiteral(..) | AST::FloatLiteral(..) => ATOM + 1,
+                    _ => POSTFIX,
+                };
+                format!("{}.{}", self.expr(expr, min, indent, column), name)
+            }
+            AST::Super(_, None) => "super".to_string(),
+            AST::Super(_, Some(class)) => {
+                format!("super({})", self.expr(class, 0, indent, column + 6))
+            }
+            AST::Function { name: None, .. } => self.function(ast, indent, ""),
+            AST::ArrayLiteral(span, items) => {
+                let items: Vec<_> = items.iter().map(Item::Expr).collect();
+                self.list(("[", "]"), &items, span.0, &span.1, indent, column)
+            }
+            AST::TupleLiteral(span, items) => {
+                let items: Vec<_> = items.iter().map(Item::Expr).collect();
+                let text = self.list(("(", ")"), &items, span.0, &span.1, indent, column);
+                // A tuple of one needs a comma, which it already has if it's
+                // been split over lines
+                let inside = text[..text.len() - 1].trim_end();
+                match items.len() == 1 && !inside.ends_with(',') {
+                    true => format!("{},)", inside),
+                    false => text,
+                }
+            }
+            AST::SetLiteral(span, items) => {
+                let items: Vec<_> = items.iter().map(Item::Expr).collect();
+                self.list(("{", "}"), &items, span.0, &span.1, indent, column)
+            }
+            AST::DictionaryLiteral(span, items) => {
+                let items: Vec<_> = items
+                    .iter()
+                    .map(|(key, value)| Item::Pair(key, value))
+                    .collect();
+                self.list(("{", "}"), &items, span.0, &span.1, indent, column)
+            }
+            AST::Comprehension(_, pattern, iter, body, cond, _)
+            | AST::SetComprehension(_, pattern, iter, body, cond, _) => {
+                let mut text = self.expr(body, 0, indent, column + 1);
+                text += &format!(" for {} in ", self.binding_target(pattern, indent));
+                text += &self.expr(iter, 0, indent, column);
+                if let Some(cond) = cond {
+                    text += &format!(" if {}", self.expr(cond, 0, indent, column));
+                }
+                match ast.as_ref() {
+                    AST::Comprehension(..) => format!("[{}]", text),
+                    _ => format!("{{{}}}", text),
+                }
+            }
+            AST::Match(span, subject, arms) => {
+                let subject = self.expr(subject, 0, indent, column + 6);
+                let items: Vec<_> = arms.iter().map(Item::Arm).collect();
+                let open = self.open_bracket(span, &items);
+                let column = end_column(column + 6, &subject) + 1;
+                let arms = self.list(("{", "}"), &items, open, &span.1, indent, column);
+                format!("match {} {}", subject, arms)
+            }
+            _ => self.statement(ast, indent),
+        }
+    }
+
+    // Where the bracket before some items opens, which isn't in the tree for
+    // calls and matches
+    fn open_bracket(&self, span: &Span, items: &[Item]) -> Location {
+        let end = items.first().map_or(span.1, |item| item.span().0);
+        let index = self.token_index(&end).unwrap_or(self.tokens.len());
+        let open = self.tokens[..index]
+            .iter()
+            .rev()
+            .find(|token| matches!(token.kind, TokenKind::LeftParen | TokenKind::LeftBrace));
+        open.map_or(span.0, |token| token.span.0)
+    }
+
+    fn item(&mut self, item: &Item, indent: usize, column: usize) -> String {
+        match item {
+            Item::Expr(expr) => self.expr(expr, 0, indent, column),
+            Item::Keyword(name, value) => {
+                let value = self.expr(value, 0, indent, column + name.len() + 2);
+                format!("{}: {}", name, value)
+            }
+            Item::Pair(key, value) => {
+                let key = self.expr(key, 0, indent, column);
+                let column = end_column(column, &key) + 2;
+                format!("{}: {}", key, self.expr(value, 0, indent, column))
+            }
+            Item::Arm((pattern, guard, body, _)) => {
+                let mut text = self.pattern(pattern, indent);
+                if let Some(guard) = guard {
+                    text += &format!(" if {}", self.expr(guard, 0, indent, column));
+                }
+                let column = end_column(column, &text) + 4;
+                let body = match body.as_ref() {
+                    AST::Block(..) => self.block(body, indent),
+                    _ => self.expr(body, 0, indent, column),
+                };
+                format!("{} => {}", text, body)
+            }
+        }
+    }
+
+    // Items between brackets, on one line if they were written that way and
+    // still fit, and one per line otherwise
+    fn list(
+        &mut self,
+        (open, close): (&str, &str),
+        items: &[Item],
+        start: Location,
+        end: &Location,
+        indent: usize,
+        column: usize,
+    ) -> String {
+        let arms = matches!(items.first(), Some(Item::Arm(..)));
+        if items.is_empty() && !self.has_comments_before(end) {
+            return format!("{}{}", open, close);
+        }
+        let snapshot = self.next_comment;
+        // Only the last item can spread over several lines when the rest of
+        // them are on the line the list opens on
+        let one_line = items.iter().enumerate().all(|(i, item)| {
+            let span = item.span();
+            span.0.line == start.line && (i + 1 == items.len() || span.1.line == start.line)
+        });
+        let last_line = items.last().map_or(start.line, |item| item.span().1.line);
+        if !items.is_empty() && one_line && last_line == end.line {
+            let mut text = open.to_string();
+            if arms {
+                text.push(' ');
+            }
+            let mut flat = true;
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    text += ", ";
+                }
+                let span = item.span();
+                let item = self.item(item, indent, end_column(column, &text));
+                // Unless the last item was already spread over lines, it
+                // spreads because the list doesn't fit
+                flat &=
+                    !item.contains('\n') || (i + 1 == items.len() && span.0.line != span.1.line);
+                text += &item;
+            }
+            if arms {
+                text.push(' ');
+            }
+            text += close;
+            if flat && column + width(&text) <= MAX_WIDTH && !self.has_comments_before(end) {
+                return text;
+            }
+            self.next_comment = snapshot;
+        }
+        let mut lines = Lines::new(indent + 1);
+        let column = (indent + 1) * INDENT.len();
+        for (i, item) in items.iter().enumerate() {
+            let span = item.span();
+            self.comments_before(&span.0, &mut lines);
+            let mut text = self.item(item, indent + 1, column);
+            // A new line doesn't end a match arm before `-`
+            let next_negative = items.get(i + 1).is_some_and(|next| match next {
+                Item::Arm((pattern, ..)) => self.pattern(pattern, indent).starts_with('-'),
+                _ => false,
+            });
+            if !arms || next_negative {
+                text.push(',');
+            }
+            lines.push(&text, span.0.line, span.1.line);
+        }
+        self.comments_before(end, &mut lines);
+        format!("{}\n{}{}{}", open, lines.out, INDENT.repeat(indent), close)
+    }
+}
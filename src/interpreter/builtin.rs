@@ -6,42 +6,182 @@
 
 use crate::common::{make, Ref, Span};
 use crate::error::{runtime_error as error, Result};
-use crate::interpreter::value::{CallArgValues, Value};
+use crate::interpreter::debugger::StopReason;
+use crate::interpreter::native::Registry;
+use crate::interpreter::value::{builtin, CallArgValues, Type, Value};
 use crate::interpreter::{Interpreter, Scope};
 use crate::interpreter::random::RandomState;
+use std::collections::HashSet;
 use std::io::{Read, Write};
 use std::rc::Rc;
 
+// Registers the builtins under the names scripts use for them
+pub fn register(registry: &mut Registry) {
+    macro_rules! globals {
+        ($($name:literal => $function:ident),* $(,)?) => {
+            $(registry.add_global($name, builtin!($function));)*
+        };
+    }
+    macro_rules! methods {
+        ($type:expr, $($name:literal => $function:ident),* $(,)?) => {
+            $(registry.add_method($type, $name, builtin!($function));)*
+        };
+    }
+
+    globals!(
+        "len" => len,
+        "print" => print,
+        "input" => input,
+        "repr" => repr,
+        "open" => file_open,
+        "exit" => exit,
+        "dump" => dump,
+        "breakpoint" => breakpoint,
+        "iter" => to_iter,
+        "type" => type_of,
+        "isinstance" => isinstance,
+        "new_random_state" => new_random_state,
+    );
+    for type_ in [
+        Type::Integer,
+        Type::Float,
+        Type::String,
+        Type::Boolean,
+        Type::Array,
+        Type::Tuple,
+        Type::Dict,
+        Type::Set,
+        Type::Range,
+        Type::Function,
+        Type::StopIteration,
+    ] {
+        registry.add_global(type_.name(), Value::Type(type_));
+    }
+
+    methods!(
+        Type::Array,
+        "len" => len,
+        "push" => push,
+        "pop" => pop,
+        "str" => to_str,
+        "iter" => to_iter,
+        "dbg" => debug,
+    );
+    methods!(
+        Type::Tuple,
+        "len" => len,
+        "str" => to_str,
+        "iter" => to_iter,
+        "dbg" => debug,
+    );
+    methods!(Type::Float, "int" => to_int, "str" => to_str, "dbg" => debug);
+    methods!(Type::Boolean, "int" => to_int, "str" => to_str, "dbg" => debug);
+    methods!(Type::Integer, "str" => to_str, "float" => to_float, "dbg" => debug);
+    methods!(
+        Type::String,
+        "len" => len,
+        "split" => split,
+        "int" => to_int,
+        "float" => to_float,
+        "iter" => to_iter,
+        "dbg" => debug,
+        "strip" => strip,
+        "lower" => lower,
+        "upper" => upper,
+    );
+    methods!(
+        Type::Dict,
+        "len" => len,
+        "str" => to_str,
+        "get" => dict_get,
+        "keys" => dict_keys,
+        "values" => dict_values,
+        "items" => dict_items,
+        "dbg" => debug,
+    );
+    methods!(
+        Type::Set,
+        "len" => len,
+        "str" => to_str,
+        "add" => set_add,
+        "remove" => set_remove,
+        "union" => set_union,
+        "intersection" => set_intersection,
+        "difference" => set_difference,
+        "is_subset" => set_is_subset,
+        "dbg" => debug,
+    );
+    methods!(
+        Type::Iterator,
+        "join" => join,
+        "enumerate" => iter_enumerate,
+        "to_array" => to_array,
+        "map" => map,
+    );
+    methods!(Type::File, "read" => file_read, "write" => file_write);
+    methods!(Type::Nothing, "str" => to_str, "dbg" => debug);
+    methods!(Type::Error, "str" => to_str, "dbg" => debug);
+    methods!(Type::RandomState, "rand_f" => randf, "rand_i" => randi);
+}
+
 pub fn print(
-    _interpreter: &mut Interpreter,
+    interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
-    _span: &Span,
+    span: &Span,
     args: Vec<Value>,
 ) -> Result<Value> {
+    let mut text = String::new();
     for (i, arg) in args.iter().enumerate() {
         if i != 0 {
-            print!(" ");
+            text.push(' ');
         }
-        print!("{:?}", arg)
+        text.push_str(&interpreter.format_value(span, arg)?);
     }
-    println!();
+    text.push('\n');
+    interpreter.write_output(span, &text)?;
     Ok(Value::Nothing)
 }
 
-pub fn repr(
+pub fn type_of(
     _interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
     span: &Span,
     args: Vec<Value>,
+) -> Result<Value> {
+    if args.len() != 1 {
+        error!(span, "type() takes exactly one argument");
+    }
+    Ok(args[0].get_type())
+}
+
+pub fn isinstance(
+    _interpreter: &mut Interpreter,
+    _scope: Ref<Scope>,
+    span: &Span,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if args.len() != 2 {
+        error!(span, "isinstance() takes exactly two arguments");
+    }
+    Ok(Value::Boolean(args[0].is_instance_of(span, &args[1])?))
+}
+
+pub fn repr(
+    interpreter: &mut Interpreter,
+    _scope: Ref<Scope>,
+    span: &Span,
+    args: Vec<Value>,
 ) -> Result<Value> {
     if args.len() != 1 {
         error!(span, "repr() takes exactly one argument");
     }
-    Ok(Value::String(Rc::new(args[0].repr())))
+    let value = interpreter.repr_value(span, &args[0])?;
+    interpreter.check_size(span, value.len())?;
+    Ok(Value::String(Rc::new(value)))
 }
 
 pub fn len(
-    _interpreter: &mut Interpreter,
+    interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
     span: &Span,
     args: Vec<Value>,
@@ -54,13 +194,20 @@ pub fn len(
         Value::String(string) => Value::Integer(string.len() as i64),
         Value::Array(array) | Value::Tuple(array) => Value::Integer(array.borrow().len() as i64),
         Value::Dict(dict) => Value::Integer(dict.borrow().len() as i64),
+        Value::Set(set) => Value::Integer(set.borrow().len() as i64),
         Value::Range(start, end) => Value::Integer(end - start),
+        instance @ Value::ClassInstance(..) => {
+            match interpreter.call_special_method(span, instance, "__len__", vec![])? {
+                Some(value) => value,
+                None => error!(span, "len() does not support {:?}", instance),
+            }
+        }
         other => error!(span, "len() does not support {:?}", other),
     })
 }
 
 pub fn dump(
-    _interpreter: &mut Interpreter,
+    interpreter: &mut Interpreter,
     scope: Ref<Scope>,
     _span: &Span,
     args: Vec<Value>,
@@ -69,12 +216,13 @@ pub fn dump(
     if !args.is_empty() {
         error!(_span, "dump() takes no arguments");
     }
-    println!("{:#?}", scope.borrow().parent);
+    let text = format!("{:#?}\n", scope.borrow().parent);
+    interpreter.write_output(_span, &text)?;
     Ok(Value::Nothing)
 }
 
 pub fn push(
-    _interpreter: &mut Interpreter,
+    interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
     span: &Span,
     args: Vec<Value>,
@@ -85,6 +233,7 @@ pub fn push(
     match &args[0] {
         Value::Array(array) => {
             array.borrow_mut().push(args[1].clone());
+            interpreter.check_size(span, array.borrow().len())?;
             Ok(Value::Nothing)
         }
         other => error!(span, "push() does not support {:?}", other),
@@ -113,12 +262,13 @@ pub fn pop(
 }
 
 pub fn exit(
-    _interpreter: &mut Interpreter,
+    interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
     span: &Span,
     args: Vec<Value>,
 ) -> Result<Value> {
-    let code = match args.get(0) {
+    interpreter.sandbox.check_exit(span)?;
+    let code = match args.first() {
         Some(val) => match val {
             Value::Integer(i) => *i,
             _ => error!(span, "exit() may only take an integer as argument"),
@@ -133,11 +283,12 @@ pub fn exit(
 }
 
 pub fn input(
-    _interpreter: &mut Interpreter,
+    interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
     span: &Span,
     args: Vec<Value>,
 ) -> Result<Value> {
+    interpreter.sandbox.check_stdin(span)?;
     let prompt = if args.len() == 1 {
         match &args[0] {
             Value::String(string) => string.to_string(),
@@ -149,8 +300,7 @@ pub fn input(
         error!(span, "input() takes either one or no arguments");
     };
     let mut input = String::new();
-    print!("{}", prompt);
-    std::io::Write::flush(&mut std::io::stdout()).unwrap();
+    interpreter.write_output(span, &prompt)?;
     std::io::stdin().read_line(&mut input).unwrap();
     input = input.trim_end().to_string();
     Ok(Value::String(Rc::new(input)))
@@ -170,6 +320,9 @@ pub fn dict_get(
         _ => error!(span, "dict_get() may only take a dict as first argument"),
     };
     let key = &args[1];
+    if !key.is_hashable() {
+        error!(span, "Key must be hashable");
+    }
     let default = if args.len() == 3 {
         &args[2]
     } else {
@@ -246,6 +399,142 @@ pub fn dict_values(
     Ok(Value::Array(make!(values)))
 }
 
+#[allow(clippy::mutable_key_type)]
+pub fn to_set(
+    interpreter: &mut Interpreter,
+    _scope: Ref<Scope>,
+    span: &Span,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if args.len() > 1 {
+        error!(span, "set() takes at most one argument");
+    }
+    let mut set = HashSet::new();
+    if let Some(iterable) = args.first() {
+        let iter = interpreter.iterate(iterable, span)?;
+        while let Some(item) = iter.next(interpreter, span)? {
+            if !item.is_hashable() {
+                error!(span, "Set items must be hashable");
+            }
+            set.insert(item);
+        }
+    }
+    Ok(Value::Set(make!(set)))
+}
+
+pub fn set_add(
+    interpreter: &mut Interpreter,
+    _scope: Ref<Scope>,
+    span: &Span,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if args.len() != 2 {
+        error!(span, "add() takes exactly one argument");
+    }
+    let set = match &args[0] {
+        Value::Set(set) => set,
+        _ => error!(span, "add() may only be called on a set"),
+    };
+    if !args[1].is_hashable() {
+        error!(span, "Set items must be hashable");
+    }
+    set.borrow_mut().insert(args[1].clone());
+    interpreter.check_size(span, set.borrow().len())?;
+    Ok(Value::Nothing)
+}
+
+pub fn set_remove(
+    _interpreter: &mut Interpreter,
+    _scope: Ref<Scope>,
+    span: &Span,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if args.len() != 2 {
+        error!(span, "remove() takes exactly one argument");
+    }
+    let set = match &args[0] {
+        Value::Set(set) => set,
+        _ => error!(span, "remove() may only be called on a set"),
+    };
+    if !args[1].is_hashable() {
+        error!(span, "Set items must be hashable");
+    }
+    if !set.borrow_mut().remove(&args[1]) {
+        error!(span, "Item {} not found in set", args[1].repr());
+    }
+    Ok(Value::Nothing)
+}
+
+fn set_operands(
+    name: &str,
+    span: &Span,
+    args: &[Value],
+) -> Result<(HashSet<Value>, HashSet<Value>)> {
+    if args.len() != 2 {
+        error!(span, "{}() takes exactly one argument", name);
+    }
+    match (&args[0], &args[1]) {
+        (Value::Set(left), Value::Set(right)) => {
+            Ok((left.borrow().clone(), right.borrow().clone()))
+        }
+        (_, other) => error!(
+            span,
+            "{}() expects a set, but got {}",
+            name,
+            other.type_of()
+        ),
+    }
+}
+
+#[allow(clippy::mutable_key_type)]
+pub fn set_union(
+    interpreter: &mut Interpreter,
+    _scope: Ref<Scope>,
+    span: &Span,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let (left, right) = set_operands("union", span, &args)?;
+    let set: HashSet<Value> = left.union(&right).cloned().collect();
+    interpreter.check_size(span, set.len())?;
+    Ok(Value::Set(make!(set)))
+}
+
+#[allow(clippy::mutable_key_type)]
+pub fn set_intersection(
+    interpreter: &mut Interpreter,
+    _scope: Ref<Scope>,
+    span: &Span,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let (left, right) = set_operands("intersection", span, &args)?;
+    let set: HashSet<Value> = left.intersection(&right).cloned().collect();
+    interpreter.check_size(span, set.len())?;
+    Ok(Value::Set(make!(set)))
+}
+
+#[allow(clippy::mutable_key_type)]
+pub fn set_difference(
+    interpreter: &mut Interpreter,
+    _scope: Ref<Scope>,
+    span: &Span,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let (left, right) = set_operands("difference", span, &args)?;
+    let set: HashSet<Value> = left.difference(&right).cloned().collect();
+    interpreter.check_size(span, set.len())?;
+    Ok(Value::Set(make!(set)))
+}
+
+pub fn set_is_subset(
+    _interpreter: &mut Interpreter,
+    _scope: Ref<Scope>,
+    span: &Span,
+    args: Vec<Value>,
+) -> Result<Value> {
+    let (left, right) = set_operands("is_subset", span, &args)?;
+    Ok(Value::Boolean(left.is_subset(&right)))
+}
+
 pub fn split(
     _interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
@@ -299,7 +588,7 @@ pub fn strip(
 }
 
 pub fn lower(
-    _interpreter: &mut Interpreter,
+    interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
     span: &Span,
     args: Vec<Value>,
@@ -311,11 +600,13 @@ pub fn lower(
         Value::String(string) => string.to_string(),
         _ => error!(span, "lower() may only take a string as argument"),
     };
-    Ok(Value::String(Rc::new(string.to_lowercase())))
+    let string = string.to_lowercase();
+    interpreter.check_size(span, string.len())?;
+    Ok(Value::String(Rc::new(string)))
 }
 
 pub fn upper(
-    _interpreter: &mut Interpreter,
+    interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
     span: &Span,
     args: Vec<Value>,
@@ -327,11 +618,13 @@ pub fn upper(
         Value::String(string) => string.to_string(),
         _ => error!(span, "upper() may only take a string as argument"),
     };
-    Ok(Value::String(Rc::new(string.to_uppercase())))
+    let string = string.to_uppercase();
+    interpreter.check_size(span, string.len())?;
+    Ok(Value::String(Rc::new(string)))
 }
 
 pub fn join(
-    _interpreter: &mut Interpreter,
+    interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
     span: &Span,
     args: Vec<Value>,
@@ -353,15 +646,17 @@ pub fn join(
         _ => error!(span, "join() may only take a string as the second argument"),
     };
     let mut result = String::new();
-    let iter = &mut *(*iter.0).borrow_mut();
-    for (i, item) in iter.enumerate() {
-        if i > 0 {
+    let mut first = true;
+    while let Some(item) = iter.next(interpreter, span)? {
+        if !first {
             result.push_str(separator.as_str());
         }
+        first = false;
         match item {
             Value::String(string) => result.push_str(string.as_str()),
             _ => error!(span, "join() may only take an iterator of strings"),
         }
+        interpreter.check_size(span, result.len())?;
     }
     Ok(Value::String(Rc::new(result)))
 }
@@ -386,16 +681,18 @@ pub fn map(
     match function {
         Value::Function(_) => {}
         Value::BuiltInFunction(_) => {}
+        Value::Type(type_) if type_.constructor().is_some() => {}
         _ => error!(
             span,
             "map() may only take a function as the second argument"
         ),
     };
     let mut result = Vec::new();
-    let iter = &mut *(*iter.0).borrow_mut();
-    for item in iter {
-        let args: CallArgValues = vec![(None, item.clone())];
+    while let Some(item) = iter.next(interpreter, span)? {
+        let args: CallArgValues = vec![(None, item)];
         result.push(interpreter.do_call(span, scope.clone(), None, function.clone(), &args)?);
+        interpreter.step(span)?;
+        interpreter.check_size(span, result.len())?;
     }
     Value::Array(make!(result)).iterator(span)
 }
@@ -443,21 +740,35 @@ pub fn to_float(
     Ok(Value::Float(value))
 }
 
-pub fn to_str(
+// `StopIteration()` is the same signal as `StopIteration` on its own
+pub fn stop_iteration(
     _interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
     span: &Span,
     args: Vec<Value>,
+) -> Result<Value> {
+    if !args.is_empty() {
+        error!(span, "StopIteration() takes no arguments");
+    }
+    Ok(Value::Type(Type::StopIteration))
+}
+
+pub fn to_str(
+    interpreter: &mut Interpreter,
+    _scope: Ref<Scope>,
+    span: &Span,
+    args: Vec<Value>,
 ) -> Result<Value> {
     if args.len() != 1 {
         error!(span, "str() takes exactly one argument");
     }
-    let value = format!("{:?}", args[0]);
+    let value = interpreter.format_value(span, &args[0])?;
+    interpreter.check_size(span, value.len())?;
     Ok(Value::String(Rc::new(value)))
 }
 
 pub fn to_iter(
-    _interpreter: &mut Interpreter,
+    interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
     span: &Span,
     args: Vec<Value>,
@@ -465,11 +776,11 @@ pub fn to_iter(
     if args.len() != 1 {
         error!(span, "iter() takes exactly one argument");
     }
-    args[0].iterator(span)
+    Ok(Value::Iterator(interpreter.iterate(&args[0], span)?))
 }
 
 pub fn to_array(
-    _interpreter: &mut Interpreter,
+    interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
     span: &Span,
     args: Vec<Value>,
@@ -481,16 +792,17 @@ pub fn to_array(
         Value::Iterator(iter) => iter,
         _ => error!(span, "collect() may only take an iterable as argument"),
     };
-    let iter = &mut *(*iter.0).borrow_mut();
     let mut items = Vec::new();
-    for item in iter {
-        items.push(item.clone());
+    while let Some(item) = iter.next(interpreter, span)? {
+        items.push(item);
+        interpreter.step(span)?;
+        interpreter.check_size(span, items.len())?;
     }
     Ok(Value::Array(make!(items)))
 }
 
 pub fn iter_enumerate(
-    _interpreter: &mut Interpreter,
+    interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
     span: &Span,
     args: Vec<Value>,
@@ -502,16 +814,16 @@ pub fn iter_enumerate(
         Value::Iterator(iter) => iter,
         _ => error!(span, "enumerate() may only take an iterable as argument"),
     };
-    let iter = &mut *(*iter.0).borrow_mut();
     let mut items = Vec::new();
-    for (i, item) in iter.enumerate() {
-        items.push(Value::Tuple(make!(vec![Value::Integer(i as i64), item])));
+    while let Some(item) = iter.next(interpreter, span)? {
+        let index = Value::Integer(items.len() as i64);
+        items.push(Value::Tuple(make!(vec![index, item])));
     }
     Ok(Value::Array(make!(items)))
 }
 
 pub fn file_open(
-    _interpreter: &mut Interpreter,
+    interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
     span: &Span,
     args: Vec<Value>,
@@ -523,6 +835,7 @@ pub fn file_open(
         Value::String(string) => string.clone(),
         _ => error!(span, "open() may only take a string as first argument"),
     };
+    interpreter.sandbox.check_open(span, &path)?;
     let file = match std::fs::File::open(path.to_string()) {
         Ok(file) => file,
         Err(err) => error!(span, "Could not open file: {}", err),
@@ -576,7 +889,7 @@ pub fn file_write(
 }
 
 pub fn debug(
-    _interpreter: &mut Interpreter,
+    interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
     span: &Span,
     args: Vec<Value>,
@@ -584,17 +897,31 @@ pub fn debug(
     if args.len() != 1 {
         error!(span, "debug() takes exactly one argument");
     }
-    println!("{:?}", args[0]);
+    interpreter.write_output(span, &format!("{:?}\n", args[0]))?;
     Ok(args[0].clone())
 }
 
+// Pauses like a breakpoint when a debugger is attached
+pub fn breakpoint(
+    interpreter: &mut Interpreter,
+    _scope: Ref<Scope>,
+    span: &Span,
+    args: Vec<Value>,
+) -> Result<Value> {
+    if !args.is_empty() {
+        error!(span, "breakpoint() takes no arguments");
+    }
+    interpreter.pause(StopReason::Breakpoint, None);
+    Ok(Value::Nothing)
+}
+
 pub fn new_random_state(
     _interpreter: &mut Interpreter,
     _scope: Ref<Scope>,
     span: &Span,
     args: Vec<Value>,
 ) -> Result<Value> {
-    if args.len() != 0 {
+    if !args.is_empty() {
         error!(span, "rand() takes no arguments");
     }
     Ok(Value::RandomState(make!(RandomState::new())))
@@ -623,7 +950,7 @@ pub fn randi(
     span: &Span,
     args: Vec<Value>,
 ) -> Result<Value> {
-    let state = match args.get(0) {
+    let state = match args.first() {
         Some(Value::RandomState(ref state)) => state,
         _ => error!(span, "randi() requires a random state as first argument"),
     };
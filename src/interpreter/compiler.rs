@@ -0,0 +1,816 @@
+/*
+   Copyright (C) 2023  Haven Selph
+   Copyright (C) 2023  Mustafa Quraish
+   Check the LICENSE file for more information.
+*/
+
+use crate::ast::{Layout, Pattern, Slot, AST};
+use crate::common::Span;
+use crate::error::Result;
+use crate::interpreter::value::Value;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Binary operators, which call the matching special method when the left
+// operand is a class instance that defines it
+#[derive(Debug, Clone, Copy)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    Equals,
+    NotEquals,
+    LessThan,
+    GreaterThan,
+    LessEquals,
+    GreaterEquals,
+    // `a in b`, with the container `b` as the left operand
+    Contains,
+}
+
+impl BinaryOp {
+    pub fn method(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "__add__",
+            BinaryOp::Subtract => "__sub__",
+            BinaryOp::Multiply => "__mul__",
+            BinaryOp::Divide => "__div__",
+            BinaryOp::Modulo => "__mod__",
+            BinaryOp::Power => "__pow__",
+            BinaryOp::Equals => "__eq__",
+            BinaryOp::NotEquals => "__ne__",
+            BinaryOp::LessThan => "__lt__",
+            BinaryOp::GreaterThan => "__gt__",
+            BinaryOp::LessEquals => "__le__",
+            BinaryOp::GreaterEquals => "__ge__",
+            BinaryOp::Contains => "__contains__",
+        }
+    }
+
+    pub fn apply(&self, left: &Value, right: &Value, span: &Span) -> Result<Value> {
+        match self {
+            BinaryOp::Add => left.plus(right, span),
+            BinaryOp::Subtract => left.minus(right, span),
+            BinaryOp::Multiply => left.multiply(right, span),
+            BinaryOp::Divide => left.divide(right, span),
+            BinaryOp::Modulo => left.modulo(right, span),
+            BinaryOp::Power => left.power(right, span),
+            BinaryOp::Equals => left.equals(right, span),
+            BinaryOp::NotEquals => left.not_equals(right, span),
+            BinaryOp::LessThan => left.less_than(right, span),
+            BinaryOp::GreaterThan => left.greater_than(right, span),
+            BinaryOp::LessEquals => left.less_equals(right, span),
+            BinaryOp::GreaterEquals => left.greater_equals(right, span),
+            BinaryOp::Contains => left.contains(right, span),
+        }
+    }
+}
+
+// A single VM instruction. Operands index into the tables of the chunk, and
+// jump targets are instruction indices.
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    Constant(usize),
+    Pop,
+    Dup,
+    // Variables index into the table of variable uses, which holds the slot
+    // the resolver found for each
+    Load(usize),
+    // Pops a value into a new variable in the current scope
+    Declare(usize),
+    // Pops a value into an existing variable
+    Assign(usize),
+    // Pops a value and destructures it into a pattern
+    Bind(usize),
+    GetField(usize),
+    SetField(usize),
+    // Pushes a field of the object on top of the stack, keeping the object
+    // around as `self` for `CallMethod`
+    GetMethod(usize),
+    Index,
+    SetIndex,
+    Binary(BinaryOp),
+    Negate,
+    Not,
+    // Short circuiting, jumps with the left operand on the stack if it
+    // decides the result and pops it otherwise
+    And(usize),
+    Or(usize),
+    CheckBoolean,
+    Jump(usize),
+    // Pops a condition, failing with the message if it isn't a boolean
+    JumpIfFalse(usize, &'static str),
+    Iter,
+    // Pushes the next item of the iterator on top of the stack, or jumps
+    // once it is exhausted
+    Next(usize),
+    // Pushes a scope with the variables of a layout
+    PushScope(usize),
+    PopScope,
+    ClearScope,
+    Call(usize),
+    CallMethod(usize),
+    Return,
+    // A break or continue outside of any loop in this chunk
+    Break,
+    Continue,
+    MakeArray(usize),
+    MakeTuple(usize),
+    MakeDict(usize),
+    MakeSet(usize),
+    CheckHashable(&'static str),
+    MakeRange,
+    // Increments an integer variable, pushing its old value if `post` is set
+    Increment {
+        variable: usize,
+        offset: i64,
+        post: bool,
+    },
+    Format,
+    BuildString(usize, usize),
+    // Counts a statement starting to run for the profiler, which is the only
+    // time it's emitted
+    Statement,
+    // Runs a node on the tree walking interpreter. Breaks and continues it
+    // leaves behind are handled with the loop exit, if any.
+    Eval(usize, Option<usize>),
+}
+
+// Where a break or continue coming out of an `Eval` goes, and how much of
+// the stack and scopes to unwind on the way
+#[derive(Debug, Default)]
+pub struct LoopExit {
+    pub break_target: usize,
+    pub continue_target: usize,
+    pub pops: usize,
+    pub scopes: usize,
+}
+
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub spans: Vec<Span>,
+    pub constants: Vec<Value>,
+    pub names: Vec<String>,
+    pub variables: Vec<(String, Option<Slot>)>,
+    pub layouts: Vec<Rc<Vec<String>>>,
+    pub patterns: Vec<Rc<Pattern>>,
+    pub calls: Vec<Vec<Option<String>>>,
+    pub strings: Vec<Vec<String>>,
+    pub nodes: Vec<Rc<AST>>,
+    pub exits: Vec<LoopExit>,
+}
+
+struct Loop {
+    stack: usize,
+    scopes: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+    exits: Vec<usize>,
+}
+
+// How the variables of a for each loop are scoped. The tree walker creates
+// a new scope for every iteration, but unless something in the body can
+// capture it, one scope can be cleared and reused instead.
+#[derive(PartialEq)]
+enum IterationScope {
+    Fresh,
+    Reused,
+}
+
+// Compiles a function body or program into a chunk. The statements run in
+// the scope given to the VM, without a block scope of their own. With
+// `profile`, each statement starts with an instruction that counts it.
+pub fn compile(body: &Rc<AST>, profile: bool) -> Chunk {
+    let mut compiler = Compiler {
+        chunk: Chunk::default(),
+        names: HashMap::new(),
+        loops: Vec::new(),
+        stack: 0,
+        scopes: 0,
+        profile,
+    };
+    match body.as_ref() {
+        AST::Block(_, stmts, _) => compiler.statements(body.span(), stmts, true),
+        _ => {
+            compiler.count_statement(body);
+            compiler.expression(body)
+        }
+    }
+    compiler.chunk
+}
+
+struct Compiler {
+    chunk: Chunk,
+    names: HashMap<String, usize>,
+    loops: Vec<Loop>,
+    // Statically known height of the value stack and number of scopes
+    // pushed, used to unwind both on a break or continue
+    stack: usize,
+    scopes: usize,
+    profile: bool,
+}
+
+impl Compiler {
+    fn emit(&mut self, op: Op, span: &Span) -> usize {
+        let (pops, pushes) = match op {
+            Op::Constant(_) | Op::Dup | Op::Load(_) | Op::Next(_) | Op::Increment { .. } => (0, 1),
+            Op::GetMethod(_) => (1, 2),
+            Op::Pop
+            | Op::Declare(_)
+            | Op::Assign(_)
+            | Op::Bind(_)
+            | Op::And(_)
+            | Op::Or(_)
+            | Op::JumpIfFalse(..)
+            | Op::Return => (1, 0),
+            Op::SetField(_) => (2, 0),
+            Op::SetIndex => (3, 0),
+            Op::Index | Op::Binary(_) | Op::MakeRange => (2, 1),
+            Op::Call(index) => (self.chunk.calls[index].len() + 1, 1),
+            Op::CallMethod(index) => (self.chunk.calls[index].len() + 2, 1),
+            Op::MakeArray(count) | Op::MakeTuple(count) | Op::MakeSet(count) => (count, 1),
+            Op::MakeDict(count) => (count * 2, 1),
+            Op::BuildString(_, count) => (count, 1),
+            Op::Eval(..) => (0, 1),
+            Op::GetField(_)
+            | Op::Negate
+            | Op::Not
+            | Op::CheckBoolean
+            | Op::Jump(_)
+            | Op::Iter
+            | Op::PushScope(_)
+            | Op::PopScope
+            | Op::ClearScope
+            | Op::Break
+            | Op::Continue
+            | Op::CheckHashable(_)
+            | Op::Format
+            | Op::Statement => (0, 0),
+        };
+        self.stack = self.stack - pops + pushes;
+        match op {
+            Op::PushScope(_) => self.scopes += 1,
+            Op::PopScope => self.scopes -= 1,
+            _ => {}
+        }
+        self.chunk.code.push(op);
+        self.chunk.spans.push(*span);
+        self.chunk.code.len() - 1
+    }
+
+    fn here(&self) -> usize {
+        self.chunk.code.len()
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        match &mut self.chunk.code[at] {
+            Op::Jump(to) | Op::JumpIfFalse(to, _) | Op::And(to) | Op::Or(to) | Op::Next(to) => {
+                *to = target
+            }
+            op => unreachable!("Can't patch the target of {:?}", op),
+        }
+    }
+
+    fn name(&mut self, name: &str) -> usize {
+        if let Some(index) = self.names.get(name) {
+            return *index;
+        }
+        self.chunk.names.push(name.to_string());
+        self.names
+            .insert(name.to_string(), self.chunk.names.len() - 1);
+        self.chunk.names.len() - 1
+    }
+
+    fn variable(&mut self, name: &str, slot: Option<Slot>) -> usize {
+        self.chunk.variables.push((name.to_string(), slot));
+        self.chunk.variables.len() - 1
+    }
+
+    fn push_scope(&mut self, layout: &Layout, span: &Span) {
+        self.chunk
+            .layouts
+            .push(layout.get().cloned().unwrap_or_default());
+        self.emit(Op::PushScope(self.chunk.layouts.len() - 1), span);
+    }
+
+    fn constant(&mut self, value: Value, span: &Span) {
+        self.chunk.constants.push(value);
+        self.emit(Op::Constant(self.chunk.constants.len() - 1), span);
+    }
+
+    // Hands a node to the tree walker
+    fn eval(&mut self, ast: &Rc<AST>) {
+        let exit = match self.loops.last() {
+            Some(current) => {
+                self.chunk.exits.push(LoopExit {
+                    pops: self.stack - current.stack,
+                    scopes: self.scopes - current.scopes,
+                    ..LoopExit::default()
+                });
+                let exit = self.chunk.exits.len() - 1;
+                self.loops.last_mut().unwrap().exits.push(exit);
+                Some(exit)
+            }
+            None => None,
+        };
+        self.chunk.nodes.push(ast.clone());
+        self.emit(Op::Eval(self.chunk.nodes.len() - 1, exit), ast.span());
+    }
+
+    fn count_statement(&mut self, stmt: &Rc<AST>) {
+        if self.profile {
+            self.emit(Op::Statement, stmt.span());
+        }
+    }
+
+    // Compiles the statements of a block, leaving the value of the last one
+    // on the stack if `keep` is set
+    fn statements(&mut self, span: &Span, stmts: &[Rc<AST>], keep: bool) {
+        for (i, stmt) in stmts.iter().enumerate() {
+            self.count_statement(stmt);
+            if keep && i == stmts.len() - 1 {
+                self.expression(stmt);
+            } else {
+                self.statement(stmt);
+            }
+        }
+        if keep && stmts.is_empty() {
+            self.constant(Value::Nothing, span);
+        }
+    }
+
+    // Blocks that don't declare anything run in the enclosing scope
+    fn block(&mut self, span: &Span, stmts: &[Rc<AST>], layout: &Layout, keep: bool) {
+        let scoped = layout.get().is_some();
+        if scoped {
+            self.push_scope(layout, span);
+        }
+        self.statements(span, stmts, keep);
+        if scoped {
+            self.emit(Op::PopScope, span);
+        }
+    }
+
+    // Compiles a node whose value is not needed
+    fn statement(&mut self, ast: &Rc<AST>) {
+        match ast.as_ref() {
+            AST::VarDeclaration(span, name, value) => {
+                self.expression(value);
+                let name = self.name(name);
+                self.emit(Op::Declare(name), span);
+            }
+            AST::Destructure(span, pattern, value) => {
+                self.expression(value);
+                self.chunk.patterns.push(pattern.clone());
+                self.emit(Op::Bind(self.chunk.patterns.len() - 1), span);
+            }
+            AST::Assignment(_, lhs, value) if is_assignable(lhs) => {
+                self.expression(value);
+                self.assign(lhs);
+            }
+            AST::Block(span, stmts, layout) => self.block(span, stmts, layout, false),
+            AST::If(span, cond, body, else_body) => self.branch(span, cond, body, else_body, false),
+            AST::While(span, cond, body) => self.while_loop(span, cond, body),
+            AST::For {
+                span,
+                init,
+                cond,
+                step,
+                body,
+                layout,
+            } => self.for_loop(span, init, cond, step, body, layout),
+            AST::ForEach(span, pattern, iter, body, layout) => {
+                self.for_each(span, pattern, iter, body, layout)
+            }
+            AST::Return(span, value) => {
+                self.expression(value);
+                self.emit(Op::Return, span);
+            }
+            AST::Break(span) | AST::Continue(span) => {
+                let is_break = matches!(ast.as_ref(), AST::Break(_));
+                let Some(current) = self.loops.last() else {
+                    self.emit(if is_break { Op::Break } else { Op::Continue }, span);
+                    return;
+                };
+                let (pops, scopes) = (self.stack - current.stack, self.scopes - current.scopes);
+                for _ in 0..scopes {
+                    self.chunk.code.push(Op::PopScope);
+                    self.chunk.spans.push(*span);
+                }
+                for _ in 0..pops {
+                    self.chunk.code.push(Op::Pop);
+                    self.chunk.spans.push(*span);
+                }
+                // The code after this is unreachable, so the stack and scope
+                // counts are left as they were
+                let jump = self.here();
+                self.chunk.code.push(Op::Jump(0));
+                self.chunk.spans.push(*span);
+                let current = self.loops.last_mut().unwrap();
+                if is_break {
+                    current.breaks.push(jump);
+                } else {
+                    current.continues.push(jump);
+                }
+            }
+            _ => {
+                self.expression(ast);
+                self.emit(Op::Pop, ast.span());
+            }
+        }
+    }
+
+    // Compiles a node that pushes its value
+    fn expression(&mut self, ast: &Rc<AST>) {
+        macro_rules! binary {
+            ($span:expr, $op:ident, $left:expr, $right:expr) => {{
+                self.expression($left);
+                self.expression($right);
+                self.emit(Op::Binary(BinaryOp::$op), $span);
+            }};
+        }
+        match ast.as_ref() {
+            AST::BooleanLiteral(span, value) => self.constant(Value::Boolean(*value), span),
+            AST::IntegerLiteral(span, num) => self.constant(Value::Integer(*num), span),
+            AST::FloatLiteral(span, num) => self.constant(Value::Float(*num), span),
+            AST::StringLiteral(span, string) => {
+                self.constant(Value::String(Rc::new(string.clone())), span)
+            }
+            AST::Nothing(span) => self.constant(Value::Nothing, span),
+            AST::Variable(span, name, slot) => {
+                let variable = self.variable(name, slot.get());
+                self.emit(Op::Load(variable), span);
+            }
+
+            AST::Plus(span, left, right) => binary!(span, Add, left, right),
+            AST::Minus(span, left, right) => binary!(span, Subtract, left, right),
+            AST::Multiply(span, left, right) => binary!(span, Multiply, left, right),
+            AST::Divide(span, left, right) => binary!(span, Divide, left, right),
+            AST::Modulo(span, left, right) => binary!(span, Modulo, left, right),
+            AST::Power(span, left, right) => binary!(span, Power, left, right),
+            AST::Equals(span, left, right) => binary!(span, Equals, left, right),
+            AST::NotEquals(span, left, right) => binary!(span, NotEquals, left, right),
+            AST::LessThan(span, left, right) => binary!(span, LessThan, left, right),
+            AST::GreaterThan(span, left, right) => binary!(span, GreaterThan, left, right),
+            AST::LessEquals(span, left, right) => binary!(span, LessEquals, left, right),
+            AST::GreaterEquals(span, left, right) => binary!(span, GreaterEquals, left, right),
+            AST::In(span, left, right) => binary!(span, Contains, right, left),
+            AST::Negate(span, expr) => {
+                self.expression(expr);
+                self.emit(Op::Negate, span);
+            }
+            AST::Not(span, expr) => {
+                self.expression(expr);
+                self.emit(Op::Not, span);
+            }
+            AST::And(_, left, right) | AST::Or(_, left, right) => {
+                self.expression(left);
+                let jump = match ast.as_ref() {
+                    AST::And(..) => self.emit(Op::And(0), left.span()),
+                    _ => self.emit(Op::Or(0), left.span()),
+                };
+                self.expression(right);
+                self.emit(Op::CheckBoolean, right.span());
+                self.patch(jump, self.here());
+            }
+
+            AST::Call(span, callee, args) => self.call(ast, span, callee, args),
+            AST::FieldAccess(span, obj, field) if !matches!(obj.as_ref(), AST::Super(..)) => {
+                self.expression(obj);
+                let field = self.name(field);
+                self.emit(Op::GetField(field), span);
+            }
+            AST::Index(span, left, right) => {
+                self.expression(left);
+                self.expression(right);
+                self.emit(Op::Index, span);
+            }
+            AST::Range(span, start, end) => {
+                self.expression(start);
+                self.expression(end);
+                self.emit(Op::MakeRange, span);
+            }
+            AST::ArrayLiteral(span, items) | AST::TupleLiteral(span, items) => {
+                items.iter().for_each(|item| self.expression(item));
+                match ast.as_ref() {
+                    AST::ArrayLiteral(..) => self.emit(Op::MakeArray(items.len()), span),
+                    _ => self.emit(Op::MakeTuple(items.len()), span),
+                };
+            }
+            AST::DictionaryLiteral(span, items) => {
+                for (key, value) in items {
+                    self.expression(key);
+                    self.emit(
+                        Op::CheckHashable("Dictionary key must be hashable"),
+                        key.span(),
+                    );
+                    self.expression(value);
+                }
+                self.emit(Op::MakeDict(items.len()), span);
+            }
+            AST::SetLiteral(span, items) => {
+                for item in items {
+                    self.expression(item);
+                    self.emit(Op::CheckHashable("Set items must be hashable"), item.span());
+                }
+                self.emit(Op::MakeSet(items.len()), span);
+            }
+            AST::FormatStringLiteral(span, strings, exprs) => {
+                let count = strings.len().min(exprs.len());
+                for expr in &exprs[..count] {
+                    self.expression(expr);
+                    self.emit(Op::Format, expr.span());
+                }
+                self.chunk.strings.push(strings.clone());
+                self.emit(Op::BuildString(self.chunk.strings.len() - 1, count), span);
+            }
+            AST::PostIncrement(span, target, offset) | AST::PreIncrement(span, target, offset) => {
+                let AST::Variable(_, name, slot) = target.as_ref() else {
+                    return self.eval(ast);
+                };
+                let post = matches!(ast.as_ref(), AST::PostIncrement(..));
+                let variable = self.variable(name, slot.get());
+                self.emit(
+                    Op::Increment {
+                        variable,
+                        offset: *offset,
+                        post,
+                    },
+                    span,
+                );
+            }
+            AST::Assignment(_, lhs, value) if is_assignable(lhs) => {
+                self.expression(value);
+                self.emit(Op::Dup, value.span());
+                self.assign(lhs);
+            }
+            AST::Block(span, stmts, layout) => self.block(span, stmts, layout, true),
+            AST::If(span, cond, body, else_body) => self.branch(span, cond, body, else_body, true),
+            AST::VarDeclaration(..)
+            | AST::Destructure(..)
+            | AST::While(..)
+            | AST::For { .. }
+            | AST::ForEach(..)
+            | AST::Return(..)
+            | AST::Break(..)
+            | AST::Continue(..) => {
+                self.statement(ast);
+                self.constant(Value::Nothing, ast.span());
+            }
+            _ => self.eval(ast),
+        }
+    }
+
+    // Stores the value on top of the stack into an assignment target
+    fn assign(&mut self, target: &Rc<AST>) {
+        match target.as_ref() {
+            AST::Variable(span, name, slot) => {
+                let variable = self.variable(name, slot.get());
+                self.emit(Op::Assign(variable), span);
+            }
+            AST::Index(span, left, right) => {
+                self.expression(left);
+                self.expression(right);
+                self.emit(Op::SetIndex, span);
+            }
+            AST::FieldAccess(span, obj, field) => {
+                self.expression(obj);
+                let field = self.name(field);
+                self.emit(Op::SetField(field), span);
+            }
+            _ => unreachable!("Only assignable targets are compiled"),
+        }
+    }
+
+    fn call(&mut self, ast: &Rc<AST>, span: &Span, callee: &Rc<AST>, args: &crate::ast::CallArgs) {
+        let spread = args.iter().any(|(_, arg)| {
+            matches!(
+                arg.as_ref(),
+                AST::StarExpression(..) | AST::StarStarExpression(..)
+            )
+        });
+        if spread {
+            return self.eval(ast);
+        }
+        let method = match callee.as_ref() {
+            AST::FieldAccess(_, obj, _) if matches!(obj.as_ref(), AST::Super(..)) => {
+                return self.eval(ast)
+            }
+            AST::FieldAccess(_, obj, field) => {
+                self.expression(obj);
+                let field = self.name(field);
+                self.emit(Op::GetMethod(field), span);
+                true
+            }
+            _ => {
+                self.expression(callee);
+                false
+            }
+        };
+        args.iter().for_each(|(_, arg)| self.expression(arg));
+        self.chunk
+            .calls
+            .push(args.iter().map(|(name, _)| name.clone()).collect());
+        let index = self.chunk.calls.len() - 1;
+        self.emit(
+            if method {
+                Op::CallMethod(index)
+            } else {
+                Op::Call(index)
+            },
+            span,
+        );
+    }
+
+    fn branch(
+        &mut self,
+        span: &Span,
+        cond: &Rc<AST>,
+        body: &Rc<AST>,
+        else_body: &Option<Rc<AST>>,
+        keep: bool,
+    ) {
+        self.expression(cond);
+        let skip = self.emit(Op::JumpIfFalse(0, "If condition must be a boolean"), span);
+        let stack = self.stack;
+        self.arm(body, keep);
+        let end = self.emit(Op::Jump(0), span);
+        self.patch(skip, self.here());
+        self.stack = stack;
+        match else_body {
+            Some(else_body) => self.arm(else_body, keep),
+            None if keep => self.constant(Value::Nothing, span),
+            None => {}
+        }
+        self.patch(end, self.here());
+    }
+
+    fn arm(&mut self, ast: &Rc<AST>, keep: bool) {
+        if keep {
+            self.expression(ast);
+        } else {
+            self.statement(ast);
+        }
+    }
+
+    fn iteration_scope(&self, body: &Rc<AST>) -> IterationScope {
+        if body.any(&|node| {
+            matches!(
+                node,
+                AST::Function { .. } | AST::Class { .. } | AST::Enum { .. } | AST::Namespace { .. }
+            )
+        }) {
+            IterationScope::Fresh
+        } else {
+            IterationScope::Reused
+        }
+    }
+
+    // Starts a loop whose breaks and continues land with the current stack
+    // and scopes
+    fn begin_loop(&mut self) {
+        self.loops.push(Loop {
+            stack: self.stack,
+            scopes: self.scopes,
+            breaks: Vec::new(),
+            continues: Vec::new(),
+            exits: Vec::new(),
+        });
+    }
+
+    fn end_loop(&mut self, break_target: usize, continue_target: usize) {
+        let current = self.loops.pop().expect("Loop stack is empty");
+        for jump in current.breaks {
+            self.patch(jump, break_target);
+        }
+        for jump in current.continues {
+            self.patch(jump, continue_target);
+        }
+        for exit in current.exits {
+            self.chunk.exits[exit].break_target = break_target;
+            self.chunk.exits[exit].continue_target = continue_target;
+        }
+        self.stack = current.stack;
+        self.scopes = current.scopes;
+    }
+
+    // The statements of a loop body, which runs directly in the iteration
+    // scope
+    fn loop_body(&mut self, body: &Rc<AST>) {
+        match body.as_ref() {
+            AST::Block(span, stmts, _) => self.statements(span, stmts, false),
+            _ => self.statement(body),
+        }
+    }
+
+    // A block body gets a scope for each iteration if it declares anything
+    fn while_loop(&mut self, span: &Span, cond: &Rc<AST>, body: &Rc<AST>) {
+        let top = self.here();
+        self.expression(cond);
+        let exit = self.emit(
+            Op::JumpIfFalse(0, "While condition must be a boolean"),
+            span,
+        );
+        self.begin_loop();
+        self.statement(body);
+        self.emit(Op::Jump(top), span);
+        let end = self.here();
+        self.patch(exit, end);
+        self.end_loop(end, top);
+    }
+
+    fn for_loop(
+        &mut self,
+        span: &Span,
+        init: &Option<Rc<AST>>,
+        cond: &Option<Rc<AST>>,
+        step: &Option<Rc<AST>>,
+        body: &Rc<AST>,
+        layout: &Layout,
+    ) {
+        self.push_scope(layout, span);
+        if let Some(init) = init {
+            self.statement(init);
+        }
+        let top = self.here();
+        let exit = cond.as_ref().map(|cond| {
+            self.expression(cond);
+            self.emit(Op::JumpIfFalse(0, "For condition must be a boolean"), span)
+        });
+        self.begin_loop();
+        self.statement(body);
+        let next = self.here();
+        if let Some(step) = step {
+            self.statement(step);
+        }
+        self.emit(Op::Jump(top), span);
+        let end = self.here();
+        if let Some(exit) = exit {
+            self.patch(exit, end);
+        }
+        self.end_loop(end, next);
+        self.emit(Op::PopScope, span);
+    }
+
+    fn for_each(
+        &mut self,
+        span: &Span,
+        pattern: &Rc<Pattern>,
+        iter: &Rc<AST>,
+        body: &Rc<AST>,
+        layout: &Layout,
+    ) {
+        // The loop variables live in the iteration scope along with the
+        // variables of the body
+        let mode = self.iteration_scope(body);
+        self.expression(iter);
+        self.emit(Op::Iter, span);
+        if mode == IterationScope::Reused {
+            self.push_scope(layout, span);
+        }
+        let next = self.here();
+        if mode == IterationScope::Reused {
+            self.emit(Op::ClearScope, span);
+        }
+        let exit = self.emit(Op::Next(0), span);
+        self.stack -= 1;
+        self.begin_loop();
+        self.stack += 1;
+        if mode == IterationScope::Fresh {
+            self.push_scope(layout, span);
+        }
+        match pattern.as_ref() {
+            Pattern::Binding(span, name) => {
+                let name = self.name(name);
+                self.emit(Op::Declare(name), span);
+            }
+            _ => {
+                self.chunk.patterns.push(pattern.clone());
+                self.emit(Op::Bind(self.chunk.patterns.len() - 1), pattern.span());
+            }
+        }
+        self.loop_body(body);
+        if mode == IterationScope::Fresh {
+            self.emit(Op::PopScope, span);
+        }
+        self.emit(Op::Jump(next), span);
+        let end = self.here();
+        self.patch(exit, end);
+        self.end_loop(end, next);
+        if mode == IterationScope::Reused {
+            self.emit(Op::PopScope, span);
+        }
+        self.emit(Op::Pop, span);
+    }
+}
+
+fn is_assignable(target: &AST) -> bool {
+    matches!(
+        target,
+        AST::Variable(..) | AST::Index(..) | AST::FieldAccess(..)
+    )
+}
@@ -0,0 +1,443 @@
+/*
+   Copyright (C) 2023  Haven Selph
+   Copyright (C) 2023  Mustafa Quraish
+   Check the LICENSE file for more information.
+*/
+
+use crate::ast::AST;
+use crate::common::{Ref, Span};
+use crate::error::{runtime_error as error, Result};
+use crate::interpreter::value::Value;
+use crate::interpreter::{ControlFlow, Interpreter, Scope};
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Takes over whenever a program being debugged pauses, like an interactive
+/// prompt or an editor speaking the Debug Adapter Protocol.
+pub trait DebugClient {
+    /// Called before the statement the program paused at runs, to look
+    /// around with `paused` and decide how the program carries on. Nothing
+    /// run from here, like an expression being evaluated, pauses again.
+    fn paused(&mut self, paused: &mut Paused) -> Resume;
+}
+
+/// Why a program paused.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    /// Before its first statement.
+    Entry,
+    /// At a breakpoint, or a call to `breakpoint()`.
+    Breakpoint,
+    /// After a step.
+    Step,
+}
+
+/// How a paused program carries on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resume {
+    /// Until the next breakpoint.
+    Continue,
+    /// Until the next statement, even one in a function it calls.
+    StepIn,
+    /// Until the next statement of the current function, or of the one it
+    /// returns to.
+    StepOver,
+    /// Until the next statement of the function the current one returns to.
+    StepOut,
+}
+
+/// A function call that hasn't returned yet, or the program itself, as a
+/// paused program's call stack lists it.
+#[derive(Debug, Clone)]
+pub struct StackFrame {
+    pub name: String,
+    /// The statement it's running, or the call it's waiting on.
+    pub span: Span,
+}
+
+// The state of debugging, shared with the interpreters that run imported
+// files
+pub(crate) struct Debugger {
+    // Taken while the program is paused, which keeps what the client runs
+    // from pausing again
+    client: Option<Box<dyn DebugClient>>,
+    breakpoints: HashMap<PathBuf, BTreeSet<usize>>,
+    // Spans name files the way they were opened, so each name is looked up
+    // once to compare it with the breakpoints
+    paths: HashMap<&'static str, PathBuf>,
+    resume: Resume,
+    // How many frames there were when the program last carried on
+    resume_depth: usize,
+    paused_before: bool,
+    // The statement the program paused at while it runs, and its depth.
+    // Breakpoints on its line don't pause again until it's done, so a line
+    // like `if x { y() }` only pauses once.
+    paused_at: Option<(Rc<AST>, usize)>,
+    // The statement the program last paused before and its depth, until
+    // `breakpoint()` is next called. A call on the same line doesn't pause
+    // again, so continuing from a breakpoint on that line doesn't stop twice.
+    paused_line: Option<(Span, usize)>,
+    // The program first, then the calls and imports in progress
+    frames: Vec<DebugFrame>,
+}
+
+struct DebugFrame {
+    name: String,
+    // None until its first statement runs
+    span: Option<Span>,
+    scope: Option<Ref<Scope>>,
+}
+
+impl Debugger {
+    pub fn new(client: Box<dyn DebugClient>, stop_on_entry: bool) -> Debugger {
+        Debugger {
+            client: Some(client),
+            breakpoints: HashMap::new(),
+            paths: HashMap::new(),
+            resume: if stop_on_entry {
+                Resume::StepIn
+            } else {
+                Resume::Continue
+            },
+            resume_depth: 0,
+            paused_before: false,
+            paused_at: None,
+            paused_line: None,
+            frames: vec![DebugFrame {
+                name: "<program>".to_string(),
+                span: None,
+                scope: None,
+            }],
+        }
+    }
+
+    pub fn set_breakpoints(&mut self, path: &Path, lines: &[usize]) {
+        let path = canonical(path);
+        match lines.is_empty() {
+            true => self.breakpoints.remove(&path),
+            false => self
+                .breakpoints
+                .insert(path, lines.iter().copied().collect()),
+        };
+    }
+
+    // Whether to pause before a statement, now that it's the current one
+    fn stop_reason(&mut self, stmt: &Rc<AST>) -> Option<StopReason> {
+        let depth = self.frames.len();
+        let stepped = match self.resume {
+            Resume::Continue => false,
+            Resume::StepIn => true,
+            Resume::StepOver => depth <= self.resume_depth,
+            Resume::StepOut => depth < self.resume_depth,
+        };
+        if stepped {
+            return Some(match self.paused_before {
+                true => StopReason::Step,
+                false => StopReason::Entry,
+            });
+        }
+        if self.breakpoints.is_empty() {
+            return None;
+        }
+
+        let start = stmt.span().0;
+        if let Some((paused_at, paused_depth)) = &self.paused_at {
+            let paused_at = paused_at.span().0;
+            if *paused_depth == depth
+                && paused_at.line == start.line
+                && paused_at.filename == start.filename
+            {
+                return None;
+            }
+        }
+        let path = self
+            .paths
+            .entry(start.filename)
+            .or_insert_with(|| canonical(Path::new(start.filename)));
+        let lines = self.breakpoints.get(path)?;
+        lines
+            .contains(&start.line)
+            .then_some(StopReason::Breakpoint)
+    }
+
+    // Whether the statement running now is on the line the program last
+    // paused before, at the same depth, with no `breakpoint()` call since
+    fn paused_on_this_line(&mut self) -> bool {
+        let Some((paused, depth)) = self.paused_line.take() else {
+            return false;
+        };
+        let current = self.frames.last().and_then(|frame| frame.span);
+        current.is_some_and(|current| {
+            depth == self.frames.len()
+                && paused.0.line == current.0.line
+                && paused.0.filename == current.0.filename
+        })
+    }
+
+    // The scope and position of a frame, counting from the innermost one
+    fn frame(&self, index: usize) -> Option<(Span, Ref<Scope>)> {
+        let frames = self.frames.iter().rev();
+        let frame = frames.filter(|frame| frame.span.is_some()).nth(index)?;
+        Some((frame.span?, frame.scope.clone()?))
+    }
+}
+
+// Breakpoints and spans can name the same file differently, like with a
+// relative and an absolute path
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// A paused program, which a [`DebugClient`] can look around in.
+///
+/// Frames are numbered from the innermost one, where the program paused, so
+/// frame 0 is always the current function.
+pub struct Paused<'a> {
+    interpreter: &'a mut Interpreter,
+    reason: StopReason,
+}
+
+impl Paused<'_> {
+    pub fn reason(&self) -> StopReason {
+        self.reason
+    }
+
+    /// The calls and imports in progress, innermost first, ending with the
+    /// program itself.
+    pub fn frames(&self) -> Vec<StackFrame> {
+        let debugger = self.debugger().borrow();
+        let frames = debugger.frames.iter().rev();
+        frames
+            .filter_map(|frame| {
+                Some(StackFrame {
+                    name: frame.name.clone(),
+                    span: frame.span?,
+                })
+            })
+            .collect()
+    }
+
+    /// The variables a frame can see besides the globals, with their values
+    /// written the way `repr()` does.
+    pub fn locals(&mut self, frame: usize) -> Vec<(String, String)> {
+        let Some((span, scope)) = self.debugger().borrow().frame(frame) else {
+            return vec![];
+        };
+        let mut scopes = vec![];
+        let mut current = Some(scope);
+        while let Some(scope) = current {
+            current = scope.borrow().parent.clone();
+            if current.is_some() {
+                scopes.push(scope);
+            }
+        }
+        self.describe(&span, &scopes)
+    }
+
+    /// The global variables of a frame's file, with their values written the
+    /// way `repr()` does.
+    pub fn globals(&mut self, frame: usize) -> Vec<(String, String)> {
+        let Some((span, mut scope)) = self.debugger().borrow().frame(frame) else {
+            return vec![];
+        };
+        while let Some(parent) = scope.clone().borrow().parent.clone() {
+            scope = parent;
+        }
+        self.describe(&span, &[scope])
+    }
+
+    // The variables of some scopes, leaving out the ones an earlier scope
+    // shadows
+    fn describe(&mut self, span: &Span, scopes: &[Ref<Scope>]) -> Vec<(String, String)> {
+        let mut variables: Vec<(String, Value)> = vec![];
+        for scope in scopes {
+            for (name, value) in scope.borrow().variables() {
+                if !variables.iter().any(|(existing, _)| existing == name) {
+                    variables.push((name.clone(), value.clone()));
+                }
+            }
+        }
+        variables
+            .into_iter()
+            .map(|(name, value)| {
+                let value = match self.interpreter.repr_value(span, &value) {
+                    Ok(value) => value,
+                    Err(err) => format!("<{}>", err.message),
+                };
+                self.interpreter.clear_control_flow();
+                (name, value)
+            })
+            .collect()
+    }
+
+    /// Runs code in a frame's scope, returning the value of its last
+    /// statement written the way `repr()` does. Variables it declares stay
+    /// in the frame.
+    pub fn evaluate(&mut self, frame: usize, source: &str) -> Result<String> {
+        let Some((span, scope)) = self.debugger().borrow().frame(frame) else {
+            error!(self.frames()[0].span, "There is no frame {}", frame)
+        };
+        let mut lexer = crate::lexer::Lexer::new(source.to_string(), "<debug>");
+        let mut parser = crate::parser::Parser::new(lexer.lex()?);
+        let ast = parser
+            .parse()
+            .map_err(|errors| errors.into_iter().next().expect("Parsing failed"))?;
+        // The code isn't resolved, since the resolver only knows the layout of
+        // a whole program, so its variables are looked up by name
+        let result = self
+            .interpreter
+            .run_block_without_new_scope(&ast, scope)
+            .and_then(|value| self.interpreter.repr_value(&span, &value));
+        self.interpreter.clear_control_flow();
+        result
+    }
+
+    /// Replaces the lines of a file the program pauses at. An empty list
+    /// removes them all.
+    pub fn set_breakpoints(&mut self, path: &Path, lines: &[usize]) {
+        self.debugger().borrow_mut().set_breakpoints(path, lines);
+    }
+
+    /// Adds a line of a file to the ones the program pauses at.
+    pub fn add_breakpoint(&mut self, path: &Path, line: usize) {
+        let mut debugger = self.debugger().borrow_mut();
+        let lines = debugger.breakpoints.entry(canonical(path)).or_default();
+        lines.insert(line);
+    }
+
+    /// Removes a line of a file from the ones the program pauses at,
+    /// returning whether it was one.
+    pub fn remove_breakpoint(&mut self, path: &Path, line: usize) -> bool {
+        let mut debugger = self.debugger().borrow_mut();
+        let path = canonical(path);
+        let Some(lines) = debugger.breakpoints.get_mut(&path) else {
+            return false;
+        };
+        let removed = lines.remove(&line);
+        if lines.is_empty() {
+            debugger.breakpoints.remove(&path);
+        }
+        removed
+    }
+
+    /// The lines the program pauses at, by file.
+    pub fn breakpoints(&self) -> Vec<(PathBuf, usize)> {
+        let debugger = self.debugger().borrow();
+        let breakpoints = debugger.breakpoints.iter();
+        let mut breakpoints: Vec<(PathBuf, usize)> = breakpoints
+            .flat_map(|(path, lines)| lines.iter().map(|line| (path.clone(), *line)))
+            .collect();
+        breakpoints.sort();
+        breakpoints
+    }
+
+    fn debugger(&self) -> &Rc<RefCell<Debugger>> {
+        self.interpreter
+            .debugger
+            .as_ref()
+            .expect("Paused without a debugger")
+    }
+}
+
+impl Interpreter {
+    pub fn set_debugger(&mut self, client: Box<dyn DebugClient>, stop_on_entry: bool) {
+        let debugger = Debugger::new(client, stop_on_entry);
+        self.debugger = Some(Rc::new(RefCell::new(debugger)));
+    }
+
+    pub fn set_breakpoints(&mut self, path: &Path, lines: &[usize]) {
+        if let Some(debugger) = &self.debugger {
+            debugger.borrow_mut().set_breakpoints(path, lines);
+        }
+    }
+
+    // Runs a statement, pausing before it if a breakpoint or step says so
+    pub(super) fn run_statement(&mut self, stmt: &Rc<AST>, scope: Ref<Scope>) -> Result<Value> {
+        self.count_line(stmt.span());
+        let Some(debugger) = self.debugger.clone() else {
+            return self.run(stmt, scope);
+        };
+        let reason = {
+            let mut debugger = debugger.borrow_mut();
+            match debugger.client.is_some() {
+                true => {
+                    let frame = debugger.frames.last_mut().expect("No program frame");
+                    frame.span = Some(*stmt.span());
+                    frame.scope = Some(scope.clone());
+                    debugger.stop_reason(stmt)
+                }
+                false => None,
+            }
+        };
+        if let Some(reason) = reason {
+            self.pause(reason, Some(stmt));
+        }
+
+        let result = self.run(stmt, scope);
+        let mut debugger = debugger.borrow_mut();
+        if let Some((paused_at, _)) = &debugger.paused_at {
+            if Rc::ptr_eq(paused_at, stmt) {
+                debugger.paused_at = None;
+            }
+        }
+        result
+    }
+
+    // Hands control to the debug client until it says to carry on
+    pub(super) fn pause(&mut self, reason: StopReason, stmt: Option<&Rc<AST>>) {
+        let Some(debugger) = self.debugger.clone() else {
+            return;
+        };
+        if stmt.is_none() && debugger.borrow_mut().paused_on_this_line() {
+            return;
+        }
+        let Some(mut client) = debugger.borrow_mut().client.take() else {
+            return;
+        };
+        // `breakpoint()` pauses in the middle of an expression
+        let control_flow = std::mem::replace(&mut self.control_flow, ControlFlow::None);
+        let resume = client.paused(&mut Paused {
+            interpreter: self,
+            reason,
+        });
+        self.control_flow = control_flow;
+
+        let mut debugger = debugger.borrow_mut();
+        let depth = debugger.frames.len();
+        debugger.client = Some(client);
+        debugger.resume = resume;
+        debugger.resume_depth = depth;
+        debugger.paused_before = true;
+        debugger.paused_at = stmt.map(|stmt| (stmt.clone(), depth));
+        debugger.paused_line = stmt.map(|stmt| (*stmt.span(), depth));
+    }
+
+    // Adds a frame for a call or import while it runs, with the caller
+    // waiting at `span`
+    pub(super) fn enter_frame(&mut self, name: &str, span: &Span) {
+        if let Some(debugger) = &self.debugger {
+            let mut debugger = debugger.borrow_mut();
+            if debugger.client.is_some() {
+                if let Some(frame) = debugger.frames.last_mut() {
+                    frame.span = Some(*span);
+                }
+                debugger.frames.push(DebugFrame {
+                    name: name.to_string(),
+                    span: None,
+                    scope: None,
+                });
+            }
+        }
+    }
+
+    pub(super) fn exit_frame(&mut self) {
+        if let Some(debugger) = &self.debugger {
+            let mut debugger = debugger.borrow_mut();
+            if debugger.client.is_some() {
+                debugger.frames.pop();
+            }
+        }
+    }
+}
@@ -0,0 +1,423 @@
+/*
+   Copyright (C) 2023  Haven Selph
+   Copyright (C) 2023  Mustafa Quraish
+   Check the LICENSE file for more information.
+*/
+
+use crate::ast::AST;
+use crate::common::{Ref, Span};
+use crate::error::{runtime_error as error, Error, Result};
+use crate::interpreter::value::{IteratorValue, Value, ValueIterator};
+use crate::interpreter::{block_layout, ControlFlow, Interpreter, Scope};
+use std::rc::Rc;
+
+// A generator runs the body of a function containing `yield` one statement at
+// a time. Statements that contain a `yield` are unrolled into frames so the
+// body can be suspended there and resumed on the next call, everything else
+// is handed to the regular interpreter.
+pub struct Generator {
+    frames: Vec<Frame>,
+    // The call that created it, which resuming it counts as
+    span: Span,
+}
+
+enum Frame {
+    Block {
+        block: Rc<AST>,
+        index: usize,
+        scope: Ref<Scope>,
+    },
+    While {
+        ast: Rc<AST>,
+        scope: Ref<Scope>,
+    },
+    For {
+        ast: Rc<AST>,
+        scope: Ref<Scope>,
+        started: bool,
+    },
+    ForEach {
+        ast: Rc<AST>,
+        iter: IteratorValue,
+        scope: Ref<Scope>,
+    },
+    Try {
+        ast: Rc<AST>,
+        scope: Ref<Scope>,
+        in_catch: bool,
+    },
+}
+
+impl Frame {
+    fn is_loop(&self) -> bool {
+        matches!(
+            self,
+            Frame::While { .. } | Frame::For { .. } | Frame::ForEach { .. }
+        )
+    }
+}
+
+impl Generator {
+    pub fn new(body: Rc<AST>, scope: Ref<Scope>, span: Span) -> Generator {
+        Generator {
+            frames: vec![Frame::Block {
+                block: body,
+                index: 0,
+                scope,
+            }],
+            span,
+        }
+    }
+
+    fn resume(&mut self, interpreter: &mut Interpreter) -> Result<Option<Value>> {
+        while !self.frames.is_empty() {
+            match self.step(interpreter) {
+                Ok(Some(value)) => return Ok(Some(value)),
+                Ok(None) => {}
+                Err(err) => self.recover(interpreter, err)?,
+            }
+            self.unwind(interpreter)?;
+        }
+        Ok(None)
+    }
+
+    // Runs the next statement of the innermost frame
+    fn step(&mut self, interpreter: &mut Interpreter) -> Result<Option<Value>> {
+        let frame = match self.frames.last_mut() {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        match frame {
+            Frame::Block {
+                block,
+                index,
+                scope,
+            } => {
+                let stmt = match block.as_ref() {
+                    AST::Block(_, stmts, _) => stmts.get(*index).cloned(),
+                    _ => unreachable!("Block frame without a block"),
+                };
+                *index += 1;
+                let scope = scope.clone();
+                match stmt {
+                    Some(stmt) => self.enter(interpreter, &stmt, scope),
+                    None => {
+                        self.frames.pop();
+                        Ok(None)
+                    }
+                }
+            }
+            Frame::While { ast, scope } => {
+                let (ast, scope) = (ast.clone(), scope.clone());
+                let AST::While(span, cond, body) = ast.as_ref() else {
+                    unreachable!("While frame without a while loop")
+                };
+                match interpreter.run(cond, scope.clone())? {
+                    Value::Boolean(true) => self.enter(interpreter, body, scope),
+                    Value::Boolean(false) => {
+                        self.frames.pop();
+                        Ok(None)
+                    }
+                    _ => error!(span, "While condition must be a boolean"),
+                }
+            }
+            Frame::For {
+                ast,
+                scope,
+                started,
+            } => {
+                let resumed = std::mem::replace(started, true);
+                let (ast, scope) = (ast.clone(), scope.clone());
+                let AST::For {
+                    span,
+                    cond,
+                    step,
+                    body,
+                    ..
+                } = ast.as_ref()
+                else {
+                    unreachable!("For frame without a for loop")
+                };
+                if let (true, Some(step)) = (resumed, step) {
+                    interpreter.run(step, scope.clone())?;
+                }
+                if let Some(cond) = cond {
+                    match interpreter.run(cond, scope.clone())? {
+                        Value::Boolean(true) => {}
+                        Value::Boolean(false) => {
+                            self.frames.pop();
+                            return Ok(None);
+                        }
+                        _ => error!(span, "For condition must be a boolean"),
+                    }
+                }
+                self.enter(interpreter, body, scope)
+            }
+            Frame::ForEach { ast, iter, scope } => {
+                let (ast, iter, scope) = (ast.clone(), iter.clone(), scope.clone());
+                let AST::ForEach(span, var, _, body, layout) = ast.as_ref() else {
+                    unreachable!("ForEach frame without a for loop")
+                };
+                match iter.next(interpreter, span)? {
+                    Some(value) => {
+                        let loop_scope = Scope::with_layout(Some(scope), true, layout.get());
+                        interpreter.bind_pattern(var, value, loop_scope.clone())?;
+                        self.enter_body(interpreter, body, loop_scope)
+                    }
+                    None => {
+                        self.frames.pop();
+                        Ok(None)
+                    }
+                }
+            }
+            Frame::Try { ast, scope, .. } => {
+                // The try or catch block has finished without an error
+                let (ast, scope) = (ast.clone(), scope.clone());
+                self.frames.pop();
+                if let AST::Try {
+                    finally: Some(finally),
+                    ..
+                } = ast.as_ref()
+                {
+                    interpreter.run(finally, scope)?;
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    // Starts running a statement, pushing a frame if it contains a `yield`
+    fn enter(
+        &mut self,
+        interpreter: &mut Interpreter,
+        stmt: &Rc<AST>,
+        scope: Ref<Scope>,
+    ) -> Result<Option<Value>> {
+        if !stmt.contains_yield() {
+            interpreter.run(stmt, scope)?;
+            return Ok(None);
+        }
+        match stmt.as_ref() {
+            AST::Yield(_, expr) => return Ok(Some(interpreter.run(expr, scope)?)),
+            AST::Block(_, _, layout) => {
+                let scope = match layout.get() {
+                    Some(layout) => Scope::with_layout(Some(scope), true, Some(layout)),
+                    None => scope,
+                };
+                self.frames.push(Frame::Block {
+                    block: stmt.clone(),
+                    index: 0,
+                    scope,
+                })
+            }
+            AST::If(span, cond, body, else_body) => {
+                return match interpreter.run(cond, scope.clone())? {
+                    Value::Boolean(true) => self.enter(interpreter, body, scope),
+                    Value::Boolean(false) => match else_body {
+                        Some(else_body) => self.enter(interpreter, else_body, scope),
+                        None => Ok(None),
+                    },
+                    _ => error!(span, "If condition must be a boolean"),
+                }
+            }
+            AST::Match(_, subject, arms) => {
+                let subject = interpreter.run(subject, scope.clone())?;
+                return match interpreter.match_arm(arms, &subject, &scope)? {
+                    Some((body, arm_scope)) => self.enter_body(interpreter, body, arm_scope),
+                    None => Ok(None),
+                };
+            }
+            AST::While(..) => self.frames.push(Frame::While {
+                ast: stmt.clone(),
+                scope,
+            }),
+            AST::For { init, layout, .. } => {
+                let loop_scope = Scope::with_layout(Some(scope), true, layout.get());
+                if let Some(init) = init {
+                    interpreter.run(init, loop_scope.clone())?;
+                }
+                self.frames.push(Frame::For {
+                    ast: stmt.clone(),
+                    scope: loop_scope,
+                    started: false,
+                })
+            }
+            AST::ForEach(span, _, iter, ..) => {
+                let value = interpreter.run(iter, scope.clone())?;
+                let iter = interpreter.iterate(&value, span)?;
+                self.frames.push(Frame::ForEach {
+                    ast: stmt.clone(),
+                    iter,
+                    scope,
+                })
+            }
+            AST::Try {
+                span,
+                body,
+                finally,
+                ..
+            } => {
+                if finally.as_ref().is_some_and(|body| body.contains_yield()) {
+                    error!(span, "Yield is not supported inside a finally block");
+                }
+                self.frames.push(Frame::Try {
+                    ast: stmt.clone(),
+                    scope: scope.clone(),
+                    in_catch: false,
+                });
+                return self.enter(interpreter, body, scope);
+            }
+            _ => error!(stmt.span(), "Yield is not supported inside this statement"),
+        }
+        Ok(None)
+    }
+
+    // Starts running a body that shares the scope it's given, like the body
+    // of a for loop does with the scope of its iteration
+    fn enter_body(
+        &mut self,
+        interpreter: &mut Interpreter,
+        body: &Rc<AST>,
+        scope: Ref<Scope>,
+    ) -> Result<Option<Value>> {
+        match body.as_ref() {
+            AST::Block(..) if body.contains_yield() => self.frames.push(Frame::Block {
+                block: body.clone(),
+                index: 0,
+                scope,
+            }),
+            AST::Block(..) => {
+                interpreter.run_block_without_new_scope(body, scope)?;
+            }
+            _ => return self.enter(interpreter, body, scope),
+        }
+        Ok(None)
+    }
+
+    // Unwinds frames up to the nearest try block that can handle the error
+    fn recover(&mut self, interpreter: &mut Interpreter, err: Error) -> Result<()> {
+        while let Some(frame) = self.frames.pop() {
+            let Frame::Try {
+                ast,
+                scope,
+                in_catch,
+            } = frame
+            else {
+                continue;
+            };
+            let AST::Try {
+                span,
+                catch,
+                finally,
+                ..
+            } = ast.as_ref()
+            else {
+                unreachable!("Try frame without a try block")
+            };
+            if let (false, Some((name, catch_body))) = (in_catch, catch) {
+                let value =
+                    match std::mem::replace(&mut interpreter.control_flow, ControlFlow::None) {
+                        ControlFlow::Throw(value) => value,
+                        _ => Value::Error(Rc::new(err)),
+                    };
+                let catch_scope =
+                    Scope::with_layout(Some(scope.clone()), true, block_layout(catch_body));
+                if let Some(name) = name {
+                    interpreter.check_arg_name(name, span)?;
+                    catch_scope.borrow_mut().insert(name, value, false, span)?;
+                }
+                self.frames.push(Frame::Try {
+                    ast: ast.clone(),
+                    scope,
+                    in_catch: true,
+                });
+                self.frames.push(Frame::Block {
+                    block: catch_body.clone(),
+                    index: 0,
+                    scope: catch_scope,
+                });
+                return Ok(());
+            }
+            if let Some(finally) = finally {
+                let pending = std::mem::replace(&mut interpreter.control_flow, ControlFlow::None);
+                interpreter.run(finally, scope)?;
+                match interpreter.control_flow {
+                    ControlFlow::None => interpreter.control_flow = pending,
+                    // A return, break or continue inside finally wins
+                    _ => return Ok(()),
+                }
+            }
+        }
+        Err(err)
+    }
+
+    // Handles a break, continue or return left behind by the last statement
+    fn unwind(&mut self, interpreter: &mut Interpreter) -> Result<()> {
+        loop {
+            match interpreter.control_flow {
+                ControlFlow::None | ControlFlow::Throw(_) => return Ok(()),
+                ControlFlow::Return(_) => {
+                    // Returning ends the generator, the value is discarded
+                    interpreter.control_flow = ControlFlow::None;
+                    while let Some(frame) = self.frames.pop() {
+                        self.run_finally(interpreter, frame)?;
+                    }
+                    interpreter.control_flow = ControlFlow::None;
+                    return Ok(());
+                }
+                ControlFlow::Break | ControlFlow::Continue => {
+                    let is_break = matches!(interpreter.control_flow, ControlFlow::Break);
+                    interpreter.control_flow = ControlFlow::None;
+                    while let Some(frame) = self.frames.pop() {
+                        if frame.is_loop() {
+                            if !is_break {
+                                self.frames.push(frame);
+                            }
+                            break;
+                        }
+                        self.run_finally(interpreter, frame)?;
+                        if !matches!(interpreter.control_flow, ControlFlow::None) {
+                            // The finally block replaced the break or continue
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn run_finally(&mut self, interpreter: &mut Interpreter, frame: Frame) -> Result<()> {
+        if let Frame::Try { ast, scope, .. } = frame {
+            if let AST::Try {
+                finally: Some(finally),
+                ..
+            } = ast.as_ref()
+            {
+                let pending = std::mem::replace(&mut interpreter.control_flow, ControlFlow::None);
+                interpreter.run(finally, scope)?;
+                if let ControlFlow::None = interpreter.control_flow {
+                    interpreter.control_flow = pending;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ValueIterator for Generator {
+    fn next(&mut self, interpreter: &mut Interpreter) -> Result<Option<Value>> {
+        // The body runs on the Rust stack like a call's, so a generator that
+        // iterates over itself must not recurse without limit
+        interpreter.enter_call(&self.span)?;
+        let result = self.resume(interpreter);
+        interpreter.exit_call();
+        match result {
+            Ok(value) => Ok(value),
+            Err(err) => {
+                // A generator that raised an error is finished
+                self.frames.clear();
+                Err(err)
+            }
+        }
+    }
+}
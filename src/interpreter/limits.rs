@@ -0,0 +1,171 @@
+/*
+   Copyright (C) 2023  Haven Selph
+   Copyright (C) 2023  Mustafa Quraish
+   Check the LICENSE file for more information.
+*/
+
+use crate::common::Span;
+use crate::error::{limit_error as error, Result};
+use crate::interpreter::value::Value;
+use crate::interpreter::Interpreter;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// Limits on how much work a script can do. Going over one fails with an
+/// error of kind `ErrorKind::Limit` at the code that went over it.
+///
+/// Only the call depth is limited by default, because running out of stack
+/// aborts the whole process. The default of 1000 calls needs about 16 MB of
+/// stack in release builds and much more in debug builds, so a host with a
+/// smaller stack, like that of a spawned thread, should lower it or run the
+/// engine on a thread with a bigger stack.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// How many expressions, statements and VM instructions a run may
+    /// evaluate.
+    pub max_steps: Option<u64>,
+    /// How many calls may be in progress at once.
+    pub max_call_depth: Option<usize>,
+    /// How long a run may take.
+    pub timeout: Option<Duration>,
+    /// How many items an array, tuple, dict or set, or how many bytes a
+    /// string, may grow to.
+    pub max_collection_size: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_steps: None,
+            max_call_depth: Some(1000),
+            timeout: None,
+            max_collection_size: None,
+        }
+    }
+}
+
+// What a run has used of its limits, shared with the interpreters of the
+// files it imports
+#[derive(Default)]
+pub(crate) struct Budget {
+    pub limits: Limits,
+    steps: Cell<u64>,
+    depth: Cell<usize>,
+    deadline: Cell<Option<Instant>>,
+    // Once the time is up every step fails, like every step past the step
+    // limit does, so catching the error can't keep a script running
+    timed_out: Cell<bool>,
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Budget {
+        Budget {
+            limits,
+            ..Budget::default()
+        }
+    }
+}
+
+// Checking the clock is slow compared to a step, so it only happens this often
+const STEPS_PER_CLOCK_CHECK: u64 = 1024;
+
+impl Interpreter {
+    // Starts a run from the host with none of the limits used up
+    pub(crate) fn start_run(&mut self) {
+        self.budget.steps.set(0);
+        self.budget.depth.set(0);
+        self.budget.timed_out.set(false);
+        let timeout = self.budget.limits.timeout;
+        self.budget
+            .deadline
+            .set(timeout.map(|timeout| Instant::now() + timeout));
+    }
+
+    // Counts one step of evaluation
+    pub(super) fn step(&self, span: &Span) -> Result<()> {
+        let steps = self.budget.steps.get() + 1;
+        self.budget.steps.set(steps);
+        if let Some(max) = self.budget.limits.max_steps {
+            if steps > max {
+                error!(span, "Exceeded the limit of {} steps", max)
+            }
+        }
+        if steps.is_multiple_of(STEPS_PER_CLOCK_CHECK) {
+            if let Some(deadline) = self.budget.deadline.get() {
+                if Instant::now() > deadline {
+                    self.budget.timed_out.set(true);
+                }
+            }
+        }
+        if self.budget.timed_out.get() {
+            let timeout = self.budget.limits.timeout.unwrap_or_default();
+            error!(span, "Exceeded the time limit of {:?}", timeout)
+        }
+        Ok(())
+    }
+
+    pub(super) fn enter_call(&self, span: &Span) -> Result<()> {
+        let depth = self.budget.depth.get();
+        if let Some(max) = self.budget.limits.max_call_depth {
+            if depth >= max {
+                error!(span, "Exceeded the maximum call depth of {}", max)
+            }
+        }
+        self.budget.depth.set(depth + 1);
+        Ok(())
+    }
+
+    pub(super) fn exit_call(&self) {
+        self.budget.depth.set(self.budget.depth.get() - 1);
+    }
+
+    // Checks the size a collection or string has grown to
+    pub(super) fn check_size(&self, span: &Span, size: usize) -> Result<()> {
+        if let Some(max) = self.budget.limits.max_collection_size {
+            if size > max {
+                error!(span, "Exceeded the maximum collection size of {}", max)
+            }
+        }
+        Ok(())
+    }
+
+    // Assigning to an index can grow a dict past the limit
+    pub(super) fn set_index(
+        &self,
+        span: &Span,
+        container: &Value,
+        index: &Value,
+        value: &Value,
+    ) -> Result<()> {
+        container.set_index(index, value, span)?;
+        if let Value::Dict(dict) = container {
+            self.check_size(span, dict.borrow().len())?;
+        }
+        Ok(())
+    }
+
+    // Adding strings or arrays, or repeating a string, is checked before the
+    // result is built, so it can't use up all the memory first
+    pub(super) fn check_operands(
+        &self,
+        span: &Span,
+        method: &str,
+        left: &Value,
+        right: &Value,
+    ) -> Result<()> {
+        if self.budget.limits.max_collection_size.is_none() {
+            return Ok(());
+        }
+        let size = match (method, left, right) {
+            ("__add__", Value::String(left), Value::String(right)) => left.len() + right.len(),
+            ("__add__", Value::Array(left), Value::Array(right)) => {
+                left.borrow().len() + right.borrow().len()
+            }
+            ("__mul__", Value::String(string), Value::Integer(count)) => {
+                string.len().saturating_mul((*count).max(0) as usize)
+            }
+            _ => return Ok(()),
+        };
+        self.check_size(span, size)
+    }
+}
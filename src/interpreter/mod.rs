@@ -5,59 +5,200 @@
  */
 
 use crate::ast::ArgumentType::Keyword;
-use crate::ast::{ArgumentType, CallArgs, AST};
+use crate::ast::{import_name, ArgumentType, CallArgs, MatchArm, Pattern, Slot, AST};
 use crate::common::{make, Ref, Span};
-use crate::error::{runtime_error as error, Result};
+use crate::error::{runtime_error as error, thrown_error, Error, ErrorKind, Frame, Result};
+use crate::interpreter::debugger::Debugger;
+use crate::interpreter::generator::Generator;
+use crate::interpreter::limits::{Budget, Limits};
+use crate::interpreter::native::Registry;
+use crate::interpreter::profiler::Profiler;
+use crate::interpreter::sandbox::Sandbox;
 use crate::interpreter::value::{
-    builtin, CallArgValues, Class, ClassInstance, Function, IteratorValue, Value,
+    CallArgValues, Class, ClassInstance, Enum, EnumVariant, Function, IteratorValue, Value,
 };
-use std::collections::HashMap;
-use std::io::Read;
+use crate::interpreter::vm::ChunkCache;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
 use std::ops::Deref;
 use std::rc::Rc;
 
 mod builtin;
+mod compiler;
+pub mod debugger;
+mod generator;
+pub mod limits;
+pub mod native;
+pub mod profiler;
 mod random;
+pub mod sandbox;
 pub mod value;
+mod vm;
 
-#[derive(Debug)]
 pub struct Scope {
-    pub vars: HashMap<String, Value>,
+    // The names of the variables by slot, shared by all the scopes created
+    // from the same layout until one declares a name the resolver didn't see
+    names: Rc<Vec<String>>,
+    // None until the variable is declared
+    values: Vec<Option<Value>>,
     pub parent: Option<Ref<Scope>>,
     pub in_function: bool,
 }
 
 impl Scope {
     pub fn new(parent: Option<Ref<Scope>>, in_function: bool) -> Ref<Scope> {
+        Scope::with_layout(parent, in_function, None)
+    }
+
+    // A scope with a slot for each variable of a layout from the resolver
+    pub fn with_layout(
+        parent: Option<Ref<Scope>>,
+        in_function: bool,
+        layout: Option<&Rc<Vec<String>>>,
+    ) -> Ref<Scope> {
+        let names = layout.cloned().unwrap_or_default();
         make!(Scope {
-            vars: HashMap::new(),
+            values: vec![None; names.len()],
+            names,
             parent,
             in_function,
         })
     }
 
+    fn slot(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|existing| existing == name)
+    }
+
+    // Names the resolver didn't know about, like the ones `from x import *`
+    // brings in, get a new slot at the end
+    pub(crate) fn declare(&mut self, name: &str, value: Value) {
+        match self.slot(name) {
+            Some(index) => self.values[index] = Some(value),
+            None => {
+                Rc::make_mut(&mut self.names).push(name.to_string());
+                self.values.push(Some(value));
+            }
+        }
+    }
+
     fn insert(&mut self, name: &str, value: Value, update: bool, loc: &Span) -> Result<()> {
-        if !update || self.vars.contains_key(name) {
-            self.vars.insert(name.to_string(), value);
-        } else {
-            match &self.parent {
+        if !update {
+            self.declare(name, value);
+            return Ok(());
+        }
+        match self.slot(name) {
+            Some(index) if self.values[index].is_some() => self.values[index] = Some(value),
+            _ => match &self.parent {
                 Some(parent) => parent.borrow_mut().insert(name, value, update, loc)?,
                 None => error!(loc, "Variable {} not found, couldn't update", name),
-            }
+            },
         }
         Ok(())
     }
 
-    fn get(&self, name: &str) -> Option<Value> {
-        if self.vars.contains_key(name) {
-            self.vars.get(name).cloned()
-        } else {
-            match &self.parent {
-                Some(parent) => parent.borrow().get(name),
-                None => None,
+    pub(crate) fn get(&self, name: &str) -> Option<Value> {
+        match self.slot(name).and_then(|index| self.values[index].clone()) {
+            Some(value) => Some(value),
+            None => self.parent.as_ref()?.borrow().get(name),
+        }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.slot(name)
+            .is_some_and(|index| self.values[index].is_some())
+    }
+
+    // Reads the variable in a slot found by the resolver, or None if it hasn't
+    // been declared yet
+    fn load(&self, slot: Slot, name: &str) -> Option<Value> {
+        if slot.depth > 0 {
+            let slot = Slot {
+                depth: slot.depth - 1,
+                ..slot
+            };
+            return self.parent.as_ref()?.borrow().load(slot, name);
+        }
+        debug_assert_eq!(self.names.get(slot.index).map(String::as_str), Some(name));
+        self.values.get(slot.index)?.clone()
+    }
+
+    // Updates the variable in a slot found by the resolver, handing the value
+    // back if it hasn't been declared yet
+    fn store(&mut self, slot: Slot, name: &str, value: Value) -> Option<Value> {
+        if slot.depth > 0 {
+            let slot = Slot {
+                depth: slot.depth - 1,
+                ..slot
+            };
+            return match &self.parent {
+                Some(parent) => parent.borrow_mut().store(slot, name, value),
+                None => Some(value),
+            };
+        }
+        debug_assert_eq!(self.names.get(slot.index).map(String::as_str), Some(name));
+        match self.values.get_mut(slot.index) {
+            Some(current @ Some(_)) => {
+                *current = Some(value);
+                None
+            }
+            _ => Some(value),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.values.iter_mut().for_each(|value| *value = None);
+    }
+
+    // Makes room for the variables of a layout that extends this scope's,
+    // like the next input run in the global scope of the REPL
+    pub fn extend(&mut self, layout: Option<&Rc<Vec<String>>>) {
+        for name in layout.into_iter().flat_map(|names| names.iter()) {
+            if self.slot(name).is_none() {
+                Rc::make_mut(&mut self.names).push(name.clone());
+                self.values.push(None);
             }
         }
     }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    // The declared variables of this scope and the scopes around it
+    fn visible_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.variables().map(|(name, _)| name.clone()).collect();
+        if let Some(parent) = &self.parent {
+            names.extend(parent.borrow().visible_names());
+        }
+        names
+    }
+
+    // The variables that have been declared, in slot order
+    pub fn variables(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.names
+            .iter()
+            .zip(&self.values)
+            .filter_map(|(name, value)| Some((name, value.as_ref()?)))
+    }
+}
+
+impl std::fmt::Debug for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Scope")
+            .field("vars", &self.variables().collect::<HashMap<_, _>>())
+            .field("parent", &self.parent)
+            .field("in_function", &self.in_function)
+            .finish()
+    }
+}
+
+// The layout of a block the resolver has seen
+fn block_layout(ast: &AST) -> Option<&Rc<Vec<String>>> {
+    match ast {
+        AST::Block(_, _, layout) => layout.get(),
+        _ => None,
+    }
 }
 
 #[derive(Debug)]
@@ -66,28 +207,120 @@ enum ControlFlow {
     Continue,
     Break,
     Return(Value),
+    Throw(Value),
 }
 
 pub struct Interpreter {
     control_flow: ControlFlow,
+    // Compiled function bodies and programs, keyed by their AST. None unless
+    // the bytecode VM is enabled.
+    chunks: Option<ChunkCache>,
+    // Shared with the interpreters that run imported files
+    registry: Rc<Registry>,
+    sandbox: Rc<Sandbox>,
+    budget: Rc<Budget>,
+    // Where `print()` and the like write to
+    output: Rc<RefCell<dyn Write>>,
+    // None unless a debugger is attached
+    debugger: Option<Rc<RefCell<Debugger>>>,
+    // None unless the program is being profiled
+    profiler: Option<Rc<RefCell<Profiler>>>,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
         Self {
             control_flow: ControlFlow::None,
+            chunks: None,
+            registry: Rc::new(Registry::new()),
+            sandbox: Rc::new(Sandbox::default()),
+            budget: Rc::new(Budget::new(Limits::default())),
+            output: Rc::new(RefCell::new(std::io::stdout())),
+            debugger: None,
+            profiler: None,
+        }
+    }
+
+    // An interpreter for an imported file, with the same builtins and
+    // restrictions as this one
+    fn for_import(&self) -> Self {
+        Self {
+            control_flow: ControlFlow::None,
+            chunks: self.chunks.as_ref().map(|_| HashMap::new()),
+            registry: self.registry.clone(),
+            sandbox: self.sandbox.clone(),
+            budget: self.budget.clone(),
+            output: self.output.clone(),
+            debugger: self.debugger.clone(),
+            profiler: self.profiler.clone(),
+        }
+    }
+
+    pub fn set_sandbox(&mut self, sandbox: Sandbox) {
+        self.sandbox = Rc::new(sandbox);
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.budget = Rc::new(Budget::new(limits));
+    }
+
+    pub fn set_output(&mut self, output: Rc<RefCell<dyn Write>>) {
+        self.output = output;
+    }
+
+    // Writes what a script prints
+    fn write_output(&self, span: &Span, text: &str) -> Result<()> {
+        let mut output = self.output.borrow_mut();
+        let result = output.write_all(text.as_bytes());
+        if let Err(err) = result.and_then(|_| output.flush()) {
+            error!(span, "Couldn't write output: {}", err)
         }
+        Ok(())
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    // Registering copies the registry if an import is still using it
+    pub fn registry_mut(&mut self) -> &mut Registry {
+        Rc::make_mut(&mut self.registry)
+    }
+
+    // Runs programs and function bodies on the bytecode VM instead of
+    // walking the AST
+    pub fn enable_vm(&mut self) {
+        self.chunks.get_or_insert_with(HashMap::new);
+    }
+
+    // Clears any pending control flow, e.g. a thrown value left behind by an
+    // uncaught exception in the REPL.
+    pub fn clear_control_flow(&mut self) {
+        self.control_flow = ControlFlow::None;
     }
 
     pub fn run_and_return_scope(&mut self, ast: &Rc<AST>) -> Result<Ref<Scope>> {
-        let scope = Scope::new(None, false);
-        self.run_block_without_new_scope(ast, scope.clone())?;
+        let scope = Scope::with_layout(None, false, block_layout(ast));
+        self.run_body(ast, scope.clone())?;
         Ok(scope)
     }
 
-    pub fn execute(&mut self, ast: &Rc<AST>) -> Result<Value> {
-        let scope = Scope::new(None, false);
-        self.run(ast, scope)
+    // Runs a program or function body directly in the given scope. Only the
+    // tree walker can pause for the debugger.
+    pub(crate) fn run_body(&mut self, body: &Rc<AST>, scope: Ref<Scope>) -> Result<Value> {
+        if self.chunks.is_some() && self.debugger.is_none() {
+            return self.run_compiled(body, scope);
+        }
+        self.run_in_scope(body, scope)
+    }
+
+    // Runs a body that shares the scope it's given, where a block would
+    // otherwise create a scope of its own
+    fn run_in_scope(&mut self, body: &Rc<AST>, scope: Ref<Scope>) -> Result<Value> {
+        match body.as_ref() {
+            AST::Block(..) => self.run_block_without_new_scope(body, scope),
+            _ => self.run_statement(body, scope),
+        }
     }
 
     pub fn run_block_without_new_scope(
@@ -96,10 +329,10 @@ impl Interpreter {
         scope: Ref<Scope>,
     ) -> Result<Value> {
         match ast.as_ref() {
-            AST::Block(_, stmts) => {
+            AST::Block(_, stmts, _) => {
                 let mut last = None;
                 for stmt in stmts {
-                    last = Some(self.run(stmt, scope.clone())?);
+                    last = Some(self.run_statement(stmt, scope.clone())?);
                     match self.control_flow {
                         ControlFlow::None => {}
                         _ => break,
@@ -112,31 +345,71 @@ impl Interpreter {
     }
 
     pub fn run_file(&mut self, span: &Span, path: &str) -> Result<Ref<Scope>> {
-        // Open file and read contents
-        let mut file = std::fs::File::open(path).expect("File not found");
-        let mut contents = String::new();
-        match file.read_to_string(&mut contents) {
-            Ok(_) => {}
-            Err(_) => error!(span, "Failed to read file"),
-        }
+        self.import_file(span, path).map_err(|mut err| {
+            err.push_frame(Frame {
+                name: path.to_string(),
+                span: *span,
+                import: true,
+            });
+            err
+        })
+    }
+
+    fn import_file(&mut self, span: &Span, path: &str) -> Result<Ref<Scope>> {
+        self.sandbox.check_import(span, path)?;
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => error!(span, "Couldn't import {}: {}", path, err),
+        };
 
         let mut lexer =
             crate::lexer::Lexer::new(contents, Box::leak(path.to_string().into_boxed_str()));
         let tokens = lexer.lex()?;
 
         let mut parser = crate::parser::Parser::new(tokens);
-        let ast = parser.parse()?;
+        // Only the first syntax error of an imported file is reported
+        let ast = match parser.parse() {
+            Ok(ast) => ast,
+            Err(errors) => return Err(errors.into_iter().next().expect("Parsing failed")),
+        };
+        crate::resolver::resolve(&ast, &[], &self.registry)?;
 
-        let mut interpreter = Interpreter::new();
-        interpreter.run_and_return_scope(&ast)
+        let mut interpreter = self.for_import();
+        self.enter_frame(path, span);
+        self.enter_profile(path);
+        let result = interpreter.run_and_return_scope(&ast);
+        self.exit_profile();
+        self.exit_frame();
+        if result.is_err() {
+            // Keep the thrown value so it can be caught across the import
+            self.control_flow = interpreter.control_flow;
+        }
+        result
     }
 
+    #[allow(clippy::mutable_key_type)]
     fn run(&mut self, ast: &Rc<AST>, scope: Ref<Scope>) -> Result<Value> {
+        // Operators call the matching special method (e.g. `__add__`) when the
+        // left operand is a class instance that defines it
         macro_rules! dispatch_op {
-            ($span:expr, $op:path, $left:expr, $right:expr) => {{
+            ($span:expr, $op:path, $method:literal, $left:expr, $right:expr) => {{
                 let left = self.run($left, scope.clone())?;
                 let right = self.run($right, scope.clone())?;
-                $op(&left, &right, $span)?
+                match self.call_operator_method($span, $method, &left, &right)? {
+                    Some(value) => value,
+                    None => {
+                        self.check_operands($span, $method, &left, &right)?;
+                        $op(&left, &right, $span)?
+                    }
+                }
+            }};
+
+            ($span:expr, $op:path, $method:literal, $val:expr) => {{
+                let val = self.run($val, scope.clone())?;
+                match self.call_special_method($span, &val, $method, vec![])? {
+                    Some(value) => value,
+                    None => $op(&val, $span)?,
+                }
             }};
 
             ($span:expr, $op:path, $val:expr) => {{
@@ -144,6 +417,7 @@ impl Interpreter {
                 $op(&val, $span)?
             }};
         }
+        self.step(ast.span())?;
         Ok(match ast.as_ref() {
             // Literals
             AST::BooleanLiteral(_, value) => Value::Boolean(*value),
@@ -152,13 +426,23 @@ impl Interpreter {
             AST::StringLiteral(_, string) => Value::String(Rc::new(string.clone())),
             AST::Nothing(_) => Value::Nothing,
 
-            AST::Plus(span, left, right) => dispatch_op!(span, Value::plus, left, right),
-            AST::Minus(span, left, right) => dispatch_op!(span, Value::minus, left, right),
-            AST::Multiply(span, left, right) => dispatch_op!(span, Value::multiply, left, right),
-            AST::Power(span, left, right) => dispatch_op!(span, Value::power, left, right),
-            AST::Divide(span, left, right) => dispatch_op!(span, Value::divide, left, right),
-            AST::Modulo(span, left, right) => dispatch_op!(span, Value::modulo, left, right),
-            AST::Negate(span, expr) => dispatch_op!(span, Value::negate, expr),
+            AST::Plus(span, left, right) => dispatch_op!(span, Value::plus, "__add__", left, right),
+            AST::Minus(span, left, right) => {
+                dispatch_op!(span, Value::minus, "__sub__", left, right)
+            }
+            AST::Multiply(span, left, right) => {
+                dispatch_op!(span, Value::multiply, "__mul__", left, right)
+            }
+            AST::Power(span, left, right) => {
+                dispatch_op!(span, Value::power, "__pow__", left, right)
+            }
+            AST::Divide(span, left, right) => {
+                dispatch_op!(span, Value::divide, "__div__", left, right)
+            }
+            AST::Modulo(span, left, right) => {
+                dispatch_op!(span, Value::modulo, "__mod__", left, right)
+            }
+            AST::Negate(span, expr) => dispatch_op!(span, Value::negate, "__neg__", expr),
             AST::Not(span, expr) => dispatch_op!(span, Value::not, expr),
             AST::And(_, _left, _right) => {
                 // Short circuiting
@@ -192,103 +476,86 @@ impl Interpreter {
                     _ => error!(_left.span(), "Expected boolean, but got {}", left.type_of()),
                 }
             }
-            AST::In(span, left, right) => dispatch_op!(span, Value::contains, right, left),
+            AST::In(span, left, right) => {
+                let container = self.run(right, scope.clone())?;
+                let item = self.run(left, scope)?;
+                self.contains(span, &container, &item)?
+            }
 
-            AST::Equals(span, left, right) => dispatch_op!(span, Value::equals, left, right),
-            AST::NotEquals(span, left, right) => dispatch_op!(span, Value::not_equals, left, right),
-            AST::LessThan(span, left, right) => dispatch_op!(span, Value::less_than, left, right),
+            AST::Equals(span, left, right) => {
+                dispatch_op!(span, Value::equals, "__eq__", left, right)
+            }
+            AST::NotEquals(span, left, right) => {
+                let left = self.run(left, scope.clone())?;
+                let right = self.run(right, scope.clone())?;
+                self.not_equals(span, &left, &right)?
+            }
+            AST::LessThan(span, left, right) => {
+                dispatch_op!(span, Value::less_than, "__lt__", left, right)
+            }
 
             AST::GreaterThan(span, left, right) => {
-                dispatch_op!(span, Value::greater_than, left, right)
+                dispatch_op!(span, Value::greater_than, "__gt__", left, right)
             }
             AST::LessEquals(span, left, right) => {
-                dispatch_op!(span, Value::less_equals, left, right)
+                dispatch_op!(span, Value::less_equals, "__le__", left, right)
             }
             AST::GreaterEquals(span, left, right) => {
-                dispatch_op!(span, Value::greater_equals, left, right)
+                dispatch_op!(span, Value::greater_equals, "__ge__", left, right)
             }
 
             AST::Call(span, func, args) => self.handle_call(scope, span, func, args)?,
 
-            AST::Function {
-                span,
-                name,
-                args,
-                required,
-                is_static,
-                in_class,
-                body,
-            } => {
-                let func = Value::Function(make!(Function {
-                    span: *span,
-                    name: name.clone().unwrap_or_else(|| "<anon>".to_string()),
-                    args: args
-                        .iter()
-                        .map(|(name, default, argtype)| (
-                            name.clone(),
-                            default
-                                .as_ref()
-                                .map(|def| self.run(def, scope.clone()).unwrap()),
-                            *argtype,
-                        ))
-                        .collect(),
-                    required: *required,
-                    class_method: (*in_class && !*is_static),
-                    body: body.clone(),
-                    scope: scope.clone()
-                }));
-                match name {
-                    Some(name) => scope.borrow_mut().insert(name, func.clone(), false, span)?,
-                    None => {}
+            AST::Function { span, name, .. } => {
+                let func = self.make_function(ast, &scope)?;
+                if let Some(name) = name {
+                    scope.borrow_mut().insert(name, func.clone(), false, span)?
                 }
                 func
             }
-            AST::FieldAccess(span, obj, field) => {
-                let obj = self.run(obj, scope)?;
-                obj.get_field(span, field)?
-            }
+            AST::FieldAccess(span, obj, field) => match obj.as_ref() {
+                AST::Super(_, class) => self.resolve_super(span, scope, class, field)?.1,
+                _ => {
+                    let obj = self.run(obj, scope)?;
+                    self.get_field(span, &obj, field)?
+                }
+            },
             AST::Class {
                 span,
                 name,
                 fields,
                 parents,
+                layout,
             } => {
                 let mut static_fields: HashMap<String, Value> = HashMap::new();
                 let mut instance_fields: HashMap<String, Value> = HashMap::new();
-                let parents: Option<Vec<Value>> = match parents {
-                    Some(parents) => {
-                        let mut parent_vals: Vec<Value> = Vec::new();
-                        for parent in parents {
-                            let parent_val = scope.borrow().get(parent.as_str());
-                            let class = match parent_val.clone() {
-                                Some(Value::Class(class)) => class,
-                                Some(val) => {
-                                    error!(span, "Parent class `{}` is not a class", val.type_of());
-                                }
-                                None => {
-                                    error!(span, "Parent class `{}` does not exist", parent);
-                                }
-                            };
-                            let class = class.borrow();
-                            let Class {
-                                span: _,
-                                name: _,
-                                parents: _,
-                                static_fields: parent_static_fields,
-                                fields: parent_fields,
-                            } = class.deref();
-
-                            static_fields.extend(parent_static_fields.borrow().clone()); // Handle static fields
-                            instance_fields.extend(parent_fields.clone()); // Handle instance fields
-                            parent_vals.push(parent_val.unwrap());
+                let mut parent_classes: Vec<Ref<Class>> = Vec::new();
+                for parent in parents.iter().flatten() {
+                    let class = match scope.borrow().get(parent.as_str()) {
+                        Some(Value::Class(class)) => class,
+                        Some(val) => {
+                            error!(span, "Parent class `{}` is not a class", val.type_of());
                         }
-                        Some(parent_vals)
-                    }
-                    None => None,
+                        None => {
+                            error!(span, "Parent class `{}` does not exist", parent);
+                        }
+                    };
+                    parent_classes.push(class);
+                }
+                // Inherited fields are found through the MRO, not copied
+                let Some(mro) = Class::linearize(&parent_classes) else {
+                    error!(
+                        span,
+                        "Cannot create a consistent method resolution order for class '{}'", name
+                    );
                 };
 
+                // Fields are defined in their own scope so they don't leak into the
+                // enclosing one. Nested classes stay visible to the methods, but the
+                // methods themselves aren't declared so they can't shadow builtins.
+                let body_scope = Scope::with_layout(Some(scope.clone()), false, layout.get());
                 for (name, (val, is_static)) in fields.iter() {
-                    let val = self.run(val, scope.clone())?;
+                    let val = self.run_field(val, &body_scope)?;
                     if *is_static {
                         static_fields.insert(name.to_string(), val);
                     } else {
@@ -299,7 +566,7 @@ impl Interpreter {
                 let class = Value::Class(make!(Class {
                     span: *span,
                     name: name.clone(),
-                    parents,
+                    mro,
                     static_fields: make!(static_fields),
                     fields: instance_fields,
                 }));
@@ -309,6 +576,39 @@ impl Interpreter {
                     .insert(name, class.clone(), false, span)?;
                 class
             }
+            AST::Enum {
+                span,
+                name,
+                variants,
+                fields,
+                layout,
+            } => {
+                let mut static_fields: HashMap<String, Value> = HashMap::new();
+                let mut instance_fields: HashMap<String, Value> = HashMap::new();
+                // Same as for classes, see above
+                let body_scope = Scope::with_layout(Some(scope.clone()), false, layout.get());
+                for (name, (val, is_static)) in fields.iter() {
+                    let val = self.run_field(val, &body_scope)?;
+                    if *is_static {
+                        static_fields.insert(name.to_string(), val);
+                    } else {
+                        instance_fields.insert(name.to_string(), val);
+                    }
+                }
+
+                let enum_ = Value::Enum(make!(Enum {
+                    span: *span,
+                    name: name.clone(),
+                    variants: variants.clone(),
+                    static_fields: make!(static_fields),
+                    fields: instance_fields,
+                }));
+
+                scope
+                    .borrow_mut()
+                    .insert(name, enum_.clone(), false, span)?;
+                enum_
+            }
             AST::Slice {
                 span,
                 lhs,
@@ -332,13 +632,20 @@ impl Interpreter {
                 lhs.slice(start, end, step, span)?
             }
 
-            AST::Block(..) => {
-                let block_scope = Scope::new(Some(scope.clone()), scope.borrow().in_function);
-                self.run_block_without_new_scope(ast, block_scope)?
-            }
+            // Blocks that don't declare anything run in the enclosing scope
+            AST::Block(_, _, layout) => match layout.get() {
+                Some(layout) => {
+                    let in_function = scope.borrow().in_function;
+                    let block_scope = Scope::with_layout(Some(scope), in_function, Some(layout));
+                    self.run_block_without_new_scope(ast, block_scope)?
+                }
+                None => self.run_block_without_new_scope(ast, scope)?,
+            },
             AST::Namespace { span, name, body } => {
                 // Create a new scope for the namespace
-                let namespace_scope = Scope::new(Some(scope.clone()), scope.borrow().in_function);
+                let in_function = scope.borrow().in_function;
+                let namespace_scope =
+                    Scope::with_layout(Some(scope.clone()), in_function, block_layout(body));
 
                 // Run the namespace body
                 self.run_block_without_new_scope(body, namespace_scope.clone())?;
@@ -352,23 +659,9 @@ impl Interpreter {
                 // Return nothing, namespaces are not an expression
                 Value::Nothing
             }
-            AST::Variable(span, name) => match name.as_str() {
-                "len" => builtin!(len),
-                "print" => builtin!(print),
-                "input" => builtin!(input),
-                "str" => builtin!(to_str),
-                "repr" => builtin!(repr),
-                "open" => builtin!(file_open),
-                "exit" => builtin!(exit),
-                "dump" => builtin!(dump),
-                "new_random_state" => builtin!(new_random_state),
-                _ => match scope.borrow().get(name) {
-                    Some(val) => val,
-                    None => {
-                        error!(span, "Variable '{}' not found", name);
-                    }
-                },
-            },
+            AST::Variable(span, name, slot) => {
+                self.load_variable(&scope, span, name, slot.get())?
+            }
             AST::Return(span, val) => {
                 if !scope.borrow().in_function {
                     error!(span, "Return statement outside of function")
@@ -387,6 +680,59 @@ impl Interpreter {
                 scope.borrow_mut().insert(name, value, false, span)?;
                 Value::Nothing
             }
+            AST::Destructure(_, pattern, value) => {
+                let value = self.run(value, scope.clone())?;
+                self.bind_pattern(pattern, value, scope)?;
+                Value::Nothing
+            }
+            AST::Yield(span, _) => error!(span, "Yield statement outside of function"),
+            AST::Super(span, _) => error!(span, "Expected a field access after `super`"),
+            AST::Throw(span, expr) => {
+                let value = self.run(expr, scope)?;
+                if let Value::Error(error) = value {
+                    // Re-throwing a caught error keeps its original kind and location
+                    return Err(error.deref().clone());
+                }
+                let message = format!("{:?}", value);
+                self.control_flow = ControlFlow::Throw(value);
+                thrown_error!(span, "Uncaught exception: {}", message)
+            }
+            AST::Try {
+                span,
+                body,
+                catch,
+                finally,
+            } => {
+                let mut result = self.run(body, scope.clone());
+                if let (Err(error), Some((name, catch_body))) = (&result, catch) {
+                    let value = match std::mem::replace(&mut self.control_flow, ControlFlow::None) {
+                        ControlFlow::Throw(value) => value,
+                        _ => Value::Error(Rc::new(error.clone())),
+                    };
+                    let in_function = scope.borrow().in_function;
+                    let catch_scope = Scope::with_layout(
+                        Some(scope.clone()),
+                        in_function,
+                        block_layout(catch_body),
+                    );
+                    if let Some(name) = name {
+                        self.check_arg_name(name, span)?;
+                        catch_scope.borrow_mut().insert(name, value, false, span)?;
+                    }
+                    result = self.run_block_without_new_scope(catch_body, catch_scope);
+                }
+                if let Some(finally) = finally {
+                    // Set aside whatever is propagating while the finally block runs
+                    let pending = std::mem::replace(&mut self.control_flow, ControlFlow::None);
+                    self.run(finally, scope)?;
+                    match self.control_flow {
+                        ControlFlow::None => self.control_flow = pending,
+                        // A return, break or continue inside finally wins
+                        _ => return Ok(Value::Nothing),
+                    }
+                }
+                result?
+            }
             AST::Assert(loc, cond, msg) => {
                 let cond = self.run(cond, scope)?;
                 match cond {
@@ -423,7 +769,7 @@ impl Interpreter {
                                     self.control_flow = ControlFlow::None;
                                     break;
                                 }
-                                ControlFlow::Return(_) => break,
+                                ControlFlow::Return(_) | ControlFlow::Throw(_) => break,
                             }
                         }
                         Value::Boolean(false) => break,
@@ -432,64 +778,55 @@ impl Interpreter {
                 }
                 Value::Nothing
             }
-            AST::ForEach(span, loop_var, iter, body) => {
-                self.check_arg_name(loop_var, span)?;
+            AST::ForEach(span, loop_var, iter, body, layout) => {
                 let val = self.run(iter, scope.clone())?;
-                let iter = val.iterator(span)?;
-                match iter {
-                    Value::Iterator(IteratorValue(iter)) => {
-                        let iter = &mut *(*iter).borrow_mut();
-                        for val in iter {
-                            let loop_scope =
-                                Scope::new(Some(scope.clone()), scope.borrow().in_function);
-                            loop_scope
-                                .borrow_mut()
-                                .insert(loop_var, val.clone(), false, span)?;
-                            self.run(body, loop_scope)?;
-                            match self.control_flow {
-                                ControlFlow::None => {}
-                                ControlFlow::Continue => self.control_flow = ControlFlow::None,
-                                ControlFlow::Break => {
-                                    self.control_flow = ControlFlow::None;
-                                    break;
-                                }
-                                ControlFlow::Return(_) => break,
-                            }
+                let iter = self.iterate(&val, span)?;
+                let in_function = scope.borrow().in_function;
+                while let Some(val) = iter.next(self, span)? {
+                    let loop_scope =
+                        Scope::with_layout(Some(scope.clone()), in_function, layout.get());
+                    self.bind_pattern(loop_var, val, loop_scope.clone())?;
+                    self.run_in_scope(body, loop_scope)?;
+                    match self.control_flow {
+                        ControlFlow::None => {}
+                        ControlFlow::Continue => self.control_flow = ControlFlow::None,
+                        ControlFlow::Break => {
+                            self.control_flow = ControlFlow::None;
+                            break;
                         }
+                        ControlFlow::Return(_) | ControlFlow::Throw(_) => break,
                     }
-                    _ => error!(span, "For loop must iterate over an iterable"),
-                };
+                }
                 Value::Nothing
             }
-            AST::Comprehension(span, var, iter, expr, cond) => {
+            AST::Comprehension(span, var, iter, expr, cond, layout)
+            | AST::SetComprehension(span, var, iter, expr, cond, layout) => {
                 let val = self.run(iter, scope.clone())?;
-                let iter_value = val.iterator(span)?;
-                match iter_value {
-                    Value::Iterator(IteratorValue(iter)) => {
-                        let iter = &mut *(*iter).borrow_mut();
-                        let mut vec = Vec::new();
-                        for val in iter {
-                            let loop_scope =
-                                Scope::new(Some(scope.clone()), scope.borrow().in_function);
-                            loop_scope
-                                .borrow_mut()
-                                .insert(var, val.clone(), false, span)?;
-                            if let Some(cond) = cond {
-                                let condition = self.run(cond, loop_scope.clone())?;
-                                match condition {
-                                    Value::Boolean(true) => {}
-                                    Value::Boolean(false) => continue,
-                                    _ => error!(
-                                        cond.span(),
-                                        "Comprehension condition must be a boolean"
-                                    ),
-                                };
-                            }
-                            vec.push(self.run(expr, loop_scope)?);
-                        }
-                        Value::Array(make!(vec))
+                let iter_value = self.iterate(&val, iter.span())?;
+                let in_function = scope.borrow().in_function;
+                let mut vec = Vec::new();
+                while let Some(val) = iter_value.next(self, span)? {
+                    let loop_scope =
+                        Scope::with_layout(Some(scope.clone()), in_function, layout.get());
+                    self.bind_pattern(var, val, loop_scope.clone())?;
+                    if let Some(cond) = cond {
+                        let condition = self.run(cond, loop_scope.clone())?;
+                        match condition {
+                            Value::Boolean(true) => {}
+                            Value::Boolean(false) => continue,
+                            _ => error!(cond.span(), "Comprehension condition must be a boolean"),
+                        };
+                    }
+                    vec.push(self.run(expr, loop_scope)?);
+                    self.check_size(span, vec.len())?;
+                }
+                if let AST::SetComprehension(..) = ast.as_ref() {
+                    if vec.iter().any(|item| !item.is_hashable()) {
+                        error!(expr.span(), "Set items must be hashable")
                     }
-                    _ => error!(iter.span(), "Comprehension target must be iterable"),
+                    Value::Set(make!(vec.into_iter().collect()))
+                } else {
+                    Value::Array(make!(vec))
                 }
             }
             AST::For {
@@ -498,8 +835,10 @@ impl Interpreter {
                 cond,
                 step,
                 body,
+                layout,
             } => {
-                let loop_scope = Scope::new(Some(scope.clone()), scope.borrow().in_function);
+                let in_function = scope.borrow().in_function;
+                let loop_scope = Scope::with_layout(Some(scope.clone()), in_function, layout.get());
                 if let Some(init) = init {
                     self.run(init, loop_scope.clone())?;
                 }
@@ -520,7 +859,7 @@ impl Interpreter {
                             self.control_flow = ControlFlow::None;
                             break;
                         }
-                        ControlFlow::Return(_) => break,
+                        ControlFlow::Return(_) | ControlFlow::Throw(_) => break,
                     }
                     if let Some(step) = step {
                         self.run(step, loop_scope.clone())?;
@@ -528,17 +867,25 @@ impl Interpreter {
                 }
                 Value::Nothing
             }
-            AST::FormatStringLiteral(_, strings, exprs) => {
+            AST::FormatStringLiteral(span, strings, exprs) => {
                 let mut result = String::new();
                 for (i, string) in strings.iter().enumerate() {
                     result.push_str(string);
                     if i < exprs.len() {
                         let expr = self.run(&exprs[i], scope.clone())?;
-                        result.push_str(format!("{:?}", expr).as_str());
+                        result.push_str(&self.format_value(exprs[i].span(), &expr)?);
                     }
                 }
+                self.check_size(span, result.len())?;
                 Value::String(Rc::new(result))
             }
+            AST::Match(_, subject, arms) => {
+                let subject = self.run(subject, scope.clone())?;
+                match self.match_arm(arms, &subject, &scope)? {
+                    Some((body, arm_scope)) => return self.run_in_scope(body, arm_scope),
+                    None => Value::Nothing,
+                }
+            }
             AST::Range(span, start, end) => {
                 let start = self.run(start, scope.clone())?;
                 let end = self.run(end, scope)?;
@@ -560,7 +907,15 @@ impl Interpreter {
                 //     // Value::Dict
                 //     _ => left.index(&right, span)?,
                 // }
-                left.index(&right, span)?
+                match self.call_special_method(
+                    span,
+                    &left,
+                    "__getitem__",
+                    vec![(None, right.clone())],
+                )? {
+                    Some(value) => value,
+                    None => left.index(&right, span)?,
+                }
             }
             AST::PostIncrement(span, expr, offset) => {
                 let value = self.run(expr, scope.clone())?;
@@ -605,21 +960,21 @@ impl Interpreter {
                 }
                 Value::Dict(make!(map))
             }
+            AST::SetLiteral(_, items) => {
+                let mut set = HashSet::new();
+                for item in items {
+                    let span = item.span();
+                    let item = self.run(item, scope.clone())?;
+                    if !item.is_hashable() {
+                        error!(span, "Set items must be hashable")
+                    }
+                    set.insert(item);
+                }
+                Value::Set(make!(set))
+            }
             AST::Import { span, path, alias } => {
                 let program = self.run_file(span, path)?;
-                let name = match alias {
-                    Some(name) => name.clone(),
-                    None => {
-                        // Get last part of path
-                        let path = std::path::Path::new(path);
-                        path.with_extension("")
-                            .file_name()
-                            .unwrap()
-                            .to_str()
-                            .unwrap()
-                            .to_string()
-                    }
-                };
+                let name = import_name(path, alias);
                 let program = Value::Namespace(*span, name.clone(), program);
                 scope
                     .borrow_mut()
@@ -629,7 +984,9 @@ impl Interpreter {
             AST::FromImport { span, path, names } => {
                 let program = self.run_file(span, path)?;
                 if names.first().expect("Import object is empty").0 == "*" { // Merge scopes
-                    scope.borrow_mut().vars.extend(program.borrow().vars.clone());
+                    for (name, value) in program.borrow().variables() {
+                        scope.borrow_mut().declare(name, value.clone());
+                    }
                 } else { // Insert all names into scope
                     for (name, alias) in names {
                         let alias = match alias {
@@ -654,6 +1011,164 @@ impl Interpreter {
         })
     }
 
+    // Methods are created without being declared in the class body scope
+    fn run_field(&mut self, field: &Rc<AST>, body_scope: &Ref<Scope>) -> Result<Value> {
+        match field.as_ref() {
+            AST::Function { .. } => self.make_function(field, body_scope),
+            _ => self.run(field, body_scope.clone()),
+        }
+    }
+
+    // Creates the value of a function literal, closing over the given scope
+    fn make_function(&mut self, ast: &AST, scope: &Ref<Scope>) -> Result<Value> {
+        let AST::Function {
+            span,
+            name,
+            args,
+            required,
+            is_static,
+            in_class,
+            is_generator,
+            body,
+            layout,
+        } = ast
+        else {
+            unreachable!("make_function called on non-function")
+        };
+        // Defaults are evaluated once, when the function is defined
+        let args = args
+            .iter()
+            .map(|(name, default, argtype)| {
+                let default = default
+                    .as_ref()
+                    .map(|def| self.run(def, scope.clone()))
+                    .transpose()?;
+                Ok((name.clone(), default, *argtype))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Value::Function(make!(Function {
+            span: *span,
+            name: name.clone().unwrap_or_else(|| "<anon>".to_string()),
+            args,
+            required: *required,
+            class_method: (*in_class && !*is_static),
+            is_generator: *is_generator,
+            body: body.clone(),
+            locals: layout.get().cloned().unwrap_or_default(),
+            scope: scope.clone()
+        })))
+    }
+
+    // Variables shadow built-in functions of the same name
+    fn lookup_variable(&self, scope: &Ref<Scope>, span: &Span, name: &str) -> Result<Value> {
+        match scope
+            .borrow()
+            .get(name)
+            .or_else(|| self.registry.global(name))
+        {
+            Some(val) => Ok(val),
+            None => {
+                let names = scope.borrow().visible_names();
+                let names = names.iter().map(String::as_str);
+                let message = format!("Variable '{}' not found", name);
+                Err(Error::new(ErrorKind::Runtime, *span, message)
+                    .suggest(name, names.chain(self.registry.global_names())))
+            }
+        }
+    }
+
+    // Registered methods of built-in types and host objects, then fields of
+    // the value itself
+    fn get_field(&self, span: &Span, obj: &Value, field: &str) -> Result<Value> {
+        match self.registry.method(obj, field) {
+            Some(method) => Ok(method),
+            None => obj.get_field(span, field).map_err(|err| {
+                let fields = obj.field_names();
+                let fields = fields.iter().map(String::as_str);
+                err.suggest(field, fields.chain(self.registry.method_names(obj)))
+            }),
+        }
+    }
+
+    // Reads a variable from the slot the resolver found for it, falling back
+    // to looking it up by name
+    fn load_variable(
+        &self,
+        scope: &Ref<Scope>,
+        span: &Span,
+        name: &str,
+        slot: Option<Slot>,
+    ) -> Result<Value> {
+        if let Some(value) = slot.and_then(|slot| scope.borrow().load(slot, name)) {
+            return Ok(value);
+        }
+        self.lookup_variable(scope, span, name)
+    }
+
+    fn store_variable(
+        &self,
+        scope: &Ref<Scope>,
+        span: &Span,
+        name: &str,
+        slot: Option<Slot>,
+        value: Value,
+    ) -> Result<()> {
+        let value = match slot {
+            Some(slot) => match scope.borrow_mut().store(slot, name, value) {
+                Some(value) => value,
+                None => return Ok(()),
+            },
+            None => value,
+        };
+        if scope.borrow().get(name).is_none() {
+            error!(span, "Variable {} doesn't exist", name)
+        }
+        scope.borrow_mut().insert(name, value, true, span)
+    }
+
+    fn not_equals(&mut self, span: &Span, left: &Value, right: &Value) -> Result<Value> {
+        match self.call_operator_method(span, "__ne__", left, right)? {
+            Some(value) => Ok(value),
+            // Without `__ne__`, `!=` is the negation of `__eq__`
+            None => match self.call_operator_method(span, "__eq__", left, right)? {
+                Some(value) => value.not(span),
+                None => left.not_equals(right, span),
+            },
+        }
+    }
+
+    // Compares two values the way `==` does
+    fn values_equal(&mut self, span: &Span, left: &Value, right: &Value) -> Result<bool> {
+        match self.call_operator_method(span, "__eq__", left, right)? {
+            Some(Value::Boolean(equal)) => Ok(equal),
+            Some(value) => error!(
+                span,
+                "Expected __eq__ to return a boolean, but got {}",
+                value.type_of()
+            ),
+            None => Ok(left == right),
+        }
+    }
+
+    // `item in container`. Arrays and tuples compare their items with `==`,
+    // so items that define `__eq__` are found by it.
+    fn contains(&mut self, span: &Span, container: &Value, item: &Value) -> Result<Value> {
+        let args = vec![(None, item.clone())];
+        if let Some(value) = self.call_special_method(span, container, "__contains__", args)? {
+            return Ok(value);
+        }
+        let items = match container {
+            Value::Array(items) | Value::Tuple(items) => items.borrow().clone(),
+            _ => return container.contains(item, span),
+        };
+        for other in &items {
+            if self.values_equal(span, item, other)? {
+                return Ok(Value::Boolean(true));
+            }
+        }
+        Ok(Value::Boolean(false))
+    }
+
     fn handle_assign(
         &mut self,
         scope: Ref<Scope>,
@@ -662,41 +1177,388 @@ impl Interpreter {
         value: Value,
     ) -> Result<()> {
         match &**left {
-            AST::Variable(span, name) => {
-                if scope.borrow().get(name.as_str()).is_none() {
-                    error!(span, "Variable {} doesn't exist", name)
-                }
-                // if self.builtins.contains_key(name.as_str()) {
-                //     error!(span, "`{}` is a built-in function, can't override it", name)
-                // }
-                scope
-                    .borrow_mut()
-                    .insert(name.as_str(), value, true, span)?;
+            AST::Variable(span, name, slot) => {
+                self.store_variable(&scope, span, name, slot.get(), value)?
             }
             AST::Index(span, left, right) => {
                 let left = self.run(left, scope.clone())?;
                 let right = self.run(right, scope)?;
-                left.set_index(&right, &value, span)?;
+                let args = vec![(None, right.clone()), (None, value.clone())];
+                if self
+                    .call_special_method(span, &left, "__setitem__", args)?
+                    .is_none()
+                {
+                    self.set_index(span, &left, &right, &value)?;
+                }
             }
             AST::FieldAccess(span, left, name) => {
                 let left = self.run(left, scope)?;
                 left.set_field(span, name.as_str(), &value)?;
             }
+            AST::TupleLiteral(span, targets) | AST::ArrayLiteral(span, targets) => {
+                let mut stars = targets
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, target)| matches!(target.as_ref(), AST::StarExpression(..)));
+                let rest = stars.next().map(|(index, _)| index);
+                if let Some((_, target)) = stars.next() {
+                    error!(target.span(), "Only one starred target is allowed");
+                }
+                let values = self.unpack(span, &value, targets.len(), rest)?;
+                for (target, value) in targets.iter().zip(values) {
+                    let target = match target.as_ref() {
+                        AST::StarExpression(_, target) => target,
+                        _ => target,
+                    };
+                    self.handle_assign(scope.clone(), span, target, value)?;
+                }
+            }
             _ => error!(span, "Invalid assignment target"),
         }
         Ok(())
     }
 
-    fn check_arg_name(&self, name: &str, span: &Span) -> Result<()> {
-        if name == "self" {
-            error!(span, "Argument name can't be `self`")
-            // } else if self.builtins.contains_key(name) {
-            //     error!(span, "Argument name can't be a built-in function name")
-        } else {
-            Ok(())
-        }
-    }
-
+    // Binds the names in the pattern of a `let` or `for`, which unlike in a
+    // match statement is an error if the value doesn't fit
+    fn bind_pattern(
+        &mut self,
+        pattern: &Rc<Pattern>,
+        value: Value,
+        scope: Ref<Scope>,
+    ) -> Result<()> {
+        match pattern.as_ref() {
+            Pattern::Wildcard(_) => {}
+            Pattern::Binding(span, name) | Pattern::Rest(span, Some(name)) => {
+                self.check_arg_name(name, span)?;
+                scope.borrow_mut().insert(name, value, false, span)?;
+            }
+            Pattern::Rest(_, None) => {}
+            Pattern::Array(span, items) | Pattern::Tuple(span, items) => {
+                let rest = items
+                    .iter()
+                    .position(|item| matches!(item.as_ref(), Pattern::Rest(..)));
+                let values = self.unpack(span, &value, items.len(), rest)?;
+                for (item, value) in items.iter().zip(values) {
+                    self.bind_pattern(item, value, scope.clone())?;
+                }
+            }
+            _ => {
+                if !self.match_pattern(pattern, &value, scope)? {
+                    error!(
+                        pattern.span(),
+                        "Value {} does not match the pattern `{}`",
+                        value.repr(),
+                        pattern
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Splits a value into `count` values for destructuring. The target at
+    // index `rest`, if any, gets an array of whatever is left over.
+    fn unpack(
+        &mut self,
+        span: &Span,
+        value: &Value,
+        count: usize,
+        rest: Option<usize>,
+    ) -> Result<Vec<Value>> {
+        let mut values = match value {
+            Value::Array(items) | Value::Tuple(items) => items.borrow().clone(),
+            _ => {
+                let iter = match self.iterate(value, span) {
+                    Err(_) if !matches!(value, Value::ClassInstance(..)) => {
+                        error!(span, "Cannot destructure {}", value.type_of())
+                    }
+                    iter => iter?,
+                };
+                let mut values = Vec::new();
+                while let Some(value) = iter.next(self, span)? {
+                    values.push(value);
+                }
+                values
+            }
+        };
+        match rest {
+            None if values.len() != count => error!(
+                span,
+                "Expected {} values to unpack, but got {}",
+                count,
+                values.len()
+            ),
+            Some(_) if values.len() < count - 1 => error!(
+                span,
+                "Expected at least {} values to unpack, but got {}",
+                count - 1,
+                values.len()
+            ),
+            Some(index) => {
+                let after = values.split_off(values.len() - (count - 1 - index));
+                let middle = values.split_off(index);
+                values.push(Value::Array(make!(middle)));
+                values.extend(after);
+            }
+            None => {}
+        }
+        Ok(values)
+    }
+
+    // Finds the first arm whose pattern matches and whose guard passes, and
+    // returns its body with the scope its pattern bound variables in
+    fn match_arm<'a>(
+        &mut self,
+        arms: &'a [MatchArm],
+        subject: &Value,
+        scope: &Ref<Scope>,
+    ) -> Result<Option<(&'a Rc<AST>, Ref<Scope>)>> {
+        let in_function = scope.borrow().in_function;
+        for (pattern, guard, body, layout) in arms {
+            let arm_scope = Scope::with_layout(Some(scope.clone()), in_function, layout.get());
+            if !self.match_pattern(pattern, subject, arm_scope.clone())? {
+                continue;
+            }
+            if let Some(guard) = guard {
+                match self.run(guard, arm_scope.clone())? {
+                    Value::Boolean(true) => {}
+                    Value::Boolean(false) => continue,
+                    _ => error!(guard.span(), "Match guard must be a boolean"),
+                }
+            }
+            return Ok(Some((body, arm_scope)));
+        }
+        Ok(None)
+    }
+
+    #[allow(clippy::mutable_key_type)]
+    fn match_pattern(
+        &mut self,
+        pattern: &Rc<Pattern>,
+        value: &Value,
+        scope: Ref<Scope>,
+    ) -> Result<bool> {
+        Ok(match pattern.as_ref() {
+            Pattern::Wildcard(_) => true,
+            Pattern::Binding(span, name) => {
+                self.check_arg_name(name, span)?;
+                scope
+                    .borrow_mut()
+                    .insert(name, value.clone(), false, span)?;
+                true
+            }
+            Pattern::Value(_, expr) => self.run(expr, scope)? == *value,
+            Pattern::Or(_, alternatives) => {
+                for alternative in alternatives {
+                    if self.match_pattern(alternative, value, scope.clone())? {
+                        return Ok(true);
+                    }
+                }
+                false
+            }
+            Pattern::Rest(span, _) => {
+                error!(span, "Rest patterns can only be used in arrays and tuples")
+            }
+            Pattern::Array(_, items) => match value {
+                Value::Array(array) => {
+                    let values = array.borrow().clone();
+                    self.match_sequence(items, &values, scope)?
+                }
+                _ => false,
+            },
+            Pattern::Tuple(_, items) => match value {
+                Value::Tuple(tuple) => {
+                    let values = tuple.borrow().clone();
+                    self.match_sequence(items, &values, scope)?
+                }
+                _ => false,
+            },
+            Pattern::Dict(span, items, rest) => {
+                let dict = match value {
+                    Value::Dict(dict) => dict.borrow().clone(),
+                    _ => return Ok(false),
+                };
+                let mut seen = Vec::new();
+                for (key, item) in items {
+                    let key_span = key.span();
+                    let key = self.run(key, scope.clone())?;
+                    if !key.is_hashable() {
+                        error!(key_span, "Dictionary key must be hashable")
+                    }
+                    match dict.get(&key) {
+                        Some(value) => {
+                            if !self.match_pattern(item, value, scope.clone())? {
+                                return Ok(false);
+                            }
+                        }
+                        None => return Ok(false),
+                    }
+                    seen.push(key);
+                }
+                if let Some(rest) = rest {
+                    let remaining = dict
+                        .into_iter()
+                        .filter(|(key, _)| !seen.contains(key))
+                        .collect();
+                    scope
+                        .borrow_mut()
+                        .insert(rest, Value::Dict(make!(remaining)), false, span)?;
+                }
+                true
+            }
+            Pattern::Class(span, class, args) => {
+                let class = match self.run(class, scope.clone())? {
+                    Value::Class(class) => class,
+                    Value::EnumVariant(expected) => {
+                        return self.match_variant(span, &expected, args, value, scope)
+                    }
+                    other => error!(
+                        span,
+                        "Expected a class or enum variant in pattern, but got {}",
+                        other.type_of()
+                    ),
+                };
+                let instance = match value {
+                    Value::ClassInstance(instance) if instance.borrow().is_instance_of(&class) => {
+                        instance
+                    }
+                    _ => return Ok(false),
+                };
+                // Positional patterns line up with the arguments of the class initializer
+                let positional: Vec<String> = match class.borrow().lookup("new") {
+                    Some(Value::Function(function)) => function
+                        .borrow()
+                        .args
+                        .iter()
+                        .filter(|(_, _, argtype)| {
+                            matches!(argtype, ArgumentType::Positional | Keyword)
+                        })
+                        .map(|(name, ..)| name.clone())
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                for (i, (name, item)) in args.iter().enumerate() {
+                    let field = match name {
+                        Some(name) => name,
+                        None => match positional.get(i) {
+                            Some(name) => name,
+                            None => error!(
+                                item.span(),
+                                "Class '{}' does not take {} positional patterns",
+                                class.borrow().name,
+                                i + 1
+                            ),
+                        },
+                    };
+                    let field_value = match instance.borrow().fields.get(field) {
+                        Some(field_value) => field_value.clone(),
+                        None => return Ok(false),
+                    };
+                    if !self.match_pattern(item, &field_value, scope.clone())? {
+                        return Ok(false);
+                    }
+                }
+                true
+            }
+        })
+    }
+
+    fn match_variant(
+        &mut self,
+        span: &Span,
+        expected: &EnumVariant,
+        args: &[(Option<String>, Rc<Pattern>)],
+        value: &Value,
+        scope: Ref<Scope>,
+    ) -> Result<bool> {
+        let (variant, values) = match value {
+            Value::EnumVariant(variant) if variant.is_variant_of(expected) => {
+                match &variant.values {
+                    Some(values) => (variant, values),
+                    None => return Ok(false),
+                }
+            }
+            _ => return Ok(false),
+        };
+        let names = variant.field_names();
+        if args.iter().all(|(name, _)| name.is_none()) && args.len() != names.len() {
+            error!(
+                span,
+                "Variant {}.{} has {} fields, but the pattern has {}",
+                variant.parent.borrow().name,
+                variant.name,
+                names.len(),
+                args.len()
+            );
+        }
+        for (i, (name, item)) in args.iter().enumerate() {
+            let index = match name {
+                Some(name) => match names.iter().position(|field| field == name) {
+                    Some(index) => index,
+                    None => error!(item.span(), "Variant has no field '{}'", name),
+                },
+                None => i,
+            };
+            if !self.match_pattern(item, &values[index], scope.clone())? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn match_sequence(
+        &mut self,
+        items: &[Rc<Pattern>],
+        values: &[Value],
+        scope: Ref<Scope>,
+    ) -> Result<bool> {
+        let rest = items
+            .iter()
+            .position(|item| matches!(item.as_ref(), Pattern::Rest(..)));
+        let (before, after) = match rest {
+            Some(index) => {
+                if values.len() < items.len() - 1 {
+                    return Ok(false);
+                }
+                (index, items.len() - index - 1)
+            }
+            None => {
+                if values.len() != items.len() {
+                    return Ok(false);
+                }
+                (items.len(), 0)
+            }
+        };
+        for (item, value) in items[..before].iter().zip(values) {
+            if !self.match_pattern(item, value, scope.clone())? {
+                return Ok(false);
+            }
+        }
+        let tail = values.len() - after;
+        for (item, value) in items[items.len() - after..].iter().zip(&values[tail..]) {
+            if !self.match_pattern(item, value, scope.clone())? {
+                return Ok(false);
+            }
+        }
+        if let Some(index) = rest {
+            if let Pattern::Rest(span, Some(name)) = items[index].as_ref() {
+                let rest = Value::Array(make!(values[before..tail].to_vec()));
+                scope.borrow_mut().insert(name, rest, false, span)?;
+            }
+        }
+        Ok(true)
+    }
+
+    fn check_arg_name(&self, name: &str, span: &Span) -> Result<()> {
+        if name == "self" {
+            error!(span, "Argument name can't be `self`")
+            // } else if self.builtins.contains_key(name) {
+            //     error!(span, "Argument name can't be a built-in function name")
+        } else {
+            Ok(())
+        }
+    }
+
     fn handle_call(
         &mut self,
         scope: Ref<Scope>,
@@ -707,10 +1569,18 @@ impl Interpreter {
         let mut parent = None;
 
         let callee = match obj.deref() {
+            AST::FieldAccess(_, left, field) if matches!(left.as_ref(), AST::Super(..)) => {
+                let AST::Super(_, class) = left.as_ref() else {
+                    unreachable!()
+                };
+                let (instance, value) = self.resolve_super(span, scope.clone(), class, field)?;
+                parent = Some(instance);
+                value
+            }
             AST::FieldAccess(_, left, field) => {
                 let temp = self.run(left, scope.clone())?;
                 parent = Some(temp.clone());
-                temp.get_field(span, field)?
+                self.get_field(span, &temp, field)?
             }
             _ => self.run(obj, scope.clone())?,
         };
@@ -735,14 +1605,223 @@ impl Interpreter {
                     values.push(value.clone());
                 }
             }
-            _ => error!(
-                expr.span(),
-                "Star expression can only be used with arrays and tuples"
-            ),
+            other => {
+                let iter = self.iterate(&other, expr.span())?;
+                while let Some(value) = iter.next(self, expr.span())? {
+                    values.push(value);
+                }
+            }
         };
         Ok(values)
     }
 
+    // Gets an iterator for a value. Class instances can define `__iter__` to
+    // return something iterable, and `__next__` to act as an iterator
+    // themselves, which ends once it returns nothing.
+    pub fn iterate(&mut self, value: &Value, span: &Span) -> Result<IteratorValue> {
+        let value = match self.call_special_method(span, value, "__iter__", vec![])? {
+            Some(value) => value,
+            None => value.clone(),
+        };
+        if let Value::ClassInstance(instance) = &value {
+            if instance.borrow().fields.contains_key("__next__") {
+                return Ok(IteratorValue::for_instance(value.clone(), span));
+            }
+        }
+        match value.iterator(span)? {
+            Value::Iterator(iter) => Ok(iter),
+            _ => unreachable!("iterator() always returns an iterator"),
+        }
+    }
+
+    // Formats a value the way `print` and `str` show it, using `__str__` on
+    // class instances that define it
+    pub fn format_value(&mut self, span: &Span, value: &Value) -> Result<String> {
+        match self.call_special_method(span, value, "__str__", vec![])? {
+            Some(Value::String(string)) => Ok(string.to_string()),
+            Some(other) => error!(
+                span,
+                "__str__ must return a string, but got {}",
+                other.type_of()
+            ),
+            None => match value {
+                Value::Array(..) | Value::Tuple(..) | Value::Dict(..) | Value::Set(..) => {
+                    self.repr_value(span, value)
+                }
+                _ => Ok(format!("{:?}", value)),
+            },
+        }
+    }
+
+    // Like `format_value`, but for `repr`: class instances use `__repr__`,
+    // falling back to `__str__`
+    pub fn repr_value(&mut self, span: &Span, value: &Value) -> Result<String> {
+        let special = match self.call_special_method(span, value, "__repr__", vec![])? {
+            Some(value) => Some(value),
+            None => self.call_special_method(span, value, "__str__", vec![])?,
+        };
+        let mut items = Vec::new();
+        let (open, close) = match (special, value) {
+            (Some(Value::String(string)), _) => return Ok(string.to_string()),
+            (Some(other), _) => error!(
+                span,
+                "__repr__ must return a string, but got {}",
+                other.type_of()
+            ),
+            (None, Value::Array(array) | Value::Tuple(array)) => {
+                for item in array.borrow().iter() {
+                    items.push(self.repr_value(span, item)?);
+                }
+                ("[", "]")
+            }
+            (None, Value::Dict(dict)) => {
+                for (key, value) in dict.borrow().iter() {
+                    let key = self.repr_value(span, key)?;
+                    items.push(format!("{}: {}", key, self.repr_value(span, value)?));
+                }
+                ("{", "}")
+            }
+            (None, Value::Set(set)) if !set.borrow().is_empty() => {
+                for item in set.borrow().iter() {
+                    items.push(self.repr_value(span, item)?);
+                }
+                ("{", "}")
+            }
+            (None, _) => return Ok(value.repr()),
+        };
+        Ok(format!("{}{}{}", open, items.join(", "), close))
+    }
+
+    // Resolves `super.field` or `super(Parent).field` inside a method, returning
+    // the current `self` and the field. Plain `super` continues along the MRO
+    // of `self` after the class that defined the running method, while
+    // `super(Parent)` looks the field up starting at `Parent`.
+    fn resolve_super(
+        &mut self,
+        span: &Span,
+        scope: Ref<Scope>,
+        class: &Option<Rc<AST>>,
+        field: &str,
+    ) -> Result<(Value, Value)> {
+        let instance = scope.borrow().get("self");
+        let owner = scope.borrow().get("__class__");
+        let (instance, owner) = match (instance, owner) {
+            (Some(Value::ClassInstance(instance)), Some(Value::Class(owner))) => (instance, owner),
+            _ => error!(span, "`super` can only be used inside a method"),
+        };
+        let value = match class {
+            Some(class) => {
+                let parent = match self.run(class, scope)? {
+                    Value::Class(parent) => parent,
+                    other => error!(
+                        span,
+                        "Expected a class in `super`, but got {}",
+                        other.type_of()
+                    ),
+                };
+                if Rc::ptr_eq(&parent, &owner) || !owner.borrow().is_subclass_of(&parent) {
+                    error!(
+                        span,
+                        "Class '{}' is not a parent of '{}'",
+                        parent.borrow().name,
+                        owner.borrow().name
+                    );
+                }
+                let value = parent.borrow().lookup(field);
+                value
+            }
+            None => {
+                let class = instance.borrow().class.clone();
+                let class = class.borrow();
+                let start = match class.mro.iter().position(|class| Rc::ptr_eq(class, &owner)) {
+                    Some(index) => index + 1,
+                    None => 0,
+                };
+                class.mro[start..]
+                    .iter()
+                    .find_map(|class| class.borrow().own_field(field))
+            }
+        };
+        match value {
+            Some(value) => Ok((Value::ClassInstance(instance), value)),
+            None => error!(
+                span,
+                "Field '{}' not found on any parent of '{}'",
+                field,
+                owner.borrow().name
+            ),
+        }
+    }
+
+    // Finds the class in an instance's MRO that defines the given method
+    fn method_owner(&self, instance: &Value, method: &Ref<Function>) -> Option<Value> {
+        let Value::ClassInstance(instance) = instance else {
+            return None;
+        };
+        let class = instance.borrow().class.clone();
+        let mro = class.borrow().mro.clone();
+        std::iter::once(class).chain(mro).find_map(|class| {
+            let defines = class.borrow().fields.values().any(|value| match value {
+                Value::Function(function) => Rc::ptr_eq(function, method),
+                _ => false,
+            });
+            defines.then_some(Value::Class(class))
+        })
+    }
+
+    // Calls a special method such as `__iter__` on a class instance, returning
+    // None if the value doesn't define it
+    // Calls the special method of a binary operator on the left operand, or
+    // for a comparison the reflected one on the right operand, so `a < b`
+    // calls `b.__gt__(a)` when only `b` defines it
+    fn call_operator_method(
+        &mut self,
+        span: &Span,
+        method: &str,
+        left: &Value,
+        right: &Value,
+    ) -> Result<Option<Value>> {
+        let args = vec![(None, right.clone())];
+        if let Some(value) = self.call_special_method(span, left, method, args)? {
+            return Ok(Some(value));
+        }
+        let reflected = match method {
+            "__eq__" | "__ne__" => method,
+            "__lt__" => "__gt__",
+            "__gt__" => "__lt__",
+            "__le__" => "__ge__",
+            "__ge__" => "__le__",
+            _ => return Ok(None),
+        };
+        self.call_special_method(span, right, reflected, vec![(None, left.clone())])
+    }
+
+    fn call_special_method(
+        &mut self,
+        span: &Span,
+        value: &Value,
+        name: &str,
+        args: CallArgValues,
+    ) -> Result<Option<Value>> {
+        let method = match value {
+            Value::ClassInstance(instance) => instance.borrow().fields.get(name).cloned(),
+            _ => None,
+        };
+        match method {
+            Some(method) => {
+                let scope = Scope::new(None, false);
+                Ok(Some(self.do_call(
+                    span,
+                    scope,
+                    Some(value.clone()),
+                    method,
+                    &args,
+                )?))
+            }
+            None => Ok(None),
+        }
+    }
+
     pub fn handle_star_star_expression(
         &mut self,
         scope: Ref<Scope>,
@@ -805,6 +1884,128 @@ impl Interpreter {
         Ok(values)
     }
 
+    // Binds call arguments to the parameters of a function in its scope
+    #[allow(clippy::mutable_key_type)]
+    fn bind_arguments(
+        &self,
+        span: &Span,
+        func: &Function,
+        run_scope: &Ref<Scope>,
+        args: &CallArgValues,
+    ) -> Result<()> {
+        let mut variadic_name = None;
+        let mut variadic_keyword_name = None;
+
+        let mut need: Vec<String> = Vec::new();
+        let mut seen: Vec<String> = Vec::new();
+        let mut arguments: HashMap<String, Value> = HashMap::new();
+        let mut variadic: Vec<Value> = Vec::new();
+        let mut variadic_keyword: HashMap<Value, Value> = HashMap::new();
+
+        for (name, arg, argtype) in func.args.iter() {
+            match argtype {
+                ArgumentType::Positional => need.push(name.to_string()),
+                Keyword => {
+                    run_scope.borrow_mut().insert(
+                        name,
+                        arg.clone().expect("Keywords always have default values."),
+                        false,
+                        span,
+                    )?;
+                }
+                ArgumentType::Variadic => {
+                    variadic_name = Some(name);
+                }
+                ArgumentType::VariadicKeyword => {
+                    variadic_keyword_name = Some(name);
+                }
+            };
+        }
+
+        let mut state = ArgumentType::Positional;
+        for (i, (name, arg)) in args.iter().enumerate() {
+            match name {
+                Some(name) => {
+                    if seen.contains(name) {
+                        error!(span, "Duplicate keyword argument: `{}`", name);
+                    } else if run_scope.borrow().contains(name) || need.contains(name) {
+                        arguments.insert(name.to_string(), arg.clone());
+                        seen.push(name.clone());
+                        state = Keyword;
+                    } else if variadic_keyword_name.is_some() {
+                        variadic_keyword
+                            .insert(Value::String(Rc::new(name.clone())), arg.clone());
+                        seen.push(name.clone());
+                        state = Keyword;
+                    } else {
+                        let message = format!("Unexpected keyword in {}: `{}`", func.name, name);
+                        let names = func
+                            .args
+                            .iter()
+                            .filter(|(_, _, kind)| {
+                                matches!(kind, ArgumentType::Positional | Keyword)
+                            })
+                            .map(|(name, ..)| name.as_str());
+                        let defined = Span(func.span.0, func.span.0);
+                        return Err(Error::new(ErrorKind::Runtime, *span, message)
+                            .suggest(name, names)
+                            .with_label(defined, &format!("{} is defined here", func.name)));
+                    }
+                }
+                None => {
+                    if i < func.required {
+                        if state != ArgumentType::Positional {
+                            error!(
+                                span,
+                                "Positional arguments must be the first provided."
+                            );
+                        }
+                        let (name, ..) = func.args.get(i).unwrap();
+                        arguments.insert(name.to_string(), arg.clone());
+                        seen.push(name.clone());
+                    } else if variadic_name.is_some() {
+                        if state != ArgumentType::Variadic
+                            && state != ArgumentType::Positional
+                        {
+                            error!(span, "Variadic arguments must be the last provided.");
+                        }
+                        variadic.push(arg.clone());
+                        state = ArgumentType::Variadic;
+                    } else {
+                        error!(span, "Unexpected positional argument in {}", func.name);
+                    }
+                }
+            }
+        }
+
+        // Check if all required arguments are provided
+        for name in need {
+            if !seen.contains(&name) {
+                error!(span, "Missing required argument: `{}`", name);
+            }
+        }
+
+        if let Some(variadic_name) = variadic_name {
+            arguments.insert(variadic_name.to_string(), Value::Array(make!(variadic)));
+        }
+
+        if let Some(variadic_keyword_name) = variadic_keyword_name {
+            arguments.insert(
+                variadic_keyword_name.to_string(),
+                Value::Dict(make!(variadic_keyword)),
+            );
+        }
+        for key in arguments.keys() {
+            run_scope.borrow_mut().insert(
+                key,
+                arguments.get(key).unwrap().clone(),
+                false,
+                span,
+            )?;
+        }
+        Ok(())
+    }
+
     pub fn do_call(
         &mut self,
         span: &Span,
@@ -812,13 +2013,49 @@ impl Interpreter {
         parent: Option<Value>,
         callee: Value,
         args: &CallArgValues,
+    ) -> Result<Value> {
+        self.enter_call(span)?;
+        let profiled = self.profile_call(&callee);
+        let result = self.call_value(span, scope, parent, callee.clone(), args);
+        if profiled {
+            self.exit_profile();
+        }
+        self.exit_call();
+        // Builtins and classes don't get a frame of their own, the functions
+        // they call do
+        match (result, callee) {
+            (Err(mut err), Value::Function(func)) => {
+                err.push_frame(Frame {
+                    name: func.borrow().name.clone(),
+                    span: *span,
+                    import: false,
+                });
+                Err(err)
+            }
+            (result, _) => result,
+        }
+    }
+
+    fn call_value(
+        &mut self,
+        span: &Span,
+        scope: Ref<Scope>,
+        parent: Option<Value>,
+        callee: Value,
+        args: &CallArgValues,
     ) -> Result<Value> {
         // Handle the call
         Ok(match callee.clone() {
             Value::Function(func) => {
                 // Setup scope
-
-                let run_scope = Scope::new(Some(func.borrow().scope.clone()), true);
+                let run_scope = {
+                    let func = func.borrow();
+                    Scope::with_layout(Some(func.scope.clone()), true, Some(&func.locals))
+                };
+                let owner = match (&parent, func.borrow().class_method) {
+                    (Some(parent), true) => self.method_owner(parent, &func),
+                    _ => None,
+                };
 
                 // This will always inject parent if it exists, meaning even if function is not a class method.
                 // if let Some(parent) = parent {
@@ -829,115 +2066,43 @@ impl Interpreter {
                 let func = func.borrow();
 
                 // Let's check if self should be injected
-                if func.class_method && parent.is_some() {
-                    run_scope.borrow_mut().insert("self", parent.unwrap(), false, span)?;
-                }
-
-                let mut variadic_name = None;
-                let mut variadic_keyword_name = None;
-
-                let mut need: Vec<String> = Vec::new();
-                let mut seen: Vec<String> = Vec::new();
-                let mut arguments: HashMap<String, Value> = HashMap::new();
-                let mut variadic: Vec<Value> = Vec::new();
-                let mut variadic_keyword: HashMap<Value, Value> = HashMap::new();
-
-                for (name, arg, argtype) in func.args.iter() {
-                    match argtype {
-                        ArgumentType::Positional => need.push(name.to_string()),
-                        Keyword => {
-                            run_scope.borrow_mut().insert(
-                                name,
-                                arg.clone().expect("Keywords always have default values."),
-                                false,
-                                span,
-                            )?;
-                        }
-                        ArgumentType::Variadic => {
-                            variadic_name = Some(name);
-                        }
-                        ArgumentType::VariadicKeyword => {
-                            variadic_keyword_name = Some(name);
-                        }
-                    };
+                if let (true, Some(parent)) = (func.class_method, parent) {
+                    run_scope.borrow_mut().insert("self", parent, false, span)?;
                 }
-
-                let mut state = ArgumentType::Positional;
-                for (i, (name, arg)) in args.iter().enumerate() {
-                    match name {
-                        Some(name) => {
-                            if seen.contains(name) {
-                                error!(span, "Duplicate keyword argument: `{}`", name);
-                            } else if run_scope.borrow().vars.contains_key(name)
-                                || need.contains(name)
-                            {
-                                arguments.insert(name.to_string(), arg.clone());
-                                seen.push(name.clone());
-                                state = Keyword;
-                            } else if variadic_keyword_name.is_some() {
-                                variadic_keyword
-                                    .insert(Value::String(Rc::new(name.clone())), arg.clone());
-                                seen.push(name.clone());
-                                state = Keyword;
-                            } else {
-                                error!(span, "Unexpected keyword  in {}: `{}`", func.name, name);
-                            }
-                        }
-                        None => {
-                            if i < func.required {
-                                if state != ArgumentType::Positional {
-                                    error!(
-                                        span,
-                                        "Positional arguments must be the first provided."
-                                    );
-                                }
-                                let (name, ..) = func.args.get(i).unwrap();
-                                arguments.insert(name.to_string(), arg.clone());
-                                seen.push(name.clone());
-                            } else if variadic_name.is_some() {
-                                if state != ArgumentType::Variadic
-                                    && state != ArgumentType::Positional
-                                {
-                                    error!(span, "Variadic arguments must be the last provided.");
-                                }
-                                variadic.push(arg.clone());
-                                state = ArgumentType::Variadic;
-                            } else {
-                                error!(span, "Unexpected positional argument in {}", func.name);
-                            }
-                        }
-                    }
+                // `super` continues the method lookup after the class defining this method
+                if let Some(owner) = owner {
+                    run_scope
+                        .borrow_mut()
+                        .insert("__class__", owner, false, span)?;
                 }
 
-                // Check if all required arguments are provided
-                for name in need {
-                    if !seen.contains(&name) {
-                        error!(span, "Missing required argument: `{}`", name);
+                // Plain positional calls skip the bookkeeping for keyword and
+                // variadic arguments
+                let positional = args.len() == func.required
+                    && func.args.len() == func.required
+                    && args.iter().all(|(name, _)| name.is_none());
+                if positional {
+                    let mut run_scope = run_scope.borrow_mut();
+                    for ((name, ..), (_, arg)) in func.args.iter().zip(args) {
+                        run_scope.insert(name, arg.clone(), false, span)?;
                     }
+                } else {
+                    self.bind_arguments(span, &func, &run_scope, args)?;
                 }
 
-                if let Some(variadic_name) = variadic_name {
-                    arguments.insert(variadic_name.to_string(), Value::Array(make!(variadic)));
-                }
-
-                if let Some(variadic_keyword_name) = variadic_keyword_name {
-                    arguments.insert(
-                        variadic_keyword_name.to_string(),
-                        Value::Dict(make!(variadic_keyword)),
-                    );
-                }
-                for key in arguments.keys() {
-                    run_scope.borrow_mut().insert(
-                        key,
-                        arguments.get(key).unwrap().clone(),
-                        false,
-                        span,
-                    )?;
+                // Calling a generator function only sets it up, the body runs
+                // as the generator is iterated
+                if func.is_generator {
+                    let generator = Generator::new(func.body.clone(), run_scope, *span);
+                    return Ok(Value::Iterator(IteratorValue::for_generator(generator)));
                 }
 
                 // Run the function
                 let body = func.body.clone();
-                self.run(&body, run_scope)?;
+                self.enter_frame(&func.name, span);
+                let result = self.run_body(&body, run_scope);
+                self.exit_frame();
+                result?;
                 let value = if let ControlFlow::Return(value) = &self.control_flow {
                     value.clone()
                 } else {
@@ -952,18 +2117,17 @@ impl Interpreter {
                 if let Some(parent) = parent {
                     args.insert(0, parent);
                 }
-                func.1.borrow()(self, run_scope, span, args)?
+                (func.1)(self, run_scope, span, args)?
             }
             Value::Class(_class) => {
                 let class = _class.borrow();
                 let name = class.name.clone();
-                let fields = class.fields.to_owned();
+                let fields = class.instance_fields();
 
                 let mut instance = Value::ClassInstance(make!(ClassInstance {
                     span: *span,
                     name: name.clone(),
                     class: _class.clone(),
-                    parents: class.parents.clone(),
                     in_initializer: true,
                     static_fields: class.static_fields.clone(),
                     fields: fields.clone()
@@ -986,6 +2150,60 @@ impl Interpreter {
                 instance.class_instance_set_in_initializer(false);
                 instance
             }
+            Value::Type(type_) => match type_.constructor() {
+                Some(constructor) => self.do_call(span, scope, None, constructor, args)?,
+                None => error!(span, "Type '{}' cannot be called", type_.name()),
+            },
+            Value::ClassInstance(ref instance)
+                if instance.borrow().fields.contains_key("__call__") =>
+            {
+                let method = instance.borrow().fields["__call__"].clone();
+                self.do_call(span, scope, Some(callee.clone()), method, args)?
+            }
+            Value::EnumVariant(variant) if variant.values.is_none() => {
+                let names = variant.field_names();
+                let mut values: Vec<Option<Value>> = vec![None; names.len()];
+                for (i, (name, arg)) in args.iter().enumerate() {
+                    let index = match name {
+                        Some(name) => match names.iter().position(|field| field == name) {
+                            Some(index) => index,
+                            None => {
+                                let message =
+                                    format!("Unexpected keyword in {}: `{}`", callee.repr(), name);
+                                let names = names.iter().map(String::as_str);
+                                return Err(Error::new(ErrorKind::Runtime, *span, message)
+                                    .suggest(name, names));
+                            }
+                        },
+                        None => i,
+                    };
+                    match values.get_mut(index) {
+                        Some(None) => values[index] = Some(arg.clone()),
+                        Some(Some(_)) => {
+                            error!(span, "Duplicate argument: `{}`", names[index])
+                        }
+                        None => error!(
+                            span,
+                            "{} takes {} arguments but {} were given",
+                            callee.repr(),
+                            names.len(),
+                            args.len()
+                        ),
+                    }
+                }
+                let mut filled = Vec::new();
+                for (name, value) in names.iter().zip(values) {
+                    match value {
+                        Some(value) => filled.push(value),
+                        None => error!(span, "Missing required argument: `{}`", name),
+                    }
+                }
+                Value::EnumVariant(Rc::new(EnumVariant {
+                    parent: variant.parent.clone(),
+                    name: variant.name.clone(),
+                    values: Some(filled),
+                }))
+            }
             _ => error!(span, "Can't call object {:?}", callee),
         })
     }
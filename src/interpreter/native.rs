@@ -0,0 +1,397 @@
+/*
+   Copyright (C) 2023  Haven Selph
+   Copyright (C) 2023  Mustafa Quraish
+   Check the LICENSE file for more information.
+*/
+
+use crate::common::Span;
+use crate::error::{runtime_error as error, Result};
+use crate::interpreter::value::{BuiltInFunction, BuiltInFunctionType, HostObject, Type, Value};
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::rc::Rc;
+
+// The global functions, and the methods of built-in types and host objects.
+// The interpreter registers its builtins here when it starts, and an engine
+// adds whatever its host registers on top of them.
+#[derive(Clone, Default)]
+pub struct Registry {
+    globals: HashMap<String, Value>,
+    methods: HashMap<Type, HashMap<String, Value>>,
+    // Keyed by the type of the `RefCell` a host object holds
+    object_methods: HashMap<TypeId, HashMap<String, Value>>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        let mut registry = Registry::default();
+        crate::interpreter::builtin::register(&mut registry);
+        registry
+    }
+
+    pub fn add_global(&mut self, name: &str, value: Value) {
+        self.globals.insert(name.to_string(), value);
+    }
+
+    pub fn add_method(&mut self, type_: Type, name: &str, value: Value) {
+        let methods = self.methods.entry(type_).or_default();
+        methods.insert(name.to_string(), value);
+    }
+
+    pub fn add_object_method<T: Any>(&mut self, name: &str, value: Value) {
+        let methods = self
+            .object_methods
+            .entry(TypeId::of::<RefCell<T>>())
+            .or_default();
+        methods.insert(name.to_string(), value);
+    }
+
+    // A global function or type, which any variable of the same name shadows
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.globals.get(name).cloned()
+    }
+
+    pub fn global_names(&self) -> impl Iterator<Item = &str> {
+        self.globals.keys().map(String::as_str)
+    }
+
+    // A method of a built-in type or host object. Class instances and enum
+    // variants have their methods as fields instead.
+    pub fn method(&self, value: &Value, name: &str) -> Option<Value> {
+        self.methods_of(value)?.get(name).cloned()
+    }
+
+    pub fn method_names(&self, value: &Value) -> impl Iterator<Item = &str> {
+        let methods = self.methods_of(value).into_iter();
+        methods.flat_map(|methods| methods.keys().map(String::as_str))
+    }
+
+    // The methods of every built-in type, for when the type isn't known
+    pub fn all_method_names(&self) -> impl Iterator<Item = (Type, &str)> {
+        let methods = self.methods.iter();
+        methods.flat_map(|(type_, methods)| methods.keys().map(|name| (*type_, name.as_str())))
+    }
+
+    fn methods_of(&self, value: &Value) -> Option<&HashMap<String, Value>> {
+        match (value, value.get_type()) {
+            (Value::Object(object), _) => self.object_methods.get(&(*object.value).type_id()),
+            (_, Value::Type(type_)) => self.methods.get(&type_),
+            _ => None,
+        }
+    }
+}
+
+/// Converts a script value into a Rust value, for the arguments of functions
+/// registered with the engine.
+pub trait FromValue: Sized {
+    /// What the value should be, as shown in errors.
+    fn type_name() -> String;
+
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+/// Converts a Rust value into a script value, for the return values of
+/// functions registered with the engine.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+/// What a registered function can return: anything that converts into a
+/// value, or a `Result` whose error message becomes a runtime error.
+pub trait NativeResult {
+    fn into_result(self, span: &Span) -> Result<Value>;
+}
+
+impl<T: IntoValue> NativeResult for T {
+    fn into_result(self, _span: &Span) -> Result<Value> {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue> NativeResult for std::result::Result<T, String> {
+    fn into_result(self, span: &Span) -> Result<Value> {
+        match self {
+            Ok(value) => Ok(value.into_value()),
+            Err(message) => error!(span, "{}", message),
+        }
+    }
+}
+
+/// A Rust function that can be registered with the engine: a closure taking
+/// up to six arguments that implement [`FromValue`] and returning a
+/// [`NativeResult`].
+pub trait NativeFunction<Args> {
+    fn into_builtin(self, name: &str) -> Value;
+}
+
+// Converts one argument of a registered function, or fails with an error
+// naming the function and the position of the argument
+fn argument<T: FromValue>(function: &str, span: &Span, index: usize, value: &Value) -> Result<T> {
+    match T::from_value(value) {
+        Some(value) => Ok(value),
+        None => error!(
+            span,
+            "Expected {} for argument {} of {}(), but got {}",
+            T::type_name(),
+            index + 1,
+            function,
+            value.type_of()
+        ),
+    }
+}
+
+macro_rules! native_function {
+    ($count:literal $(, $arg:ident $index:literal)*) => {
+        impl<F, R, $($arg),*> NativeFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: NativeResult,
+            $($arg: FromValue,)*
+        {
+            fn into_builtin(self, name: &str) -> Value {
+                let name: Rc<str> = name.into();
+                let function_name = name.clone();
+                let function: Rc<BuiltInFunctionType> = Rc::new(move |_, _, span, args| {
+                    if args.len() != $count {
+                        error!(
+                            span,
+                            "{}() takes {} arguments, but got {}",
+                            function_name,
+                            $count,
+                            args.len()
+                        )
+                    }
+                    self($(argument::<$arg>(&function_name, span, $index, &args[$index])?),*)
+                        .into_result(span)
+                });
+                Value::BuiltInFunction(BuiltInFunction(name, function))
+            }
+        }
+    };
+}
+
+native_function!(0);
+native_function!(1, A 0);
+native_function!(2, A 0, B 1);
+native_function!(3, A 0, B 1, C 2);
+native_function!(4, A 0, B 1, C 2, D 3);
+native_function!(5, A 0, B 1, C 2, D 3, E 4);
+native_function!(6, A 0, B 1, C 2, D 3, E 4, G 5);
+
+impl Value {
+    /// Wraps a Rust value so scripts can hold it and call the methods
+    /// registered for its type with [`Engine::register_object_method`].
+    ///
+    /// [`Engine::register_object_method`]: crate::Engine::register_object_method
+    pub fn object<T: Any>(value: T) -> Value {
+        Rc::new(RefCell::new(value)).into_value()
+    }
+}
+
+// The name of a Rust type without its module path, e.g. `Counter` for
+// `my_crate::Counter`
+fn short_type_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    let path = name.split('<').next().unwrap_or(name);
+    &name[path.rfind("::").map_or(0, |index| index + 2)..]
+}
+
+impl FromValue for i64 {
+    fn type_name() -> String {
+        "integer".to_string()
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn type_name() -> String {
+        "float".to_string()
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Float(value) => Some(*value),
+            Value::Integer(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn type_name() -> String {
+        "boolean".to_string()
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for String {
+    fn type_name() -> String {
+        "string".to_string()
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(value) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for Value {
+    fn type_name() -> String {
+        "value".to_string()
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn type_name() -> String {
+        format!("array of {}", T::type_name())
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Array(items) | Value::Tuple(items) => {
+                items.borrow().iter().map(T::from_value).collect()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<K: FromValue + Eq + Hash, V: FromValue> FromValue for HashMap<K, V> {
+    fn type_name() -> String {
+        format!("dict of {} to {}", K::type_name(), V::type_name())
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Dict(items) => items
+                .borrow()
+                .iter()
+                .map(|(key, value)| Some((K::from_value(key)?, V::from_value(value)?)))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+// `nothing` converts to None
+impl<T: FromValue> FromValue for Option<T> {
+    fn type_name() -> String {
+        format!("{} or nothing", T::type_name())
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Nothing => Some(None),
+            _ => T::from_value(value).map(Some),
+        }
+    }
+}
+
+// Host objects convert back to the shared value they wrap
+impl<T: Any> FromValue for Rc<RefCell<T>> {
+    fn type_name() -> String {
+        format!("object {}", short_type_name::<T>())
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Object(object) => object.value.clone().downcast().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        Value::Integer(self)
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(Rc::new(self))
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(Rc::new(self.to_string()))
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Nothing
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        let items = self.into_iter().map(T::into_value).collect();
+        Value::Array(Rc::new(RefCell::new(items)))
+    }
+}
+
+impl<K: IntoValue, V: IntoValue> IntoValue for HashMap<K, V> {
+    #[allow(clippy::mutable_key_type)]
+    fn into_value(self) -> Value {
+        let items = self
+            .into_iter()
+            .map(|(key, value)| (key.into_value(), value.into_value()))
+            .collect();
+        Value::Dict(Rc::new(RefCell::new(items)))
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        self.map_or(Value::Nothing, T::into_value)
+    }
+}
+
+impl<T: Any> IntoValue for Rc<RefCell<T>> {
+    fn into_value(self) -> Value {
+        Value::Object(HostObject {
+            name: short_type_name::<T>(),
+            value: self,
+        })
+    }
+}
@@ -0,0 +1,293 @@
+/*
+   Copyright (C) 2023  Haven Selph
+   Copyright (C) 2023  Mustafa Quraish
+   Check the LICENSE file for more information.
+*/
+
+use crate::common::Span;
+use crate::interpreter::value::Value;
+use crate::interpreter::Interpreter;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// What a profiled program spent its time on, timed from when profiling was
+/// enabled.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub total: Duration,
+    /// The functions, builtins and imports that were called, with the
+    /// program itself as `<program>`, the most exclusive time first.
+    pub functions: Vec<FunctionProfile>,
+    /// How often each line's statements ran, the most first.
+    pub lines: Vec<LineProfile>,
+    /// The exclusive time of each call stack, outermost call first.
+    pub stacks: Vec<(Vec<String>, Duration)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionProfile {
+    /// The name of the function with where it's defined, like
+    /// `fib (fib.rat:1)`, or just the name of a builtin.
+    pub name: String,
+    pub calls: u64,
+    /// The time from calls to it until they returned. Recursive calls are
+    /// only timed once, by the outermost one.
+    pub inclusive: Duration,
+    /// The inclusive time, without that of the calls it made.
+    pub exclusive: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct LineProfile {
+    pub file: String,
+    pub line: usize,
+    pub hits: u64,
+}
+
+impl Profile {
+    /// Writes a table of the functions and one of the lines, in the order
+    /// they are sorted in.
+    pub fn write_report(&self, output: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(output, "Total time: {}", millis(self.total))?;
+        writeln!(output)?;
+        writeln!(
+            output,
+            "{:>10} {:>13} {:>13}  Function",
+            "Calls", "Inclusive", "Exclusive"
+        )?;
+        for function in &self.functions {
+            writeln!(
+                output,
+                "{:>10} {:>13} {:>13}  {}",
+                function.calls,
+                millis(function.inclusive),
+                millis(function.exclusive),
+                function.name
+            )?;
+        }
+        writeln!(output)?;
+        writeln!(output, "{:>10}  Line", "Hits")?;
+        for line in &self.lines {
+            writeln!(output, "{:>10}  {}:{}", line.hits, line.file, line.line)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the call stacks in the folded format that flame graph tools
+    /// read, one per line with the calls separated by `;` and followed by
+    /// the exclusive time in microseconds.
+    pub fn write_folded(&self, output: &mut dyn Write) -> std::io::Result<()> {
+        for (stack, time) in &self.stacks {
+            let micros = time.as_micros();
+            if micros > 0 {
+                writeln!(output, "{} {}", stack.join(";"), micros)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.3} ms", duration.as_secs_f64() * 1000.0)
+}
+
+// A call that hasn't returned yet
+#[derive(Clone)]
+struct Call {
+    name: Rc<str>,
+    // The names of the calls down to this one, separated by `;`
+    stack: Rc<str>,
+    started: Instant,
+    // The inclusive time of the calls it made
+    children: Duration,
+}
+
+#[derive(Clone, Default)]
+struct FunctionStats {
+    calls: u64,
+    // How many of its calls haven't returned, so recursion is timed once
+    active: usize,
+    inclusive: Duration,
+    exclusive: Duration,
+}
+
+// What a profiled program has done so far, shared with the interpreters that
+// run imported files
+#[derive(Clone)]
+pub(crate) struct Profiler {
+    // The program first, then the calls and imports in progress
+    calls: Vec<Call>,
+    functions: HashMap<Rc<str>, FunctionStats>,
+    lines: HashMap<(&'static str, usize), u64>,
+    stacks: HashMap<Rc<str>, Duration>,
+}
+
+impl Profiler {
+    fn new() -> Profiler {
+        let mut profiler = Profiler {
+            calls: vec![],
+            functions: HashMap::new(),
+            lines: HashMap::new(),
+            stacks: HashMap::new(),
+        };
+        profiler.enter("<program>");
+        profiler
+    }
+
+    fn enter(&mut self, name: &str) {
+        let name: Rc<str> = Rc::from(name);
+        let stack = match self.calls.last() {
+            Some(parent) => Rc::from(format!("{};{}", parent.stack, name)),
+            None => name.clone(),
+        };
+        let stats = self.functions.entry(name.clone()).or_default();
+        stats.calls += 1;
+        stats.active += 1;
+        self.calls.push(Call {
+            name,
+            stack,
+            started: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    fn exit(&mut self) {
+        let Some(call) = self.calls.pop() else {
+            return;
+        };
+        let elapsed = call.started.elapsed();
+        let exclusive = elapsed.saturating_sub(call.children);
+        let stats = self
+            .functions
+            .get_mut(&call.name)
+            .expect("Call not counted");
+        stats.active -= 1;
+        stats.exclusive += exclusive;
+        if stats.active == 0 {
+            stats.inclusive += elapsed;
+        }
+        *self.stacks.entry(call.stack).or_default() += exclusive;
+        if let Some(parent) = self.calls.last_mut() {
+            parent.children += elapsed;
+        }
+    }
+
+    // The profile so far, as if every call in progress returned now
+    fn profile(&self) -> Profile {
+        let mut profiler = self.clone();
+        while !profiler.calls.is_empty() {
+            profiler.exit();
+        }
+        // The program returns last, so no function took longer than it
+        let total = profiler
+            .functions
+            .get("<program>")
+            .map(|program| program.inclusive);
+
+        let mut functions: Vec<FunctionProfile> = profiler
+            .functions
+            .into_iter()
+            .map(|(name, stats)| FunctionProfile {
+                name: name.to_string(),
+                calls: stats.calls,
+                inclusive: stats.inclusive,
+                exclusive: stats.exclusive,
+            })
+            .collect();
+        functions.sort_by(|a, b| b.exclusive.cmp(&a.exclusive).then(a.name.cmp(&b.name)));
+
+        let mut lines: Vec<LineProfile> = profiler
+            .lines
+            .into_iter()
+            .map(|((file, line), hits)| LineProfile {
+                file: file.to_string(),
+                line,
+                hits,
+            })
+            .collect();
+        lines.sort_by(|a, b| {
+            b.hits
+                .cmp(&a.hits)
+                .then(a.file.cmp(&b.file))
+                .then(a.line.cmp(&b.line))
+        });
+
+        let mut stacks: Vec<(Vec<String>, Duration)> = profiler
+            .stacks
+            .into_iter()
+            .map(|(stack, time)| (stack.split(';').map(str::to_string).collect(), time))
+            .collect();
+        stacks.sort();
+
+        Profile {
+            total: total.unwrap_or_default(),
+            functions,
+            lines,
+            stacks,
+        }
+    }
+}
+
+impl Interpreter {
+    // Starts timing calls and counting lines, from scratch if it already was
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Rc::new(RefCell::new(Profiler::new())));
+        // Chunks only count lines when compiled for the profiler
+        if let Some(chunks) = &mut self.chunks {
+            chunks.clear();
+        }
+    }
+
+    pub fn profile(&self) -> Option<Profile> {
+        let profiler = self.profiler.as_ref()?;
+        let profile = profiler.borrow().profile();
+        Some(profile)
+    }
+
+    // Starts timing a call if it's to a function or a builtin, and returns
+    // whether it did. Classes and the like aren't timed themselves, the
+    // functions they call are.
+    pub(super) fn profile_call(&self, callee: &Value) -> bool {
+        if self.profiler.is_none() {
+            return false;
+        }
+        let name = match callee {
+            Value::Function(func) => {
+                let func = func.borrow();
+                let location = func.span.0;
+                format!("{} ({}:{})", func.name, location.filename, location.line)
+            }
+            Value::BuiltInFunction(func) => func.0.to_string(),
+            _ => return false,
+        };
+        self.enter_profile(&name);
+        true
+    }
+
+    pub(super) fn enter_profile(&self, name: &str) {
+        if let Some(profiler) = &self.profiler {
+            profiler.borrow_mut().enter(name);
+        }
+    }
+
+    pub(super) fn exit_profile(&self) {
+        if let Some(profiler) = &self.profiler {
+            profiler.borrow_mut().exit();
+        }
+    }
+
+    // Counts a statement starting to run
+    pub(super) fn count_line(&self, span: &Span) {
+        if let Some(profiler) = &self.profiler {
+            let location = span.0;
+            let mut profiler = profiler.borrow_mut();
+            *profiler
+                .lines
+                .entry((location.filename, location.line))
+                .or_default() += 1;
+        }
+    }
+}
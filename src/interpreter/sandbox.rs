@@ -0,0 +1,104 @@
+/*
+   Copyright (C) 2023  Haven Selph
+   Copyright (C) 2023  Mustafa Quraish
+   Check the LICENSE file for more information.
+*/
+
+use crate::common::Span;
+use crate::error::{sandbox_error as error, Result};
+use std::path::{Path, PathBuf};
+
+/// What scripts may do outside the interpreter. The default allows
+/// everything, like the `rattlescript` binary does, and [`Sandbox::deny_all`]
+/// is a starting point for running untrusted scripts.
+///
+/// Anything denied fails with an error of kind `ErrorKind::Sandbox`, which
+/// scripts can catch like any other error.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    /// The files and directories `open()` may open files in, or None to allow
+    /// any path.
+    pub allowed_paths: Option<Vec<PathBuf>>,
+    /// Files and directories `open()` may never open files in, even inside an
+    /// allowed path.
+    pub denied_paths: Vec<PathBuf>,
+    /// The directories modules may be imported from, or None to allow any.
+    /// Importing from the standard library needs its directory in here too.
+    pub import_roots: Option<Vec<PathBuf>>,
+    /// Whether `exit()` may end the process.
+    pub allow_exit: bool,
+    /// Whether `input()` may read from stdin.
+    pub allow_stdin: bool,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Sandbox {
+            allowed_paths: None,
+            denied_paths: Vec::new(),
+            import_roots: None,
+            allow_exit: true,
+            allow_stdin: true,
+        }
+    }
+}
+
+impl Sandbox {
+    /// Denies opening files, importing, `exit()` and `input()`.
+    pub fn deny_all() -> Sandbox {
+        Sandbox {
+            allowed_paths: Some(Vec::new()),
+            denied_paths: Vec::new(),
+            import_roots: Some(Vec::new()),
+            allow_exit: false,
+            allow_stdin: false,
+        }
+    }
+
+    pub(crate) fn check_open(&self, span: &Span, path: &str) -> Result<()> {
+        if self.allowed_paths.is_none() && self.denied_paths.is_empty() {
+            return Ok(());
+        }
+        let allowed = self.allowed_paths.as_deref();
+        if !is_inside(path, allowed) || is_inside(path, Some(&self.denied_paths)) {
+            error!(span, "Opening '{}' is not allowed", path)
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_import(&self, span: &Span, path: &str) -> Result<()> {
+        if !is_inside(path, self.import_roots.as_deref()) {
+            error!(span, "Importing '{}' is not allowed", path)
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_exit(&self, span: &Span) -> Result<()> {
+        if !self.allow_exit {
+            error!(span, "exit() is not allowed")
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_stdin(&self, span: &Span) -> Result<()> {
+        if !self.allow_stdin {
+            error!(span, "input() is not allowed")
+        }
+        Ok(())
+    }
+}
+
+// Whether a path is one of the roots or inside one of them, None meaning
+// anywhere. Paths are compared after resolving `..` and symlinks, so neither
+// can escape a root, and a path that doesn't exist is inside nothing.
+fn is_inside(path: &str, roots: Option<&[PathBuf]>) -> bool {
+    let Some(roots) = roots else {
+        return true;
+    };
+    let Ok(path) = Path::new(path).canonicalize() else {
+        return false;
+    };
+    roots
+        .iter()
+        .any(|root| root.canonicalize().is_ok_and(|root| path.starts_with(root)))
+}
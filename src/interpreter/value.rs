@@ -7,15 +7,29 @@
 use crate::ast::{ArgumentType, AST};
 use crate::common::{make, Ref, Span};
 use crate::error::{runtime_error as error, Result};
-use crate::interpreter::{Interpreter, Scope};
+use crate::interpreter::generator::Generator;
 use crate::interpreter::random::RandomState;
-use std::collections::HashMap;
+use crate::interpreter::{ControlFlow, Interpreter, Scope};
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::ops::Deref;
 use std::rc::Rc;
 
 #[derive(Clone)]
-pub struct IteratorValue(pub Ref<dyn Iterator<Item = Value>>);
+pub struct IteratorValue(pub Ref<dyn ValueIterator>);
+
+// Iterators that are driven by the interpreter. Plain Rust iterators get this
+// for free, generators need the interpreter to resume their function body.
+pub trait ValueIterator {
+    fn next(&mut self, interpreter: &mut Interpreter) -> Result<Option<Value>>;
+}
+
+impl<T: Iterator<Item = Value>> ValueIterator for T {
+    fn next(&mut self, _interpreter: &mut Interpreter) -> Result<Option<Value>> {
+        Ok(Iterator::next(self))
+    }
+}
 
 
 mod rc_chars {
@@ -102,6 +116,46 @@ impl Iterator for DictIterator {
     }
 }
 
+struct SetIterator {
+    set: Ref<HashSet<Value>>,
+    index: usize,
+}
+
+impl Iterator for SetIterator {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        let set = self.set.borrow();
+        if self.index >= set.len() {
+            None
+        } else {
+            let item = set.iter().nth(self.index).unwrap();
+            self.index += 1;
+            Some(item.clone())
+        }
+    }
+}
+
+struct InstanceIterator {
+    instance: Value,
+    span: Span,
+}
+
+impl ValueIterator for InstanceIterator {
+    fn next(&mut self, interpreter: &mut Interpreter) -> Result<Option<Value>> {
+        match interpreter.call_special_method(&self.span, &self.instance, "__next__", vec![]) {
+            Ok(value) => Ok(value),
+            Err(err) => match interpreter.control_flow {
+                ControlFlow::Throw(Value::Type(Type::StopIteration)) => {
+                    interpreter.clear_control_flow();
+                    Ok(None)
+                }
+                _ => Err(err),
+            },
+        }
+    }
+}
+
 impl IteratorValue {
     pub fn for_string(data: Rc<String>) -> IteratorValue {
         IteratorValue(make!(StringIterator {
@@ -120,6 +174,28 @@ impl IteratorValue {
     pub fn for_dict(dict: Ref<HashMap<Value, Value>>) -> IteratorValue {
         IteratorValue(make!(DictIterator { dict, index: 0 }))
     }
+
+    pub fn for_set(set: Ref<HashSet<Value>>) -> IteratorValue {
+        IteratorValue(make!(SetIterator { set, index: 0 }))
+    }
+
+    pub fn for_generator(generator: Generator) -> IteratorValue {
+        IteratorValue(make!(generator))
+    }
+
+    pub fn for_instance(instance: Value, span: &Span) -> IteratorValue {
+        IteratorValue(make!(InstanceIterator {
+            instance,
+            span: *span,
+        }))
+    }
+
+    pub fn next(&self, interpreter: &mut Interpreter, span: &Span) -> Result<Option<Value>> {
+        match self.0.try_borrow_mut() {
+            Ok(mut iter) => iter.next(interpreter),
+            Err(_) => error!(span, "Iterator is already running"),
+        }
+    }
 }
 
 impl std::fmt::Debug for IteratorValue {
@@ -135,42 +211,169 @@ pub struct Function {
     pub args: Vec<(String, Option<Value>, ArgumentType)>,
     pub required: usize,
     pub class_method: bool,
+    pub is_generator: bool,
+    // Slot names the resolver laid out for a call's scope
+    pub locals: Rc<Vec<String>>,
     pub scope: Ref<Scope>,
 }
 
 pub struct Class {
     pub span: Span,
     pub name: String,
-    pub parents: Option<Vec<Value>>,
+    // Ancestors in C3 method resolution order, not including the class itself
+    pub mro: Vec<Ref<Class>>,
     pub static_fields: Ref<HashMap<String, Value>>,
     pub fields: HashMap<String, Value>,
 }
 
 pub struct ClassInstance {
+    #[allow(dead_code)]
     pub span: Span,
     pub name: String,
     pub class: Ref<Class>,
-    pub parents: Option<Vec<Value>>,
     pub in_initializer: bool,
     pub static_fields: Ref<HashMap<String, Value>>,
     pub fields: HashMap<String, Value>,
 }
 
+pub struct Enum {
+    pub span: Span,
+    pub name: String,
+    pub variants: Vec<(String, Vec<String>)>,
+    pub static_fields: Ref<HashMap<String, Value>>,
+    pub fields: HashMap<String, Value>,
+}
+
+pub struct EnumVariant {
+    pub parent: Ref<Enum>,
+    pub name: String,
+    // `None` until a variant with a payload has been called with its values
+    pub values: Option<Vec<Value>>,
+}
+
+impl EnumVariant {
+    pub fn field_names(&self) -> Vec<String> {
+        self.parent
+            .borrow()
+            .variants
+            .iter()
+            .find(|(name, _)| *name == self.name)
+            .map(|(_, fields)| fields.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn is_variant_of(&self, other: &EnumVariant) -> bool {
+        Rc::ptr_eq(&self.parent, &other.parent) && self.name == other.name
+    }
+}
+
+impl Class {
+    // Computes the C3 linearization for a class with the given parents,
+    // without the class itself. Returns `None` when the parents can't be
+    // ordered consistently, e.g. `class C(A, B)` where `A` derives from `B`.
+    pub fn linearize(parents: &[Ref<Class>]) -> Option<Vec<Ref<Class>>> {
+        let mut sequences: Vec<Vec<Ref<Class>>> = parents
+            .iter()
+            .map(|parent| {
+                let mut sequence = vec![parent.clone()];
+                sequence.extend(parent.borrow().mro.iter().cloned());
+                sequence
+            })
+            .collect();
+        sequences.push(parents.to_vec());
+
+        let mut mro = Vec::new();
+        loop {
+            sequences.retain(|sequence| !sequence.is_empty());
+            if sequences.is_empty() {
+                return Some(mro);
+            }
+            // Take the first head that doesn't appear in the tail of any sequence
+            let head = sequences
+                .iter()
+                .map(|sequence| &sequence[0])
+                .find(|head| {
+                    !sequences
+                        .iter()
+                        .any(|sequence| sequence[1..].iter().any(|class| Rc::ptr_eq(class, head)))
+                })?
+                .clone();
+            for sequence in sequences.iter_mut() {
+                if Rc::ptr_eq(&sequence[0], &head) {
+                    sequence.remove(0);
+                }
+            }
+            mro.push(head);
+        }
+    }
+
+    // Looks up a field defined directly on this class
+    pub fn own_field(&self, field: &str) -> Option<Value> {
+        let value = self.static_fields.borrow().get(field).cloned();
+        value.or_else(|| self.fields.get(field).cloned())
+    }
+
+    // Looks up a field on this class, then on its ancestors in MRO order
+    pub fn lookup(&self, field: &str) -> Option<Value> {
+        self.own_field(field).or_else(|| {
+            self.mro
+                .iter()
+                .find_map(|class| class.borrow().own_field(field))
+        })
+    }
+
+    fn own_field_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.static_fields.borrow().keys().cloned().collect();
+        names.extend(self.fields.keys().cloned());
+        names
+    }
+
+    // The names of the fields `lookup` can find
+    pub fn field_names(&self) -> Vec<String> {
+        let mut names = self.own_field_names();
+        for class in &self.mro {
+            names.extend(class.borrow().own_field_names());
+        }
+        names
+    }
+
+    // The fields a new instance starts with, where classes earlier in the MRO
+    // override later ones
+    pub fn instance_fields(&self) -> HashMap<String, Value> {
+        let mut fields = HashMap::new();
+        for class in self.mro.iter().rev() {
+            fields.extend(class.borrow().fields.clone());
+        }
+        fields.extend(self.fields.clone());
+        fields
+    }
+
+    // Whether `self` is `other` or derives from it
+    pub fn is_subclass_of(&self, other: &Ref<Class>) -> bool {
+        std::ptr::eq(self, other.as_ptr()) || self.mro.iter().any(|class| Rc::ptr_eq(class, other))
+    }
+}
+
 impl ClassInstance {
     pub fn set_in_initializer(&mut self, in_initializer: bool) {
         self.in_initializer = in_initializer;
     }
+
+    pub fn is_instance_of(&self, class: &Ref<Class>) -> bool {
+        self.class.borrow().is_subclass_of(class)
+    }
 }
 
 pub type CallArgValues = Vec<(Option<String>, Value)>;
-pub type BuiltInFunctionType = fn(&mut Interpreter, Ref<Scope>, &Span, Vec<Value>) -> Result<Value>;
+pub type BuiltInFunctionType =
+    dyn Fn(&mut Interpreter, Ref<Scope>, &Span, Vec<Value>) -> Result<Value>;
 
 macro_rules! builtin {
     ($name:ident) => {
         crate::interpreter::value::Value::BuiltInFunction(
             crate::interpreter::value::BuiltInFunction(
-                stringify!($name),
-                make!(crate::interpreter::builtin::$name),
+                stringify!($name).into(),
+                std::rc::Rc::new(crate::interpreter::builtin::$name),
             ),
         )
     };
@@ -178,8 +381,107 @@ macro_rules! builtin {
 
 pub(crate) use builtin;
 
+// A function implemented in Rust, either one of the builtins or one the host
+// registered through the engine
 #[derive(Clone)]
-pub struct BuiltInFunction(pub &'static str, pub Ref<BuiltInFunctionType>);
+pub struct BuiltInFunction(pub Rc<str>, pub Rc<BuiltInFunctionType>);
+
+// A value owned by the host program. Scripts can pass it around and call the
+// methods the host registered for its Rust type, and nothing else.
+#[derive(Clone)]
+pub struct HostObject {
+    // The Rust type name, without its module path
+    pub name: &'static str,
+    // A `RefCell` holding the host's value
+    pub value: Rc<dyn Any>,
+}
+
+// The type of a built-in value, as returned by `type()`. Class instances and
+// enum variants use their class or enum as their type instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Type {
+    Integer,
+    Float,
+    String,
+    Boolean,
+    Array,
+    Tuple,
+    Dict,
+    Set,
+    Range,
+    Function,
+    Iterator,
+    Nothing,
+    File,
+    Error,
+    Namespace,
+    RandomState,
+    Class,
+    Enum,
+    Object,
+    // The type of type objects themselves
+    Meta,
+    // Never the type of a value, `__next__` throws it to end an iteration
+    StopIteration,
+}
+
+impl Type {
+    // Looks up the global name of a type, e.g. `int`
+    pub fn from_name(name: &str) -> Option<Type> {
+        Some(match name {
+            "int" => Type::Integer,
+            "float" => Type::Float,
+            "str" => Type::String,
+            "bool" => Type::Boolean,
+            "array" => Type::Array,
+            "tuple" => Type::Tuple,
+            "dict" => Type::Dict,
+            "set" => Type::Set,
+            "range" => Type::Range,
+            "function" => Type::Function,
+            "StopIteration" => Type::StopIteration,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Type::Integer => "int",
+            Type::Float => "float",
+            Type::String => "str",
+            Type::Boolean => "bool",
+            Type::Array => "array",
+            Type::Tuple => "tuple",
+            Type::Dict => "dict",
+            Type::Set => "set",
+            Type::Range => "range",
+            Type::Function => "function",
+            Type::Iterator => "iterator",
+            Type::Nothing => "nothing",
+            Type::File => "file",
+            Type::Error => "error",
+            Type::Namespace => "namespace",
+            Type::RandomState => "random_state",
+            Type::Class => "class",
+            Type::Enum => "enum",
+            Type::Object => "object",
+            Type::Meta => "type",
+            Type::StopIteration => "StopIteration",
+        }
+    }
+
+    // The built-in function that is called when the type is called, e.g. `int("3")`
+    pub fn constructor(&self) -> Option<Value> {
+        Some(match self {
+            Type::Integer => builtin!(to_int),
+            Type::Float => builtin!(to_float),
+            Type::String => builtin!(to_str),
+            Type::Set => builtin!(to_set),
+            Type::StopIteration => builtin!(stop_iteration),
+            _ => return None,
+        })
+    }
+}
 
 #[derive(Clone)]
 pub enum Value {
@@ -197,9 +499,15 @@ pub enum Value {
     Nothing,
     Range(i64, i64),
     Dict(Ref<HashMap<Value, Value>>),
+    Set(Ref<HashSet<Value>>),
+    Enum(Ref<Enum>),
+    EnumVariant(Rc<EnumVariant>),
+    Error(Rc<crate::error::Error>),
     String(Rc<String>),
-    Namespace(Span, String, Ref<Scope>),
+    Namespace(#[allow(dead_code)] Span, String, Ref<Scope>),
     RandomState(Ref<RandomState>),
+    Object(HostObject),
+    Type(Type),
 }
 
 impl Value {
@@ -216,6 +524,9 @@ impl Value {
     }
 }
 
+// Arrays and dicts hash their contents, so changing one that is the key of a
+// dict or set loses it, as the functions that build maps keyed by values
+// acknowledge by allowing `clippy::mutable_key_type`.
 impl Hash for Value {
     fn hash<H: Hasher>(&self, state: &mut H) {
         match self {
@@ -234,6 +545,16 @@ impl Hash for Value {
             Value::Function(func) => func.as_ptr().hash(state),
             Value::Class(class) => class.as_ptr().hash(state),
             Value::ClassInstance(instance) => instance.as_ptr().hash(state),
+            Value::Enum(enum_) => enum_.as_ptr().hash(state),
+            Value::EnumVariant(variant) => {
+                variant.parent.as_ptr().hash(state);
+                variant.name.hash(state);
+                variant
+                    .values
+                    .iter()
+                    .flatten()
+                    .for_each(|item| item.hash(state));
+            }
             Value::Array(array) => {
                 for item in array.borrow().iter() {
                     item.hash(state);
@@ -241,6 +562,10 @@ impl Hash for Value {
             }
             Value::Tuple(tuple) => tuple.borrow().iter().for_each(|item| item.hash(state)),
             Value::Dict(items) => items.borrow().iter().for_each(|item| item.hash(state)),
+            Value::Object(object) => Rc::as_ptr(&object.value).cast::<()>().hash(state),
+            Value::Type(type_) => type_.hash(state),
+            // Errors are only equal to themselves
+            Value::Error(error) => Rc::as_ptr(error).hash(state),
             _ => unreachable!("{} is not hashable", self.type_of()),
         }
     }
@@ -290,8 +615,17 @@ impl std::fmt::Debug for Value {
                 }
                 write!(f, "}}")
             }
+            Value::Set(..) => write!(f, "{}", self.repr()),
             Value::Namespace(_, name, _) => write!(f, "<namespace {}>", name),
             Value::RandomState(_) => write!(f, "<random-state>"),
+            Value::Object(object) => write!(f, "<object {}>", object.name),
+            Value::Error(error) => write!(f, "{}", error),
+            Value::Enum(enum_) => {
+                let enum_ = enum_.borrow();
+                write!(f, "<enum {}: {}>", enum_.name, enum_.span.0)
+            }
+            Value::EnumVariant(..) => write!(f, "{}", self.repr()),
+            Value::Type(type_) => write!(f, "<type {}>", type_.name()),
         }
     }
 }
@@ -328,23 +662,20 @@ impl std::cmp::PartialEq for Value {
             (Value::ClassInstance(left), Value::ClassInstance(right)) => {
                 left.as_ptr() == right.as_ptr()
             }
-            (Value::ClassInstance(left), Value::Class(right)) => {
-                // This will check if the class instance is a subclass of the class
-                // it might not be the best way to do this, but it works.
-                left.borrow().class.as_ptr() == right.as_ptr()
-            }
-            (Value::Class(left), Value::ClassInstance(right)) => {
-                // This will check if the class instance is a subclass of the class
-                // it might not be the best way to do this, but it works.
-                // Get last parent
-                left.as_ptr() == right.borrow().class.as_ptr()
-            }
             (Value::Function(left), Value::Function(right)) => left.as_ptr() == right.as_ptr(),
+            (Value::Error(left), Value::Error(right)) => Rc::ptr_eq(left, right),
+            (Value::Object(left), Value::Object(right)) => Rc::ptr_eq(&left.value, &right.value),
+            (Value::Enum(left), Value::Enum(right)) => Rc::ptr_eq(left, right),
+            (Value::EnumVariant(left), Value::EnumVariant(right)) => {
+                left.is_variant_of(right) && left.values == right.values
+            }
             (Value::Iterator(..), Value::Iterator(..)) => false,
             (Value::Range(left_start, left_end), Value::Range(right_start, right_end)) => {
                 left_start == right_start && left_end == right_end
             }
             (Value::Nothing, Value::Nothing) => true,
+            (Value::Type(left), Value::Type(right)) => left == right,
+            (Value::Set(left), Value::Set(right)) => *left.borrow() == *right.borrow(),
             (Value::Dict(left, ..), Value::Dict(right, ..)) => {
                 let left = left.borrow();
                 let right = right.borrow();
@@ -494,7 +825,6 @@ impl Value {
 
         match self {
             Value::String(s) => {
-                let s = s;
                 let (start, end, step) = get_slice_params(span, start, end, step, s.len() as i64)?;
                 let res = s
                     .chars()
@@ -520,7 +850,55 @@ impl Value {
         }
     }
 
-    pub fn get_field(&self, span: &Span, field: &String) -> Result<Value> {
+    // The names of the fields `get_field` can find, to suggest one of them
+    // when it doesn't find a field
+    pub fn field_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut enum_fields = |enum_: &Enum| {
+            names.extend(enum_.static_fields.borrow().keys().cloned());
+            names.extend(enum_.fields.keys().cloned());
+        };
+        match self {
+            Value::Namespace(_, _, scope) => {
+                return scope
+                    .borrow()
+                    .variables()
+                    .map(|(name, _)| name.clone())
+                    .collect();
+            }
+            Value::Class(class) => return class.borrow().field_names(),
+            Value::Enum(enum_) => {
+                let enum_ = enum_.borrow();
+                enum_fields(&enum_);
+                names.extend(enum_.variants.iter().map(|(name, _)| name.clone()));
+            }
+            Value::EnumVariant(variant) => {
+                enum_fields(&variant.parent.borrow());
+                names.extend(variant.field_names());
+            }
+            Value::ClassInstance(instance) => {
+                let instance = instance.borrow();
+                names.extend(instance.fields.keys().cloned());
+                names.extend(instance.class.borrow().field_names());
+            }
+            Value::Error(_) => {
+                let fields = [
+                    "message",
+                    "kind",
+                    "file",
+                    "line",
+                    "column",
+                    "end_line",
+                    "end_column",
+                ];
+                names.extend(fields.map(str::to_string));
+            }
+            _ => {}
+        }
+        names
+    }
+
+    pub fn get_field(&self, span: &Span, field: &str) -> Result<Value> {
         Ok(match self {
             Value::Namespace(_, _, scope) => match scope.borrow().get(field) {
                 Some(value) => value,
@@ -530,31 +908,76 @@ impl Value {
             },
             Value::Class(class) => {
                 let class = class.borrow();
-                let Class {
-                    span: _,
-                    name,
-                    parents: _,
-                    static_fields,
-                    fields,
-                } = class.deref();
-
-                // Check if the field exists in the class
-                let val = if let Some(value) = static_fields.borrow().get(field) {
+                match class.lookup(field) {
+                    Some(value) => value,
+                    None => error!(
+                        span,
+                        "Field '{}' not found on class '{}'", field, class.name
+                    ),
+                }
+            }
+            Value::Enum(enum_) => {
+                let enum_ref = enum_.borrow();
+                if let Some((name, fields)) =
+                    enum_ref.variants.iter().find(|(name, _)| name == field)
+                {
+                    // Variants without a payload are values, the others are constructors
+                    return Ok(Value::EnumVariant(Rc::new(EnumVariant {
+                        parent: enum_.clone(),
+                        name: name.clone(),
+                        values: if fields.is_empty() {
+                            Some(vec![])
+                        } else {
+                            None
+                        },
+                    })));
+                }
+                let val = if let Some(value) = enum_ref.static_fields.borrow().get(field) {
                     value.clone()
-                } else if let Some(value) = fields.get(field) {
+                } else if let Some(value) = enum_ref.fields.get(field) {
                     value.clone()
                 } else {
-                    error!(span, "Field '{}' not found on class '{}'", field, name);
+                    error!(
+                        span,
+                        "Field '{}' not found on enum '{}'", field, enum_ref.name
+                    );
                 };
                 val
             }
+            Value::EnumVariant(variant) => {
+                let position = variant.field_names().iter().position(|name| name == field);
+                match (&variant.values, position) {
+                    (Some(values), Some(index)) => values[index].clone(),
+                    (None, _) => error!(
+                        span,
+                        "Variant '{}' must be constructed before accessing '{}'",
+                        self.repr(),
+                        field
+                    ),
+                    _ => {
+                        let parent = variant.parent.borrow();
+                        let val = if let Some(value) = parent.static_fields.borrow().get(field) {
+                            value.clone()
+                        } else if let Some(value) = parent.fields.get(field) {
+                            value.clone()
+                        } else {
+                            error!(
+                                span,
+                                "Field '{}' not found on variant '{}'",
+                                field,
+                                self.repr()
+                            );
+                        };
+                        val
+                    }
+                }
+            }
             Value::ClassInstance(instance) => {
                 let instance = instance.borrow();
                 let ClassInstance {
                     span: _,
                     name,
-                    class: _,
-                    parents: _,
+                    class,
                     in_initializer: _,
                     static_fields,
                     fields,
@@ -564,6 +987,9 @@ impl Value {
                     value.clone()
                 } else if let Some(value) = fields.get(field) {
                     value.clone()
+                } else if let Some(value) = class.borrow().lookup(field) {
+                    // Static fields inherited from a parent class
+                    value
                 } else {
                     error!(
                         span,
@@ -572,105 +998,36 @@ impl Value {
                 };
                 val
             }
-            Value::Array(_) => match field.as_str() {
-                "len" => builtin!(len),
-                "push" => builtin!(push),
-                "pop" => builtin!(pop),
-                "str" => builtin!(to_str),
-                "iter" => builtin!(to_iter),
-                "dbg" => builtin!(debug),
+            Value::Error(error) => match field {
+                "message" => Value::String(Rc::new(error.message.clone())),
+                "kind" => Value::String(Rc::new(format!("{:?}", error.kind))),
+                "file" => Value::String(Rc::new(error.span.0.filename.to_string())),
+                "line" => Value::Integer(error.span.0.line as i64),
+                "column" => Value::Integer(error.span.0.column as i64),
+                "end_line" => Value::Integer(error.span.1.line as i64),
+                "end_column" => Value::Integer(error.span.1.column as i64),
                 _ => {
-                    error!(span, "Field '{}' not found on array", field);
+                    error!(span, "Field '{}' not found on error", field);
                 }
             },
-            Value::Tuple(_) => match field.as_str() {
-                "len" => builtin!(len),
-                "str" => builtin!(to_str),
-                "iter" => builtin!(to_iter),
-                "dbg" => builtin!(debug),
-                _ => {
-                    error!(span, "Field '{}' not found on tuple", field);
-                }
-            },
-            Value::Float(_) => match field.as_str() {
-                "int" => builtin!(to_int),
-                "str" => builtin!(to_str),
-                "dbg" => builtin!(debug),
-                _ => {
-                    error!(span, "Field '{}' not found on float", field);
-                }
-            },
-            Value::Boolean(_) => match field.as_str() {
-                "int" => builtin!(to_int),
-                "str" => builtin!(to_str),
-                "dbg" => builtin!(debug),
-                _ => {
-                    error!(span, "Field '{}' not found on boolean", field);
-                }
-            },
-            Value::Integer(_) => match field.as_str() {
-                "str" => builtin!(to_str),
-                "float" => builtin!(to_float),
-                "dbg" => builtin!(debug),
-                _ => {
-                    error!(span, "Field '{}' not found on integer", field);
-                }
-            },
-            Value::String(_) => match field.as_str() {
-                "len" => builtin!(len),
-                "split" => builtin!(split),
-                "int" => builtin!(to_int),
-                "float" => builtin!(to_float),
-                "iter" => builtin!(to_iter),
-                "dbg" => builtin!(debug),
-                "strip" => builtin!(strip),
-                "lower" => builtin!(lower),
-                "upper" => builtin!(upper),
-                _ => {
-                    error!(span, "Field '{}' not found on string", field);
-                }
-            },
-            Value::Dict(_) => match field.as_str() {
-                "len" => builtin!(len),
-                "str" => builtin!(to_str),
-                "get" => builtin!(dict_get),
-                "keys" => builtin!(dict_keys),
-                "values" => builtin!(dict_values),
-                "items" => builtin!(dict_items),
-                "dbg" => builtin!(debug),
-                _ => {
-                    error!(span, "Field '{}' not found on dict", field);
-                }
-            },
-            Value::Iterator(_) => match field.as_str() {
-                "join" => builtin!(join),
-                "enumerate" => builtin!(iter_enumerate),
-                "to_array" => builtin!(to_array),
-                "map" => builtin!(map),
-                _ => {
-                    error!(span, "Field '{}' not found on iterator", field);
-                }
-            },
-            Value::File(_) => match field.as_str() {
-                "read" => builtin!(file_read),
-                "write" => builtin!(file_write),
-                _ => {
-                    error!(span, "Field '{}' not found on file", field);
-                }
-            },
-            Value::Nothing => match field.as_str() {
-                "str" => builtin!(to_str),
-                "dbg" => builtin!(debug),
-                _ => {
-                    error!(span, "Field '{}' not found on nothing", field);
-                }
-            },
-            Value::RandomState(_) => match field.as_str() {
-                "rand_f" => builtin!(randf),
-                "rand_i" => builtin!(randi),
-                _ => {
-                    error!(span, "Field '{}' not found on random state", field);
-                }
+            // The methods of the other built-in types are in the registry
+            Value::Array(_) => error!(span, "Field '{}' not found on array", field),
+            Value::Tuple(_) => error!(span, "Field '{}' not found on tuple", field),
+            Value::Float(_) => error!(span, "Field '{}' not found on float", field),
+            Value::Boolean(_) => error!(span, "Field '{}' not found on boolean", field),
+            Value::Integer(_) => error!(span, "Field '{}' not found on integer", field),
+            Value::String(_) => error!(span, "Field '{}' not found on string", field),
+            Value::Dict(_) => error!(span, "Field '{}' not found on dict", field),
+            Value::Set(_) => error!(span, "Field '{}' not found on set", field),
+            Value::Iterator(_) => error!(span, "Field '{}' not found on iterator", field),
+            Value::File(_) => error!(span, "Field '{}' not found on file", field),
+            Value::Nothing => error!(span, "Field '{}' not found on nothing", field),
+            Value::RandomState(_) => error!(span, "Field '{}' not found on random state", field),
+            Value::Object(object) => {
+                error!(
+                    span,
+                    "Field '{}' not found on object {}", field, object.name
+                )
             }
             _ => {
                 error!(
@@ -717,8 +1074,14 @@ impl Value {
             (Value::String(left), Value::String(right)) => {
                 Value::Boolean(left.contains(right.as_str()))
             }
-            (Value::Array(left), right) => Value::Boolean(left.borrow().deref().contains(right)),
+            (Value::Array(left) | Value::Tuple(left), right) => {
+                Value::Boolean(left.borrow().deref().contains(right))
+            }
+            (Value::Dict(_) | Value::Set(_), right) if !right.is_hashable() => {
+                error!(span, "{} is not hashable", right.type_of())
+            }
             (Value::Dict(left), right) => Value::Boolean(left.borrow().deref().contains_key(right)),
+            (Value::Set(left), right) => Value::Boolean(left.borrow().contains(right)),
             _ => error!(
                 span,
                 "Invalid types for contains {} and {}",
@@ -772,6 +1135,8 @@ impl Value {
                 Value::Iterator(IteratorValue::for_array(arr.clone()))
             }
             Value::Dict(dict) => Value::Iterator(IteratorValue::for_dict(dict.clone())),
+            Value::Set(set) => Value::Iterator(IteratorValue::for_set(set.clone())),
+            Value::Iterator(_) => self.clone(),
             _ => error!(span, "Cannot iterate over this type"),
         })
     }
@@ -826,10 +1191,52 @@ impl Value {
                 s.push('}');
                 s
             }
+            Value::Set(set) => {
+                let set = set.borrow();
+                if set.is_empty() {
+                    return "set()".to_string();
+                }
+                let mut s = "{".to_string();
+                for (i, v) in set.iter().enumerate() {
+                    if i > 0 {
+                        s.push_str(", ");
+                    }
+                    s.push_str(&v.repr());
+                }
+                s.push('}');
+                s
+            }
             Value::Namespace(_, name, scope) => {
-                format!("<namespace {}> {:#?}", name, scope.borrow().vars.clone())
+                format!(
+                    "<namespace {}> {:#?}",
+                    name,
+                    scope.borrow().variables().collect::<HashMap<_, _>>()
+                )
             }
             Value::RandomState(_) => "<random-state>".to_string(),
+            Value::Object(object) => format!("<object {}>", object.name),
+            Value::Error(error) => format!("<error {}>", error),
+            Value::Type(type_) => format!("<type {}>", type_.name()),
+            Value::Enum(enum_) => {
+                let enum_ = enum_.borrow();
+                format!("<enum {}: {}>", enum_.name, enum_.span.0)
+            }
+            Value::EnumVariant(variant) => {
+                let mut s = format!("{}.{}", variant.parent.borrow().name, variant.name);
+                if let Some(values) = &variant.values {
+                    if !values.is_empty() {
+                        s.push('(');
+                        for (i, v) in values.iter().enumerate() {
+                            if i > 0 {
+                                s.push_str(", ");
+                            }
+                            s.push_str(&v.repr());
+                        }
+                        s.push(')');
+                    }
+                }
+                s
+            }
         }
     }
 
@@ -897,15 +1304,19 @@ impl Value {
                 let Class {
                     span: _,
                     name,
-                    parents: _,
+                    mro,
                     static_fields,
-                    fields,
+                    fields: _,
                 } = class.deref();
-                if static_fields.borrow().contains_key(field) {
+                // Inherited static fields are shared with the class that defines them
+                let owner = std::iter::once(static_fields.clone())
+                    .chain(mro.iter().map(|class| class.borrow().static_fields.clone()))
+                    .find(|static_fields| static_fields.borrow().contains_key(field));
+                if let Some(static_fields) = owner {
                     static_fields
                         .borrow_mut()
                         .insert(field.to_string(), value.clone());
-                } else if fields.contains_key(field) {
+                } else if class.lookup(field).is_some() {
                     error!(
                         span,
                         "Cannot mutate non-static field '{}' on class {}", field, name
@@ -914,20 +1325,33 @@ impl Value {
                     error!(span, "Field '{}' not found in class '{}'", field, name);
                 }
             }
+            Value::Enum(enum_) => {
+                let enum_ = enum_.borrow();
+                if enum_.static_fields.borrow().contains_key(field) {
+                    enum_
+                        .static_fields
+                        .borrow_mut()
+                        .insert(field.to_string(), value.clone());
+                } else {
+                    error!(
+                        span,
+                        "Static field '{}' not found in enum '{}'", field, enum_.name
+                    );
+                }
+            }
             Value::ClassInstance(inst) => {
                 let mut instance = inst.borrow_mut();
                 let ClassInstance {
                     span: _,
                     name,
-                    class: _,
-                    parents: _,
+                    class,
                     in_initializer,
-                    static_fields,
+                    static_fields: _,
                     fields: _,
                 } = instance.deref();
                 if *in_initializer || instance.fields.contains_key(field) {
                     instance.fields.insert(field.to_string(), value.clone());
-                } else if static_fields.borrow().contains_key(field) {
+                } else if class.borrow().lookup(field).is_some() {
                     error!(
                         span,
                         "Cannot mutate static field '{}' on class-instance {}", field, name
@@ -945,24 +1369,31 @@ impl Value {
     }
 
     pub fn is_hashable(&self) -> bool {
-        matches!(
-            self,
+        match self {
+            // These hash what they hold, so it has to be hashable too
+            Value::Array(items) | Value::Tuple(items) => {
+                items.borrow().iter().all(Value::is_hashable)
+            }
+            Value::Dict(items) => items.borrow().values().all(Value::is_hashable),
+            Value::EnumVariant(variant) => variant.values.iter().flatten().all(Value::is_hashable),
             Value::Integer(..)
-                | Value::Float(..)
-                | Value::String(..)
-                | Value::Boolean(..)
-                | Value::Nothing
-                | Value::Iterator(..)
-                | Value::Range(..)
-                | Value::File(..)
-                | Value::BuiltInFunction(..)
-                | Value::Function(..)
-                | Value::Class(..)
-                | Value::ClassInstance(..)
-                | Value::Array(..)
-                | Value::Tuple(..)
-                | Value::Dict(..)
-        )
+            | Value::Float(..)
+            | Value::String(..)
+            | Value::Boolean(..)
+            | Value::Nothing
+            | Value::Iterator(..)
+            | Value::Range(..)
+            | Value::File(..)
+            | Value::BuiltInFunction(..)
+            | Value::Function(..)
+            | Value::Class(..)
+            | Value::ClassInstance(..)
+            | Value::Enum(..)
+            | Value::Object(..)
+            | Value::Type(..)
+            | Value::Error(..) => true,
+            _ => false,
+        }
     }
 
     pub fn type_of(&self) -> &str {
@@ -981,12 +1412,78 @@ impl Value {
             Value::Array(..) => "Array",
             Value::Tuple(..) => "Tuple",
             Value::Dict(..) => "Dict",
+            Value::Set(..) => "Set",
             Value::Iterator(..) => "Iterator",
             Value::Namespace(..) => "Namespace",
             Value::RandomState(..) => "RandomState",
+            Value::Object(..) => "Object",
+            Value::Error(..) => "Error",
+            Value::Enum(..) => "Enum",
+            Value::EnumVariant(..) => "EnumVariant",
+            Value::Type(..) => "Type",
         }
     }
 
+    // The type object for a value, as returned by `type()`
+    pub fn get_type(&self) -> Value {
+        Value::Type(match self {
+            Value::ClassInstance(instance) => return Value::Class(instance.borrow().class.clone()),
+            Value::EnumVariant(variant) => return Value::Enum(variant.parent.clone()),
+            Value::Integer(..) => Type::Integer,
+            Value::Float(..) => Type::Float,
+            Value::String(..) => Type::String,
+            Value::Boolean(..) => Type::Boolean,
+            Value::Array(..) => Type::Array,
+            Value::Tuple(..) => Type::Tuple,
+            Value::Dict(..) => Type::Dict,
+            Value::Set(..) => Type::Set,
+            Value::Range(..) => Type::Range,
+            Value::Function(..) | Value::BuiltInFunction(..) => Type::Function,
+            Value::Iterator(..) => Type::Iterator,
+            Value::Nothing => Type::Nothing,
+            Value::File(..) => Type::File,
+            Value::Error(..) => Type::Error,
+            Value::Namespace(..) => Type::Namespace,
+            Value::RandomState(..) => Type::RandomState,
+            Value::Class(..) => Type::Class,
+            Value::Enum(..) => Type::Enum,
+            Value::Object(..) => Type::Object,
+            Value::Type(..) => Type::Meta,
+        })
+    }
+
+    // Whether a value is an instance of a type, class or enum, or of any of
+    // the ones in a tuple or array of them
+    pub fn is_instance_of(&self, span: &Span, types: &Value) -> Result<bool> {
+        Ok(match types {
+            Value::Type(type_) => match self.get_type() {
+                Value::Type(other) => other == *type_,
+                _ => false,
+            },
+            Value::Class(class) => match self {
+                Value::ClassInstance(instance) => instance.borrow().is_instance_of(class),
+                _ => false,
+            },
+            Value::Enum(enum_) => match self {
+                Value::EnumVariant(variant) => Rc::ptr_eq(&variant.parent, enum_),
+                _ => false,
+            },
+            Value::Tuple(items) | Value::Array(items) => {
+                for item in items.borrow().iter() {
+                    if self.is_instance_of(span, item)? {
+                        return Ok(true);
+                    }
+                }
+                false
+            }
+            other => error!(
+                span,
+                "isinstance() expects a type, class or enum, or a tuple of them, but got {}",
+                other.type_of()
+            ),
+        })
+    }
+
     // pub fn get_class_field(&self, field: &str) -> Option<(Value, bool)> {
     //     match self {
     //         Value::Class(class) => {
@@ -0,0 +1,316 @@
+/*
+   Copyright (C) 2023  Haven Selph
+   Copyright (C) 2023  Mustafa Quraish
+   Check the LICENSE file for more information.
+*/
+
+use crate::ast::AST;
+use crate::common::{make, Ref, Span};
+use crate::error::{runtime_error as error, Result};
+use crate::interpreter::compiler::{compile, BinaryOp, Chunk, Op};
+use crate::interpreter::value::{CallArgValues, Value};
+use crate::interpreter::{ControlFlow, Interpreter, Scope};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+// Compiled chunks keyed by the address of their AST, which is kept alive
+// alongside so the address can't be reused
+pub type ChunkCache = HashMap<*const AST, (Rc<AST>, Rc<Chunk>)>;
+
+impl Interpreter {
+    // Runs a function body or program on the VM in the given scope,
+    // compiling it the first time it runs
+    pub(super) fn run_compiled(&mut self, body: &Rc<AST>, scope: Ref<Scope>) -> Result<Value> {
+        let chunks = self.chunks.as_mut().expect("The VM is not enabled");
+        let chunk = match chunks.get(&Rc::as_ptr(body)) {
+            Some((_, chunk)) => chunk.clone(),
+            None => {
+                let chunk = Rc::new(compile(body, self.profiler.is_some()));
+                chunks.insert(Rc::as_ptr(body), (body.clone(), chunk.clone()));
+                chunk
+            }
+        };
+        self.run_chunk(&chunk, scope)
+    }
+
+    #[allow(clippy::mutable_key_type)]
+    fn run_chunk(&mut self, chunk: &Chunk, mut scope: Ref<Scope>) -> Result<Value> {
+        let mut stack: Vec<Value> = Vec::with_capacity(8);
+        let mut ip = 0;
+        macro_rules! pop {
+            () => {
+                stack.pop().expect("VM stack underflow")
+            };
+        }
+        macro_rules! pop_many {
+            ($count:expr) => {
+                stack.split_off(stack.len() - $count)
+            };
+        }
+        while ip < chunk.code.len() {
+            let span = &chunk.spans[ip];
+            let op = chunk.code[ip];
+            ip += 1;
+            self.step(span)?;
+            match op {
+                Op::Constant(index) => stack.push(chunk.constants[index].clone()),
+                Op::Pop => {
+                    pop!();
+                }
+                Op::Dup => stack.push(stack.last().expect("VM stack underflow").clone()),
+                Op::Load(variable) => {
+                    let (name, slot) = &chunk.variables[variable];
+                    stack.push(self.load_variable(&scope, span, name, *slot)?);
+                }
+                Op::Declare(name) => {
+                    let name = &chunk.names[name];
+                    self.check_arg_name(name, span)?;
+                    scope.borrow_mut().insert(name, pop!(), false, span)?;
+                }
+                Op::Assign(variable) => {
+                    let (name, slot) = &chunk.variables[variable];
+                    self.store_variable(&scope, span, name, *slot, pop!())?;
+                }
+                Op::Bind(pattern) => {
+                    let value = pop!();
+                    self.bind_pattern(&chunk.patterns[pattern], value, scope.clone())?;
+                }
+                Op::GetField(field) => {
+                    let obj = pop!();
+                    stack.push(self.get_field(span, &obj, &chunk.names[field])?);
+                }
+                Op::SetField(field) => {
+                    let obj = pop!();
+                    let value = pop!();
+                    obj.set_field(span, &chunk.names[field], &value)?;
+                }
+                Op::GetMethod(field) => {
+                    let obj = pop!();
+                    let method = self.get_field(span, &obj, &chunk.names[field])?;
+                    stack.push(obj);
+                    stack.push(method);
+                }
+                Op::Index => {
+                    let right = pop!();
+                    let left = pop!();
+                    let args = vec![(None, right.clone())];
+                    let value = match self.call_special_method(span, &left, "__getitem__", args)? {
+                        Some(value) => value,
+                        None => left.index(&right, span)?,
+                    };
+                    stack.push(value);
+                }
+                Op::SetIndex => {
+                    let right = pop!();
+                    let left = pop!();
+                    let value = pop!();
+                    let args = vec![(None, right.clone()), (None, value.clone())];
+                    if self
+                        .call_special_method(span, &left, "__setitem__", args)?
+                        .is_none()
+                    {
+                        self.set_index(span, &left, &right, &value)?;
+                    }
+                }
+                Op::Binary(op) => {
+                    let right = pop!();
+                    let left = pop!();
+                    stack.push(self.binary_op(span, op, left, right)?);
+                }
+                Op::Negate => {
+                    let value = pop!();
+                    let value = match self.call_special_method(span, &value, "__neg__", vec![])? {
+                        Some(value) => value,
+                        None => value.negate(span)?,
+                    };
+                    stack.push(value);
+                }
+                Op::Not => {
+                    let value = pop!();
+                    stack.push(value.not(span)?);
+                }
+                Op::And(target) | Op::Or(target) => {
+                    let short_circuit = matches!(op, Op::Or(_));
+                    match stack.last() {
+                        Some(Value::Boolean(value)) if *value == short_circuit => ip = target,
+                        Some(Value::Boolean(_)) => {
+                            pop!();
+                        }
+                        Some(value) => {
+                            error!(span, "Expected boolean, but got {}", value.type_of())
+                        }
+                        None => unreachable!("VM stack underflow"),
+                    }
+                }
+                Op::CheckBoolean => match stack.last() {
+                    Some(Value::Boolean(_)) => {}
+                    Some(value) => error!(span, "Expected boolean, but got {}", value.type_of()),
+                    None => unreachable!("VM stack underflow"),
+                },
+                Op::Jump(target) => ip = target,
+                Op::JumpIfFalse(target, message) => match pop!() {
+                    Value::Boolean(true) => {}
+                    Value::Boolean(false) => ip = target,
+                    _ => error!(span, "{}", message),
+                },
+                Op::Iter => {
+                    let value = pop!();
+                    stack.push(Value::Iterator(self.iterate(&value, span)?));
+                }
+                Op::Next(target) => {
+                    let Some(Value::Iterator(iter)) = stack.last() else {
+                        unreachable!("Next without an iterator")
+                    };
+                    match iter.clone().next(self, span)? {
+                        Some(value) => stack.push(value),
+                        None => ip = target,
+                    }
+                }
+                Op::PushScope(layout) => {
+                    let in_function = scope.borrow().in_function;
+                    scope =
+                        Scope::with_layout(Some(scope), in_function, Some(&chunk.layouts[layout]));
+                }
+                Op::PopScope => {
+                    let parent = scope.borrow().parent.clone();
+                    scope = parent.expect("Popped the outermost scope");
+                }
+                Op::ClearScope => scope.borrow_mut().clear(),
+                Op::Call(index) | Op::CallMethod(index) => {
+                    let names = &chunk.calls[index];
+                    let args: CallArgValues = names
+                        .iter()
+                        .cloned()
+                        .zip(stack.drain(stack.len() - names.len()..))
+                        .collect();
+                    let callee = pop!();
+                    let parent = match op {
+                        Op::CallMethod(_) => Some(pop!()),
+                        _ => None,
+                    };
+                    stack.push(self.do_call(span, scope.clone(), parent, callee, &args)?);
+                }
+                Op::Return => {
+                    if !scope.borrow().in_function {
+                        error!(span, "Return statement outside of function")
+                    }
+                    self.control_flow = ControlFlow::Return(pop!());
+                    return Ok(Value::Nothing);
+                }
+                Op::Break => {
+                    self.control_flow = ControlFlow::Break;
+                    return Ok(Value::Nothing);
+                }
+                Op::Continue => {
+                    self.control_flow = ControlFlow::Continue;
+                    return Ok(Value::Nothing);
+                }
+                Op::MakeArray(count) => {
+                    let items = pop_many!(count);
+                    stack.push(Value::Array(make!(items)));
+                }
+                Op::MakeTuple(count) => {
+                    let items = pop_many!(count);
+                    stack.push(Value::Tuple(make!(items)));
+                }
+                Op::MakeDict(count) => {
+                    let mut items = pop_many!(count * 2).into_iter();
+                    let mut map = HashMap::new();
+                    while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                        map.insert(key, value);
+                    }
+                    stack.push(Value::Dict(make!(map)));
+                }
+                Op::MakeSet(count) => {
+                    let set: HashSet<Value> = pop_many!(count).into_iter().collect();
+                    stack.push(Value::Set(make!(set)));
+                }
+                Op::CheckHashable(message) => {
+                    if !stack.last().expect("VM stack underflow").is_hashable() {
+                        error!(span, "{}", message)
+                    }
+                }
+                Op::MakeRange => {
+                    let end = pop!();
+                    let start = pop!();
+                    stack.push(Value::create_range(&start, &end, span)?);
+                }
+                Op::Increment {
+                    variable,
+                    offset,
+                    post,
+                } => {
+                    let (name, slot) = &chunk.variables[variable];
+                    let value = self.load_variable(&scope, span, name, *slot)?;
+                    let Value::Integer(old) = value else {
+                        error!(span, "Operation only supported for integers")
+                    };
+                    let new_value = Value::Integer(old + offset);
+                    self.store_variable(&scope, span, name, *slot, new_value.clone())?;
+                    stack.push(if post { value } else { new_value });
+                }
+                Op::Format => {
+                    let value = pop!();
+                    let string = self.format_value(span, &value)?;
+                    stack.push(Value::String(Rc::new(string)));
+                }
+                Op::BuildString(strings, count) => {
+                    let values = pop_many!(count);
+                    let mut result = String::new();
+                    for (i, string) in chunk.strings[strings].iter().enumerate() {
+                        result.push_str(string);
+                        if let Some(Value::String(value)) = values.get(i) {
+                            result.push_str(value);
+                        }
+                    }
+                    self.check_size(span, result.len())?;
+                    stack.push(Value::String(Rc::new(result)));
+                }
+                Op::Statement => self.count_line(span),
+                Op::Eval(node, exit) => {
+                    let value = self.run(&chunk.nodes[node], scope.clone())?;
+                    match (&self.control_flow, exit) {
+                        (ControlFlow::None, _) => stack.push(value),
+                        (ControlFlow::Break | ControlFlow::Continue, Some(exit)) => {
+                            let exit = &chunk.exits[exit];
+                            ip = match self.control_flow {
+                                ControlFlow::Break => exit.break_target,
+                                _ => exit.continue_target,
+                            };
+                            self.control_flow = ControlFlow::None;
+                            for _ in 0..exit.scopes {
+                                let parent = scope.borrow().parent.clone();
+                                scope = parent.expect("Popped the outermost scope");
+                            }
+                            stack.truncate(stack.len() - exit.pops);
+                        }
+                        // Anything else leaves this chunk, like in the tree walker
+                        _ => return Ok(Value::Nothing),
+                    }
+                }
+            }
+        }
+        Ok(stack.pop().unwrap_or(Value::Nothing))
+    }
+
+    fn binary_op(&mut self, span: &Span, op: BinaryOp, left: Value, right: Value) -> Result<Value> {
+        if let BinaryOp::Contains = op {
+            return self.contains(span, &left, &right);
+        }
+        let instance = |value: &Value| matches!(value, Value::ClassInstance(..));
+        if !instance(&left) && !instance(&right) {
+            self.check_operands(span, op.method(), &left, &right)?;
+            return op.apply(&left, &right, span);
+        }
+        if let BinaryOp::NotEquals = op {
+            return self.not_equals(span, &left, &right);
+        }
+        match self.call_operator_method(span, op.method(), &left, &right)? {
+            Some(value) => Ok(value),
+            None => {
+                self.check_operands(span, op.method(), &left, &right)?;
+                op.apply(&left, &right, span)
+            }
+        }
+    }
+}
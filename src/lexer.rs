@@ -6,7 +6,7 @@
 
 use crate::common::{Location, Span};
 use crate::error::{lexer_error as error, Result};
-use crate::token::{Token, TokenKind};
+use crate::token::{Comment, Token, TokenKind};
 
 #[derive(Debug)]
 pub struct Lexer {
@@ -14,6 +14,7 @@ pub struct Lexer {
     input: String,
     current_index: usize,
     seen_newline: bool,
+    comments: Vec<Comment>,
 }
 
 impl Lexer {
@@ -27,9 +28,15 @@ impl Lexer {
             input,
             current_index: 0,
             seen_newline: false,
+            comments: vec![],
         }
     }
 
+    // The comments skipped by `lex`, in order
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        std::mem::take(&mut self.comments)
+    }
+
     fn cur(&self) -> Option<char> {
         self.input.chars().nth(self.current_index)
     }
@@ -80,6 +87,16 @@ impl Lexer {
         self.seen_newline = false;
     }
 
+    fn push_comment(&mut self, start: Location, text: String, own_line: bool) {
+        let span = Span(start, self.loc());
+        let text = text.trim_end().to_string();
+        self.comments.push(Comment {
+            span,
+            text,
+            own_line,
+        });
+    }
+
     pub fn lex(&mut self) -> Result<Vec<Token>> {
         let mut tokens: Vec<Token> = vec![];
         while let Some(c) = self.cur() {
@@ -88,7 +105,7 @@ impl Lexer {
                 c if c.is_whitespace() => self.increment(),
 
                 // base N literals, i.e. 0b_1101, 0o_567, 0x_ff
-                '0' if self.peek(1).map_or(false, |c| "box".contains(c)) => {
+                '0' if self.peek(1).is_some_and(|c| "box".contains(c)) => {
                     let mut num = String::new();
 
                     let base = match self.peek(1) {
@@ -156,14 +173,21 @@ impl Lexer {
                 },
                 '/' => match self.peek(1) {
                     Some('/') => {
+                        let own_line = self.seen_newline || tokens.is_empty();
+                        let mut text = String::new();
                         while let Some(c) = self.cur() {
-                            self.increment();
                             if c == '\n' {
                                 break;
                             }
+                            text.push(c);
+                            self.increment();
                         }
+                        self.push_comment(start, text, own_line);
+                        self.increment();
                     }
                     Some('*') => {
+                        let own_line = self.seen_newline || tokens.is_empty();
+                        let begin = self.current_index;
                         let mut closed = false;
                         while let Some(c) = self.cur() {
                             self.increment();
@@ -176,6 +200,9 @@ impl Lexer {
                         if !closed {
                             error!(Span(start, self.loc()), "Unterminated block comment");
                         }
+                        let text = self.input.chars().skip(begin);
+                        let text = text.take(self.current_index - begin).collect();
+                        self.push_comment(start, text, own_line);
                     }
                     Some('=') => self.push_simple(&mut tokens, TokenKind::SlashEquals, 2),
                     _ => self.push_simple(&mut tokens, TokenKind::Slash, 1),
@@ -0,0 +1,55 @@
+/*
+    Rattlescript is a dynamically typed, interpreted programming language written in Rust.
+    Copyright (C) 2023  Haven Selph
+    Copyright (C) 2023  Mustafa Quraish
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! RattleScript as a library. An [`Engine`] runs scripts from a host
+//! program, and the `rattlescript` binary is a thin client of it.
+
+#![allow(clippy::upper_case_acronyms)]
+
+mod ast;
+mod common;
+mod dap;
+mod engine;
+mod error;
+mod formatter;
+mod interpreter;
+mod json;
+mod lexer;
+mod lsp;
+mod parser;
+mod repl;
+mod resolver;
+mod test_runner;
+mod token;
+
+pub use common::{Location, Span};
+pub use dap::DebugAdapter;
+pub use engine::Engine;
+pub use error::{Error, ErrorKind, Frame, Label, Result};
+pub use formatter::format_source;
+pub use interpreter::debugger::{DebugClient, Paused, Resume, StackFrame, StopReason};
+pub use interpreter::limits::Limits;
+pub use interpreter::native::{FromValue, IntoValue, NativeFunction, NativeResult};
+pub use interpreter::profiler::{FunctionProfile, LineProfile, Profile};
+pub use interpreter::sandbox::Sandbox;
+pub use interpreter::value::{HostObject, Type, Value};
+pub use json::Json;
+pub use lsp::LanguageServer;
+pub use repl::{DebugPrompt, Repl, REPL_VERSION};
+pub use test_runner::TestRunner;
@@ -0,0 +1,1031 @@
+/*
+   Copyright (C) 2023  Haven Selph
+   Copyright (C) 2023  Mustafa Quraish
+   Check the LICENSE file for more information.
+*/
+
+use crate::ast::{import_name, ArgumentType, FunctionArgs, Pattern, AST};
+use crate::common::{Location, Span};
+use crate::error::Error;
+use crate::interpreter::native::Registry;
+use crate::json::{read_message, write_message, Json};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::token::{Token, TokenKind};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+/// A language server for RattleScript files, speaking the Language Server
+/// Protocol. It checks open files for errors as they change, and offers
+/// go-to-definition, hover, document symbols and completion.
+pub struct LanguageServer {
+    registry: Registry,
+    documents: HashMap<String, Document>,
+    // Spans need a static filename, so each URI is leaked only once
+    filenames: HashMap<String, &'static str>,
+    shutdown: bool,
+}
+
+// What the server knows about an open file. When a change doesn't lex or
+// parse, whatever could no longer be worked out is kept from before it.
+#[derive(Default)]
+struct Document {
+    tokens: Vec<Token>,
+    definitions: Vec<Definition>,
+}
+
+// A name declared somewhere in a file
+struct Definition {
+    name: String,
+    kind: SymbolKind,
+    // The name where it's declared, and the whole declaration
+    name_span: Span,
+    span: Span,
+    // Where the name can be used on its own. Fields and methods of classes
+    // and enums can only be used through a `.`, so they have none.
+    scope: Option<Span>,
+    // The class, enum, namespace or function it's declared in
+    parent: Option<usize>,
+    // What hovering over it shows, like the signature of a function
+    detail: String,
+}
+
+// The numbers are the ones the protocol uses for each kind of symbol
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SymbolKind {
+    Module = 2,
+    Namespace = 3,
+    Class = 5,
+    Method = 6,
+    Field = 8,
+    Enum = 10,
+    Function = 12,
+    Variable = 13,
+    EnumMember = 22,
+}
+
+impl SymbolKind {
+    // The closest kind of completion item, which the protocol numbers
+    // differently
+    fn completion_kind(self) -> usize {
+        match self {
+            SymbolKind::Module | SymbolKind::Namespace => 9,
+            SymbolKind::Class => 7,
+            SymbolKind::Method => 2,
+            SymbolKind::Field => 5,
+            SymbolKind::Enum => 13,
+            SymbolKind::Function => 3,
+            SymbolKind::Variable => 6,
+            SymbolKind::EnumMember => 20,
+        }
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "and",
+    "as",
+    "assert",
+    "break",
+    "catch",
+    "class",
+    "continue",
+    "def",
+    "elif",
+    "else",
+    "enum",
+    "false",
+    "finally",
+    "for",
+    "from",
+    "if",
+    "import",
+    "in",
+    "let",
+    "match",
+    "namespace",
+    "not",
+    "nothing",
+    "or",
+    "return",
+    "static",
+    "super",
+    "throw",
+    "true",
+    "try",
+    "while",
+    "yield",
+];
+
+// Error codes from the protocol
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_REQUEST: i64 = -32600;
+
+impl Default for LanguageServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LanguageServer {
+    pub fn new() -> LanguageServer {
+        LanguageServer {
+            registry: Registry::new(),
+            documents: HashMap::new(),
+            filenames: HashMap::new(),
+            shutdown: false,
+        }
+    }
+
+    /// Answers messages from `input` until the client asks the server to
+    /// exit or the input ends. Returns whether the client shut the server
+    /// down properly first, which decides its exit code.
+    pub fn serve(
+        &mut self,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> std::io::Result<bool> {
+        while let Some(message) = read_message(input)? {
+            let method = message.get("method").and_then(Json::as_str);
+            let params = message.get("params").unwrap_or(&Json::Null);
+            match (method, message.get("id")) {
+                (Some("exit"), _) => return Ok(self.shutdown),
+                (Some(method), Some(id)) => {
+                    let response = match self.request(method, params) {
+                        Ok(result) => Json::object(vec![
+                            ("jsonrpc", "2.0".into()),
+                            ("id", id.clone()),
+                            ("result", result),
+                        ]),
+                        Err((code, message)) => Json::object(vec![
+                            ("jsonrpc", "2.0".into()),
+                            ("id", id.clone()),
+                            (
+                                "error",
+                                Json::object(vec![
+                                    ("code", Json::Number(code as f64)),
+                                    ("message", message.into()),
+                                ]),
+                            ),
+                        ]),
+                    };
+                    write_message(output, &response)?;
+                }
+                (Some(method), None) => {
+                    if let Some(diagnostics) = self.notification(method, params) {
+                        write_message(output, &diagnostics)?;
+                    }
+                }
+                // Responses to requests the server never sends
+                (None, _) => {}
+            }
+        }
+        Ok(false)
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, (i64, String)> {
+        if self.shutdown && method != "shutdown" {
+            return Err((INVALID_REQUEST, "The server is shutting down".to_string()));
+        }
+        match method {
+            "initialize" => Ok(Json::object(vec![
+                (
+                    "capabilities",
+                    Json::object(vec![
+                        // The client sends the whole file on every change
+                        ("textDocumentSync", 1usize.into()),
+                        ("definitionProvider", true.into()),
+                        ("hoverProvider", true.into()),
+                        ("documentSymbolProvider", true.into()),
+                        (
+                            "completionProvider",
+                            Json::object(vec![("triggerCharacters", vec![".".into()].into())]),
+                        ),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    Json::object(vec![
+                        ("name", "rattlescript".into()),
+                        ("version", env!("CARGO_PKG_VERSION").into()),
+                    ]),
+                ),
+            ])),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/definition" => Ok(self.with_position(params, |document, uri, pos| {
+                let definition = document.definition_at(pos)?;
+                Some(Json::object(vec![
+                    ("uri", uri.into()),
+                    ("range", range(&definition.name_span)),
+                ]))
+            })),
+            "textDocument/hover" => Ok(self.with_position(params, |document, _, pos| {
+                let token = document.identifier_at(pos)?;
+                let detail = match document.definition_at(pos) {
+                    Some(definition) => format!("```rattlescript\n{}\n```", definition.detail),
+                    None => self.builtin_detail(document, token)?,
+                };
+                Some(Json::object(vec![
+                    (
+                        "contents",
+                        Json::object(vec![("kind", "markdown".into()), ("value", detail.into())]),
+                    ),
+                    ("range", range(&token.span)),
+                ]))
+            })),
+            "textDocument/documentSymbol" => {
+                let uri = text_document_uri(params).unwrap_or_default();
+                match self.documents.get(uri) {
+                    Some(document) => Ok(document.symbols(None).into()),
+                    None => Ok(Json::Null),
+                }
+            }
+            "textDocument/completion" => Ok(self.with_position(params, |document, _, pos| {
+                Some(self.completions(document, pos).into())
+            })),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        }
+    }
+
+    // Handles a notification, returning the diagnostics to publish for the
+    // file it changed, if any
+    fn notification(&mut self, method: &str, params: &Json) -> Option<Json> {
+        let uri = text_document_uri(params)?.to_string();
+        let errors = match method {
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument")?.get("text")?.as_str()?;
+                self.update(&uri, text.to_string())
+            }
+            "textDocument/didChange" => {
+                let changes = params.get("contentChanges")?.as_array()?;
+                let text = changes.last()?.get("text")?.as_str()?;
+                self.update(&uri, text.to_string())
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![]
+            }
+            _ => return None,
+        };
+        let diagnostics = errors.iter().map(|err| diagnostic(&uri, err)).collect();
+        Some(Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            (
+                "params",
+                Json::object(vec![
+                    ("uri", uri.into()),
+                    ("diagnostics", Json::Array(diagnostics)),
+                ]),
+            ),
+        ]))
+    }
+
+    // Lexes, parses and resolves a new version of a file, like running it
+    // would, returning the errors in it
+    fn update(&mut self, uri: &str, text: String) -> Vec<Error> {
+        let filename = *self
+            .filenames
+            .entry(uri.to_string())
+            .or_insert_with(|| Box::leak(uri_to_path(uri).into_boxed_str()));
+        let document = self.documents.entry(uri.to_string()).or_default();
+        let tokens = match Lexer::new(text, filename).lex() {
+            Ok(tokens) => tokens,
+            Err(err) => return vec![err],
+        };
+        let (ast, mut errors) = Parser::new(tokens.clone()).parse_partial();
+        document.tokens = tokens;
+        if let Some(ast) = ast {
+            if errors.is_empty() {
+                if let Err(err) = crate::resolver::resolve(&ast, &[], &self.registry) {
+                    errors.push(err);
+                }
+            }
+            document.definitions = Definitions::collect(&ast, &document.tokens);
+        }
+        errors
+    }
+
+    // Runs a request about a position in an open file, answering null when
+    // there's nothing to say about it
+    fn with_position(
+        &self,
+        params: &Json,
+        f: impl FnOnce(&Document, &str, (usize, usize)) -> Option<Json>,
+    ) -> Json {
+        let uri = text_document_uri(params).unwrap_or_default();
+        let position = params.get("position");
+        let line = position
+            .and_then(|pos| pos.get("line"))
+            .and_then(Json::as_usize);
+        let character = position
+            .and_then(|pos| pos.get("character"))
+            .and_then(Json::as_usize);
+        match (self.documents.get(uri), line, character) {
+            (Some(document), Some(line), Some(character)) => {
+                f(document, uri, (line + 1, character + 1)).unwrap_or(Json::Null)
+            }
+            _ => Json::Null,
+        }
+    }
+
+    // What hovering over a name that isn't declared in the file shows, if
+    // it's a builtin
+    fn builtin_detail(&self, document: &Document, token: &Token) -> Option<String> {
+        if document.after_dot(token) {
+            let types = self.method_types(&token.text);
+            if types.is_empty() {
+                return None;
+            }
+            return Some(format!("Built-in method of {}", types.join(", ")));
+        }
+        self.registry.global(&token.text)?;
+        Some(format!("Built-in `{}`", token.text))
+    }
+
+    // The built-in types with a method of the given name, sorted
+    fn method_types(&self, name: &str) -> Vec<&'static str> {
+        let mut types: Vec<&str> = self
+            .registry
+            .all_method_names()
+            .filter(|(_, method)| *method == name)
+            .map(|(type_, _)| type_.name())
+            .collect();
+        types.sort();
+        types
+    }
+
+    fn completions(&self, document: &Document, pos: (usize, usize)) -> Vec<Json> {
+        let mut items: Vec<(String, usize, String)> = vec![];
+        if let Some(receiver) = document.receiver_at(pos) {
+            // Literals are the only receivers whose type is known
+            let only = match receiver.kind {
+                TokenKind::StringLiteral | TokenKind::FormatStringLiteral => Some("str"),
+                TokenKind::IntegerLiteralDec
+                | TokenKind::IntegerLiteralBin
+                | TokenKind::IntegerLiteralHex
+                | TokenKind::IntegerLiteralOct => Some("int"),
+                TokenKind::FloatLiteral => Some("float"),
+                _ => None,
+            };
+            for (type_, name) in self.registry.all_method_names() {
+                if only.is_none_or(|only| only == type_.name()) {
+                    let detail =
+                        format!("Built-in method of {}", self.method_types(name).join(", "));
+                    items.push((
+                        name.to_string(),
+                        SymbolKind::Method.completion_kind(),
+                        detail,
+                    ));
+                }
+            }
+            for definition in &document.definitions {
+                if only.is_none() && document.is_member(definition) {
+                    let kind = definition.kind.completion_kind();
+                    items.push((definition.name.clone(), kind, definition.detail.clone()));
+                }
+            }
+        } else {
+            for definition in document.visible_at(pos) {
+                let kind = definition.kind.completion_kind();
+                items.push((definition.name.clone(), kind, definition.detail.clone()));
+            }
+            for name in self.registry.global_names() {
+                let detail = format!("Built-in `{}`", name);
+                items.push((
+                    name.to_string(),
+                    SymbolKind::Function.completion_kind(),
+                    detail,
+                ));
+            }
+            for keyword in KEYWORDS {
+                items.push((keyword.to_string(), 14, "keyword".to_string()));
+            }
+        }
+        // The first of each name wins, so declarations in the file shadow
+        // builtins
+        let mut seen = std::collections::HashSet::new();
+        items.retain(|(name, _, _)| seen.insert(name.clone()));
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        items
+            .into_iter()
+            .map(|(label, kind, detail)| {
+                Json::object(vec![
+                    ("label", label.into()),
+                    ("kind", kind.into()),
+                    ("detail", detail.into()),
+                ])
+            })
+            .collect()
+    }
+}
+
+impl Document {
+    fn identifier_at(&self, pos: (usize, usize)) -> Option<&Token> {
+        self.tokens
+            .iter()
+            .find(|token| token.kind == TokenKind::Identifier && contains(&token.span, pos))
+    }
+
+    // Whether a token is a field name, like `y` in `x.y`
+    fn after_dot(&self, token: &Token) -> bool {
+        let index = self
+            .tokens
+            .iter()
+            .position(|other| std::ptr::eq(other, token));
+        index.is_some_and(|index| index > 0 && self.tokens[index - 1].kind == TokenKind::Dot)
+    }
+
+    // The token before the `.` of a field being typed at a position, like `x`
+    // in `x.` or `x.fo`
+    fn receiver_at(&self, pos: (usize, usize)) -> Option<&Token> {
+        let before = self
+            .tokens
+            .iter()
+            .rposition(|token| location(&token.span.0) < pos)?;
+        let dot = match self.tokens[before].kind {
+            TokenKind::Dot => before,
+            TokenKind::Identifier if before > 0 => before - 1,
+            _ => return None,
+        };
+        match (self.tokens[dot].kind == TokenKind::Dot, dot.checked_sub(1)) {
+            (true, Some(receiver)) => Some(&self.tokens[receiver]),
+            _ => None,
+        }
+    }
+
+    fn is_member(&self, definition: &Definition) -> bool {
+        definition.parent.is_some_and(|parent| {
+            matches!(
+                self.definitions[parent].kind,
+                SymbolKind::Class | SymbolKind::Enum | SymbolKind::Namespace
+            )
+        })
+    }
+
+    // The definitions whose names can be used on their own at a position,
+    // innermost scope first
+    fn visible_at(&self, pos: (usize, usize)) -> Vec<&Definition> {
+        let mut visible: Vec<&Definition> = self
+            .definitions
+            .iter()
+            .filter(|definition| definition.scope.is_some_and(|scope| contains(&scope, pos)))
+            .collect();
+        visible.sort_by_key(|definition| {
+            let scope = definition.scope.expect("Filtered on having a scope");
+            std::cmp::Reverse(location(&scope.0))
+        });
+        visible
+    }
+
+    // The declaration the name at a position refers to
+    fn definition_at(&self, pos: (usize, usize)) -> Option<&Definition> {
+        let token = self.identifier_at(pos)?;
+        if let Some(definition) = self
+            .definitions
+            .iter()
+            .find(|definition| contains(&definition.name_span, pos))
+        {
+            return Some(definition);
+        }
+        if self.after_dot(token) {
+            return self
+                .definitions
+                .iter()
+                .find(|definition| definition.name == token.text && self.is_member(definition));
+        }
+        // In the innermost scope with the name, the last declaration before
+        // the use, or else the first one after it, like a function declared
+        // further down
+        let candidates: Vec<&Definition> = self
+            .visible_at(pos)
+            .into_iter()
+            .filter(|definition| definition.name == token.text)
+            .collect();
+        let innermost = candidates.first()?.scope;
+        let candidates = candidates
+            .into_iter()
+            .filter(|definition| definition.scope == innermost);
+        let (before, after): (Vec<_>, Vec<_>) =
+            candidates.partition(|definition| location(&definition.name_span.0) <= pos);
+        before
+            .into_iter()
+            .max_by_key(|definition| location(&definition.name_span.0))
+            .or_else(|| {
+                after
+                    .into_iter()
+                    .min_by_key(|definition| location(&definition.name_span.0))
+            })
+    }
+
+    // The outline of the classes, enums, functions and namespaces in the file
+    fn symbols(&self, parent: Option<usize>) -> Vec<Json> {
+        self.definitions
+            .iter()
+            .enumerate()
+            .filter(|(_, definition)| {
+                definition.parent == parent
+                    && !matches!(definition.kind, SymbolKind::Variable | SymbolKind::Module)
+            })
+            .map(|(index, definition)| {
+                Json::object(vec![
+                    ("name", definition.name.as_str().into()),
+                    ("detail", definition.detail.as_str().into()),
+                    ("kind", (definition.kind as usize).into()),
+                    ("range", range(&definition.span)),
+                    ("selectionRange", range(&definition.name_span)),
+                    ("children", self.symbols(Some(index)).into()),
+                ])
+            })
+            .collect()
+    }
+}
+
+// Walks a program collecting everything declared in it
+struct Definitions<'a> {
+    tokens: &'a [Token],
+    definitions: Vec<Definition>,
+}
+
+impl Definitions<'_> {
+    fn collect(ast: &Rc<AST>, tokens: &[Token]) -> Vec<Definition> {
+        let mut collector = Definitions {
+            tokens,
+            definitions: vec![],
+        };
+        // Globals can be used anywhere in the file
+        let filename = ast.span().0.filename;
+        let whole_file = Span(
+            Location {
+                line: 0,
+                column: 0,
+                filename,
+            },
+            Location {
+                line: usize::MAX,
+                column: usize::MAX,
+                filename,
+            },
+        );
+        match ast.as_ref() {
+            AST::Block(_, stmts, _) => {
+                for stmt in stmts {
+                    collector.walk(stmt, Some(whole_file), None);
+                }
+            }
+            _ => collector.walk(ast, Some(whole_file), None),
+        }
+        collector.definitions
+    }
+
+    // Adds a declaration, finding its name in the tokens after `from`
+    #[allow(clippy::too_many_arguments)]
+    fn add(
+        &mut self,
+        name: &str,
+        kind: SymbolKind,
+        from: &Location,
+        span: &Span,
+        scope: Option<Span>,
+        parent: Option<usize>,
+        detail: String,
+    ) -> usize {
+        let name_span = self
+            .tokens
+            .iter()
+            .find(|token| location(&token.span.0) >= location(from) && token.text == name)
+            .map_or(*span, |token| token.span);
+        self.definitions.push(Definition {
+            name: name.to_string(),
+            kind,
+            name_span,
+            span: *span,
+            scope,
+            parent,
+            detail,
+        });
+        self.definitions.len() - 1
+    }
+
+    fn add_pattern(&mut self, pattern: &Pattern, scope: &Span, parent: Option<usize>) {
+        let mut add = |name: &str, span: &Span| {
+            let detail = format!("let {}", name);
+            self.add(
+                name,
+                SymbolKind::Variable,
+                &span.0,
+                span,
+                Some(*scope),
+                parent,
+                detail,
+            );
+        };
+        match pattern {
+            Pattern::Binding(span, name) => add(name, span),
+            Pattern::Rest(span, Some(name)) => add(name, span),
+            Pattern::Dict(span, items, rest) => {
+                for (_, item) in items {
+                    self.add_pattern(item, scope, parent);
+                }
+                if let Some(name) = rest {
+                    let detail = format!("let {}", name);
+                    self.add(
+                        name,
+                        SymbolKind::Variable,
+                        &span.0,
+                        span,
+                        Some(*scope),
+                        parent,
+                        detail,
+                    );
+                }
+            }
+            Pattern::Or(_, items) | Pattern::Array(_, items) | Pattern::Tuple(_, items) => {
+                for item in items {
+                    self.add_pattern(item, scope, parent);
+                }
+            }
+            Pattern::Class(_, _, args) => {
+                for (_, item) in args {
+                    self.add_pattern(item, scope, parent);
+                }
+            }
+            Pattern::Wildcard(_) | Pattern::Value(..) | Pattern::Rest(_, None) => {}
+        }
+    }
+
+    // Adds the fields of a class or enum, in the order they're written
+    fn add_fields(&mut self, fields: &HashMap<String, (Rc<AST>, bool)>, parent: usize) {
+        let mut fields: Vec<_> = fields.iter().collect();
+        fields.sort_by_key(|(_, (value, _))| location(&value.span().0));
+        for (name, (value, is_static)) in fields {
+            match value.as_ref() {
+                AST::Function { .. } | AST::Class { .. } | AST::Enum { .. } => {
+                    self.walk(value, None, Some(parent))
+                }
+                _ => {
+                    // The value comes after the name, so the name is the last
+                    // token with it before the value
+                    let name_span = self
+                        .tokens
+                        .iter()
+                        .rfind(|token| {
+                            location(&token.span.0) < location(&value.span().0)
+                                && &token.text == name
+                        })
+                        .map_or(*value.span(), |token| token.span);
+                    let keyword = if *is_static { "static let" } else { "let" };
+                    let detail = format!("{} {}", keyword, name);
+                    let span = name_span.extend(value.span());
+                    let index = self.add(
+                        name,
+                        SymbolKind::Field,
+                        &name_span.0,
+                        &span,
+                        None,
+                        Some(parent),
+                        detail,
+                    );
+                    self.walk(value, Some(span), Some(index));
+                }
+            }
+        }
+    }
+
+    // Collects the declarations in a node, which can be used in `scope`.
+    // Declarations directly in a class or enum have no scope.
+    fn walk(&mut self, ast: &Rc<AST>, scope: Option<Span>, parent: Option<usize>) {
+        let scope_or_self = scope.unwrap_or(*ast.span());
+        match ast.as_ref() {
+            AST::VarDeclaration(span, name, value) => {
+                let detail = format!("let {}", name);
+                self.add(
+                    name,
+                    SymbolKind::Variable,
+                    &span.0,
+                    span,
+                    scope,
+                    parent,
+                    detail,
+                );
+                self.walk(value, scope, parent);
+            }
+            AST::Destructure(_, pattern, value) => {
+                self.add_pattern(pattern, &scope_or_self, parent);
+                self.walk(value, scope, parent);
+            }
+            AST::Function {
+                span,
+                name,
+                args,
+                is_static,
+                in_class,
+                body,
+                ..
+            } => {
+                let parent = match name {
+                    Some(name) => {
+                        let (kind, detail) = match (scope, is_static, in_class) {
+                            (None, true, _) => (
+                                SymbolKind::Method,
+                                format!("static def {}({})", name, signature(args)),
+                            ),
+                            (None, _, true) => (
+                                SymbolKind::Method,
+                                format!("def {}(self{}{})", name, comma(args), signature(args)),
+                            ),
+                            _ => (
+                                SymbolKind::Function,
+                                format!("def {}({})", name, signature(args)),
+                            ),
+                        };
+                        Some(self.add(name, kind, &span.0, span, scope, parent, detail))
+                    }
+                    None => parent,
+                };
+                // Arguments come after the name
+                let from = match parent {
+                    Some(index) if name.is_some() => self.definitions[index].name_span.1,
+                    _ => span.0,
+                };
+                for (arg, default, _) in args {
+                    let detail = match default {
+                        Some(default) => format!("(argument) {} = {}", arg, default),
+                        None => format!("(argument) {}", arg),
+                    };
+                    self.add(
+                        arg,
+                        SymbolKind::Variable,
+                        &from,
+                        span,
+                        Some(*span),
+                        parent,
+                        detail,
+                    );
+                }
+                for (_, default, _) in args {
+                    if let Some(default) = default {
+                        self.walk(default, scope, parent);
+                    }
+                }
+                self.walk(body, Some(*span), parent);
+            }
+            AST::Class {
+                span,
+                name,
+                parents,
+                fields,
+                ..
+            } => {
+                let mut detail = match parents {
+                    Some(parents) => format!("class {}({})", name, parents.join(", ")),
+                    None => format!("class {}", name),
+                };
+                if let Some((constructor, _)) = fields.get("new") {
+                    if let AST::Function { args, .. } = constructor.as_ref() {
+                        detail += &format!("\ndef new(self{}{})", comma(args), signature(args));
+                    }
+                }
+                let index = self.add(
+                    name,
+                    SymbolKind::Class,
+                    &span.0,
+                    span,
+                    scope,
+                    parent,
+                    detail,
+                );
+                self.add_fields(fields, index);
+            }
+            AST::Enum {
+                span,
+                name,
+                variants,
+                fields,
+                ..
+            } => {
+                let detail = format!("enum {}", name);
+                let index = self.add(name, SymbolKind::Enum, &span.0, span, scope, parent, detail);
+                for (variant, variant_fields) in variants {
+                    let detail = match variant_fields.is_empty() {
+                        true => format!("{}.{}", name, variant),
+                        false => format!("{}.{}({})", name, variant, variant_fields.join(", ")),
+                    };
+                    let kind = SymbolKind::EnumMember;
+                    self.add(variant, kind, &span.0, span, None, Some(index), detail);
+                }
+                self.add_fields(fields, index);
+            }
+            AST::Namespace { span, name, body } => {
+                let detail = format!("namespace {}", name);
+                let kind = SymbolKind::Namespace;
+                let index = self.add(name, kind, &span.0, span, scope, parent, detail);
+                match body.as_ref() {
+                    AST::Block(body_span, stmts, _) => {
+                        for stmt in stmts {
+                            self.walk(stmt, Some(*body_span), Some(index));
+                        }
+                    }
+                    _ => self.walk(body, Some(*body.span()), Some(index)),
+                }
+            }
+            AST::Import { span, path, alias } => {
+                let name = import_name(path, alias);
+                let detail = format!("import {}", path);
+                self.add(
+                    &name,
+                    SymbolKind::Module,
+                    &span.0,
+                    span,
+                    scope,
+                    parent,
+                    detail,
+                );
+            }
+            AST::FromImport { span, path, names } => {
+                for (name, alias) in names {
+                    let detail = format!("from {} import {}", path, name);
+                    let name = alias.as_ref().unwrap_or(name);
+                    self.add(
+                        name,
+                        SymbolKind::Variable,
+                        &span.0,
+                        span,
+                        scope,
+                        parent,
+                        detail,
+                    );
+                }
+            }
+            AST::Block(span, stmts, _) => {
+                for stmt in stmts {
+                    self.walk(stmt, Some(*span), parent);
+                }
+            }
+            AST::ForEach(span, pattern, iter, body, _) => {
+                self.add_pattern(pattern, span, parent);
+                self.walk(iter, scope, parent);
+                self.walk(body, Some(*span), parent);
+            }
+            AST::Comprehension(span, pattern, ..) | AST::SetComprehension(span, pattern, ..) => {
+                self.add_pattern(pattern, span, parent);
+                self.walk_children(ast, Some(*span), parent);
+            }
+            AST::For { span, .. } => self.walk_children(ast, Some(*span), parent),
+            AST::Match(_, value, arms) => {
+                self.walk(value, scope, parent);
+                for (pattern, guard, body, _) in arms {
+                    let arm = pattern.span().extend(body.span());
+                    self.add_pattern(pattern, &arm, parent);
+                    if let Some(guard) = guard {
+                        self.walk(guard, Some(arm), parent);
+                    }
+                    self.walk(body, Some(arm), parent);
+                }
+            }
+            AST::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.walk(body, scope, parent);
+                if let Some((name, block)) = catch {
+                    if let Some(name) = name {
+                        // The name is the last token with it before the block
+                        let from = self
+                            .tokens
+                            .iter()
+                            .rfind(|token| {
+                                location(&token.span.0) < location(&block.span().0)
+                                    && &token.text == name
+                            })
+                            .map_or(block.span().0, |token| token.span.0);
+                        let detail = format!("(error) {}", name);
+                        let kind = SymbolKind::Variable;
+                        let block_span = Some(*block.span());
+                        self.add(name, kind, &from, block.span(), block_span, parent, detail);
+                    }
+                    self.walk(block, scope, parent);
+                }
+                if let Some(finally) = finally {
+                    self.walk(finally, scope, parent);
+                }
+            }
+            _ => self.walk_children(ast, scope, parent),
+        }
+    }
+
+    fn walk_children(&mut self, ast: &Rc<AST>, scope: Option<Span>, parent: Option<usize>) {
+        let mut children = vec![];
+        ast.for_each_child(&mut |child| children.push(child.clone()));
+        for child in children {
+            self.walk(&child, scope, parent);
+        }
+    }
+}
+
+// The arguments of a function as they're written in its declaration
+fn signature(args: &FunctionArgs) -> String {
+    let args: Vec<String> = args
+        .iter()
+        .map(|(name, default, type_)| match (type_, default) {
+            (ArgumentType::Variadic, _) => format!("*{}", name),
+            (ArgumentType::VariadicKeyword, _) => format!("**{}", name),
+            (_, Some(default)) => format!("{} = {}", name, default),
+            (_, None) => name.clone(),
+        })
+        .collect();
+    args.join(", ")
+}
+
+fn comma(args: &FunctionArgs) -> &'static str {
+    match args.is_empty() {
+        true => "",
+        false => ", ",
+    }
+}
+
+// Locations compare by line and then column, both counting from 1
+fn location(location: &Location) -> (usize, usize) {
+    (location.line, location.column)
+}
+
+// Whether a span contains a position, counting the one just after it so the
+// end of a name being typed still counts
+fn contains(span: &Span, pos: (usize, usize)) -> bool {
+    location(&span.0) <= pos && pos <= location(&span.1)
+}
+
+// The protocol counts lines and characters from 0
+fn range(span: &Span) -> Json {
+    let position = |location: &Location| {
+        Json::object(vec![
+            ("line", location.line.saturating_sub(1).into()),
+            ("character", location.column.saturating_sub(1).into()),
+        ])
+    };
+    Json::object(vec![
+        ("start", position(&span.0)),
+        ("end", position(&span.1)),
+    ])
+}
+
+fn diagnostic(uri: &str, err: &Error) -> Json {
+    let mut message = err.message.clone();
+    for hint in err.hints() {
+        message += &format!("\nhint: {}", hint);
+    }
+    let related: Vec<Json> = err
+        .labels()
+        .iter()
+        .map(|label| {
+            let location = Json::object(vec![("uri", uri.into()), ("range", range(&label.span))]);
+            Json::object(vec![
+                ("location", location),
+                ("message", label.message.as_str().into()),
+            ])
+        })
+        .collect();
+    Json::object(vec![
+        ("range", range(&err.span)),
+        // Everything the interpreter reports is an error
+        ("severity", 1usize.into()),
+        ("source", "rattlescript".into()),
+        ("message", message.into()),
+        ("relatedInformation", related.into()),
+    ])
+}
+
+fn text_document_uri(params: &Json) -> Option<&str> {
+    params.get("textDocument")?.get("uri")?.as_str()
+}
+
+// The path of a `file://` URI, which is what errors show
+fn uri_to_path(uri: &str) -> String {
+    let Some(path) = uri.strip_prefix("file://") else {
+        return uri.to_string();
+    };
+    let mut bytes = vec![];
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (byte, escaped) {
+            (b'%', Some(escaped)) => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
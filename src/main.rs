@@ -17,50 +17,38 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-#![allow(clippy::upper_case_acronyms)]
-
-use crate::error::Result;
-
-mod ast;
-mod common;
-mod error;
-mod interpreter;
-mod lexer;
-mod parser;
-mod repl;
-mod token;
+use rattlescript::{
+    format_source, DebugAdapter, DebugPrompt, Engine, Error, ErrorKind, LanguageServer, Repl,
+    TestRunner, REPL_VERSION,
+};
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 
 const LICENSE: &str = include_str!("../LICENSE.md");
 
-fn run_file(filename: &str, verbose: bool) -> Result<()> {
-    let content = std::fs::read_to_string(filename).expect("Couldn't open input file");
-
-    let mut lex = lexer::Lexer::new(content, Box::leak(filename.to_string().into_boxed_str()));
-    let tokens = lex.lex()?;
-
-    if verbose {
-        for token in &tokens {
-            println!("{:?}", token);
-        }
-    }
-
-    let mut parser = parser::Parser::new(tokens);
-    let ast = parser.parse()?;
-
-    if verbose {
-        println!("{:#?}", ast);
-    }
-
-    let mut interpreter = interpreter::Interpreter::new();
-    interpreter.execute(&ast)?;
-    Ok(())
-}
+// Script calls recurse on the Rust stack, so scripts run on a thread with
+// room for the default call depth limit even in debug builds. Only the pages
+// that get used are ever allocated.
+const STACK_SIZE: usize = 1 << 30;
 
 fn print_help(filename: &str) {
     println!("Usage: {} [options] [filename]", filename);
+    println!("       {} <command>", filename);
+    println!("Commands:");
+    println!("  lsp                           Run a language server on stdin and stdout");
+    println!("  dap                           Run a debug adapter on stdin and stdout");
+    println!("  fmt [--check] [paths]         Format files, directories or stdin in place");
+    println!("  test [--vm] [--jobs=<n>] [paths]");
+    println!("                                Run test files and directories (default: tests)");
     println!("Options:");
     println!("  -d, --disable-error-context   Disable error context (default: false)");
     println!("  -v, --verbose                 Enable verbose output (default: false)");
+    println!("      --vm                      Run on the bytecode VM (default: false)");
+    println!("      --call=<function>         Call a function after running the file");
+    println!("      --debug                   Pause before the first statement in a debug prompt");
+    println!("      --profile[=<path>]        Print a profile of the run on stderr");
+    println!("                                Write its stacks to path (default: profile.folded)");
+    println!("      --error-format=<format>   Print errors as `human` or `json` (default: human)");
     println!("  -i, --info                    Print info about the REPL");
     println!("  -l, --license                 Print the license");
     println!("  -h, --help                    Print this help message");
@@ -72,11 +60,44 @@ fn main() {
     let mut filename = None;
     let mut disable_error_context = false;
     let mut verbose = false;
+    let mut use_vm = false;
+    let mut error_format = ErrorFormat::Human;
+    let mut call = None;
+    let mut debug = false;
+    let mut profile = None;
+
+    if args.get(1).map(String::as_str) == Some("lsp") {
+        // Checking a file looks for the modules it imports
+        find_std();
+        std::process::exit(lsp());
+    }
+    if args.get(1).map(String::as_str) == Some("dap") {
+        find_std();
+        std::process::exit(dap());
+    }
+    if args.get(1).map(String::as_str) == Some("fmt") {
+        // The parser looks for the modules that are imported
+        find_std();
+        std::process::exit(fmt(&args[2..]));
+    }
+    if args.get(1).map(String::as_str) == Some("test") {
+        // Each test runs in a new process, which finds the std directory itself
+        std::process::exit(test(&args[2..]));
+    }
 
     for arg in args.iter().skip(1) {
         match arg.as_str() {
             "-d" | "--disable-error-context" => disable_error_context = true,
             "-v" | "--verbose" => verbose = true,
+            "--vm" => use_vm = true,
+            "--debug" => debug = true,
+            "--profile" => profile = Some("profile.folded".to_string()),
+            arg if arg.starts_with("--profile=") => {
+                profile = Some(arg["--profile=".len()..].to_string())
+            }
+            "--error-format=human" => error_format = ErrorFormat::Human,
+            "--error-format=json" => error_format = ErrorFormat::Json,
+            arg if arg.starts_with("--call=") => call = Some(arg["--call=".len()..].to_string()),
             "-l" | "--license" => {
                 println!("{}", LICENSE);
                 std::process::exit(0);
@@ -84,7 +105,7 @@ fn main() {
             "-i" | "--info" => {
                 println!(
                     "RattleScript REPL Version: {} | Language Version: {}",
-                    repl::REPL_VERSION,
+                    REPL_VERSION,
                     env!("CARGO_PKG_VERSION")
                 );
                 println!("Author: Haven Selph <havenselph@gmail.com>");
@@ -112,6 +133,40 @@ fn main() {
         }
     }
 
+    if profile.is_some() && filename.is_none() {
+        eprintln!("--profile needs a file to run");
+        std::process::exit(1);
+    }
+
+    find_std();
+
+    let filename = filename.map(str::to_string);
+    let interpreter = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            run(
+                filename,
+                call,
+                verbose,
+                use_vm,
+                debug,
+                profile,
+                disable_error_context,
+                error_format,
+            )
+        });
+    match interpreter.map(|thread| thread.join()) {
+        Ok(Ok(code)) => std::process::exit(code),
+        Ok(Err(_)) => std::process::exit(101),
+        Err(err) => {
+            eprintln!("Couldn't start the interpreter: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Points RATTLESCRIPT_PATH at the std directory, or exits if it can't be found
+fn find_std() {
     let rattle_script_path = match std::env::var("RATTLESCRIPT_PATH") {
         Ok(path) => {
             // Check if path is a directory
@@ -136,33 +191,288 @@ fn main() {
         eprintln!("Couldn't find std directory in RATTLESCRIPT_PATH, set RATTLESCRIPT_PATH environment variable to the path of the RattleScript repository.");
         std::process::exit(1);
     }
+}
 
-    if filename.is_none() {
-        let mut repl = repl::Repl::new(verbose);
-        repl.run();
-        std::process::exit(0);
+#[derive(Clone, Copy)]
+enum ErrorFormat {
+    Human,
+    // One JSON object per line, for tools
+    Json,
+}
+
+// Runs a file, or the REPL without one, and returns the exit code
+#[allow(clippy::too_many_arguments)]
+fn run(
+    filename: Option<String>,
+    call: Option<String>,
+    verbose: bool,
+    use_vm: bool,
+    debug: bool,
+    profile: Option<String>,
+    disable_error_context: bool,
+    error_format: ErrorFormat,
+) -> i32 {
+    let mut engine = Engine::new();
+    engine.set_verbose(verbose);
+    if use_vm {
+        engine.enable_vm();
+    }
+    if debug {
+        // The REPL only pauses at breakpoints, set with `breakpoint()`
+        engine.set_debugger(DebugPrompt::new(), filename.is_some());
+    }
+    if profile.is_some() {
+        engine.enable_profiler();
     }
 
-    let filename = match filename {
-        Some(filename) => filename,
-        None => {
-            eprintln!("No filename provided");
-            std::process::exit(1);
+    let Some(filename) = filename else {
+        Repl::new(engine).run();
+        return 0;
+    };
+
+    let result = match (engine.eval_file(&filename), call) {
+        (Ok(_), Some(name)) => engine.call(&name, vec![]),
+        (result, _) => result,
+    };
+
+    let code = match result {
+        Ok(_) => 0,
+        Err(err) => {
+            // The engine stops at the first syntax error, so look for the rest
+            let errors = match err.kind {
+                ErrorKind::Lexer | ErrorKind::Parser | ErrorKind::UnexpectedEOF => {
+                    engine.check_file(&filename)
+                }
+                _ => vec![],
+            };
+            let errors = if errors.is_empty() { vec![err] } else { errors };
+            for err in errors {
+                match error_format {
+                    ErrorFormat::Human => print_error(&err, disable_error_context),
+                    ErrorFormat::Json => eprintln!("{}", err.to_json()),
+                }
+            }
+            1
+        }
+    };
+
+    // A failed run is profiled too, up to where it failed
+    if let Some(path) = profile {
+        if let Err(err) = write_profile(&engine, &path) {
+            eprintln!("Couldn't write the profile to {}: {}", path, err);
+            return 1;
         }
+    }
+    code
+}
+
+// Prints the report of a profiled run on stderr, and writes its call stacks
+// to a file in the folded format flame graph tools read
+fn write_profile(engine: &Engine, path: &str) -> std::io::Result<()> {
+    let Some(profile) = engine.profile() else {
+        return Ok(());
     };
+    profile.write_report(&mut std::io::stderr())?;
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    profile.write_folded(&mut file)?;
+    file.flush()
+}
+
+fn print_error(err: &Error, disable_error_context: bool) {
+    if disable_error_context {
+        eprintln!("{} {}", err.span.0, err.message);
+        // The outermost call first, like the traceback printed with context
+        for (frame, count) in err.frames() {
+            match count {
+                1 => eprintln!("  in {}", frame),
+                _ => eprintln!("  in {} ({} times)", frame, count),
+            }
+        }
+        for label in err.labels() {
+            eprintln!("  note: {} {}", label.span.0, label.message);
+        }
+        for hint in err.hints() {
+            eprintln!("  hint: {}", hint);
+        }
+    } else {
+        // Colours would only get in the way of whatever reads a redirected stderr
+        let colors = std::io::stderr().is_terminal();
+        let _ = err.write_with_source(&mut std::io::stderr(), colors);
+    }
+}
 
-    let result = run_file(filename, verbose);
+// Serves the language server protocol until the editor exits, with the exit
+// code the protocol asks for
+fn lsp() -> i32 {
+    let stdin = std::io::stdin();
+    match LanguageServer::new().serve(&mut stdin.lock(), &mut std::io::stdout()) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(err) => {
+            eprintln!("Language server error: {}", err);
+            1
+        }
+    }
+}
 
-    match result {
-        Ok(_) => std::process::exit(0),
+// Serves the debug adapter protocol until the editor disconnects, running the
+// file it launches on a thread with room for deep calls like any other file
+fn dap() -> i32 {
+    let adapter = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(|| DebugAdapter::new(std::io::stdin().lock(), std::io::stdout()).serve());
+    match adapter.map(|thread| thread.join()) {
+        Ok(Ok(Ok(()))) => 0,
+        Ok(Ok(Err(err))) => {
+            eprintln!("Debug adapter error: {}", err);
+            1
+        }
+        Ok(Err(_)) => 101,
         Err(err) => {
-            if disable_error_context {
-                eprintln!("{} {}", err.span.0, err.message);
-                std::process::exit(1);
-            } else {
-                err.print_with_source();
-                std::process::exit(1);
+            eprintln!("Couldn't start the debug adapter: {}", err);
+            1
+        }
+    }
+}
+
+// Formats files in place, or reads stdin and writes stdout without any, and
+// returns the exit code. With `--check` nothing is written and the files that
+// would change are listed instead.
+fn fmt(args: &[String]) -> i32 {
+    let check = args.iter().any(|arg| arg == "--check");
+    let paths: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if let Some(arg) = paths.iter().find(|arg| arg.starts_with('-')) {
+        eprintln!("Unknown option: {}", arg);
+        return 1;
+    }
+
+    if paths.is_empty() {
+        let mut source = String::new();
+        if let Err(err) = std::io::stdin().read_to_string(&mut source) {
+            eprintln!("Couldn't read stdin: {}", err);
+            return 1;
+        }
+        return match format_source(source.clone(), "<stdin>") {
+            Ok(formatted) if check => (formatted != source) as i32,
+            Ok(formatted) => {
+                print!("{}", formatted);
+                0
             }
+            Err(errors) => {
+                errors.iter().for_each(|err| print_error(err, false));
+                1
+            }
+        };
+    }
+
+    let mut files = vec![];
+    for path in paths {
+        let path = PathBuf::from(path);
+        if let Err(err) = collect_files(&path, &mut files) {
+            eprintln!("Couldn't read {}: {}", path.display(), err);
+            return 1;
         }
     }
+    let mut code = 0;
+    for file in files {
+        let source = match std::fs::read_to_string(&file) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("Couldn't read {}: {}", file.display(), err);
+                code = 1;
+                continue;
+            }
+        };
+        // Errors keep the name of the file they are in
+        let filename = Box::leak(file.to_string_lossy().into_owned().into_boxed_str());
+        let formatted = match format_source(source.clone(), filename) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                errors.iter().for_each(|err| print_error(err, false));
+                code = 1;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{}", file.display());
+            code = 1;
+        } else if let Err(err) = std::fs::write(&file, formatted) {
+            eprintln!("Couldn't write {}: {}", file.display(), err);
+            code = 1;
+        }
+    }
+    code
+}
+
+// Runs tests with this binary and returns the exit code
+fn test(args: &[String]) -> i32 {
+    let interpreter = match std::env::current_exe() {
+        Ok(interpreter) => interpreter,
+        Err(err) => {
+            eprintln!("Couldn't find the interpreter: {}", err);
+            return 1;
+        }
+    };
+    let mut runner = TestRunner::new(interpreter);
+    let mut paths = vec![];
+    for arg in args {
+        match arg.as_str() {
+            "--vm" => runner.enable_vm(),
+            arg if arg.starts_with("--jobs=") => match arg["--jobs=".len()..].parse() {
+                Ok(jobs) => runner.set_jobs(jobs),
+                Err(_) => {
+                    eprintln!("Invalid number of jobs: {}", arg);
+                    return 1;
+                }
+            },
+            arg if arg.starts_with('-') => {
+                eprintln!("Unknown option: {}", arg);
+                return 1;
+            }
+            arg => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        paths.push(PathBuf::from("tests"));
+    }
+
+    let mut files = vec![];
+    for path in paths {
+        if let Err(err) = collect_files(&path, &mut files) {
+            eprintln!("Couldn't read {}: {}", path.display(), err);
+            return 1;
+        }
+    }
+    let mut stdout = std::io::stdout();
+    let colors = stdout.is_terminal();
+    match runner.run(&files, &mut stdout, colors) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(err) => {
+            eprintln!("Couldn't run the tests: {}", err);
+            1
+        }
+    }
+}
+
+// The `.rat` files in a directory and the ones below it, or the path itself if
+// it isn't a directory
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() || entry.extension().is_some_and(|ext| ext == "rat") {
+            collect_files(&entry, files)?;
+        }
+    }
+    Ok(())
 }
@@ -5,10 +5,13 @@
  */
 
 use crate::ast::ArgumentType::{Keyword, Positional, Variadic, VariadicKeyword};
-use crate::ast::{ArgumentType, CallArgs, FunctionArgs, ImportObject, AST};
+use crate::ast::{
+    ArgumentType, CallArgs, FunctionArgs, ImportObject, Layout, MatchArm, Pattern, AST,
+};
 use crate::common::Span;
-use crate::error::{eof_error, parser_error as error, Result};
+use crate::error::{eof_error, parser_error as error, Error, ErrorKind, Result};
 use crate::token::{Token, TokenKind};
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::rc::Rc;
@@ -16,6 +19,12 @@ use std::rc::Rc;
 pub struct Parser {
     tokens: Vec<Token>,
     current_index: usize,
+    // Set when a `yield` is parsed, so the enclosing function becomes a generator
+    seen_yield: bool,
+    // Errors in statements that were skipped so parsing could go on
+    errors: Vec<Error>,
+    // Whether imported modules are looked for, which only running code needs
+    resolve_imports: bool,
 }
 
 impl Parser {
@@ -23,9 +32,19 @@ impl Parser {
         Parser {
             tokens,
             current_index: 0,
+            seen_yield: false,
+            errors: vec![],
+            resolve_imports: true,
         }
     }
 
+    // Parses imports without looking for the modules, so code can be
+    // formatted even where they can't be found
+    pub fn without_resolving_imports(mut self) -> Parser {
+        self.resolve_imports = false;
+        self
+    }
+
     fn cur(&self) -> Token {
         self.tokens
             .get(self.current_index)
@@ -82,10 +101,75 @@ impl Parser {
         self.consume_line_end()
     }
 
-    pub fn parse(&mut self) -> Result<Rc<AST>> {
-        let res = self.parse_block(/*global*/ true)?;
-        self.consume(TokenKind::EOF)?;
-        Ok(res)
+    // Parses a whole program, returning every syntax error in it instead of
+    // stopping at the first one
+    pub fn parse(&mut self) -> std::result::Result<Rc<AST>, Vec<Error>> {
+        match self.parse_partial() {
+            (Some(res), errors) if errors.is_empty() => Ok(res),
+            (_, errors) => Err(errors),
+        }
+    }
+
+    // Parses what it can of a program with syntax errors in it, leaving out
+    // the statements that failed. There's no program at all if the input
+    // ended in the middle of something.
+    pub fn parse_partial(&mut self) -> (Option<Rc<AST>>, Vec<Error>) {
+        let result = self
+            .parse_block(/*global*/ true)
+            .and_then(|res| self.consume(TokenKind::EOF).map(|_| res));
+        let mut errors = std::mem::take(&mut self.errors);
+        match result {
+            Ok(res) => (Some(res), errors),
+            Err(err) => {
+                errors.push(err);
+                (None, errors)
+            }
+        }
+    }
+
+    // Skips the rest of a statement that failed to parse, up to the next
+    // newline, `;` or `}` that isn't inside brackets opened while skipping
+    fn synchronize(&mut self, start: usize) {
+        // Always skip something, or the same statement would fail again
+        if self.current_index == start {
+            self.increment();
+        }
+        let mut depth: usize = 0;
+        loop {
+            let token = self.cur();
+            if depth == 0 && token.newline_before {
+                break;
+            }
+            match token.kind {
+                TokenKind::EOF => break,
+                TokenKind::SemiColon if depth == 0 => {
+                    self.increment();
+                    break;
+                }
+                TokenKind::RightBrace if depth == 0 => break,
+                TokenKind::LeftBrace | TokenKind::LeftBracket | TokenKind::LeftParen => depth += 1,
+                TokenKind::RightBrace | TokenKind::RightBracket | TokenKind::RightParen => {
+                    depth = depth.saturating_sub(1)
+                }
+                _ => {}
+            }
+            self.increment();
+        }
+    }
+
+    // Keeps the error of a statement or member that failed to parse, and skips
+    // the rest of it so the ones after it are parsed too
+    fn recover<T>(&mut self, result: Result<T>, start: usize) -> Result<Option<T>> {
+        match result {
+            Ok(res) => Ok(Some(res)),
+            // Code that ends early has nothing left to recover with
+            Err(err) if err.kind == ErrorKind::UnexpectedEOF => Err(err),
+            Err(err) => {
+                self.errors.push(err);
+                self.synchronize(start);
+                Ok(None)
+            }
+        }
     }
 
     fn parse_block(&mut self, global: bool) -> Result<Rc<AST>> {
@@ -107,12 +191,23 @@ impl Parser {
                 span = span.extend(&self.cur().span);
                 break;
             }
-            statements.push(self.parse_statement(until.clone())?);
+            if self.cur().kind == TokenKind::EOF {
+                eof_error!(
+                    self.cur().span,
+                    "Expected token {:?}",
+                    TokenKind::RightBrace
+                );
+            }
+            let start = self.current_index;
+            let statement = self.parse_statement(until.clone());
+            if let Some(statement) = self.recover(statement, start)? {
+                statements.push(statement);
+            }
             while self.cur().kind == TokenKind::SemiColon {
                 self.increment();
             }
         }
-        Ok(Rc::new(AST::Block(span, statements)))
+        Ok(Rc::new(AST::Block(span, statements, Layout::default())))
     }
 
     fn parse_class(&mut self) -> Result<Rc<AST>> {
@@ -138,72 +233,199 @@ impl Parser {
 
         self.consume(TokenKind::LeftBrace)?;
         while self.cur().kind != TokenKind::RightBrace {
-            let is_static = if self.cur().kind == TokenKind::Static {
-                self.increment();
-                true
-            } else {
-                false
-            };
-            match self.cur().kind {
-                TokenKind::Class => {
-                    let class = self.parse_class()?;
-                    match class.as_ref() {
-                        AST::Class { span, name, .. } => {
-                            if fields.contains_key(name) {
-                                error!(span, "Duplicate field name");
-                            }
-                            fields.insert(name.clone(), (class.clone(), is_static));
+            let start = self.current_index;
+            let field = self.parse_class_field(&mut fields);
+            self.recover(field, start)?;
+        }
+        let end = self.consume(TokenKind::RightBrace)?.span;
+        self.consume_line_end()?;
+        Ok(Rc::new(AST::Class {
+            span: start.extend(&end),
+            name: name.text,
+            parents,
+            fields,
+            layout: Layout::default(),
+        }))
+    }
+
+    fn parse_class_field(&mut self, fields: &mut HashMap<String, (Rc<AST>, bool)>) -> Result<()> {
+        let is_static = if self.cur().kind == TokenKind::Static {
+            self.increment();
+            true
+        } else {
+            false
+        };
+        match self.cur().kind {
+            TokenKind::Class => {
+                let class = self.parse_class()?;
+                match class.as_ref() {
+                    AST::Class { span, name, .. } => {
+                        if fields.contains_key(name) {
+                            error!(span, "Duplicate field name");
                         }
-                        _ => unreachable!(),
+                        fields.insert(name.clone(), (class.clone(), is_static));
                     }
+                    _ => unreachable!(),
                 }
-                TokenKind::Def => {
-                    let func = self.parse_function(!is_static, is_static)?;
-                    match func.as_ref() {
-                        AST::Function { span, name, .. } => {
-                            if fields.contains_key(name.clone().unwrap().as_str()) {
-                                error!(span, "Duplicate field name");
-                            }
-                            fields.insert(name.clone().unwrap(), (func.clone(), is_static));
+            }
+            TokenKind::Enum => {
+                let enum_ = self.parse_enum()?;
+                match enum_.as_ref() {
+                    AST::Enum { span, name, .. } => {
+                        if fields.contains_key(name) {
+                            error!(span, "Duplicate field name");
                         }
-                        _ => unreachable!(),
+                        fields.insert(name.clone(), (enum_.clone(), is_static));
                     }
+                    _ => unreachable!(),
                 }
-                TokenKind::Let => {
-                    let assignment = self.parse_statement(TokenKind::RightBrace)?;
-                    match assignment.as_ref() {
-                        AST::VarDeclaration {
-                            0: span,
-                            1: lhs,
-                            2: val,
-                        } => {
-                            if fields.contains_key(lhs.as_str()) {
-                                error!(span, "Duplicate field name");
-                            }
-                            fields.insert(lhs.clone(), (val.clone(), is_static));
+            }
+            TokenKind::Def => {
+                let func = self.parse_function(!is_static, is_static)?;
+                match func.as_ref() {
+                    AST::Function { span, name, .. } => {
+                        if fields.contains_key(name.clone().unwrap().as_str()) {
+                            error!(span, "Duplicate field name");
                         }
-                        _ => unreachable!(),
+                        fields.insert(name.clone().unwrap(), (func.clone(), is_static));
                     }
+                    _ => unreachable!(),
                 }
-                _ => {
-                    error!(self.cur().span, "Expected class or function declaration");
+            }
+            TokenKind::Let => {
+                let assignment = self.parse_statement(TokenKind::RightBrace)?;
+                match assignment.as_ref() {
+                    AST::VarDeclaration {
+                        0: span,
+                        1: lhs,
+                        2: val,
+                    } => {
+                        if fields.contains_key(lhs.as_str()) {
+                            error!(span, "Duplicate field name");
+                        }
+                        fields.insert(lhs.clone(), (val.clone(), is_static));
+                    }
+                    other => error!(other.span(), "Fields can't be destructured"),
                 }
             }
+            TokenKind::EOF => eof_error!(self.cur().span, "Expected `}}` to close class"),
+            _ => {
+                error!(self.cur().span, "Expected class or function declaration");
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_enum(&mut self) -> Result<Rc<AST>> {
+        let start = self.consume(TokenKind::Enum)?.span;
+        let name = self.consume(TokenKind::Identifier)?;
+        let mut variants: Vec<(String, Vec<String>)> = vec![];
+        let mut fields: HashMap<String, (Rc<AST>, bool)> = HashMap::new();
+
+        self.consume(TokenKind::LeftBrace)?;
+        while self.cur().kind != TokenKind::RightBrace {
+            let start = self.current_index;
+            let member = self.parse_enum_member(&mut variants, &mut fields);
+            self.recover(member, start)?;
+        }
+        for (variant, _) in variants.iter() {
+            if let Some((field, _)) = fields.get(variant) {
+                error!(
+                    field.span(),
+                    "Field '{}' clashes with a variant name", variant
+                );
+            }
         }
         let end = self.consume(TokenKind::RightBrace)?.span;
         self.consume_line_end()?;
-        Ok(Rc::new(AST::Class {
+        Ok(Rc::new(AST::Enum {
             span: start.extend(&end),
             name: name.text,
-            parents,
+            variants,
             fields,
+            layout: Layout::default(),
         }))
     }
 
+    fn parse_enum_member(
+        &mut self,
+        variants: &mut Vec<(String, Vec<String>)>,
+        fields: &mut HashMap<String, (Rc<AST>, bool)>,
+    ) -> Result<()> {
+        let is_static = if self.cur().kind == TokenKind::Static {
+            self.increment();
+            true
+        } else {
+            false
+        };
+        match self.cur().kind {
+            TokenKind::Identifier if !is_static => {
+                let variant = self.consume(TokenKind::Identifier)?;
+                let mut names = vec![];
+                if self.cur().kind == TokenKind::LeftParen {
+                    self.increment();
+                    while self.cur().kind != TokenKind::RightParen {
+                        let field = self.consume(TokenKind::Identifier)?;
+                        if names.contains(&field.text) {
+                            error!(field.span, "Duplicate variant field '{}'", field.text);
+                        }
+                        names.push(field.text);
+                        if self.cur().kind == TokenKind::Comma {
+                            self.increment();
+                        } else {
+                            break;
+                        }
+                    }
+                    self.consume(TokenKind::RightParen)?;
+                }
+                if variants.iter().any(|(name, _)| *name == variant.text) {
+                    error!(variant.span, "Duplicate variant name");
+                }
+                variants.push((variant.text, names));
+                if self.cur().kind == TokenKind::Comma {
+                    self.increment();
+                }
+            }
+            TokenKind::Def => {
+                let func = self.parse_function(!is_static, is_static)?;
+                match func.as_ref() {
+                    AST::Function { span, name, .. } => {
+                        if fields.contains_key(name.clone().unwrap().as_str()) {
+                            error!(span, "Duplicate field name");
+                        }
+                        fields.insert(name.clone().unwrap(), (func.clone(), is_static));
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            TokenKind::Let if is_static => {
+                let assignment = self.parse_statement(TokenKind::RightBrace)?;
+                match assignment.as_ref() {
+                    AST::VarDeclaration(span, lhs, val) => {
+                        if fields.contains_key(lhs.as_str()) {
+                            error!(span, "Duplicate field name");
+                        }
+                        fields.insert(lhs.clone(), (val.clone(), is_static));
+                    }
+                    other => error!(other.span(), "Fields can't be destructured"),
+                }
+            }
+            TokenKind::EOF => eof_error!(self.cur().span, "Expected `}}` to close enum"),
+            _ => {
+                error!(
+                    self.cur().span,
+                    "Expected enum variant, function or static variable"
+                );
+            }
+        }
+        Ok(())
+    }
+
     fn parse_lambda(&mut self) -> Result<Rc<AST>> {
         let start = self.consume(TokenKind::Pipe)?.span;
         let (args, required) = self.parse_function_arguments(&start, TokenKind::Pipe, false)?;
         self.consume(TokenKind::Pipe)?;
+        let outer_yield = std::mem::replace(&mut self.seen_yield, false);
         let body = if self.cur().kind == TokenKind::FatArrow {
             self.increment();
             let expr = self.parse_expression()?;
@@ -218,7 +440,9 @@ impl Parser {
             required,
             is_static: false,
             in_class: false,
+            is_generator: std::mem::replace(&mut self.seen_yield, outer_yield),
             body,
+            layout: Layout::default(),
         }))
     }
 
@@ -229,6 +453,7 @@ impl Parser {
         let (args, required) =
             self.parse_function_arguments(&start, TokenKind::RightParen, in_class)?;
         self.consume(TokenKind::RightParen)?;
+        let outer_yield = std::mem::replace(&mut self.seen_yield, false);
         let body = if self.cur().kind == TokenKind::FatArrow {
             self.increment();
             let hint = self.cur().kind == TokenKind::LeftBrace;
@@ -256,7 +481,9 @@ impl Parser {
             required,
             is_static,
             in_class,
+            is_generator: std::mem::replace(&mut self.seen_yield, outer_yield),
             body,
+            layout: Layout::default(),
         }))
     }
 
@@ -295,7 +522,7 @@ impl Parser {
         while self.cur().kind != closer {
             let val = self.parse_expression()?;
             let name = match val.deref() {
-                AST::Variable(span, name) => {
+                AST::Variable(span, name, _) => {
                     // we've found a positional argument
                     if !accepting.contains(&Positional) {
                         error!(span, "Unexpected positional argument");
@@ -311,7 +538,7 @@ impl Parser {
                     } else {
                         accepting = vec![Keyword, VariadicKeyword]; // only accept keyword arguments
                                                                     // Check if expr is a variable
-                        if let AST::Variable(_, name) = expr.deref() {
+                        if let AST::Variable(_, name, _) = expr.deref() {
                             args.push((name.clone(), None, Variadic));
                             name
                         } else {
@@ -327,7 +554,7 @@ impl Parser {
                         accepting = vec![Keyword, VariadicKeyword]; // only accept keyword arguments
 
                         // check if lhs is a variable
-                        if let AST::Variable(_, name) = lhs.deref() {
+                        if let AST::Variable(_, name, _) = lhs.deref() {
                             args.push((name.clone(), Some(rhs.clone()), Keyword));
                             name
                         } else {
@@ -342,7 +569,7 @@ impl Parser {
                     } else {
                         accepting = vec![]; // don't accept any more arguments
                                             // Check if expr is a variable
-                        if let AST::Variable(_, name) = expr.deref() {
+                        if let AST::Variable(_, name, _) = expr.deref() {
                             args.push((name.clone(), None, VariadicKeyword));
                             name
                         } else {
@@ -390,17 +617,17 @@ impl Parser {
             None
         };
         // If the first module is std, we need to look in the RATTLESCRIPT_PATH
-        let path = if module.first().expect("Import module is empty") == "std" {
+        let path = if !self.resolve_imports {
+            std::path::PathBuf::new()
+        } else if module.first().expect("Import module is empty") == "std" {
             module.remove(0);
             if module.is_empty() {
                 error!(span, "'std' is not a module");
             }
-            std::path::Path::new(
-                std::env::var("RATTLESCRIPT_PATH")
-                    .expect("RATTLESCRIPT_PATH not set")
-                    .as_str(),
-            )
-            .to_path_buf()
+            match std::env::var("RATTLESCRIPT_PATH") {
+                Ok(path) => std::path::PathBuf::from(path),
+                Err(_) => error!(span, "Can't import from std, RATTLESCRIPT_PATH is not set"),
+            }
         } else {
             // Otherwise, we look relative to the current running file
             std::path::Path::new(span.0.filename)
@@ -413,7 +640,7 @@ impl Parser {
         .join(module.join(std::path::MAIN_SEPARATOR_STR))
         .with_extension("rat");
 
-        if !path.exists() {
+        if self.resolve_imports && !path.exists() {
             error!(span, "Module {} not found", path.display());
         }
         Ok((path.to_string_lossy().to_string(), alias, span))
@@ -486,7 +713,7 @@ impl Parser {
                 self.consume_line_end()?;
 
                 let path = std::path::Path::new(&module).with_extension("rat");
-                if !path.exists() {
+                if self.resolve_imports && !path.exists() {
                     error!(span, "Module '{}' does not exist", module);
                 }
 
@@ -501,7 +728,7 @@ impl Parser {
                 self.consume_line_end()?;
 
                 let path = std::path::Path::new(module.as_str());
-                if !path.exists() {
+                if self.resolve_imports && !path.exists() {
                     error!(
                         start.span.extend(&module_span),
                         "Module '{}' does not exist", module
@@ -528,6 +755,21 @@ impl Parser {
         }
     }
 
+    // Namespaces only hold functions, classes, enums and variables
+    fn parse_namespace_member(&mut self) -> Result<Rc<AST>> {
+        match self.cur().kind {
+            TokenKind::Def => self.parse_function(false, false),
+            TokenKind::Class => self.parse_class(),
+            TokenKind::Enum => self.parse_enum(),
+            TokenKind::Let => self.parse_statement(TokenKind::RightBrace),
+            TokenKind::EOF => eof_error!(self.cur().span, "Expected `}}` to close namespace"),
+            _ => error!(
+                self.cur().span,
+                "Expected function, class, enum or variable"
+            ),
+        }
+    }
+
     fn parse_statement(&mut self, until: TokenKind) -> Result<Rc<AST>> {
         match self.cur() {
             Token {
@@ -538,33 +780,20 @@ impl Parser {
                 self.increment();
                 let ident = self.consume(TokenKind::Identifier)?;
 
-                // Parse block of only functions, classes or variables
                 let start = self.consume(TokenKind::LeftBrace)?.span;
                 let mut body = Vec::new();
-                loop {
-                    match self.cur().kind {
-                        TokenKind::Def => {
-                            body.push(self.parse_function(false, false)?);
-                        }
-                        TokenKind::Class => {
-                            body.push(self.parse_class()?);
-                        }
-                        TokenKind::Let => {
-                            body.push(self.parse_statement(TokenKind::RightBrace)?);
-                        }
-                        TokenKind::RightBrace => {
-                            break;
-                        }
-                        _ => {
-                            error!(self.cur().span, "Expected function, class or variable");
-                        }
+                while self.cur().kind != TokenKind::RightBrace {
+                    let start = self.current_index;
+                    let member = self.parse_namespace_member();
+                    if let Some(member) = self.recover(member, start)? {
+                        body.push(member);
                     }
                     while self.cur().kind == TokenKind::SemiColon {
                         self.increment();
                     }
                 }
                 let end = self.consume(TokenKind::RightBrace)?.span;
-                let body = Rc::new(AST::Block(start.extend(&end), body));
+                let body = Rc::new(AST::Block(start.extend(&end), body, Layout::default()));
                 self.consume_line_end_until(until)?;
                 Ok(Rc::new(AST::Namespace {
                     span: span.extend(body.span()),
@@ -586,13 +815,22 @@ impl Parser {
                 ..
             } => {
                 self.increment();
-                let ident = self.consume(TokenKind::Identifier)?;
+                let target = self.parse_binding_target(TokenKind::Equals)?;
                 self.consume(TokenKind::Equals)?;
-                let expr = self.parse_expression()?;
+                if let Pattern::Binding(_, name) = target.as_ref() {
+                    let expr = self.parse_expression()?;
+                    self.consume_line_end_until(until)?;
+                    return Ok(Rc::new(AST::VarDeclaration(
+                        span.extend(expr.span()),
+                        name.clone(),
+                        expr,
+                    )));
+                }
+                let expr = self.parse_tuple_expression()?;
                 self.consume_line_end_until(until)?;
-                Ok(Rc::new(AST::VarDeclaration(
+                Ok(Rc::new(AST::Destructure(
                     span.extend(expr.span()),
-                    ident.text,
+                    target,
                     expr,
                 )))
             }
@@ -609,7 +847,6 @@ impl Parser {
                 match self.cur() {
                     Token {
                         kind: TokenKind::Else,
-                        span,
                         ..
                     } => {
                         self.increment();
@@ -637,6 +874,10 @@ impl Parser {
                 kind: TokenKind::Class,
                 ..
             } => Ok(self.parse_class()?),
+            Token {
+                kind: TokenKind::Enum,
+                ..
+            } => Ok(self.parse_enum()?),
             Token {
                 kind: TokenKind::Static,
                 ..
@@ -666,7 +907,11 @@ impl Parser {
                 self.consume_line_end_until(until)?;
                 Ok(Rc::new(AST::Assignment(
                     span.extend(deco.span()),
-                    Rc::new(AST::Variable(span.extend(deco.span()), name.unwrap())),
+                    Rc::new(AST::Variable(
+                        span.extend(deco.span()),
+                        name.unwrap(),
+                        Cell::default(),
+                    )),
                     Rc::new(AST::Call(
                         span.extend(deco.span()),
                         deco,
@@ -742,18 +987,20 @@ impl Parser {
                         cond,
                         step,
                         body,
+                        layout: Layout::default(),
                     }))
                 } else {
                     // For each loop
-                    let ident = self.consume(TokenKind::Identifier)?;
+                    let target = self.parse_binding_target(TokenKind::In)?;
                     self.consume(TokenKind::In)?;
                     let expr = self.parse_expression()?;
                     let body = self.parse_block(/*global*/ false)?;
                     Ok(Rc::new(AST::ForEach(
                         span.extend(body.span()),
-                        ident.text,
+                        target,
                         expr,
                         body,
+                        Layout::default(),
                     )))
                 }
             }
@@ -767,6 +1014,74 @@ impl Parser {
                 self.consume_line_end_until(until)?;
                 Ok(Rc::new(AST::Return(span.extend(expr.span()), expr)))
             }
+            Token {
+                kind: TokenKind::Yield,
+                span,
+                ..
+            } => {
+                self.increment();
+                let expr = self.parse_expression()?;
+                self.consume_line_end_until(until)?;
+                self.seen_yield = true;
+                Ok(Rc::new(AST::Yield(span.extend(expr.span()), expr)))
+            }
+            Token {
+                kind: TokenKind::Throw,
+                span,
+                ..
+            } => {
+                self.increment();
+                let expr = self.parse_expression()?;
+                self.consume_line_end_until(until)?;
+                Ok(Rc::new(AST::Throw(span.extend(expr.span()), expr)))
+            }
+            Token {
+                kind: TokenKind::Try,
+                span,
+                ..
+            } => {
+                self.increment();
+                let body = self.parse_block(/*global*/ false)?;
+                let mut end = *body.span();
+                let catch = if self.cur().kind == TokenKind::Catch {
+                    self.increment();
+                    let name = if self.cur().kind == TokenKind::Identifier {
+                        Some(self.consume(TokenKind::Identifier)?.text)
+                    } else {
+                        None
+                    };
+                    let catch_body = self.parse_block(/*global*/ false)?;
+                    end = *catch_body.span();
+                    Some((name, catch_body))
+                } else {
+                    None
+                };
+                let finally = if self.cur().kind == TokenKind::Finally {
+                    self.increment();
+                    let finally_body = self.parse_block(/*global*/ false)?;
+                    end = *finally_body.span();
+                    Some(finally_body)
+                } else {
+                    None
+                };
+                if catch.is_none() && finally.is_none() {
+                    match self.cur().kind {
+                        TokenKind::EOF => {
+                            eof_error!(self.cur().span, "Expected `catch` or `finally`")
+                        }
+                        _ => error!(
+                            self.cur().span,
+                            "Expected `catch` or `finally` after try block"
+                        ),
+                    }
+                }
+                Ok(Rc::new(AST::Try {
+                    span: span.extend(&end),
+                    body,
+                    catch,
+                    finally,
+                }))
+            }
             Token {
                 kind: TokenKind::Assert,
                 span,
@@ -785,13 +1100,70 @@ impl Parser {
                 Ok(Rc::new(AST::Assert(span, cond, msg)))
             }
             _ => {
-                let expr = self.parse_expression()?;
+                let mut expr = self.parse_expression()?;
+                if self.cur().kind == TokenKind::Comma {
+                    // `a, b = b, a` assigns to several targets at once
+                    let mut targets = vec![expr];
+                    while self.cur().kind == TokenKind::Comma {
+                        self.increment();
+                        targets.push(match self.cur() {
+                            Token {
+                                kind: TokenKind::Star,
+                                span,
+                                ..
+                            } => {
+                                self.increment();
+                                let target = self.parse_logical_or()?;
+                                Rc::new(AST::StarExpression(span.extend(target.span()), target))
+                            }
+                            _ => self.parse_logical_or()?,
+                        });
+                    }
+                    let span = targets[0].span().extend(targets[targets.len() - 1].span());
+                    let targets = Rc::new(AST::TupleLiteral(span, targets));
+                    self.consume(TokenKind::Equals)?;
+                    let value = self.parse_tuple_expression()?;
+                    expr = Rc::new(AST::Assignment(span.extend(value.span()), targets, value));
+                }
                 self.consume_line_end_until(until)?;
                 Ok(expr)
             }
         }
     }
 
+    // Parses the target of a `let` or `for`, which is a single name or a
+    // pattern. Several comma separated patterns form a tuple pattern.
+    fn parse_binding_target(&mut self, until: TokenKind) -> Result<Rc<Pattern>> {
+        let start = self.cur().span;
+        let (mut items, tup) = self.parse_sequence_pattern(until)?;
+        match items.len() {
+            0 => error!(self.cur().span, "Expected a name or pattern"),
+            1 if !tup && !matches!(items[0].as_ref(), Pattern::Rest(..)) => {
+                Ok(items.pop().unwrap())
+            }
+            _ => {
+                let span = start.extend(items[items.len() - 1].span());
+                Ok(Rc::new(Pattern::Tuple(span, items)))
+            }
+        }
+    }
+
+    // Parses the value of a destructuring `let` or assignment, where `a, b`
+    // is short for the tuple `(a, b)`
+    fn parse_tuple_expression(&mut self) -> Result<Rc<AST>> {
+        let first = self.parse_expression()?;
+        if self.cur().kind != TokenKind::Comma {
+            return Ok(first);
+        }
+        let mut items = vec![first];
+        while self.cur().kind == TokenKind::Comma {
+            self.increment();
+            items.push(self.parse_expression()?);
+        }
+        let span = items[0].span().extend(items[items.len() - 1].span());
+        Ok(Rc::new(AST::TupleLiteral(span, items)))
+    }
+
     fn parse_expression(&mut self) -> Result<Rc<AST>> {
         self.parse_assignment()
     }
@@ -1060,7 +1432,7 @@ impl Parser {
                     offset,
                 )))
             }
-            TokenKind::Star { .. } => {
+            TokenKind::Star => {
                 let start = self.cur().span;
                 self.increment();
                 // Can't allow another prefix, so we parse a postfix
@@ -1070,7 +1442,7 @@ impl Parser {
                     expr,
                 )))
             }
-            TokenKind::StarStar { .. } => {
+            TokenKind::StarStar => {
                 let start = self.cur().span;
                 self.increment();
                 // Can't allow another prefix, so we parse a postfix
@@ -1204,7 +1576,7 @@ impl Parser {
             if self.cur().kind == TokenKind::Colon {
                 self.increment();
                 let name = match lhs.as_ref() {
-                    AST::Variable(_, name) => name.to_string(),
+                    AST::Variable(_, name, _) => name.to_string(),
                     _ => error!(lhs.span(), "Expected identifier: key pair"),
                 };
                 args.push((Some(name), self.parse_expression()?));
@@ -1284,7 +1656,7 @@ impl Parser {
                 }
                 if comp {
                     self.consume(TokenKind::For)?;
-                    let var = self.consume(TokenKind::Identifier)?;
+                    let var = self.parse_binding_target(TokenKind::In)?;
                     self.consume(TokenKind::In)?;
                     let iter = self.parse_expression()?;
                     let cond = if self.cur().kind == TokenKind::If {
@@ -1296,10 +1668,11 @@ impl Parser {
                     let end = self.consume(TokenKind::RightBracket)?.span;
                     Ok(Rc::new(AST::Comprehension(
                         span.extend(&end),
-                        var.text,
+                        var,
                         iter,
                         arr.pop().unwrap(),
                         cond,
+                        Layout::default(),
                     )))
                 } else {
                     let end = self.consume(TokenKind::RightBracket)?.span;
@@ -1315,6 +1688,9 @@ impl Parser {
                 let mut items = vec![];
                 while self.cur().kind != TokenKind::RightBrace {
                     let key = self.parse_expression()?;
+                    if items.is_empty() && self.cur().kind != TokenKind::Colon {
+                        return self.parse_set(span, key);
+                    }
                     self.consume(TokenKind::Colon)?;
                     let val = self.parse_expression()?;
                     items.push((key, val));
@@ -1338,6 +1714,10 @@ impl Parser {
                 kind: TokenKind::Pipe,
                 ..
             } => self.parse_lambda(),
+            Token {
+                kind: TokenKind::Match,
+                ..
+            } => self.parse_match(),
             Token {
                 kind: TokenKind::IntegerLiteralDec,
                 span,
@@ -1428,7 +1808,7 @@ impl Parser {
                 ..
             } => {
                 self.increment();
-                Ok(Rc::new(AST::Variable(span, text)))
+                Ok(Rc::new(AST::Variable(span, text, Cell::default())))
             }
             Token {
                 kind: TokenKind::True,
@@ -1454,6 +1834,20 @@ impl Parser {
                 self.increment();
                 Ok(Rc::new(AST::Nothing(span)))
             }
+            Token {
+                kind: TokenKind::Super,
+                span,
+                ..
+            } => {
+                self.increment();
+                if self.cur().kind != TokenKind::LeftParen {
+                    return Ok(Rc::new(AST::Super(span, None)));
+                }
+                self.increment();
+                let class = self.parse_expression()?;
+                let end = self.consume(TokenKind::RightParen)?;
+                Ok(Rc::new(AST::Super(span.extend(&end.span), Some(class))))
+            }
             Token {
                 kind: TokenKind::EOF,
                 span,
@@ -1467,6 +1861,316 @@ impl Parser {
         }
     }
 
+    fn parse_match(&mut self) -> Result<Rc<AST>> {
+        let start = self.consume(TokenKind::Match)?.span;
+        let subject = self.parse_expression()?;
+        self.consume(TokenKind::LeftBrace)?;
+        let mut arms: Vec<MatchArm> = vec![];
+        while self.cur().kind != TokenKind::RightBrace {
+            let pattern = self.parse_pattern()?;
+            let guard = if self.cur().kind == TokenKind::If {
+                self.increment();
+                Some(self.parse_expression()?)
+            } else {
+                None
+            };
+            self.consume(TokenKind::FatArrow)?;
+            let body = if self.cur().kind == TokenKind::LeftBrace {
+                self.parse_block(/*global*/ false)?
+            } else {
+                self.parse_expression()?
+            };
+            arms.push((pattern, guard, body, Layout::default()));
+            while let TokenKind::Comma | TokenKind::SemiColon = self.cur().kind {
+                self.increment();
+            }
+        }
+        let end = self.consume(TokenKind::RightBrace)?.span;
+        Ok(Rc::new(AST::Match(start.extend(&end), subject, arms)))
+    }
+
+    fn parse_set(&mut self, span: Span, first: Rc<AST>) -> Result<Rc<AST>> {
+        if self.cur().kind == TokenKind::For {
+            self.increment();
+            let var = self.parse_binding_target(TokenKind::In)?;
+            self.consume(TokenKind::In)?;
+            let iter = self.parse_expression()?;
+            let cond = if self.cur().kind == TokenKind::If {
+                self.increment();
+                Some(self.parse_expression()?)
+            } else {
+                None
+            };
+            let end = self.consume(TokenKind::RightBrace)?.span;
+            return Ok(Rc::new(AST::SetComprehension(
+                span.extend(&end),
+                var,
+                iter,
+                first,
+                cond,
+                Layout::default(),
+            )));
+        }
+
+        let mut items = vec![first];
+        loop {
+            match self.cur().kind {
+                TokenKind::Comma => self.increment(),
+                TokenKind::RightBrace => break,
+                TokenKind::EOF => eof_error!(self.cur().span, "Expected `}}` or ',' but got EOF"),
+                _ => error!(
+                    self.cur().span,
+                    "Expected `}}` or `,` but got {:?}",
+                    self.cur().kind
+                ),
+            }
+            if self.cur().kind == TokenKind::RightBrace {
+                break;
+            }
+            items.push(self.parse_expression()?);
+        }
+        let end = self.consume(TokenKind::RightBrace)?.span;
+        Ok(Rc::new(AST::SetLiteral(span.extend(&end), items)))
+    }
+
+    fn parse_pattern(&mut self) -> Result<Rc<Pattern>> {
+        let first = self.parse_pattern_atom()?;
+        if self.cur().kind != TokenKind::Pipe {
+            return Ok(first);
+        }
+        let mut span = *first.span();
+        let mut alternatives = vec![first];
+        while self.cur().kind == TokenKind::Pipe {
+            self.increment();
+            let alternative = self.parse_pattern_atom()?;
+            span = span.extend(alternative.span());
+            alternatives.push(alternative);
+        }
+        Ok(Rc::new(Pattern::Or(span, alternatives)))
+    }
+
+    fn parse_pattern_atom(&mut self) -> Result<Rc<Pattern>> {
+        match self.cur() {
+            Token {
+                kind: TokenKind::Identifier,
+                span,
+                text,
+                ..
+            } => {
+                self.increment();
+                if text == "_" {
+                    return Ok(Rc::new(Pattern::Wildcard(span)));
+                }
+                if !matches!(self.cur().kind, TokenKind::Dot | TokenKind::LeftParen) {
+                    return Ok(Rc::new(Pattern::Binding(span, text)));
+                }
+                // Dotted paths are compared by value, unless they are followed by
+                // arguments, in which case they name a class to destructure.
+                let mut path = Rc::new(AST::Variable(span, text, Cell::default()));
+                while self.cur().kind == TokenKind::Dot {
+                    self.increment();
+                    let name = self.consume(TokenKind::Identifier)?;
+                    path = Rc::new(AST::FieldAccess(
+                        path.span().extend(&name.span),
+                        path,
+                        name.text,
+                    ));
+                }
+                if self.cur().kind != TokenKind::LeftParen {
+                    return Ok(Rc::new(Pattern::Value(*path.span(), path)));
+                }
+                self.increment();
+                let mut args = vec![];
+                while self.cur().kind != TokenKind::RightParen {
+                    let is_keyword = self.cur().kind == TokenKind::Identifier
+                        && self.tokens[self.current_index + 1].kind == TokenKind::Colon;
+                    if is_keyword {
+                        let name = self.consume(TokenKind::Identifier)?.text;
+                        self.consume(TokenKind::Colon)?;
+                        args.push((Some(name), self.parse_pattern()?));
+                    } else {
+                        if args.iter().any(|(name, _)| name.is_some()) {
+                            error!(
+                                self.cur().span,
+                                "Positional patterns must come before keyword patterns"
+                            );
+                        }
+                        args.push((None, self.parse_pattern()?));
+                    }
+                    match self.cur().kind {
+                        TokenKind::Comma => self.increment(),
+                        TokenKind::RightParen => {}
+                        TokenKind::EOF => {
+                            eof_error!(self.cur().span, "Expected `)` or ',' but got EOF")
+                        }
+                        _ => error!(
+                            self.cur().span,
+                            "Expected `)` or `,` but got {:?}",
+                            self.cur().kind
+                        ),
+                    }
+                }
+                let end = self.consume(TokenKind::RightParen)?.span;
+                Ok(Rc::new(Pattern::Class(span.extend(&end), path, args)))
+            }
+            Token {
+                kind: TokenKind::Minus,
+                span,
+                ..
+            } => {
+                self.increment();
+                let literal = match self.cur().kind {
+                    TokenKind::IntegerLiteralDec
+                    | TokenKind::IntegerLiteralBin
+                    | TokenKind::IntegerLiteralOct
+                    | TokenKind::IntegerLiteralHex
+                    | TokenKind::FloatLiteral => self.parse_atom()?,
+                    _ => error!(self.cur().span, "Expected number after `-` in pattern"),
+                };
+                let span = span.extend(literal.span());
+                Ok(Rc::new(Pattern::Value(
+                    span,
+                    Rc::new(AST::Negate(span, literal)),
+                )))
+            }
+            Token {
+                kind:
+                    TokenKind::IntegerLiteralDec
+                    | TokenKind::IntegerLiteralBin
+                    | TokenKind::IntegerLiteralOct
+                    | TokenKind::IntegerLiteralHex
+                    | TokenKind::FloatLiteral
+                    | TokenKind::StringLiteral
+                    | TokenKind::True
+                    | TokenKind::False
+                    | TokenKind::Nothing,
+                ..
+            } => {
+                let literal = self.parse_atom()?;
+                Ok(Rc::new(Pattern::Value(*literal.span(), literal)))
+            }
+            Token {
+                kind: TokenKind::LeftBracket,
+                span,
+                ..
+            } => {
+                self.increment();
+                let (items, _) = self.parse_sequence_pattern(TokenKind::RightBracket)?;
+                let end = self.consume(TokenKind::RightBracket)?.span;
+                Ok(Rc::new(Pattern::Array(span.extend(&end), items)))
+            }
+            Token {
+                kind: TokenKind::LeftParen,
+                span,
+                ..
+            } => {
+                self.increment();
+                let (mut items, tup) = self.parse_sequence_pattern(TokenKind::RightParen)?;
+                let end = self.consume(TokenKind::RightParen)?.span;
+                match items.len() {
+                    1 if !tup && !matches!(items[0].as_ref(), Pattern::Rest(..)) => {
+                        Ok(items.pop().unwrap())
+                    }
+                    _ => Ok(Rc::new(Pattern::Tuple(span.extend(&end), items))),
+                }
+            }
+            Token {
+                kind: TokenKind::LeftBrace,
+                span,
+                ..
+            } => {
+                self.increment();
+                let mut items = vec![];
+                let mut rest = None;
+                while self.cur().kind != TokenKind::RightBrace {
+                    if rest.is_some() {
+                        error!(self.cur().span, "`**` pattern must be the last in a dict");
+                    }
+                    if self.cur().kind == TokenKind::StarStar {
+                        self.increment();
+                        rest = Some(self.consume(TokenKind::Identifier)?.text);
+                    } else {
+                        let key = self.parse_expression()?;
+                        self.consume(TokenKind::Colon)?;
+                        items.push((key, self.parse_pattern()?));
+                    }
+                    match self.cur().kind {
+                        TokenKind::Comma => self.increment(),
+                        TokenKind::RightBrace => {}
+                        TokenKind::EOF => {
+                            eof_error!(self.cur().span, "Expected `}}` or ',' but got EOF")
+                        }
+                        _ => error!(
+                            self.cur().span,
+                            "Expected `}}` or `,` but got {:?}",
+                            self.cur().kind
+                        ),
+                    }
+                }
+                let end = self.consume(TokenKind::RightBrace)?.span;
+                Ok(Rc::new(Pattern::Dict(span.extend(&end), items, rest)))
+            }
+            Token {
+                kind: TokenKind::Star,
+                span,
+                ..
+            } => error!(span, "Rest patterns can only be used in arrays and tuples"),
+            Token {
+                kind: TokenKind::EOF,
+                span,
+                ..
+            } => eof_error!(span, "Expected pattern"),
+            _ => error!(
+                self.cur().span,
+                "Unexpected token in pattern: {}",
+                self.cur()
+            ),
+        }
+    }
+
+    fn parse_sequence_pattern(&mut self, closer: TokenKind) -> Result<(Vec<Rc<Pattern>>, bool)> {
+        let mut items = vec![];
+        let mut seen_comma = false;
+        while self.cur().kind != closer {
+            if self.cur().kind == TokenKind::Star {
+                let start = self.cur().span;
+                self.increment();
+                let name = self.consume(TokenKind::Identifier)?;
+                if items
+                    .iter()
+                    .any(|item: &Rc<Pattern>| matches!(item.as_ref(), Pattern::Rest(..)))
+                {
+                    error!(start.extend(&name.span), "Only one rest pattern is allowed");
+                }
+                let binding = if name.text == "_" {
+                    None
+                } else {
+                    Some(name.text)
+                };
+                items.push(Rc::new(Pattern::Rest(start.extend(&name.span), binding)));
+            } else {
+                items.push(self.parse_pattern()?);
+            }
+            match self.cur().kind {
+                TokenKind::Comma => {
+                    self.increment();
+                    seen_comma = true;
+                }
+                kind if kind == closer => {}
+                TokenKind::EOF => {
+                    eof_error!(self.cur().span, "Expected {:?} or ',' but got EOF", closer)
+                }
+                _ => error!(
+                    self.cur().span,
+                    "Expected {:?} or `,` but got {:?}",
+                    closer,
+                    self.cur().kind
+                ),
+            }
+        }
+        Ok((items, seen_comma))
+    }
+
     fn parse_format_string(&mut self, span: Span, text: String) -> Result<Rc<AST>> {
         let mut parts = vec![];
         let mut buf = String::new();
@@ -18,33 +18,28 @@
  */
 
 use crate::ast::AST;
-use crate::common::Ref;
+use crate::common::Span;
+use crate::engine::Engine;
 use crate::error::{Error, ErrorKind, Result};
+use crate::interpreter::debugger::{DebugClient, Paused, Resume, StopReason};
 use crate::interpreter::value::Value;
-use crate::interpreter::{Interpreter, Scope};
-use std::io::Write;
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
 use std::rc::Rc;
 
 pub const REPL_VERSION: &str = "1.0.0";
 
 pub struct Repl {
-    interpreter: Interpreter,
-    global_scope: Ref<Scope>,
-    verbose: bool,
+    engine: Engine,
 }
 
 impl Repl {
-    pub fn new(verbose: bool) -> Repl {
-        let interpreter = Interpreter::new();
-        let global_scope = Scope::new(None, false);
-        Repl {
-            interpreter,
-            global_scope,
-            verbose,
-        }
+    pub fn new(engine: Engine) -> Repl {
+        Repl { engine }
     }
 
-    fn run_once(&mut self) -> Result<()> {
+    fn run_once(&mut self) -> std::result::Result<(), Vec<Error>> {
         let mut input = String::new();
         let ast = loop {
             let mut temp = String::new();
@@ -61,44 +56,32 @@ impl Repl {
             }
 
             input.push_str(&temp);
-            match self.try_parse(input.clone()) {
+            match self.engine.parse(input.clone(), "<repl>") {
                 Ok(ast) => break ast,
-                Err(Error {
-                    kind: ErrorKind::UnexpectedEOF,
-                    ..
-                }) => {}
-                Err(err) => return Err(err),
+                Err(errors)
+                    if matches!(
+                        errors.as_slice(),
+                        [Error {
+                            kind: ErrorKind::UnexpectedEOF,
+                            ..
+                        }]
+                    ) => {}
+                Err(errors) => return Err(errors),
             }
         };
 
-        if self.verbose {
-            println!("{:#?}", ast);
-        }
+        self.run_ast(&ast).map_err(|err| vec![err])
+    }
 
-        let val = self
-            .interpreter
-            .run_block_without_new_scope(&ast, self.global_scope.clone())?;
+    fn run_ast(&mut self, ast: &Rc<AST>) -> Result<()> {
+        let val = self.engine.run(ast)?;
         match &val {
             Value::Nothing => {}
-            _ => println!("{}", val.repr()),
+            _ => println!("{}", self.engine.repr(&val)?),
         }
         Ok(())
     }
 
-    fn try_parse(&self, input: String) -> Result<Rc<AST>> {
-        let mut lex = crate::lexer::Lexer::new(input, "<repl>");
-        let tokens = lex.lex()?;
-
-        if self.verbose {
-            for token in &tokens {
-                println!("{:?}", token);
-            }
-        }
-
-        let mut parser = crate::parser::Parser::new(tokens);
-        parser.parse()
-    }
-
     pub fn run(&mut self) {
         println!("Rattlescript  Copyright (C) 2023  Haven Selph\nThis program comes with ABSOLUTELY NO WARRANTY.\nThis is free software, and you are welcome to redistribute it\nunder certain conditions.\n");
         println!(
@@ -110,21 +93,208 @@ impl Repl {
         loop {
             match self.run_once() {
                 Ok(_) => {}
-                Err(err) => {
-                    if err.span.0.line == err.span.1.line {
-                        let len = err.span.1.column - err.span.0.column;
-                        if len <= 1 {
-                            println!("   {}\x1b[0;31m▲\x1b[0m", " ".repeat(err.span.0.column));
-                        } else {
-                            println!(
-                                "   {}\x1b[0;31m└{}┘\x1b[0m",
-                                " ".repeat(err.span.0.column),
-                                "─".repeat(len - 2)
-                            );
+                Err(errors) => {
+                    for err in errors {
+                        print_error(&err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Points at the error under the line it's on, then prints the error
+fn print_error(err: &Error) {
+    if err.span.0.line == err.span.1.line {
+        let len = err.span.1.column - err.span.0.column;
+        if len <= 1 {
+            println!("   {}\x1b[0;31m▲\x1b[0m", " ".repeat(err.span.0.column));
+        } else {
+            println!(
+                "   {}\x1b[0;31m└{}┘\x1b[0m",
+                " ".repeat(err.span.0.column),
+                "─".repeat(len - 2)
+            );
+        }
+    }
+    err.print_traceback();
+    println!("\x1b[0;31m{}\x1b[0m", err);
+    err.print_notes();
+}
+
+const DEBUG_HELP: &str = "\
+Commands:
+  c, continue          Run until the next breakpoint
+  s, step              Run until the next statement, going into calls
+  n, next              Run until the next statement, stepping over calls
+  f, finish            Run until the current function returns
+  b, break [file:]line Pause at a line, or list the breakpoints without one
+  d, delete [file:]line
+                       Remove a breakpoint
+  bt, stack            Print the call stack
+  frame <n>            Look at frame n of the call stack
+  v, vars              Print the variables of the frame, besides the globals
+  globals              Print the global variables of the frame
+  p, print <code>      Run code in the frame and print its value
+  l, list              Print the lines around the frame
+  q, quit              Stop the program
+Anything else is run as code in the frame.";
+
+/// A debugger that pauses into a prompt on stdin and stdout, where the
+/// variables of the program can be looked at, code can be run and the
+/// program can be stepped through. `help` lists its commands.
+#[derive(Default)]
+pub struct DebugPrompt {
+    // The frame commands look at, counting from the innermost one
+    frame: usize,
+    // The lines of the files shown so far, or None if they can't be read
+    sources: HashMap<&'static str, Option<Vec<String>>>,
+}
+
+impl DebugPrompt {
+    pub fn new() -> DebugPrompt {
+        DebugPrompt::default()
+    }
+
+    // Prints some lines of the file a span is in, pointing at its first one
+    fn list(&mut self, span: &Span, around: usize) {
+        let location = span.0;
+        let source = self.sources.entry(location.filename).or_insert_with(|| {
+            let source = std::fs::read_to_string(location.filename).ok()?;
+            Some(source.lines().map(str::to_string).collect())
+        });
+        let Some(lines) = source else {
+            return;
+        };
+        let first = location.line.saturating_sub(around).max(1);
+        let lines = lines.iter().enumerate().skip(first - 1);
+        for (index, line) in lines.take(around * 2 + 1) {
+            let current = index + 1 == location.line;
+            let marker = if current { "->" } else { "  " };
+            println!("{} {:4} | {}", marker, index + 1, line);
+        }
+    }
+
+    // Reads `[file:]line`, with the file of the current frame by default
+    fn breakpoint(&self, paused: &Paused, arg: &str) -> Option<(String, usize)> {
+        let (file, line) = match arg.rsplit_once(':') {
+            Some((file, line)) => (file.to_string(), line),
+            None => {
+                let frames = paused.frames();
+                let filename = frames.get(self.frame).unwrap_or(&frames[0]).span.0.filename;
+                (filename.to_string(), arg)
+            }
+        };
+        Some((file, line.trim().parse().ok()?))
+    }
+
+    // Runs a command, returning how the program carries on if it does
+    fn command(&mut self, paused: &mut Paused, line: &str) -> Option<Resume> {
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        match command {
+            "" => {}
+            "c" | "continue" => return Some(Resume::Continue),
+            "s" | "step" => return Some(Resume::StepIn),
+            "n" | "next" => return Some(Resume::StepOver),
+            "f" | "finish" => return Some(Resume::StepOut),
+            "b" | "break" if arg.is_empty() => {
+                for (path, line) in paused.breakpoints() {
+                    println!("{}:{}", path.display(), line);
+                }
+            }
+            "b" | "break" => match self.breakpoint(paused, arg) {
+                Some((file, line)) => {
+                    paused.add_breakpoint(Path::new(&file), line);
+                    println!("Breakpoint at {}:{}", file, line);
+                }
+                None => println!("Expected a line, like `break 12` or `break file.rat:12`"),
+            },
+            "d" | "delete" => match self.breakpoint(paused, arg) {
+                Some((file, line)) if paused.remove_breakpoint(Path::new(&file), line) => {
+                    println!("Removed the breakpoint at {}:{}", file, line);
+                }
+                Some((file, line)) => println!("There is no breakpoint at {}:{}", file, line),
+                None => println!("Expected a line, like `delete 12` or `delete file.rat:12`"),
+            },
+            "bt" | "stack" => {
+                for (index, frame) in paused.frames().iter().enumerate() {
+                    let marker = if index == self.frame { "->" } else { "  " };
+                    println!("{} #{} {} at {}", marker, index, frame.name, frame.span.0);
+                }
+            }
+            "frame" => match arg.parse() {
+                Ok(frame) if frame < paused.frames().len() => {
+                    self.frame = frame;
+                    let frame = &paused.frames()[frame];
+                    println!("#{} {} at {}", self.frame, frame.name, frame.span.0);
+                    self.list(&frame.span, 0);
+                }
+                _ => println!("Expected the number of a frame from `stack`"),
+            },
+            "v" | "vars" => {
+                let mut variables = paused.locals(self.frame);
+                // The program itself only has globals
+                if self.frame + 1 == paused.frames().len() {
+                    for (name, value) in paused.globals(self.frame) {
+                        if !variables.iter().any(|(local, _)| *local == name) {
+                            variables.push((name, value));
                         }
                     }
-                    println!("\x1b[0;31m{}\x1b[0m", err);
                 }
+                for (name, value) in variables {
+                    println!("{} = {}", name, value);
+                }
+            }
+            "globals" => {
+                for (name, value) in paused.globals(self.frame) {
+                    println!("{} = {}", name, value);
+                }
+            }
+            "p" | "print" => self.evaluate(paused, arg),
+            "l" | "list" => {
+                let frames = paused.frames();
+                self.list(&frames[self.frame].span, 5);
+            }
+            "q" | "quit" => std::process::exit(0),
+            "h" | "help" => println!("{}", DEBUG_HELP),
+            _ => self.evaluate(paused, line),
+        }
+        None
+    }
+
+    fn evaluate(&mut self, paused: &mut Paused, code: &str) {
+        match paused.evaluate(self.frame, code) {
+            Ok(value) => println!("{}", value),
+            // Colours would only get in the way of whatever reads a redirected stdout
+            Err(err) if std::io::stdout().is_terminal() => println!("\x1b[0;31m{}\x1b[0m", err),
+            Err(err) => println!("{}", err),
+        }
+    }
+}
+
+impl DebugClient for DebugPrompt {
+    fn paused(&mut self, paused: &mut Paused) -> Resume {
+        self.frame = 0;
+        let frame = &paused.frames()[0];
+        let reason = match paused.reason() {
+            StopReason::Entry => "Paused",
+            StopReason::Breakpoint => "Breakpoint",
+            StopReason::Step => "Stepped",
+        };
+        println!("{} in {} at {}", reason, frame.name, frame.span.0);
+        self.list(&frame.span, 0);
+        loop {
+            print!("(debug) ");
+            std::io::stdout().flush().expect("Failed to flush stdout");
+            let mut line = String::new();
+            match std::io::stdin().read_line(&mut line) {
+                // Without any more input, the program runs to the end
+                Ok(0) | Err(_) => return Resume::Continue,
+                Ok(_) => {}
+            }
+            if let Some(resume) = self.command(paused, line.trim()) {
+                return resume;
             }
         }
     }
@@ -0,0 +1,477 @@
+/*
+   Copyright (C) 2023  Haven Selph
+   Copyright (C) 2023  Mustafa Quraish
+   Check the LICENSE file for more information.
+*/
+
+use crate::ast::{import_name, Layout, Pattern, Slot, AST};
+use crate::common::Span;
+use crate::error::{resolver_error as error, Error, ErrorKind, Result};
+use crate::interpreter::native::Registry;
+use std::rc::Rc;
+
+// The resolver runs between parsing and execution. It gives every variable a
+// slot in the scope that declares it and annotates each use with where to
+// find it, so the interpreter indexes into the scope chain instead of looking
+// names up. Variables used before they are declared, or assigned without
+// ever being declared, are reported here instead of at runtime.
+//
+// Its scopes have to line up with the ones the interpreter creates: one for
+// the program, every call, class body, namespace, catch block, match arm,
+// loop iteration and comprehension item, plus one for any other block that
+// declares something. The bodies of functions, loops, arms and catch blocks
+// run directly in the scope of their construct.
+
+// Resolves a program that runs in a scope where `globals` are declared
+// already, like the global scope of the REPL. Names in the registry can be
+// used before a variable of the same name shadows them.
+pub fn resolve(program: &Rc<AST>, globals: &[String], registry: &Registry) -> Result<()> {
+    let AST::Block(_, stmts, layout) = program.as_ref() else {
+        unreachable!("resolve called on non-block")
+    };
+    let mut declarations = Declarations {
+        names: globals.to_vec(),
+        wildcard: false,
+    };
+    declarations.statements(stmts);
+    let mut resolver = Resolver {
+        scopes: Vec::new(),
+        registry,
+    };
+    resolver.scope(declarations, globals.len(), false, layout, |resolver| {
+        resolver.statements(stmts)
+    })
+}
+
+// The variables declared directly in a scope, collected before resolving it
+// so a use can tell a variable that isn't declared yet from one that doesn't
+// exist at all
+#[derive(Default)]
+struct Declarations {
+    names: Vec<String>,
+    // `from x import *` can declare anything
+    wildcard: bool,
+}
+
+impl Declarations {
+    fn add(&mut self, name: &str) {
+        if !self.names.iter().any(|existing| existing == name) {
+            self.names.push(name.to_string());
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Binding(_, name) | Pattern::Rest(_, Some(name)) => self.add(name),
+            Pattern::Wildcard(_) | Pattern::Value(..) | Pattern::Rest(_, None) => {}
+            Pattern::Or(_, items) | Pattern::Array(_, items) | Pattern::Tuple(_, items) => {
+                items.iter().for_each(|item| self.pattern(item))
+            }
+            Pattern::Dict(_, items, rest) => {
+                items.iter().for_each(|(_, item)| self.pattern(item));
+                if let Some(rest) = rest {
+                    self.add(rest);
+                }
+            }
+            Pattern::Class(_, _, args) => args.iter().for_each(|(_, item)| self.pattern(item)),
+        }
+    }
+
+    // A body that runs directly in the scope, like the statements of a function
+    fn body(&mut self, body: &AST) {
+        match body {
+            AST::Block(_, stmts, _) => self.statements(stmts),
+            _ => self.node(body),
+        }
+    }
+
+    fn statements(&mut self, stmts: &[Rc<AST>]) {
+        stmts.iter().for_each(|stmt| self.node(stmt))
+    }
+
+    // Whatever a node declares in the scope it runs in, skipping the parts
+    // that run in scopes of their own
+    fn node(&mut self, ast: &AST) {
+        match ast {
+            AST::VarDeclaration(_, name, value) => {
+                self.node(value);
+                self.add(name);
+            }
+            AST::Destructure(_, pattern, value) => {
+                self.node(value);
+                self.pattern(pattern);
+            }
+            AST::Function { name, args, .. } => {
+                for default in args.iter().filter_map(|(_, default, _)| default.as_ref()) {
+                    self.node(default);
+                }
+                if let Some(name) = name {
+                    self.add(name);
+                }
+            }
+            AST::Class { name, .. } | AST::Enum { name, .. } | AST::Namespace { name, .. } => {
+                self.add(name)
+            }
+            AST::Import { path, alias, .. } => self.add(&import_name(path, alias)),
+            AST::FromImport { names, .. } => {
+                for (name, alias) in names {
+                    match name.as_str() {
+                        "*" => self.wildcard = true,
+                        _ => self.add(alias.as_ref().unwrap_or(name)),
+                    }
+                }
+            }
+            // Blocks that declare something get a scope of their own
+            AST::Block(..) => {}
+            AST::If(_, cond, body, else_body) => {
+                self.node(cond);
+                for body in std::iter::once(body).chain(else_body) {
+                    if !matches!(body.as_ref(), AST::Block(..)) {
+                        self.node(body);
+                    }
+                }
+            }
+            AST::While(_, cond, body) => {
+                self.node(cond);
+                if !matches!(body.as_ref(), AST::Block(..)) {
+                    self.node(body);
+                }
+            }
+            AST::ForEach(_, _, iter, ..)
+            | AST::Comprehension(_, _, iter, ..)
+            | AST::SetComprehension(_, _, iter, ..) => self.node(iter),
+            AST::Match(_, subject, _) => self.node(subject),
+            AST::For { .. } | AST::Try { .. } => {}
+            _ => ast.for_each_child(&mut |child| self.node(child)),
+        }
+    }
+}
+
+struct Scope {
+    names: Vec<String>,
+    declared: Vec<bool>,
+    // Function bodies run after the code around them, so they can use
+    // variables that enclosing scopes declare further down
+    function: bool,
+    wildcard: bool,
+}
+
+struct Resolver<'a> {
+    scopes: Vec<Scope>,
+    registry: &'a Registry,
+}
+
+impl Resolver<'_> {
+    // Resolves a construct with a scope of its own, whose first `declared`
+    // variables exist from the start, and fills in its layout
+    fn scope(
+        &mut self,
+        declarations: Declarations,
+        declared: usize,
+        function: bool,
+        layout: &Layout,
+        resolve: impl FnOnce(&mut Resolver) -> Result<()>,
+    ) -> Result<()> {
+        self.scopes.push(Scope {
+            declared: (0..declarations.names.len())
+                .map(|i| i < declared)
+                .collect(),
+            names: declarations.names,
+            function,
+            wildcard: declarations.wildcard,
+        });
+        resolve(self)?;
+        let scope = self.scopes.pop().expect("Resolver scope stack is empty");
+        layout.set(scope.names);
+        Ok(())
+    }
+
+    fn declare(&mut self, name: &str) {
+        let scope = self
+            .scopes
+            .last_mut()
+            .expect("Resolver scope stack is empty");
+        match scope.names.iter().position(|existing| existing == name) {
+            Some(index) => scope.declared[index] = true,
+            None => {
+                scope.names.push(name.to_string());
+                scope.declared.push(true);
+            }
+        }
+    }
+
+    // Finds the slot of a variable, or None if it has to be looked up by name
+    // at runtime, like builtins and names from `from x import *`
+    fn lookup(&self, span: &Span, name: &str, assign: bool) -> Result<Option<Slot>> {
+        let mut same_function = true;
+        let mut later = false;
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            match scope.names.iter().position(|existing| existing == name) {
+                Some(index) if scope.declared[index] || !same_function => {
+                    return Ok(Some(Slot { depth, index }))
+                }
+                _ if scope.wildcard => return Ok(None),
+                Some(_) => later = true,
+                None => {}
+            }
+            if scope.function {
+                same_function = false;
+            }
+        }
+        if later && (assign || self.registry.global(name).is_none()) {
+            error!(span, "Variable '{}' is used before it is declared", name)
+        }
+        if assign {
+            let names = self.scopes.iter().flat_map(|scope| &scope.names);
+            let message = format!("Variable {} doesn't exist", name);
+            return Err(Error::new(ErrorKind::Resolver, *span, message)
+                .suggest(name, names.map(String::as_str)));
+        }
+        Ok(None)
+    }
+
+    fn statements(&mut self, stmts: &[Rc<AST>]) -> Result<()> {
+        stmts.iter().try_for_each(|stmt| self.node(stmt))
+    }
+
+    // A body that runs directly in the scope of its construct
+    fn body(&mut self, body: &Rc<AST>) -> Result<()> {
+        match body.as_ref() {
+            AST::Block(_, stmts, _) => self.statements(stmts),
+            _ => self.node(body),
+        }
+    }
+
+    // Resolves the expressions in a pattern, then declares its bindings
+    fn pattern(&mut self, pattern: &Pattern) -> Result<()> {
+        let mut result = Ok(());
+        pattern.for_each_expression(&mut |expr| {
+            if result.is_ok() {
+                result = self.node(expr);
+            }
+        });
+        result?;
+        let mut declarations = Declarations::default();
+        declarations.pattern(pattern);
+        declarations
+            .names
+            .iter()
+            .for_each(|name| self.declare(name));
+        Ok(())
+    }
+
+    // An assignment target, where plain variables have to exist already
+    fn target(&mut self, target: &Rc<AST>) -> Result<()> {
+        match target.as_ref() {
+            AST::Variable(span, name, slot) => slot.set(self.lookup(span, name, true)?),
+            AST::TupleLiteral(_, targets) | AST::ArrayLiteral(_, targets) => {
+                targets.iter().try_for_each(|target| self.target(target))?
+            }
+            AST::StarExpression(_, target) => self.target(target)?,
+            _ => self.node(target)?,
+        }
+        Ok(())
+    }
+
+    // A function literal, whose arguments and body get a scope for each call
+    fn function(&mut self, ast: &AST) -> Result<()> {
+        let AST::Function {
+            args,
+            in_class,
+            is_static,
+            body,
+            layout,
+            ..
+        } = ast
+        else {
+            unreachable!("function called on non-function")
+        };
+        for default in args.iter().filter_map(|(_, default, _)| default.as_ref()) {
+            self.node(default)?;
+        }
+        let mut declarations = Declarations::default();
+        for (name, ..) in args {
+            declarations.add(name);
+        }
+        if *in_class && !*is_static {
+            declarations.add("self");
+            declarations.add("__class__");
+        }
+        let count = declarations.names.len();
+        declarations.body(body);
+        self.scope(declarations, count, true, layout, |resolver| {
+            resolver.body(body)
+        })
+    }
+
+    fn node(&mut self, ast: &Rc<AST>) -> Result<()> {
+        match ast.as_ref() {
+            AST::Variable(span, name, slot) => slot.set(self.lookup(span, name, false)?),
+            AST::VarDeclaration(_, name, value) => {
+                self.node(value)?;
+                self.declare(name);
+            }
+            AST::Destructure(_, pattern, value) => {
+                self.node(value)?;
+                self.pattern(pattern)?;
+            }
+            AST::Assignment(_, target, value) => {
+                self.node(value)?;
+                self.target(target)?;
+            }
+            AST::PostIncrement(_, target, _) | AST::PreIncrement(_, target, _) => {
+                self.target(target)?
+            }
+            AST::Function { name, .. } => {
+                self.function(ast)?;
+                if let Some(name) = name {
+                    self.declare(name);
+                }
+            }
+            AST::Class {
+                name,
+                fields,
+                layout,
+                ..
+            }
+            | AST::Enum {
+                name,
+                fields,
+                layout,
+                ..
+            } => {
+                // Nested classes and enums are visible to the methods, the
+                // methods themselves aren't
+                let mut declarations = Declarations::default();
+                for (value, _) in fields.values() {
+                    if let AST::Class { name, .. } | AST::Enum { name, .. } = value.as_ref() {
+                        declarations.add(name);
+                    }
+                }
+                let count = declarations.names.len();
+                self.scope(declarations, count, false, layout, |resolver| {
+                    fields
+                        .values()
+                        .try_for_each(|(value, _)| match value.as_ref() {
+                            AST::Function { .. } => resolver.function(value),
+                            _ => resolver.node(value),
+                        })
+                })?;
+                self.declare(name);
+            }
+            AST::Namespace { name, body, .. } => {
+                let AST::Block(_, stmts, layout) = body.as_ref() else {
+                    unreachable!("Namespace without a block")
+                };
+                let mut declarations = Declarations::default();
+                declarations.statements(stmts);
+                self.scope(declarations, 0, false, layout, |resolver| {
+                    resolver.statements(stmts)
+                })?;
+                self.declare(name);
+            }
+            AST::Import { path, alias, .. } => self.declare(&import_name(path, alias)),
+            AST::FromImport { names, .. } => {
+                for (name, alias) in names.iter().filter(|(name, _)| name != "*") {
+                    self.declare(alias.as_ref().unwrap_or(name));
+                }
+            }
+            AST::Block(_, stmts, layout) => {
+                let mut declarations = Declarations::default();
+                declarations.statements(stmts);
+                if declarations.names.is_empty() && !declarations.wildcard {
+                    self.statements(stmts)?;
+                } else {
+                    self.scope(declarations, 0, false, layout, |resolver| {
+                        resolver.statements(stmts)
+                    })?;
+                }
+            }
+            AST::For {
+                init,
+                cond,
+                step,
+                body,
+                layout,
+                ..
+            } => {
+                let mut declarations = Declarations::default();
+                init.iter().for_each(|init| declarations.node(init));
+                self.scope(declarations, 0, false, layout, |resolver| {
+                    init.iter()
+                        .chain(cond)
+                        .try_for_each(|node| resolver.node(node))?;
+                    resolver.node(body)?;
+                    step.iter().try_for_each(|step| resolver.node(step))
+                })?;
+            }
+            AST::ForEach(_, pattern, iter, body, layout) => {
+                self.node(iter)?;
+                let mut declarations = Declarations::default();
+                declarations.pattern(pattern);
+                declarations.body(body);
+                self.scope(declarations, 0, false, layout, |resolver| {
+                    resolver.pattern(pattern)?;
+                    resolver.body(body)
+                })?;
+            }
+            AST::Comprehension(_, pattern, iter, expr, cond, layout)
+            | AST::SetComprehension(_, pattern, iter, expr, cond, layout) => {
+                self.node(iter)?;
+                let mut declarations = Declarations::default();
+                declarations.pattern(pattern);
+                self.scope(declarations, 0, false, layout, |resolver| {
+                    resolver.pattern(pattern)?;
+                    cond.iter().try_for_each(|cond| resolver.node(cond))?;
+                    resolver.node(expr)
+                })?;
+            }
+            AST::Match(_, subject, arms) => {
+                self.node(subject)?;
+                for (pattern, guard, body, layout) in arms {
+                    let mut declarations = Declarations::default();
+                    declarations.pattern(pattern);
+                    declarations.body(body);
+                    self.scope(declarations, 0, false, layout, |resolver| {
+                        resolver.pattern(pattern)?;
+                        guard.iter().try_for_each(|guard| resolver.node(guard))?;
+                        resolver.body(body)
+                    })?;
+                }
+            }
+            AST::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.node(body)?;
+                if let Some((name, body)) = catch {
+                    let AST::Block(_, stmts, layout) = body.as_ref() else {
+                        unreachable!("Catch without a block")
+                    };
+                    let mut declarations = Declarations::default();
+                    if let Some(name) = name {
+                        declarations.add(name);
+                    }
+                    let count = declarations.names.len();
+                    declarations.statements(stmts);
+                    self.scope(declarations, count, false, layout, |resolver| {
+                        resolver.statements(stmts)
+                    })?;
+                }
+                finally.iter().try_for_each(|finally| self.node(finally))?;
+            }
+            _ => {
+                let mut result = Ok(());
+                ast.for_each_child(&mut |child| {
+                    if result.is_ok() {
+                        result = self.node(child);
+                    }
+                });
+                result?;
+            }
+        }
+        Ok(())
+    }
+}
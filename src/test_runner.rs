@@ -0,0 +1,351 @@
+/*
+   Copyright (C) 2023  Haven Selph
+   Copyright (C) 2023  Mustafa Quraish
+   Check the LICENSE file for more information.
+*/
+
+use crate::lexer::Lexer;
+use crate::token::TokenKind;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+/// Runs RattleScript tests in parallel, each in a new `rattlescript` process
+/// so that they can't affect each other and their output can be checked.
+///
+/// A file is a test when it starts with a `///` header saying what running it
+/// should do:
+/// - `/// exit: <code>` exits with that code
+/// - `/// out: "<output>"` prints the output, ignoring whitespace around it
+/// - `/// fail: <message>` fails with an error containing the message
+/// - `/// skip` isn't run, and neither are its test functions
+///
+/// Every function declared at the top level of a file whose name starts with
+/// `test_` is a test as well, which passes when calling it after running the
+/// file doesn't fail.
+pub struct TestRunner {
+    interpreter: PathBuf,
+    use_vm: bool,
+    jobs: usize,
+}
+
+// A file to run, and maybe a function in it to call afterwards
+struct Test {
+    path: PathBuf,
+    function: Option<String>,
+    expected: Expected,
+}
+
+// What running a test should do
+enum Expected {
+    Exit(i32),
+    Output(String),
+    Fail(String),
+    // Fails without running, like when the header doesn't make sense
+    Invalid(String),
+}
+
+// The header at the start of a file
+enum Header {
+    Expect(Expected),
+    Skip,
+    Missing,
+}
+
+impl TestRunner {
+    /// Creates a runner that runs tests with the `rattlescript` binary at
+    /// `interpreter`, as many at once as there are CPUs.
+    pub fn new(interpreter: PathBuf) -> TestRunner {
+        TestRunner {
+            interpreter,
+            use_vm: false,
+            jobs: std::thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+        }
+    }
+
+    /// Runs the tests on the bytecode VM instead of the tree walker.
+    pub fn enable_vm(&mut self) {
+        self.use_vm = true;
+    }
+
+    /// Sets how many tests run at once.
+    pub fn set_jobs(&mut self, jobs: usize) {
+        self.jobs = jobs.max(1);
+    }
+
+    /// Runs the tests in `files`, reporting failures with what went wrong as
+    /// they happen and a summary at the end, and returns whether they all
+    /// passed. With `colors`, results are coloured and the progress is shown
+    /// while the tests run.
+    pub fn run(
+        &self,
+        files: &[PathBuf],
+        output: &mut dyn Write,
+        colors: bool,
+    ) -> std::io::Result<bool> {
+        let (red, green, reset) = if colors {
+            ("\x1b[91m", "\x1b[92m", "\x1b[0m")
+        } else {
+            ("", "", "")
+        };
+
+        let mut tests = vec![];
+        for path in files {
+            let source = match std::fs::read_to_string(path) {
+                Ok(source) => source,
+                Err(err) => {
+                    let expected = Expected::Invalid(format!("Couldn't read the file: {}", err));
+                    tests.push(Test {
+                        path: path.clone(),
+                        function: None,
+                        expected,
+                    });
+                    continue;
+                }
+            };
+            let functions = test_functions(&source);
+            match header(&source) {
+                Header::Skip => continue,
+                Header::Missing if functions.is_empty() => {
+                    writeln!(output, "[-] Skipping {}", path.display())?;
+                }
+                Header::Missing => {}
+                Header::Expect(expected) => tests.push(Test {
+                    path: path.clone(),
+                    function: None,
+                    expected,
+                }),
+            }
+            tests.extend(functions.into_iter().map(|function| Test {
+                path: path.clone(),
+                function: Some(function),
+                expected: Expected::Exit(0),
+            }));
+        }
+
+        let (mut passed, mut failed) = (0, 0);
+        let next = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel();
+        std::thread::scope(|scope| {
+            for _ in 0..self.jobs.min(tests.len()) {
+                let (sender, next, tests) = (sender.clone(), &next, &tests);
+                scope.spawn(move || {
+                    while let Some(test) = tests.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let _ = sender.send((test, self.run_test(test)));
+                    }
+                });
+            }
+            drop(sender);
+
+            for (test, result) in receiver {
+                match result {
+                    Ok(()) => passed += 1,
+                    Err(message) => {
+                        failed += 1;
+                        let name = match &test.function {
+                            Some(function) => format!("{} in {}", function, test.path.display()),
+                            None => test.path.display().to_string(),
+                        };
+                        if colors {
+                            write!(output, "\r\x1b[2K")?;
+                        }
+                        writeln!(output, "{}[-] Failed {}{}", red, name, reset)?;
+                        for line in message.lines() {
+                            writeln!(output, "    {}", line)?;
+                        }
+                    }
+                }
+                if colors {
+                    write!(
+                        output,
+                        "\r\x1b[2K[{}{:3}{}/{}{:3}{}] Running tests, finished {} / {}",
+                        green,
+                        passed,
+                        reset,
+                        red,
+                        failed,
+                        reset,
+                        passed + failed,
+                        tests.len()
+                    )?;
+                    output.flush()?;
+                }
+            }
+            std::io::Result::Ok(())
+        })?;
+
+        if colors {
+            write!(output, "\r\x1b[2K")?;
+        }
+        writeln!(output, "Tests passed: {}{}{}", green, passed, reset)?;
+        writeln!(output, "Tests failed: {}{}{}", red, failed, reset)?;
+        Ok(failed == 0)
+    }
+
+    // Runs a test, returning what went wrong if it failed
+    fn run_test(&self, test: &Test) -> Result<(), String> {
+        if let Expected::Invalid(message) = &test.expected {
+            return Err(message.clone());
+        }
+
+        let mut command = Command::new(&self.interpreter);
+        command.arg("--disable-error-context");
+        if self.use_vm {
+            command.arg("--vm");
+        }
+        if let Some(function) = &test.function {
+            command.arg(format!("--call={}", function));
+        }
+        let result = command.arg(&test.path).output();
+        let result = result
+            .map_err(|err| format!("Couldn't run {}: {}", self.interpreter.display(), err))?;
+        let stdout = String::from_utf8_lossy(&result.stdout).trim().to_string();
+        let stderr = String::from_utf8_lossy(&result.stderr).trim().to_string();
+
+        // Test functions don't say what they print, so it helps to see it
+        let with_output = |message: String| {
+            if test.function.is_none() || stdout.is_empty() {
+                return message;
+            }
+            format!("{}\nOutput:\n{}", message, stdout)
+        };
+        let Some(code) = result.status.code() else {
+            return Err(with_output(format!(
+                "Crashed ({})\n{}",
+                result.status, stderr
+            )));
+        };
+        match &test.expected {
+            Expected::Fail(_) if code == 0 => Err("Expected failure, but succeeded".to_string()),
+            Expected::Fail(message) if !stderr.contains(message.as_str()) => Err(format!(
+                "Did not find expected error message\nexpected: {:?}\ngot: {:?}",
+                message, stderr
+            )),
+            Expected::Fail(_) => Ok(()),
+            Expected::Exit(expected) if code != *expected => Err(with_output(format!(
+                "Expected exit code {}, but got {}\n{}",
+                expected, code, stderr
+            ))),
+            Expected::Exit(_) => Ok(()),
+            Expected::Output(_) if code != 0 => Err(format!(
+                "Expected exit code 0, but got {}\n{}",
+                code, stderr
+            )),
+            Expected::Output(expected) if stdout != expected.trim() => Err(format!(
+                "Incorrect output produced\n{}",
+                diff(expected.trim(), &stdout)
+            )),
+            Expected::Output(_) => Ok(()),
+            Expected::Invalid(_) => unreachable!(),
+        }
+    }
+}
+
+// Reads the header at the start of a file, which is made of `///` lines
+fn header(source: &str) -> Header {
+    for line in source.lines() {
+        let Some(line) = line.strip_prefix("///") else {
+            break;
+        };
+        let line = line.trim();
+        match line.split_once(':') {
+            None if line == "skip" => return Header::Skip,
+            None if line.is_empty() => continue,
+            Some((name, value)) => {
+                let value = value.trim();
+                let expected = match name.trim() {
+                    "exit" => value.parse().ok().map(Expected::Exit),
+                    "out" => parse_string(value).map(Expected::Output),
+                    "fail" => Some(Expected::Fail(value.to_string())),
+                    _ => None,
+                };
+                let invalid = || Expected::Invalid(format!("Invalid header: {}", line));
+                return Header::Expect(expected.unwrap_or_else(invalid));
+            }
+            None => return Header::Expect(Expected::Invalid(format!("Invalid header: {}", line))),
+        }
+    }
+    Header::Missing
+}
+
+// The value of an `out:` header, which is written like a string literal
+fn parse_string(value: &str) -> Option<String> {
+    let mut chars = value.chars();
+    let quote = chars.next().filter(|c| *c == '"' || *c == '\'')?;
+    let mut string = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => string.push(match chars.next()? {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                '0' => '\0',
+                c => c,
+            }),
+            c if c == quote => return chars.as_str().trim().is_empty().then_some(string),
+            c => string.push(c),
+        }
+    }
+    None
+}
+
+// The names of the functions declared at the top level of a file that start
+// with `test_`. Only the tokens are needed, so that imports don't have to be
+// found, and a file that doesn't lex fails when it runs anyway.
+fn test_functions(source: &str) -> Vec<String> {
+    let Ok(tokens) = Lexer::new(source.to_string(), "<test>").lex() else {
+        return vec![];
+    };
+    let mut depth = 0;
+    let mut functions = vec![];
+    for pair in tokens.windows(2) {
+        match pair[0].kind {
+            TokenKind::LeftBrace => depth += 1,
+            TokenKind::RightBrace => depth -= 1,
+            TokenKind::Def if depth == 0 && pair[1].text.starts_with("test_") => {
+                functions.push(pair[1].text.clone());
+            }
+            _ => {}
+        }
+    }
+    functions
+}
+
+// The lines of the expected and actual output, with `-` before the ones only
+// expected and `+` before the ones only produced
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+
+    // common[i][j] is how many lines `expected[i..]` and `actual[j..]` have in
+    // common, in the same order
+    let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = vec![];
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            lines.push(format!("  {}", expected[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if i < expected.len() && (j == actual.len() || common[i + 1][j] >= common[i][j + 1])
+        {
+            lines.push(format!("- {}", expected[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", actual[j]));
+            j += 1;
+        }
+    }
+    lines.join("\n")
+}
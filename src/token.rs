@@ -16,6 +16,7 @@ pub enum TokenKind {
     Bang,
     BangEquals,
     Break,
+    Catch,
     Class,
     Colon,
     Comma,
@@ -26,10 +27,12 @@ pub enum TokenKind {
     EOF,
     Elif,
     Else,
+    Enum,
     Equals,
     EqualsEquals,
     False,
     FatArrow,
+    Finally,
     FloatLiteral,
     For,
     FormatStringLiteral,
@@ -50,6 +53,7 @@ pub enum TokenKind {
     LessEquals,
     LessThan,
     Let,
+    Match,
     Minus,
     MinusEquals,
     MinusMinus,
@@ -74,8 +78,12 @@ pub enum TokenKind {
     StarStar,
     Static,
     StringLiteral,
+    Super,
+    Throw,
     True,
+    Try,
     While,
+    Yield,
 }
 
 #[derive(Debug, Clone)]
@@ -103,26 +111,34 @@ impl Token {
                 "as" => TokenKind::As,
                 "assert" => TokenKind::Assert,
                 "break" => TokenKind::Break,
+                "catch" => TokenKind::Catch,
                 "class" => TokenKind::Class,
                 "continue" => TokenKind::Continue,
                 "def" => TokenKind::Def,
                 "elif" => TokenKind::Elif,
                 "else" => TokenKind::Else,
+                "enum" => TokenKind::Enum,
                 "false" => TokenKind::False,
+                "finally" => TokenKind::Finally,
                 "for" => TokenKind::For,
                 "from" => TokenKind::From,
                 "if" => TokenKind::If,
                 "import" => TokenKind::Import,
                 "in" => TokenKind::In,
                 "let" => TokenKind::Let,
+                "match" => TokenKind::Match,
                 "not" => TokenKind::Not,
                 "namespace" => TokenKind::Namespace,
                 "nothing" => TokenKind::Nothing,
                 "or" => TokenKind::Or,
                 "return" => TokenKind::Return,
                 "static" => TokenKind::Static,
+                "super" => TokenKind::Super,
+                "throw" => TokenKind::Throw,
                 "true" => TokenKind::True,
+                "try" => TokenKind::Try,
                 "while" => TokenKind::While,
+                "yield" => TokenKind::Yield,
                 _ => TokenKind::Identifier,
             },
             span,
@@ -132,6 +148,15 @@ impl Token {
     }
 }
 
+// A comment, which isn't a token but is kept for the formatter
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub span: Span,
+    pub text: String,
+    // Whether nothing but whitespace comes before it on its line
+    pub own_line: bool,
+}
+
 impl std::fmt::Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         write!(f, "{:?}", self.kind)?;
@@ -0,0 +1,223 @@
+// Debugging a file from the `--debug` prompt and through the debug adapter,
+// scripted the way a person or an editor would drive them
+
+use rattlescript::Json;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+// Stops at the `breakpoint()` call twice. A breakpoint on its line must not
+// make either of them stop twice.
+const SOURCE: &str = "let total = 0
+for i in 0..2 {
+    total += i
+    breakpoint()
+}
+print(total)
+";
+
+fn write_source(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rattlescript-debugger-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.rat", name));
+    std::fs::write(&path, SOURCE).unwrap();
+    path
+}
+
+// Runs the binary with everything it will read on stdin up front. The debug
+// adapter exits the process when its input ends while the file is paused, so
+// it can't run in the test's own.
+fn run(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rattlescript"))
+        .args(args)
+        .env("RATTLESCRIPT_PATH", env!("CARGO_MANIFEST_DIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(input.as_bytes()).unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    output
+}
+
+#[test]
+fn debug_prompt_session() {
+    let path = write_source("prompt");
+    let commands = "b 4\nc\nv\np total + 10\nc\nglobals\nc\n";
+    let output = run(&["--debug", path.to_str().unwrap()], commands);
+
+    let file = path.display();
+    let expected = format!(
+        "Paused in <program> at {file}:1:1
+->    1 | let total = 0
+(debug) Breakpoint at {file}:4
+(debug) Breakpoint in <program> at {file}:4:5
+->    4 |     breakpoint()
+(debug) i = 0
+total = 0
+(debug) 10
+(debug) Breakpoint in <program> at {file}:4:5
+->    4 |     breakpoint()
+(debug) total = 1
+(debug) 1
+"
+    );
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
+
+fn request(seq: usize, command: &str, arguments: Json) -> String {
+    let body = Json::object(vec![
+        ("seq", seq.into()),
+        ("type", "request".into()),
+        ("command", command.into()),
+        ("arguments", arguments),
+    ])
+    .to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+fn messages(output: &[u8]) -> Vec<Json> {
+    let output = String::from_utf8(output.to_vec()).unwrap();
+    let mut messages = vec![];
+    let mut rest = output.as_str();
+    while let Some(header) = rest.strip_prefix("Content-Length: ") {
+        let (length, body) = header.split_once("\r\n\r\n").unwrap();
+        let (body, tail) = body.split_at(length.parse().unwrap());
+        messages.push(Json::parse(body).unwrap());
+        rest = tail;
+    }
+    assert_eq!(rest, "", "Unframed output");
+    messages
+}
+
+fn string<'a>(json: &'a Json, key: &str) -> &'a str {
+    json.get(key).and_then(Json::as_str).unwrap()
+}
+
+#[test]
+fn debug_adapter_session() {
+    let path = write_source("adapter");
+    let program = path.to_str().unwrap();
+    let source = Json::object(vec![("path", program.into())]);
+    let breakpoint = Json::object(vec![("line", 4usize.into())]);
+    let frame = |id: usize| Json::object(vec![("frameId", id.into())]);
+    let variables = |reference: usize| Json::object(vec![("variablesReference", reference.into())]);
+    let requests = [
+        request(1, "initialize", Json::object(vec![])),
+        request(2, "launch", Json::object(vec![("program", program.into())])),
+        request(
+            3,
+            "setBreakpoints",
+            Json::object(vec![
+                ("source", source),
+                ("breakpoints", Json::Array(vec![breakpoint])),
+            ]),
+        ),
+        request(4, "configurationDone", Json::Null),
+        // Paused the first time
+        request(
+            5,
+            "stackTrace",
+            Json::object(vec![("threadId", 1usize.into())]),
+        ),
+        request(6, "scopes", frame(0)),
+        request(7, "variables", variables(1)),
+        request(8, "variables", variables(2)),
+        request(9, "continue", Json::Null),
+        // Paused the second time
+        request(
+            10,
+            "evaluate",
+            Json::object(vec![
+                ("expression", "total * 10".into()),
+                ("frameId", 0usize.into()),
+            ]),
+        ),
+        request(11, "continue", Json::Null),
+        // Done
+        request(12, "disconnect", Json::Null),
+    ];
+    let output = run(&["dap"], &requests.concat());
+    let messages = messages(&output.stdout);
+
+    // Every request is answered once, in order, and succeeds
+    let responses: Vec<&Json> = messages
+        .iter()
+        .filter(|message| string(message, "type") == "response")
+        .collect();
+    let answered: Vec<usize> = responses
+        .iter()
+        .map(|response| {
+            response
+                .get("request_seq")
+                .and_then(Json::as_usize)
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(answered, (1..=12).collect::<Vec<_>>());
+    for response in &responses {
+        assert_eq!(
+            response.get("success"),
+            Some(&Json::Bool(true)),
+            "{}",
+            response
+        );
+    }
+    let body = |seq: usize| responses[seq - 1].get("body").unwrap();
+
+    let verified = body(3).get("breakpoints").and_then(Json::as_array).unwrap();
+    assert_eq!(verified.len(), 1);
+    assert_eq!(verified[0].get("verified"), Some(&Json::Bool(true)));
+    assert_eq!(verified[0].get("line").and_then(Json::as_usize), Some(4));
+
+    let frames = body(5).get("stackFrames").and_then(Json::as_array).unwrap();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(string(&frames[0], "name"), "<program>");
+    assert_eq!(frames[0].get("line").and_then(Json::as_usize), Some(4));
+
+    let scopes = body(6).get("scopes").and_then(Json::as_array).unwrap();
+    let scopes: Vec<&str> = scopes.iter().map(|scope| string(scope, "name")).collect();
+    assert_eq!(scopes, ["Locals", "Globals"]);
+
+    let names = |seq: usize| -> Vec<(String, String)> {
+        let variables = body(seq).get("variables").and_then(Json::as_array).unwrap();
+        variables
+            .iter()
+            .map(|variable| {
+                (
+                    string(variable, "name").into(),
+                    string(variable, "value").into(),
+                )
+            })
+            .collect()
+    };
+    assert_eq!(names(7), [("i".to_string(), "0".to_string())]);
+    assert_eq!(names(8), [("total".to_string(), "0".to_string())]);
+    assert_eq!(string(body(10), "result"), "10");
+
+    // The file stopped once for each call, and then ran to the end
+    let events: Vec<(&str, &Json)> = messages
+        .iter()
+        .filter(|message| string(message, "type") == "event")
+        .map(|event| (string(event, "event"), event.get("body").unwrap()))
+        .collect();
+    let stops: Vec<&str> = events
+        .iter()
+        .filter(|(event, _)| *event == "stopped")
+        .map(|(_, body)| string(body, "reason"))
+        .collect();
+    assert_eq!(stops, ["breakpoint", "breakpoint"]);
+    assert!(events.contains(&(
+        "output",
+        &Json::object(vec![
+            ("category", "stdout".into()),
+            ("output", "1\n".into())
+        ])
+    )));
+    let exited = Json::object(vec![("exitCode", 0usize.into())]);
+    assert!(events.contains(&("exited", &exited)));
+    assert_eq!(events.last().map(|(event, _)| *event), Some("terminated"));
+}
@@ -0,0 +1,116 @@
+// The embedding API, as a host uses it
+
+use rattlescript::{Engine, ErrorKind, Value};
+use std::rc::Rc;
+
+fn string(text: &str) -> Value {
+    Value::String(Rc::new(text.to_string()))
+}
+
+#[test]
+fn globals_round_trip() {
+    let mut engine = Engine::new();
+    engine.set("count", Value::Integer(41));
+    engine.set("name", string("rattle"));
+    engine.eval("count += 1\nlet shout = name.upper()").unwrap();
+
+    assert!(matches!(engine.get("count"), Some(Value::Integer(42))));
+    match engine.get("shout") {
+        Some(Value::String(shout)) => assert_eq!(shout.as_str(), "RATTLE"),
+        other => panic!("Expected a string, got {:?}", other),
+    }
+    assert!(engine.get("missing").is_none());
+
+    // Setting a global again replaces it
+    engine.set("count", Value::Boolean(true));
+    assert!(matches!(engine.eval("count"), Ok(Value::Boolean(true))));
+}
+
+#[test]
+fn eval_returns_the_last_statement() {
+    let mut engine = Engine::new();
+    assert!(matches!(
+        engine.eval("let x = 2\nx * 21"),
+        Ok(Value::Integer(42))
+    ));
+    // Each run sees what the ones before it declared
+    assert!(matches!(engine.eval("x + 1"), Ok(Value::Integer(3))));
+}
+
+#[test]
+fn calling_script_functions() {
+    let mut engine = Engine::new();
+    engine
+        .eval("def add(a, b) => a + b\ndef adder(n) => |x| => x + n")
+        .unwrap();
+
+    let sum = engine.call("add", vec![Value::Integer(1), Value::Integer(2)]);
+    assert!(matches!(sum, Ok(Value::Integer(3))));
+
+    let add_ten = engine.call("adder", vec![Value::Integer(10)]).unwrap();
+    let result = engine.call_value(&add_ten, vec![Value::Integer(5)]);
+    assert!(matches!(result, Ok(Value::Integer(15))));
+
+    let greeting = engine.call("add", vec![string("a"), string("b")]).unwrap();
+    assert_eq!(engine.format(&greeting).unwrap(), "ab");
+    assert_eq!(engine.repr(&greeting).unwrap(), "\"ab\"");
+}
+
+#[test]
+fn script_errors_in_calls() {
+    let mut engine = Engine::new();
+    engine.eval("def fail() => 1 / 0").unwrap();
+
+    let err = engine.call("fail", vec![]).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Runtime);
+    assert_eq!(err.message, "Division by zero");
+
+    let err = engine.call("nothing_here", vec![]).unwrap_err();
+    assert_eq!(err.message, "Variable 'nothing_here' not found");
+
+    // The engine still works afterwards
+    assert!(matches!(engine.eval("1 + 1"), Ok(Value::Integer(2))));
+}
+
+#[test]
+fn registered_function_errors() {
+    let mut engine = Engine::new();
+    engine.register_fn("half", |n: i64| {
+        if n % 2 == 0 {
+            Ok(n / 2)
+        } else {
+            Err(format!("{} is odd", n))
+        }
+    });
+
+    assert!(matches!(engine.eval("half(8)"), Ok(Value::Integer(4))));
+
+    let err = engine.eval("half(3)").unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Runtime);
+    assert_eq!(err.message, "3 is odd");
+
+    // Scripts can catch them like any other error
+    let caught = engine
+        .eval("try { half(3) } catch e { e.message }")
+        .unwrap();
+    assert_eq!(engine.format(&caught).unwrap(), "3 is odd");
+}
+
+#[test]
+fn eval_file_reports_the_file() {
+    let path = format!("{}/tests/bad/addition.rat", env!("CARGO_MANIFEST_DIR"));
+    let err = Engine::new().eval_file(&path).unwrap_err();
+    assert_eq!(err.span.0.filename, path);
+    assert!(
+        err.message.contains("Invalid types for addition"),
+        "{}",
+        err
+    );
+
+    let err = Engine::new().eval_file("no/such/file.rat").unwrap_err();
+    assert!(
+        err.message.starts_with("Couldn't read no/such/file.rat"),
+        "{}",
+        err
+    );
+}
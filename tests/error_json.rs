@@ -0,0 +1,101 @@
+// The shape of errors printed with `--error-format=json`, which tools parse
+
+use rattlescript::Json;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Runs a script with the binary and parses the error it prints
+fn run(name: &str, source: &str) -> (PathBuf, Json) {
+    let dir = std::env::temp_dir().join(format!("rattlescript-json-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.rat", name));
+    std::fs::write(&path, source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rattlescript"))
+        .arg("--error-format=json")
+        .arg(&path)
+        .env("RATTLESCRIPT_PATH", env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    // Each error is one line
+    assert_eq!(stderr.lines().count(), 1, "{}", stderr);
+    match Json::parse(&stderr) {
+        Some(error) => (path, error),
+        None => panic!("Not JSON: {}", stderr),
+    }
+}
+
+fn string<'a>(json: &'a Json, key: &str) -> &'a str {
+    json.get(key).and_then(Json::as_str).unwrap()
+}
+
+// Where a span starts and ends, as (line, column, end line, end column)
+fn span(json: &Json) -> (usize, usize, usize, usize) {
+    let number = |key| json.get(key).and_then(Json::as_usize).unwrap();
+    (
+        number("line"),
+        number("column"),
+        number("end_line"),
+        number("end_column"),
+    )
+}
+
+#[test]
+fn hints_labels_and_traceback() {
+    let source = "def greet(name) => print(name)
+def inner() => greet(nmae: \"x\")
+def outer() {
+    inner()
+}
+outer()
+";
+    let (path, error) = run("keyword", source);
+    let file = path.to_str().unwrap();
+    assert_eq!(string(&error, "kind"), "Runtime");
+    assert_eq!(
+        string(&error, "message"),
+        "Unexpected keyword in greet: `nmae`"
+    );
+    assert_eq!(string(&error, "file"), file);
+    assert_eq!(span(&error), (2, 16, 2, 32));
+
+    let hints: Vec<&str> = error
+        .get("hints")
+        .and_then(Json::as_array)
+        .unwrap()
+        .iter()
+        .map(|hint| hint.as_str().unwrap())
+        .collect();
+    assert_eq!(hints, ["Did you mean 'name'?"]);
+
+    let labels = error.get("labels").and_then(Json::as_array).unwrap();
+    assert_eq!(labels.len(), 1);
+    assert_eq!(string(&labels[0], "message"), "greet is defined here");
+    assert_eq!(string(&labels[0], "file"), file);
+    assert_eq!(span(&labels[0]), (1, 1, 1, 1));
+
+    // The outermost call comes first
+    let traceback = error.get("traceback").and_then(Json::as_array).unwrap();
+    let frames: Vec<(&str, usize)> = traceback
+        .iter()
+        .map(|frame| (string(frame, "name"), span(frame).0))
+        .collect();
+    assert_eq!(frames, [("outer", 6), ("inner", 4), ("greet", 2)]);
+    for frame in traceback {
+        assert_eq!(frame.get("import"), Some(&Json::Bool(false)));
+        assert_eq!(string(frame, "file"), file);
+    }
+}
+
+#[test]
+fn escaped_messages() {
+    let (_, error) = run("escapes", "let say = \"a\\\"b\\tc\"\nthrow `{say}\\n`\n");
+    assert_eq!(string(&error, "kind"), "Thrown");
+    assert_eq!(string(&error, "message"), "Uncaught exception: a\"b\tc\n");
+    assert_eq!(span(&error), (2, 1, 2, 16));
+    assert_eq!(error.get("hints"), Some(&Json::Array(vec![])));
+    assert_eq!(error.get("labels"), Some(&Json::Array(vec![])));
+    assert_eq!(error.get("traceback"), Some(&Json::Array(vec![])));
+}
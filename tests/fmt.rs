@@ -0,0 +1,35 @@
+// Formats each `.in` file in `tests/fmt` and compares it with the `.out` file
+// next to it. They aren't `.rat` files so the test runner leaves them alone.
+
+use rattlescript::format_source;
+use std::path::Path;
+
+fn format(source: &str) -> String {
+    match format_source(source.to_string(), "<test>") {
+        Ok(formatted) => formatted,
+        Err(errors) => panic!("{}", errors[0]),
+    }
+}
+
+fn assert_formats(name: &str) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fmt");
+    let input = std::fs::read_to_string(dir.join(name).with_extension("in")).unwrap();
+    let expected = std::fs::read_to_string(dir.join(name).with_extension("out")).unwrap();
+    assert_eq!(format(&input), expected, "formatting {}.in", name);
+    // Formatting formatted code changes nothing
+    assert_eq!(format(&expected), expected, "formatting {}.out", name);
+}
+
+// Comments of every kind stay where they were, and imports are formatted
+// without the modules having to exist
+#[test]
+fn comments_and_imports() {
+    assert_formats("comments");
+}
+
+#[test]
+fn layout() {
+    assert_formats("layout");
+}
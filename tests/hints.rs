@@ -0,0 +1,27 @@
+// A `fail` header can only check that a hint is there, so the names that
+// shouldn't get one are tested here
+
+use rattlescript::Engine;
+
+fn hints(source: &str) -> Vec<String> {
+    match Engine::new().eval(source) {
+        Ok(_) => panic!("{:?} didn't fail", source),
+        Err(err) => err.hints().to_vec(),
+    }
+}
+
+#[test]
+fn short_names_get_no_suggestion() {
+    assert!(hints("def f(a) => a\nprint(b)").is_empty());
+    assert!(hints("let ab = 1\nprint(ac)").is_empty());
+}
+
+#[test]
+fn builtins_are_suggested() {
+    assert_eq!(hints("lenght(\"abc\")"), ["Did you mean 'len'?"]);
+}
+
+#[test]
+fn closer_names_win_over_builtins() {
+    assert_eq!(hints("let length = 3\nlenght"), ["Did you mean 'length'?"]);
+}
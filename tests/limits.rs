@@ -0,0 +1,96 @@
+// Limits can only be set by a host, so they are tested through the engine
+// rather than with scripts in `good` and `bad`
+
+use rattlescript::{Engine, ErrorKind, Limits};
+use std::time::Duration;
+
+fn engine_with_timeout() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_limits(Limits {
+        timeout: Some(Duration::from_millis(50)),
+        ..Limits::default()
+    });
+    engine
+}
+
+// Catching the error doesn't keep a script running past its time limit, on
+// either interpreter
+fn assert_times_out(source: &str) {
+    for use_vm in [false, true] {
+        let mut engine = engine_with_timeout();
+        if use_vm {
+            engine.enable_vm();
+        }
+        match engine.eval(source) {
+            Ok(_) => panic!("{:?} finished despite the time limit", source),
+            Err(err) => {
+                assert_eq!(err.kind, ErrorKind::Limit, "{}", err);
+                assert!(err.message.contains("time limit"), "{}", err);
+            }
+        }
+    }
+}
+
+#[test]
+fn catching_a_timeout() {
+    assert_times_out("while true { try { while true {} } catch e {} }");
+}
+
+#[test]
+fn returning_from_finally_after_a_timeout() {
+    assert_times_out("def f() { try { while true {} } finally { return 1 } }\nwhile true { f() }");
+}
+
+#[test]
+fn catching_a_timeout_in_a_generator() {
+    assert_times_out(
+        "def g() { while true { try { while true {} } catch e { yield e } } }\nfor e in g() {}",
+    );
+}
+
+#[test]
+fn running_again_after_a_timeout() {
+    let mut engine = engine_with_timeout();
+    assert!(engine.eval("while true {}").is_err());
+    assert!(engine.eval("1 + 1").is_ok());
+}
+
+// Every way of building a string is held to the collection size, on either
+// interpreter
+fn assert_too_big(source: &str) {
+    for use_vm in [false, true] {
+        let mut engine = Engine::new();
+        engine.set_limits(Limits {
+            max_collection_size: Some(100),
+            ..Limits::default()
+        });
+        if use_vm {
+            engine.enable_vm();
+        }
+        engine
+            .eval(&format!("let s = \"{}\"", "a".repeat(90)))
+            .unwrap();
+        match engine.eval(source) {
+            Ok(_) => panic!("{:?} grew past the size limit", source),
+            Err(err) => {
+                assert_eq!(err.kind, ErrorKind::Limit, "{}", err);
+                assert_eq!(err.message, "Exceeded the maximum collection size of 100");
+            }
+        }
+        // Strings that stay small are fine
+        assert!(engine.eval("`{s}!`.upper()").is_ok());
+    }
+}
+
+#[test]
+fn building_strings_past_the_size_limit() {
+    assert_too_big("s + s");
+    assert_too_big("s * 2");
+    assert_too_big("`{s}{s}`");
+    assert_too_big("str([s, s])");
+    assert_too_big("[s, s].str()");
+    assert_too_big("repr(s + \"bbbbbbbbbb\")");
+    assert_too_big("[s, s].iter().join(\"\")");
+    // Upper case letters can take more bytes than lower case ones
+    assert_too_big("(\"ŉ\" * 40).upper()");
+}
@@ -0,0 +1,183 @@
+// The language server, fed messages the way an editor sends them
+
+use rattlescript::{Json, LanguageServer};
+use std::io::Cursor;
+
+const URI: &str = "file:///project/main.rat";
+
+fn message(body: Json) -> String {
+    let body = body.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+fn request(id: usize, method: &str, params: Json) -> String {
+    message(Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id.into()),
+        ("method", method.into()),
+        ("params", params),
+    ]))
+}
+
+fn notification(method: &str, params: Json) -> String {
+    message(Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("method", method.into()),
+        ("params", params),
+    ]))
+}
+
+fn did_open(text: &str) -> String {
+    let document = Json::object(vec![
+        ("uri", URI.into()),
+        ("languageId", "rattlescript".into()),
+        ("version", 1usize.into()),
+        ("text", text.into()),
+    ]);
+    notification(
+        "textDocument/didOpen",
+        Json::object(vec![("textDocument", document)]),
+    )
+}
+
+// Positions count lines and characters from 0
+fn completion(id: usize, line: usize, character: usize) -> String {
+    let position = Json::object(vec![("line", line.into()), ("character", character.into())]);
+    let params = Json::object(vec![
+        ("textDocument", Json::object(vec![("uri", URI.into())])),
+        ("position", position),
+    ]);
+    request(id, "textDocument/completion", params)
+}
+
+// Runs the server over the messages, returning what it answered and whether
+// it was shut down properly
+fn serve(messages: &[String]) -> (Vec<Json>, bool) {
+    let mut input = Cursor::new(messages.concat().into_bytes());
+    let mut output = vec![];
+    let clean = LanguageServer::new()
+        .serve(&mut input, &mut output)
+        .unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    let mut replies = vec![];
+    let mut rest = output.as_str();
+    while let Some(header) = rest.strip_prefix("Content-Length: ") {
+        let (length, body) = header.split_once("\r\n\r\n").unwrap();
+        let (body, tail) = body.split_at(length.parse().unwrap());
+        replies.push(Json::parse(body).unwrap());
+        rest = tail;
+    }
+    assert_eq!(rest, "", "Unframed output");
+    (replies, clean)
+}
+
+fn string<'a>(json: &'a Json, key: &str) -> &'a str {
+    json.get(key).and_then(Json::as_str).unwrap()
+}
+
+#[test]
+fn initialize_and_shut_down() {
+    let (replies, clean) = serve(&[
+        request(1, "initialize", Json::object(vec![])),
+        request(2, "shutdown", Json::Null),
+        notification("exit", Json::Null),
+    ]);
+    assert!(clean);
+    assert_eq!(replies.len(), 2);
+    assert_eq!(replies[0].get("id"), Some(&Json::Number(1.0)));
+    let capabilities = replies[0]
+        .get("result")
+        .and_then(|result| result.get("capabilities"))
+        .unwrap();
+    assert_eq!(
+        capabilities.get("textDocumentSync"),
+        Some(&Json::Number(1.0))
+    );
+    assert_eq!(
+        capabilities.get("completionProvider"),
+        Some(&Json::object(vec![(
+            "triggerCharacters",
+            Json::Array(vec![".".into()])
+        )]))
+    );
+    assert_eq!(replies[1].get("result"), Some(&Json::Null));
+
+    // Ending the input without shutting down is not clean
+    let (_, clean) = serve(&[request(1, "initialize", Json::object(vec![]))]);
+    assert!(!clean);
+}
+
+#[test]
+fn syntax_errors_and_completion() {
+    let source = "def greet(name) => name
+let count = 1 + / 2
+class Point {
+    let x = 0
+    def norm(self) => self.x
+}
+
+";
+    let (replies, _) = serve(&[
+        request(1, "initialize", Json::object(vec![])),
+        did_open(source),
+        completion(2, 6, 0),
+        completion(3, 4, 28),
+    ]);
+    assert_eq!(replies.len(), 4);
+
+    // The syntax error is reported where it is
+    let diagnostics = &replies[1];
+    assert_eq!(
+        string(diagnostics, "method"),
+        "textDocument/publishDiagnostics"
+    );
+    let params = diagnostics.get("params").unwrap();
+    assert_eq!(string(params, "uri"), URI);
+    let diagnostics = params.get("diagnostics").and_then(Json::as_array).unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    let error = &diagnostics[0];
+    assert_eq!(error.get("severity"), Some(&Json::Number(1.0)));
+    assert_eq!(string(error, "source"), "rattlescript");
+    assert_eq!(
+        string(error, "message"),
+        "Unexpected token in parse_atom: Slash(/)"
+    );
+    let start = error
+        .get("range")
+        .and_then(|range| range.get("start"))
+        .unwrap();
+    assert_eq!(start.get("line"), Some(&Json::Number(1.0)));
+    assert_eq!(start.get("character"), Some(&Json::Number(16.0)));
+
+    // What's declared around the error is still known to completion
+    let labels = |reply: &Json| -> Vec<(String, usize)> {
+        let items = reply.get("result").and_then(Json::as_array).unwrap();
+        items
+            .iter()
+            .map(|item| {
+                let kind = item.get("kind").and_then(Json::as_usize).unwrap();
+                (string(item, "label").to_string(), kind)
+            })
+            .collect()
+    };
+    let globals = labels(&replies[2]);
+    for expected in [("greet", 3), ("Point", 7), ("print", 3), ("while", 14)] {
+        let expected = (expected.0.to_string(), expected.1);
+        assert!(globals.contains(&expected), "{:?}", globals);
+    }
+    // Names declared inside something aren't visible outside it
+    assert!(!globals
+        .iter()
+        .any(|(label, _)| label == "name" || label == "norm"));
+    let mut sorted = globals.clone();
+    sorted.sort();
+    assert_eq!(globals, sorted);
+
+    // After a `.`, members and methods are offered instead
+    let members = labels(&replies[3]);
+    assert!(members.contains(&("x".to_string(), 5)), "{:?}", members);
+    assert!(members.contains(&("norm".to_string(), 2)), "{:?}", members);
+    assert!(members.contains(&("push".to_string(), 2)), "{:?}", members);
+    assert!(!members.iter().any(|(label, _)| label == "greet"));
+}
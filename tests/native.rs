@@ -0,0 +1,103 @@
+// Converting values for registered Rust functions, and the errors scripts get
+// when they call them wrong
+
+use rattlescript::{Engine, ErrorKind, FromValue, IntoValue, Type, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+struct Counter {
+    count: i64,
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.register_fn("add", |a: i64, b: i64| a + b);
+    engine.register_fn("total", |numbers: Vec<f64>| numbers.iter().sum::<f64>());
+    engine.register_fn("lookup", |table: HashMap<String, i64>, key: String| {
+        table.get(&key).copied()
+    });
+    engine.register_fn("greet", |name: Option<String>| {
+        format!("hi {}", name.unwrap_or_else(|| "you".to_string()))
+    });
+    engine.register_method(Type::String, "shout", |text: String| {
+        format!("{}!", text.to_uppercase())
+    });
+    engine.register_fn("counter", || Value::object(Counter { count: 0 }));
+    engine.register_object_method::<Counter, _>("bump", |counter: Rc<RefCell<Counter>>| {
+        counter.borrow_mut().count += 1;
+        counter.borrow().count
+    });
+    engine
+}
+
+fn format(source: &str) -> String {
+    let mut engine = engine();
+    let value = engine.eval(source).unwrap();
+    engine.repr(&value).unwrap()
+}
+
+fn error(source: &str) -> String {
+    let err = engine().eval(source).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Runtime, "{}", err);
+    err.message
+}
+
+#[test]
+fn conversions() {
+    assert_eq!(format("add(1, 2)"), "3");
+    // Integers convert to floats, and tuples to vectors
+    assert_eq!(format("total([1, 2.5])"), "3.5");
+    assert_eq!(format("total((1, 2))"), "3");
+    assert_eq!(format("lookup({\"a\": 1}, \"a\")"), "1");
+    assert_eq!(format("lookup({\"a\": 1}, \"b\")"), "nothing");
+    assert_eq!(format("greet(nothing)"), "\"hi you\"");
+    assert_eq!(format("greet(\"rust\")"), "\"hi rust\"");
+    assert_eq!(format("\"hey\".shout()"), "\"HEY!\"");
+    assert_eq!(format("let c = counter()\nc.bump()\nc.bump()"), "2");
+}
+
+#[test]
+fn values_convert_back() {
+    assert!(matches!(i64::from_value(&Value::Integer(3)), Some(3)));
+    assert!(i64::from_value(&Value::Float(3.0)).is_none());
+    assert_eq!(Option::<i64>::from_value(&Value::Nothing), Some(None));
+    assert_eq!(<Vec<i64>>::type_name(), "array of integer");
+    assert!(matches!(vec![1i64, 2].into_value(), Value::Array(_)));
+    assert!(matches!(None::<i64>.into_value(), Value::Nothing));
+}
+
+#[test]
+fn wrong_argument_types() {
+    assert_eq!(
+        error("add(1, \"2\")"),
+        "Expected integer for argument 2 of add(), but got String"
+    );
+    assert_eq!(
+        error("total([1, \"2\"])"),
+        "Expected array of float for argument 1 of total(), but got Array"
+    );
+    assert_eq!(
+        error("lookup({1: 1}, \"a\")"),
+        "Expected dict of string to integer for argument 1 of lookup(), but got Dict"
+    );
+    assert_eq!(
+        error("let c = counter()\nadd(c, 1)"),
+        "Expected integer for argument 1 of add(), but got Object"
+    );
+}
+
+#[test]
+fn wrong_argument_counts() {
+    assert_eq!(error("add(1)"), "add() takes 2 arguments, but got 1");
+    assert_eq!(error("add(1, 2, 3)"), "add() takes 2 arguments, but got 3");
+    assert_eq!(
+        error("counter(1)"),
+        "counter() takes 0 arguments, but got 1"
+    );
+    // Methods get the value they're called on as their first argument
+    assert_eq!(
+        error("\"a\".shout(1)"),
+        "shout() takes 1 arguments, but got 2"
+    );
+}
@@ -0,0 +1,110 @@
+// Profiling a script with known call counts through the engine. The times
+// vary from run to run, so only their format is checked.
+
+use rattlescript::{Engine, Profile};
+
+const SOURCE: &str = "def fib(n) {
+    if n < 2 {
+        return n
+    }
+    return fib(n - 1) + fib(n - 2)
+}
+let total = 0
+for i in 0..3 {
+    total += fib(5)
+}
+len([total])
+";
+
+// Even shallow recursion needs more than a test thread's stack in debug
+// builds, as in the CLI
+const STACK_SIZE: usize = 1 << 30;
+
+fn profile(use_vm: bool) -> Profile {
+    let run = move || {
+        let mut engine = Engine::new();
+        if use_vm {
+            engine.enable_vm();
+        }
+        assert!(engine.profile().is_none());
+        engine.enable_profiler();
+        engine.eval(SOURCE).unwrap();
+        engine.profile().unwrap()
+    };
+    let thread = std::thread::Builder::new().stack_size(STACK_SIZE);
+    thread.spawn(run).unwrap().join().unwrap()
+}
+
+#[test]
+fn call_and_line_counts() {
+    for use_vm in [false, true] {
+        let profile = profile(use_vm);
+        let calls: Vec<(&str, u64)> = profile
+            .functions
+            .iter()
+            .map(|function| (function.name.as_str(), function.calls))
+            .collect();
+        for expected in [("<program>", 1), ("fib (<eval>:1)", 45), ("len", 1)] {
+            assert!(calls.contains(&expected), "{:?}", calls);
+        }
+        assert_eq!(calls.len(), 3, "{:?}", calls);
+        for function in &profile.functions {
+            assert!(function.exclusive <= function.inclusive);
+            assert!(function.inclusive <= profile.total);
+        }
+
+        let hits: Vec<(usize, u64)> = profile
+            .lines
+            .iter()
+            .map(|line| {
+                assert_eq!(line.file, "<eval>");
+                (line.line, line.hits)
+            })
+            .collect();
+        // Each call of fib(5) makes 8 calls that return early and 7 that don't
+        let expected = [
+            (2, 45),
+            (3, 24),
+            (5, 21),
+            (9, 3),
+            (1, 1),
+            (7, 1),
+            (8, 1),
+            (11, 1),
+        ];
+        assert_eq!(hits, expected);
+    }
+}
+
+#[test]
+fn report_format() {
+    let profile = profile(false);
+    let mut report = vec![];
+    profile.write_report(&mut report).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+
+    // A time in milliseconds, like `4.632 ms`
+    let is_time = |time: &str| {
+        let Some(millis) = time.strip_suffix(" ms") else {
+            return false;
+        };
+        let (whole, fraction) = millis.split_once('.').unwrap_or_default();
+        whole.parse::<u64>().is_ok() && fraction.len() == 3 && fraction.parse::<u64>().is_ok()
+    };
+    assert!(is_time(lines[0].strip_prefix("Total time: ").unwrap()));
+    assert_eq!(lines[1], "");
+    assert_eq!(lines[2], "     Calls     Inclusive     Exclusive  Function");
+    // One row for each function, in the order of the profile
+    for (row, function) in lines[3..].iter().zip(&profile.functions) {
+        assert_eq!(row[..10].trim_start(), function.calls.to_string());
+        assert!(is_time(row[10..24].trim_start()), "{}", row);
+        assert!(is_time(row[24..38].trim_start()), "{}", row);
+        assert_eq!(&row[38..], format!("  {}", function.name));
+    }
+    let lines = &lines[3 + profile.functions.len()..];
+    assert_eq!(lines[0], "");
+    assert_eq!(lines[1], "      Hits  Line");
+    assert_eq!(lines[2], "        45  <eval>:2");
+    assert_eq!(lines.len(), 2 + profile.lines.len());
+}
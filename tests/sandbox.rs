@@ -0,0 +1,153 @@
+// What a sandbox lets scripts reach on the filesystem. The files the tests
+// open have to be made first, so they run through the engine rather than as
+// scripts in `good` and `bad`
+
+use rattlescript::{Engine, ErrorKind, Sandbox, Value};
+use std::path::{Path, PathBuf};
+
+// A fresh directory for each test, holding `allowed/inside.txt` and
+// `outside.txt` next to it
+fn make_root(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!(
+        "rattlescript-sandbox-{}-{}",
+        std::process::id(),
+        name
+    ));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("allowed")).unwrap();
+    std::fs::write(root.join("allowed").join("inside.txt"), "inside").unwrap();
+    std::fs::write(root.join("outside.txt"), "outside").unwrap();
+    root
+}
+
+fn engine(sandbox: Sandbox) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_sandbox(sandbox);
+    engine
+}
+
+fn only_open(root: &Path) -> Sandbox {
+    Sandbox {
+        allowed_paths: Some(vec![root.join("allowed")]),
+        ..Sandbox::deny_all()
+    }
+}
+
+fn read(engine: &mut Engine, path: &Path) -> rattlescript::Result<String> {
+    let source = format!("open({:?}).read()", path.display().to_string());
+    let value = engine.eval(&source)?;
+    Ok(engine.format(&value).unwrap())
+}
+
+fn assert_denied(engine: &mut Engine, path: &Path) {
+    let err = read(engine, path).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Sandbox, "{}", err);
+    assert_eq!(
+        err.message,
+        format!("Opening '{}' is not allowed", path.display())
+    );
+}
+
+#[test]
+fn opening_inside_the_allowed_paths() {
+    let root = make_root("inside");
+    let mut engine = engine(only_open(&root));
+    let inside = root.join("allowed").join("inside.txt");
+    assert_eq!(read(&mut engine, &inside).unwrap(), "inside");
+    // Going up and back down again stays inside
+    let around = root
+        .join("allowed")
+        .join("..")
+        .join("allowed")
+        .join("inside.txt");
+    assert_eq!(read(&mut engine, &around).unwrap(), "inside");
+}
+
+#[test]
+fn opening_outside_the_allowed_paths() {
+    let root = make_root("outside");
+    let mut engine = engine(only_open(&root));
+    assert_denied(&mut engine, &root.join("outside.txt"));
+    assert_denied(
+        &mut engine,
+        &root.join("allowed").join("..").join("outside.txt"),
+    );
+    // Files that don't exist are denied before trying to open them
+    assert_denied(&mut engine, &root.join("allowed").join("missing.txt"));
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_out_of_the_allowed_paths() {
+    let root = make_root("symlink");
+    let escape = root.join("allowed").join("escape.txt");
+    std::os::unix::fs::symlink(root.join("outside.txt"), &escape).unwrap();
+    let mut engine = engine(only_open(&root));
+    assert_denied(&mut engine, &escape);
+}
+
+#[test]
+fn denied_paths_inside_allowed_ones() {
+    let root = make_root("denied");
+    let mut engine = engine(Sandbox {
+        allowed_paths: Some(vec![root.clone()]),
+        denied_paths: vec![root.join("outside.txt")],
+        ..Sandbox::deny_all()
+    });
+    assert_eq!(
+        read(&mut engine, &root.join("allowed").join("inside.txt")).unwrap(),
+        "inside"
+    );
+    assert_denied(&mut engine, &root.join("outside.txt"));
+}
+
+#[test]
+fn scripts_can_catch_sandbox_errors() {
+    let root = make_root("catch");
+    let mut engine = engine(only_open(&root));
+    let source = format!(
+        "try {{ open({:?}) }} catch e {{ e.kind }}",
+        root.join("outside.txt").display().to_string()
+    );
+    let kind = engine.eval(&source).unwrap();
+    assert_eq!(engine.format(&kind).unwrap(), "Sandbox");
+}
+
+#[test]
+fn importing_outside_the_import_roots() {
+    let root = make_root("import");
+    let modules = root.join("allowed");
+    std::fs::write(modules.join("inside.rat"), "def one() => 1").unwrap();
+    std::fs::write(root.join("outside.rat"), "def two() => 2").unwrap();
+    std::fs::write(modules.join("good.rat"), "import inside\ninside.one()").unwrap();
+    // Modules are found next to the importing file, outside the root here
+    std::fs::write(root.join("main.rat"), "import outside\noutside.two()").unwrap();
+
+    let sandbox = Sandbox {
+        import_roots: Some(vec![modules.clone()]),
+        ..Sandbox::deny_all()
+    };
+    let mut allowed = engine(sandbox.clone());
+    assert!(matches!(
+        allowed.eval_file(modules.join("good.rat")),
+        Ok(Value::Integer(1))
+    ));
+
+    let err = engine(sandbox)
+        .eval_file(root.join("main.rat"))
+        .unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Sandbox, "{}", err);
+    assert_eq!(
+        err.message,
+        format!(
+            "Importing '{}' is not allowed",
+            root.join("outside.rat").display()
+        )
+    );
+
+    // Denying everything denies imports too
+    let err = engine(Sandbox::deny_all())
+        .eval_file(modules.join("good.rat"))
+        .unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Sandbox, "{}", err);
+}
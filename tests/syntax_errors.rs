@@ -0,0 +1,41 @@
+// The parser recovers from each syntax error and reports the rest of them,
+// which the `fail` header of a test file can't count
+
+use rattlescript::Engine;
+
+fn assert_errors(file: &str, expected: &[&str]) {
+    let path = format!("{}/tests/bad/{}", env!("CARGO_MANIFEST_DIR"), file);
+    let errors = Engine::new().check_file(path);
+    let messages: Vec<&str> = errors.iter().map(|err| err.message.as_str()).collect();
+    assert_eq!(messages, expected, "in {}", file);
+}
+
+#[test]
+fn statements() {
+    assert_errors(
+        "syntax_recovery.rat",
+        &[
+            "Unexpected token in parse_atom: Equals(=)",
+            "Expected line end, but got IntegerLiteralDec",
+        ],
+    );
+}
+
+#[test]
+fn class_methods() {
+    let expected = ["First argument of class method must be self"];
+    assert_errors("class_function_without_self.rat", &expected);
+    assert_errors("no_argument_class_function.rat", &expected);
+}
+
+#[test]
+fn members() {
+    assert_errors(
+        "member_recovery.rat",
+        &[
+            "Expected class or function declaration",
+            "Expected enum variant, function or static variable",
+            "Expected function, class, enum or variable",
+        ],
+    );
+}